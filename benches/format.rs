@@ -0,0 +1,57 @@
+//! Benchmarks for the core formatter on a large generated document.
+//!
+//! Run with `cargo bench`. The `edits` case formats through the edit list
+//! ([`blank_line_edits`] + [`apply_line_edits`]), which is how the formatter used to
+//! work; `single_pass` is the direct [`remove_multiple_blank_lines`] path.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use mdfmt::process_md::{apply_line_edits, blank_line_edits, remove_multiple_blank_lines};
+use std::hint::black_box;
+
+/// A section with headings, lists, code, and runs of blank lines to collapse.
+const SECTION: &str = "## Release notes\n\
+Some introductory text.\n\n\n\n\
+- First change\n\
+- Second change\n\
+Text right after the list.\n\n\n\
+```rust\n\
+\n\
+fn main() {}\n\
+\n\
+```\n\
+More text\n\n\n\n";
+
+/// Build a changelog-like document of roughly `size` bytes.
+fn fixture(size: usize) -> String {
+    let mut content = String::from("---\ntitle: Changelog\n---\n\n# Changelog\n\n");
+    while content.len() < size {
+        content.push_str(SECTION);
+    }
+    content
+}
+
+fn bench_format(c: &mut Criterion) {
+    let messy = fixture(8 * 1024 * 1024);
+    let clean = remove_multiple_blank_lines(&messy).into_owned();
+
+    let mut group = c.benchmark_group("format_8mb");
+    group.throughput(Throughput::Bytes(messy.len() as u64));
+    group.sample_size(20);
+
+    for (name, content) in [("messy", &messy), ("clean", &clean)] {
+        group.bench_function(format!("single_pass/{}", name), |b| {
+            b.iter(|| remove_multiple_blank_lines(black_box(content)))
+        });
+        group.bench_function(format!("edits/{}", name), |b| {
+            b.iter(|| {
+                let content = black_box(content);
+                apply_line_edits(content, &blank_line_edits(content))
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_format);
+criterion_main!(benches);
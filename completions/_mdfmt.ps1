@@ -0,0 +1,122 @@
+
+using namespace System.Management.Automation
+using namespace System.Management.Automation.Language
+
+Register-ArgumentCompleter -Native -CommandName 'mdfmt' -ScriptBlock {
+    param($wordToComplete, $commandAst, $cursorPosition)
+
+    $commandElements = $commandAst.CommandElements
+    $command = @(
+        'mdfmt'
+        for ($i = 1; $i -lt $commandElements.Count; $i++) {
+            $element = $commandElements[$i]
+            if ($element -isnot [StringConstantExpressionAst] -or
+                $element.StringConstantType -ne [StringConstantType]::BareWord -or
+                $element.Value.StartsWith('-') -or
+                $element.Value -eq $wordToComplete) {
+                break
+        }
+        $element.Value
+    }) -join ';'
+
+    $completions = @(switch ($command) {
+        'mdfmt' {
+            [CompletionResult]::new('--deleted-list', '--deleted-list', [CompletionResultType]::ParameterName, 'Write the paths of deleted files (or files that would be deleted), one per line, to this file')
+            [CompletionResult]::new('--out-dir', '--out-dir', [CompletionResultType]::ParameterName, 'Write the formatted files below DIR instead of modifying them, with their paths relative to the searched directories; clean files are copied, and files that would be deleted for being empty are not written')
+            [CompletionResult]::new('--changed-since', '--changed-since', [CompletionResultType]::ParameterName, 'Only format markdown files changed since the given git ref (implies --changed)')
+            [CompletionResult]::new('--include', '--include', [CompletionResultType]::ParameterName, 'Only process files in searched directories that match this glob (repeatable)')
+            [CompletionResult]::new('--exclude', '--exclude', [CompletionResultType]::ParameterName, 'Skip files and directories in searched directories that match this glob; prefix with `!` to re-include files excluded by an earlier pattern (repeatable)')
+            [CompletionResult]::new('--line-range', '--line-range', [CompletionResultType]::ParameterName, 'Only format lines START to END (1-based, inclusive) of a single file, e.g. an editor selection; the rest of the file is left as is')
+            [CompletionResult]::new('--stdin-format', '--stdin-format', [CompletionResultType]::ParameterName, 'Format every --stdin-json request as this flavor of Markdown; requests for `.mdx` paths are always formatted as MDX')
+            [CompletionResult]::new('--stdin-filepath', '--stdin-filepath', [CompletionResultType]::ParameterName, 'Format --stdin-json requests with the options of the file at PATH, from its config files and `.editorconfig`, and as MDX if it is an `.mdx` file; the file itself is never read or written and does not have to exist')
+            [CompletionResult]::new('--max-size', '--max-size', [CompletionResultType]::ParameterName, 'Skip files larger than this size, e.g. 500K or 5M (binary units)')
+            [CompletionResult]::new('--lock-timeout', '--lock-timeout', [CompletionResultType]::ParameterName, 'Wait up to this many milliseconds for a file that another mdfmt run is formatting, then skip it with an error [default: 5000]')
+            [CompletionResult]::new('--encoding', '--encoding', [CompletionResultType]::ParameterName, 'Read files in this encoding, e.g. latin1 or shift_jis, and write them back in it; files whose formatted content it cannot represent are skipped with an error [default: utf-8]')
+            [CompletionResult]::new('--demote-headings', '--demote-headings', [CompletionResultType]::ParameterName, 'Demote every heading by N levels, e.g. `#` to `##` for 1; headings stop at level 6')
+            [CompletionResult]::new('--promote-headings', '--promote-headings', [CompletionResultType]::ParameterName, 'Promote every heading by N levels, e.g. `##` to `#` for 1; files with headings that would go past level 1 are skipped with an error')
+            [CompletionResult]::new('--fence-style', '--fence-style', [CompletionResultType]::ParameterName, 'Rewrite code fence markers; fences whose content would close the converted fence are kept')
+            [CompletionResult]::new('--default-fence-lang', '--default-fence-lang', [CompletionResultType]::ParameterName, 'Add this language to code fences without one, e.g. `text`')
+            [CompletionResult]::new('--blank-lines-before-heading', '--blank-lines-before-heading', [CompletionResultType]::ParameterName, 'Require exactly N blank lines before headings; a list like 2,1 sets the count by heading level, the last one repeating for deeper levels')
+            [CompletionResult]::new('--blank-lines-after-heading', '--blank-lines-after-heading', [CompletionResultType]::ParameterName, 'Require exactly N blank lines after headings, set like --blank-lines-before-heading')
+            [CompletionResult]::new('--toc-levels', '--toc-levels', [CompletionResultType]::ParameterName, 'The heading levels listed in tables of contents, e.g. 2..4')
+            [CompletionResult]::new('--list-indent', '--list-indent', [CompletionResultType]::ParameterName, 'Re-indent nested list items to N spaces per nesting level, e.g. 2 or 4')
+            [CompletionResult]::new('--tab-width', '--tab-width', [CompletionResultType]::ParameterName, 'The number of columns a tab in list indentation advances to')
+            [CompletionResult]::new('--wrap-blockquotes', '--wrap-blockquotes', [CompletionResultType]::ParameterName, 'Wrap the lines of block quotes longer than WIDTH characters, keeping their `>` markers on every line')
+            [CompletionResult]::new('--tabs-to-spaces', '--tabs-to-spaces', [CompletionResultType]::ParameterName, 'Expand tabs in the indentation of lines to spaces, with tab stops WIDTH columns apart; code blocks and tabs within lines are kept')
+            [CompletionResult]::new('--end-of-line', '--end-of-line', [CompletionResultType]::ParameterName, 'Write files with these line endings, also inside code fences and frontmatter; overrides `end_of_line` in `.editorconfig`')
+            [CompletionResult]::new('--line-ending', '--line-ending', [CompletionResultType]::ParameterName, 'Write files with these line endings, also inside code fences and frontmatter; overrides `end_of_line` in `.editorconfig`')
+            [CompletionResult]::new('--output-format', '--output-format', [CompletionResultType]::ParameterName, 'Output format for the report')
+            [CompletionResult]::new('--color', '--color', [CompletionResultType]::ParameterName, 'When to color the report and error messages; `auto` colors terminals unless the NO_COLOR environment variable is set')
+            [CompletionResult]::new('--sort-by', '--sort-by', [CompletionResultType]::ParameterName, 'Order in which files are processed and listed [default: the order of the paths given, each directory sorted by path]')
+            [CompletionResult]::new('--cache-location', '--cache-location', [CompletionResultType]::ParameterName, 'Location of the cache file, relative to the current directory (implies --cache) [default: .mdfmt-cache]')
+            [CompletionResult]::new('--generate-completions', '--generate-completions', [CompletionResultType]::ParameterName, 'Print the completion script for SHELL and exit; the older spelling of `mdfmt completions SHELL`, kept for existing scripts')
+            [CompletionResult]::new('-v', '-v', [CompletionResultType]::ParameterName, 'Show verbose output; -vv also logs debug details, and -vvv which rule changed each line')
+            [CompletionResult]::new('--verbose', '--verbose', [CompletionResultType]::ParameterName, 'Show verbose output; -vv also logs debug details, and -vvv which rule changed each line')
+            [CompletionResult]::new('-q', '-q', [CompletionResultType]::ParameterName, 'Only print errors')
+            [CompletionResult]::new('--quiet', '--quiet', [CompletionResultType]::ParameterName, 'Only print errors')
+            [CompletionResult]::new('--no-progress', '--no-progress', [CompletionResultType]::ParameterName, 'Do not show a progress bar while processing files')
+            [CompletionResult]::new('-n', '-n', [CompletionResultType]::ParameterName, 'Dry run - show what would be done without making changes')
+            [CompletionResult]::new('--dry-run', '--dry-run', [CompletionResultType]::ParameterName, 'Dry run - show what would be done without making changes')
+            [CompletionResult]::new('--delete', '--delete', [CompletionResultType]::ParameterName, 'Allow deletion of empty files')
+            [CompletionResult]::new('--delete-to-trash', '--delete-to-trash', [CompletionResultType]::ParameterName, 'Move empty files to the system trash instead of deleting them permanently')
+            [CompletionResult]::new('--normalize-empty', '--normalize-empty', [CompletionResultType]::ParameterName, 'Truncate files that contain only whitespace to zero bytes instead of deleting or skipping them')
+            [CompletionResult]::new('--include-empty', '--include-empty', [CompletionResultType]::ParameterName, 'Format files that contain only frontmatter instead of deleting or skipping them')
+            [CompletionResult]::new('--warn-empty', '--warn-empty', [CompletionResultType]::ParameterName, 'Print a warning on stderr for every file that contains only frontmatter and is kept')
+            [CompletionResult]::new('--check', '--check', [CompletionResultType]::ParameterName, 'Check whether files are formatted without modifying them')
+            [CompletionResult]::new('--lint', '--lint', [CompletionResultType]::ParameterName, 'Report the blank line problems formatting would fix and the lint rule violations (bare URLs, images without alt text, code fences without a language, zero-width characters, headings ending with punctuation) as `path:line: CODE message`, without modifying files; exits with 1 if any of them is an error')
+            [CompletionResult]::new('-l', '-l', [CompletionResultType]::ParameterName, 'Only print the paths of files that would be changed, one per line and relative to the current directory, without modifying them')
+            [CompletionResult]::new('--list-different', '--list-different', [CompletionResultType]::ParameterName, 'Only print the paths of files that would be changed, one per line and relative to the current directory, without modifying them')
+            [CompletionResult]::new('--list-files', '--list-files', [CompletionResultType]::ParameterName, 'With --check, only print the absolute paths of files that would be changed, one per line, or as a JSON array of strings with --output-format json')
+            [CompletionResult]::new('--find-duplicates', '--find-duplicates', [CompletionResultType]::ParameterName, 'Report groups of files with the same content after formatting, instead of formatting them; exits with 1 if any are found')
+            [CompletionResult]::new('--extract-headings', '--extract-headings', [CompletionResultType]::ParameterName, 'Print the headings of every file with their line numbers and anchors, as an indented outline or as JSON with --output-format json, without modifying files')
+            [CompletionResult]::new('--no-error-on-unformatted', '--no-error-on-unformatted', [CompletionResultType]::ParameterName, 'Exit with 0 even when files were changed (or would be changed, with --check)')
+            [CompletionResult]::new('--report-unchanged', '--report-unchanged', [CompletionResultType]::ParameterName, 'With --check, also list the files that are already formatted; with --quiet, only their number is printed')
+            [CompletionResult]::new('--changed', '--changed', [CompletionResultType]::ParameterName, 'Only format markdown files that git reports as modified, added, or untracked')
+            [CompletionResult]::new('--format-only-staged', '--format-only-staged', [CompletionResultType]::ParameterName, 'Only format markdown files staged for commit, and stage the formatted files again unless they also have unstaged changes, e.g. in a git pre-commit hook')
+            [CompletionResult]::new('--no-restage', '--no-restage', [CompletionResultType]::ParameterName, 'With --format-only-staged, leave the formatted files unstaged to review them before committing')
+            [CompletionResult]::new('--diff-filter', '--diff-filter', [CompletionResultType]::ParameterName, 'Read a unified diff from stdin and only format the lines it changed')
+            [CompletionResult]::new('--stdin-json', '--stdin-json', [CompletionResultType]::ParameterName, 'Read newline-delimited JSON requests `{"path", "content"}` from stdin and write one formatted response per request to stdout, without touching the filesystem')
+            [CompletionResult]::new('--lsp', '--lsp', [CompletionResultType]::ParameterName, 'Run a language server on stdin and stdout that formats the documents open in an editor, with the options of their files')
+            [CompletionResult]::new('--preserve-mtime', '--preserve-mtime', [CompletionResultType]::ParameterName, 'Keep the modification times of formatted files, so build tools that compare timestamps do not rebuild them')
+            [CompletionResult]::new('--clamp-headings', '--clamp-headings', [CompletionResultType]::ParameterName, 'Promote headings that would go past level 1 to level 1 instead of skipping the file')
+            [CompletionResult]::new('--normalize-fence-lang', '--normalize-fence-lang', [CompletionResultType]::ParameterName, 'Lowercase code fence languages and trim whitespace around info strings, e.g. "``` Rust" to "```rust"; aliases come from the `[fence_aliases]` config table')
+            [CompletionResult]::new('--require-fence-lang', '--require-fence-lang', [CompletionResultType]::ParameterName, 'Warn on stderr about code fences without a language (markdownlint MD040)')
+            [CompletionResult]::new('--toc', '--toc', [CompletionResultType]::ParameterName, 'Regenerate the table of contents between `<!-- mdfmt-toc-start -->` and `<!-- mdfmt-toc-end -->` markers; files without them are not changed')
+            [CompletionResult]::new('--remove-empty-headings', '--remove-empty-headings', [CompletionResultType]::ParameterName, 'Remove headings that have no text, only `#` markers, unless they are the only heading of the file')
+            [CompletionResult]::new('--sort-frontmatter', '--sort-frontmatter', [CompletionResultType]::ParameterName, 'Sort the top-level keys of YAML frontmatter alphabetically, or with the keys from `frontmatter_key_order` in a config file first')
+            [CompletionResult]::new('--normalize-list-spacing', '--normalize-list-spacing', [CompletionResultType]::ParameterName, 'Put exactly one space between list markers and the item text, e.g. "-   item" to "- item"')
+            [CompletionResult]::new('--final-newline', '--final-newline', [CompletionResultType]::ParameterName, 'End every file with a newline')
+            [CompletionResult]::new('--no-final-newline', '--no-final-newline', [CompletionResultType]::ParameterName, 'Remove the newline at the end of every file')
+            [CompletionResult]::new('--ignore-final-newline-only', '--ignore-final-newline-only', [CompletionResultType]::ParameterName, 'Leave files alone when only the newlines at their end would change; files with other changes still get them fixed')
+            [CompletionResult]::new('--trim-trailing-whitespace', '--trim-trailing-whitespace', [CompletionResultType]::ParameterName, 'Remove trailing whitespace outside code fences, including hard line breaks written as two trailing spaces')
+            [CompletionResult]::new('--keep-trailing-whitespace', '--keep-trailing-whitespace', [CompletionResultType]::ParameterName, 'Keep trailing whitespace, even if .editorconfig or .mdfmt.toml trims it')
+            [CompletionResult]::new('--sorted-output', '--sorted-output', [CompletionResultType]::ParameterName, 'Print per-file results in input order after all files are processed, instead of as each file finishes')
+            [CompletionResult]::new('--timings', '--timings', [CompletionResultType]::ParameterName, 'Measure per-file read, format, and write times and report the slowest files')
+            [CompletionResult]::new('--cache', '--cache', [CompletionResultType]::ParameterName, 'Skip files that were already verified clean by a previous run')
+            [CompletionResult]::new('--no-cache', '--no-cache', [CompletionResultType]::ParameterName, 'Do not use the cache, even if --cache or --cache-location is given')
+            [CompletionResult]::new('--no-config', '--no-config', [CompletionResultType]::ParameterName, 'Ignore `.mdfmt.toml` config files')
+            [CompletionResult]::new('--no-editorconfig', '--no-editorconfig', [CompletionResultType]::ParameterName, 'Ignore `.editorconfig` files, which otherwise set the line endings, final newline, trailing whitespace, and tab options')
+            [CompletionResult]::new('--print-config', '--print-config', [CompletionResultType]::ParameterName, 'Print the options that apply to files in PATH as TOML and exit: the defaults, overridden by the config files, `.editorconfig` (when PATH is a file), and the command line')
+            [CompletionResult]::new('-h', '-h', [CompletionResultType]::ParameterName, 'Print help (see more with ''--help'')')
+            [CompletionResult]::new('--help', '--help', [CompletionResultType]::ParameterName, 'Print help (see more with ''--help'')')
+            [CompletionResult]::new('-V', '-V ', [CompletionResultType]::ParameterName, 'Print version')
+            [CompletionResult]::new('--version', '--version', [CompletionResultType]::ParameterName, 'Print version')
+            [CompletionResult]::new('completions', 'completions', [CompletionResultType]::ParameterValue, 'Print the completion script for SHELL to stdout')
+            [CompletionResult]::new('manpage', 'manpage', [CompletionResultType]::ParameterValue, 'Print the man page in roff format to stdout')
+            break
+        }
+        'mdfmt;completions' {
+            [CompletionResult]::new('-h', '-h', [CompletionResultType]::ParameterName, 'Print help')
+            [CompletionResult]::new('--help', '--help', [CompletionResultType]::ParameterName, 'Print help')
+            break
+        }
+        'mdfmt;manpage' {
+            [CompletionResult]::new('-h', '-h', [CompletionResultType]::ParameterName, 'Print help')
+            [CompletionResult]::new('--help', '--help', [CompletionResultType]::ParameterName, 'Print help')
+            break
+        }
+    })
+
+    $completions.Where{ $_.CompletionText -like "$wordToComplete*" } |
+        Sort-Object -Property ListItemText
+}
@@ -0,0 +1,118 @@
+# Print an optspec for argparse to handle cmd's options that are independent of any subcommand.
+function __fish_mdfmt_global_optspecs
+    string join \n v/verbose q/quiet no-progress n/dry-run delete delete-to-trash normalize-empty include-empty warn-empty deleted-list= check out-dir= lint l/list-different list-files find-duplicates extract-headings no-error-on-unformatted report-unchanged changed changed-since= format-only-staged no-restage include= exclude= diff-filter line-range= stdin-json stdin-format= stdin-filepath= lsp preserve-mtime max-size= lock-timeout= encoding= demote-headings= promote-headings= clamp-headings fence-style= normalize-fence-lang default-fence-lang= require-fence-lang blank-lines-before-heading= blank-lines-after-heading= toc toc-levels= remove-empty-headings sort-frontmatter list-indent= tab-width= normalize-list-spacing wrap-blockquotes= tabs-to-spaces= end-of-line= final-newline no-final-newline ignore-final-newline-only trim-trailing-whitespace keep-trailing-whitespace output-format= color= sort-by= sorted-output timings cache no-cache cache-location= no-config no-editorconfig print-config generate-completions= h/help V/version
+end
+
+function __fish_mdfmt_needs_command
+    # Figure out if the current invocation already has a command.
+    set -l cmd (commandline -opc)
+    set -e cmd[1]
+    argparse -s (__fish_mdfmt_global_optspecs) -- $cmd 2>/dev/null
+    or return
+    if set -q argv[1]
+        # Also print the command, so this can be used to figure out what it is.
+        echo $argv[1]
+        return 1
+    end
+    return 0
+end
+
+function __fish_mdfmt_using_subcommand
+    set -l cmd (__fish_mdfmt_needs_command)
+    test -z "$cmd"
+    and return 1
+    contains -- $cmd[1] $argv
+end
+
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l deleted-list -d 'Write the paths of deleted files (or files that would be deleted), one per line, to this file' -r -F
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l out-dir -d 'Write the formatted files below DIR instead of modifying them, with their paths relative to the searched directories; clean files are copied, and files that would be deleted for being empty are not written' -r -F
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l changed-since -d 'Only format markdown files changed since the given git ref (implies --changed)' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l include -d 'Only process files in searched directories that match this glob (repeatable)' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l exclude -d 'Skip files and directories in searched directories that match this glob; prefix with `!` to re-include files excluded by an earlier pattern (repeatable)' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l line-range -d 'Only format lines START to END (1-based, inclusive) of a single file, e.g. an editor selection; the rest of the file is left as is' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l stdin-format -d 'Format every --stdin-json request as this flavor of Markdown; requests for `.mdx` paths are always formatted as MDX' -r -f -a "markdown\t'Plain Markdown'
+mdx\t'MDX; blank lines are never inserted next to JSX tags and expressions'"
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l stdin-filepath -d 'Format --stdin-json requests with the options of the file at PATH, from its config files and `.editorconfig`, and as MDX if it is an `.mdx` file; the file itself is never read or written and does not have to exist' -r -F
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l max-size -d 'Skip files larger than this size, e.g. 500K or 5M (binary units)' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l lock-timeout -d 'Wait up to this many milliseconds for a file that another mdfmt run is formatting, then skip it with an error [default: 5000]' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l encoding -d 'Read files in this encoding, e.g. latin1 or shift_jis, and write them back in it; files whose formatted content it cannot represent are skipped with an error [default: utf-8]' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l demote-headings -d 'Demote every heading by N levels, e.g. `#` to `##` for 1; headings stop at level 6' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l promote-headings -d 'Promote every heading by N levels, e.g. `##` to `#` for 1; files with headings that would go past level 1 are skipped with an error' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l fence-style -d 'Rewrite code fence markers; fences whose content would close the converted fence are kept' -r -f -a "backtick\t'Backtick fences (```)'
+tilde\t'Tilde fences (~~~)'
+keep\t'Leave fence markers as they are'"
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l default-fence-lang -d 'Add this language to code fences without one, e.g. `text`' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l blank-lines-before-heading -d 'Require exactly N blank lines before headings; a list like 2,1 sets the count by heading level, the last one repeating for deeper levels' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l blank-lines-after-heading -d 'Require exactly N blank lines after headings, set like --blank-lines-before-heading' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l toc-levels -d 'The heading levels listed in tables of contents, e.g. 2..4' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l list-indent -d 'Re-indent nested list items to N spaces per nesting level, e.g. 2 or 4' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l tab-width -d 'The number of columns a tab in list indentation advances to' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l wrap-blockquotes -d 'Wrap the lines of block quotes longer than WIDTH characters, keeping their `>` markers on every line' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l tabs-to-spaces -d 'Expand tabs in the indentation of lines to spaces, with tab stops WIDTH columns apart; code blocks and tabs within lines are kept' -r
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l end-of-line -l line-ending -d 'Write files with these line endings, also inside code fences and frontmatter; overrides `end_of_line` in `.editorconfig`' -r -f -a "lf\t'Unix line endings (\\n)'
+crlf\t'Windows line endings (\\r\\n)'
+keep\t'The line endings most lines of each file already have'"
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l output-format -d 'Output format for the report' -r -f -a "text\t'Human readable status lines and summary'
+json\t'A single JSON document with per-file results and the summary'"
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l color -d 'When to color the report and error messages; `auto` colors terminals unless the NO_COLOR environment variable is set' -r -f -a "auto\t'Color output to terminals, unless NO_COLOR is set'
+always\t'Always color output, even when piped'
+never\t'Never color output'"
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l sort-by -d 'Order in which files are processed and listed [default: the order of the paths given, each directory sorted by path]' -r -f -a "name\t'File name only, ignoring the directory'
+path\t'Full path'
+modified\t'Modification time, newest first'
+size\t'File size, largest first'"
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l cache-location -d 'Location of the cache file, relative to the current directory (implies --cache) [default: .mdfmt-cache]' -r -F
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l generate-completions -d 'Print the completion script for SHELL and exit; the older spelling of `mdfmt completions SHELL`, kept for existing scripts' -r -f -a "bash\t''
+elvish\t''
+fish\t''
+powershell\t''
+zsh\t''"
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -s v -l verbose -d 'Show verbose output; -vv also logs debug details, and -vvv which rule changed each line'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -s q -l quiet -d 'Only print errors'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l no-progress -d 'Do not show a progress bar while processing files'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -s n -l dry-run -d 'Dry run - show what would be done without making changes'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l delete -d 'Allow deletion of empty files'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l delete-to-trash -d 'Move empty files to the system trash instead of deleting them permanently'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l normalize-empty -d 'Truncate files that contain only whitespace to zero bytes instead of deleting or skipping them'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l include-empty -d 'Format files that contain only frontmatter instead of deleting or skipping them'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l warn-empty -d 'Print a warning on stderr for every file that contains only frontmatter and is kept'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l check -d 'Check whether files are formatted without modifying them'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l lint -d 'Report the blank line problems formatting would fix and the lint rule violations (bare URLs, images without alt text, code fences without a language, zero-width characters, headings ending with punctuation) as `path:line: CODE message`, without modifying files; exits with 1 if any of them is an error'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -s l -l list-different -d 'Only print the paths of files that would be changed, one per line and relative to the current directory, without modifying them'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l list-files -d 'With --check, only print the absolute paths of files that would be changed, one per line, or as a JSON array of strings with --output-format json'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l find-duplicates -d 'Report groups of files with the same content after formatting, instead of formatting them; exits with 1 if any are found'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l extract-headings -d 'Print the headings of every file with their line numbers and anchors, as an indented outline or as JSON with --output-format json, without modifying files'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l no-error-on-unformatted -d 'Exit with 0 even when files were changed (or would be changed, with --check)'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l report-unchanged -d 'With --check, also list the files that are already formatted; with --quiet, only their number is printed'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l changed -d 'Only format markdown files that git reports as modified, added, or untracked'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l format-only-staged -d 'Only format markdown files staged for commit, and stage the formatted files again unless they also have unstaged changes, e.g. in a git pre-commit hook'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l no-restage -d 'With --format-only-staged, leave the formatted files unstaged to review them before committing'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l diff-filter -d 'Read a unified diff from stdin and only format the lines it changed'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l stdin-json -d 'Read newline-delimited JSON requests `{"path", "content"}` from stdin and write one formatted response per request to stdout, without touching the filesystem'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l lsp -d 'Run a language server on stdin and stdout that formats the documents open in an editor, with the options of their files'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l preserve-mtime -d 'Keep the modification times of formatted files, so build tools that compare timestamps do not rebuild them'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l clamp-headings -d 'Promote headings that would go past level 1 to level 1 instead of skipping the file'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l normalize-fence-lang -d 'Lowercase code fence languages and trim whitespace around info strings, e.g. "``` Rust" to "```rust"; aliases come from the `[fence_aliases]` config table'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l require-fence-lang -d 'Warn on stderr about code fences without a language (markdownlint MD040)'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l toc -d 'Regenerate the table of contents between `<!-- mdfmt-toc-start -->` and `<!-- mdfmt-toc-end -->` markers; files without them are not changed'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l remove-empty-headings -d 'Remove headings that have no text, only `#` markers, unless they are the only heading of the file'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l sort-frontmatter -d 'Sort the top-level keys of YAML frontmatter alphabetically, or with the keys from `frontmatter_key_order` in a config file first'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l normalize-list-spacing -d 'Put exactly one space between list markers and the item text, e.g. "-   item" to "- item"'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l final-newline -d 'End every file with a newline'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l no-final-newline -d 'Remove the newline at the end of every file'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l ignore-final-newline-only -d 'Leave files alone when only the newlines at their end would change; files with other changes still get them fixed'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l trim-trailing-whitespace -d 'Remove trailing whitespace outside code fences, including hard line breaks written as two trailing spaces'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l keep-trailing-whitespace -d 'Keep trailing whitespace, even if .editorconfig or .mdfmt.toml trims it'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l sorted-output -d 'Print per-file results in input order after all files are processed, instead of as each file finishes'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l timings -d 'Measure per-file read, format, and write times and report the slowest files'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l cache -d 'Skip files that were already verified clean by a previous run'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l no-cache -d 'Do not use the cache, even if --cache or --cache-location is given'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l no-config -d 'Ignore `.mdfmt.toml` config files'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l no-editorconfig -d 'Ignore `.editorconfig` files, which otherwise set the line endings, final newline, trailing whitespace, and tab options'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -l print-config -d 'Print the options that apply to files in PATH as TOML and exit: the defaults, overridden by the config files, `.editorconfig` (when PATH is a file), and the command line'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -s h -l help -d 'Print help (see more with \'--help\')'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -s V -l version -d 'Print version'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -a "completions" -d 'Print the completion script for SHELL to stdout'
+complete -c mdfmt -n "__fish_mdfmt_needs_command" -a "manpage" -d 'Print the man page in roff format to stdout'
+complete -c mdfmt -n "__fish_mdfmt_using_subcommand completions" -s h -l help -d 'Print help'
+complete -c mdfmt -n "__fish_mdfmt_using_subcommand manpage" -s h -l help -d 'Print help'
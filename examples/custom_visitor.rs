@@ -0,0 +1,82 @@
+//! Custom transformations of a document with a [`Visitor`].
+//!
+//! Upgrades `http://` links to `https://` everywhere but in code, removes the
+//! closing `#` sequences of headings, and prints the document formatted with the
+//! default options:
+//!
+//! ```sh
+//! cargo run --example custom_visitor -- docs/guide.md
+//! ```
+//!
+//! Without a path, a small sample document is used.
+
+use mdfmt::ast::{Document, SourceLine, SourceLines, Visitor};
+use mdfmt::process_md::FormatterConfig;
+use std::borrow::Cow;
+use std::{env, fs, process};
+
+const SAMPLE: &str = "# Guide #\n\nSee http://example.com for details.\n```sh\ncurl http://localhost:8080\n```\n\n## Links ##\n\n- http://example.org\n";
+
+/// Upgrades `http://` links to `https://` in prose blocks, leaving code fences
+/// alone. Only blocks with a link get new text; the others keep borrowing theirs.
+struct UpgradeLinks;
+
+impl UpgradeLinks {
+    fn upgrade(lines: &mut SourceLines<'_>) {
+        if lines.text.contains("http://") {
+            lines.text = lines.text.replace("http://", "https://").into();
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for UpgradeLinks {
+    fn visit_paragraph(&mut self, lines: &mut SourceLines<'a>) {
+        Self::upgrade(lines);
+    }
+
+    fn visit_list_group(&mut self, lines: &mut SourceLines<'a>) {
+        Self::upgrade(lines);
+    }
+
+    fn visit_block_quote(&mut self, lines: &mut SourceLines<'a>) {
+        Self::upgrade(lines);
+    }
+
+    fn visit_table(&mut self, lines: &mut SourceLines<'a>) {
+        Self::upgrade(lines);
+    }
+}
+
+/// Removes the closing `#` sequence of headings, e.g. `## Usage ##` becomes
+/// `## Usage`. The new text is the start of the old one, so nothing is allocated.
+struct OpenHeadings;
+
+impl<'a> Visitor<'a> for OpenHeadings {
+    fn visit_heading(&mut self, level: usize, line: &mut SourceLine<'a>) {
+        let text = line.text.trim_end();
+        let without_closing = text.trim_end_matches('#');
+        // `#` characters directly after text are part of it, e.g. `## C#`
+        if without_closing.len() > level && without_closing.ends_with([' ', '\t']) {
+            let len = without_closing.trim_end().len();
+            match &mut line.text {
+                Cow::Borrowed(text) => *text = &text[..len],
+                Cow::Owned(text) => text.truncate(len),
+            }
+        }
+    }
+}
+
+fn main() {
+    let content = match env::args().nth(1) {
+        Some(path) => fs::read_to_string(&path).unwrap_or_else(|e| {
+            eprintln!("{}: error: {}", path, e);
+            process::exit(2);
+        }),
+        None => SAMPLE.to_string(),
+    };
+
+    let mut document = Document::parse(&content);
+    document.visit_mut(&mut UpgradeLinks);
+    document.visit_mut(&mut OpenHeadings);
+    print!("{}", document.render(&FormatterConfig::default()));
+}
@@ -0,0 +1,1623 @@
+//! A block-level representation of Markdown documents.
+//!
+//! [`Document::parse`] splits a document into its frontmatter and a flat list of
+//! [`Block`]s, and [`Document::render`] applies the blank line rules between and
+//! inside those blocks. The formatter in [`crate::process_md`] is built on these two
+//! steps, so transformations can work on whole blocks instead of individual lines.
+//! Custom transformations implement [`Visitor`] and are applied with
+//! [`Document::visit_mut`]; `examples/custom_visitor.rs` shows two of them.
+//!
+//! Every block keeps the source lines it was parsed from, so rendering an unchanged
+//! document only ever inserts or removes blank lines.
+
+use crate::lists::marker_len;
+use crate::process_md::{
+    ContentMode, EditRule, FormatterConfig, LineSink, MATH_DELIMITER, atx_heading_text,
+    closes_math_block, fence_marker, is_fence, is_heading, is_list_marker, opens_math_block,
+};
+use crate::toc::plain_text;
+use std::borrow::Cow;
+
+/// A line of the source document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine<'a> {
+    /// 0-based line number in the source.
+    pub index: usize,
+    /// The line, without its line ending. Borrowed from the source unless a
+    /// [`Visitor`] replaced it.
+    pub text: Cow<'a, str>,
+}
+
+/// Consecutive lines of the source document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLines<'a> {
+    /// 0-based line number of the first line in the source.
+    pub start: usize,
+    /// The lines, including their line endings. Borrowed from the source unless a
+    /// [`Visitor`] replaced them.
+    pub text: Cow<'a, str>,
+}
+
+impl SourceLines<'_> {
+    /// Iterate over the lines, without their line endings.
+    pub fn iter(&self) -> impl Iterator<Item = SourceLine<'_>> {
+        let start = self.start;
+        self.text
+            .lines()
+            .enumerate()
+            .map(move |(n, text)| SourceLine {
+                index: start + n,
+                text: Cow::Borrowed(text),
+            })
+    }
+
+    /// The number of lines.
+    pub fn len(&self) -> usize {
+        self.text.lines().count()
+    }
+
+    /// Whether there are no lines at all.
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+}
+
+/// The frontmatter block at the very start of a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frontmatter<'a> {
+    /// All lines, including the opening and (if present) closing `---`.
+    pub lines: SourceLines<'a>,
+    /// Whether a closing `---` was found. Unclosed frontmatter runs to the end of
+    /// the document.
+    pub closed: bool,
+}
+
+/// A block between an opening and a closing delimiter line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FencedBlock<'a> {
+    /// The opening line, e.g. ```` ```rust ```` or `$$`.
+    pub open: SourceLine<'a>,
+    /// The lines between the delimiters.
+    pub body: SourceLines<'a>,
+    /// The closing line, or `None` if the block runs to the end of the document.
+    pub close: Option<SourceLine<'a>>,
+}
+
+impl FencedBlock<'_> {
+    /// Whether this is a `~~~` fence.
+    fn is_tilde(&self) -> bool {
+        self.open.text.trim().starts_with("~~~")
+    }
+}
+
+/// A top-level block of a [`Document`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Block<'a> {
+    /// An ATX heading, e.g. `## Usage`.
+    Heading {
+        /// The number of `#` characters, 1 to 6.
+        level: usize,
+        /// The heading line.
+        line: SourceLine<'a>,
+    },
+    /// Consecutive lines of text.
+    ///
+    /// A `---` line directly under text is a setext heading underline, so it stays
+    /// part of the paragraph.
+    Paragraph(SourceLines<'a>),
+    /// A ```` ``` ```` or `~~~` code fence.
+    ///
+    /// With [`FormatterConfig::tilde_math_blocks`], `~~~` fences are rendered like
+    /// math blocks instead.
+    CodeFence(FencedBlock<'a>),
+    /// A `$$` display math block, which is always rendered verbatim.
+    MathBlock(FencedBlock<'a>),
+    /// Consecutive bullet or ordered list item lines.
+    ListGroup(SourceLines<'a>),
+    /// A thematic break on its own, e.g. `***`.
+    ThematicBreak(SourceLine<'a>),
+    /// Consecutive `>` lines, including GFM alerts such as `> [!NOTE]`.
+    ///
+    /// Unquoted text lines directly under quoted text are lazy continuation lines of
+    /// the quote, so they are part of it too.
+    BlockQuote(SourceLines<'a>),
+    /// Consecutive lines starting with `|`, such as a table with its header row.
+    Table(SourceLines<'a>),
+    /// A line that is empty or contains only whitespace.
+    BlankLine(SourceLine<'a>),
+    /// Consecutive lines of text starting with an HTML tag, e.g. `<details>`.
+    ///
+    /// Block-level tags such as `<div>` and HTML comments also end a paragraph
+    /// directly above them, while inline tags such as `<em>` continue it.
+    HtmlBlock(SourceLines<'a>),
+    /// In MDX documents, a line starting with a JSX tag such as `<Tabs>` or a
+    /// `{expression}`, together with the lines of a tag or expression that spans
+    /// several lines.
+    ///
+    /// JSX can appear inline, so blank lines are never inserted next to it.
+    Jsx(SourceLines<'a>),
+    /// The opening fence of a Pandoc fenced div or a Docusaurus admonition, e.g.
+    /// `:::note` or `::: {.warning}`.
+    ///
+    /// The blocks up to the matching [`Block::DivClose`] are the content of the div,
+    /// which is formatted like the rest of the document. Blank lines separate the
+    /// whole div from the blocks around it, but are never inserted directly inside
+    /// its fences.
+    DivOpen(SourceLine<'a>),
+    /// The closing fence of a fenced div: a line of only colons, at least as many
+    /// as the opening fence has.
+    DivClose(SourceLine<'a>),
+    /// A kramdown attribute list such as `{: .note}`, or attributes such as
+    /// `{#custom-id}`, alone on a line that does not continue a paragraph.
+    ///
+    /// Directly under another block, the attributes belong to that block, so no
+    /// blank line is inserted before them and the blank line that would follow the
+    /// block follows them instead.
+    AttributeList(SourceLine<'a>),
+}
+
+impl Block<'_> {
+    /// The 0-based line number of the first line of the block.
+    fn start(&self) -> usize {
+        match self {
+            Block::Heading { line, .. }
+            | Block::ThematicBreak(line)
+            | Block::BlankLine(line)
+            | Block::DivOpen(line)
+            | Block::DivClose(line)
+            | Block::AttributeList(line) => line.index,
+            Block::CodeFence(fence) | Block::MathBlock(fence) => fence.open.index,
+            Block::Paragraph(lines)
+            | Block::ListGroup(lines)
+            | Block::BlockQuote(lines)
+            | Block::Table(lines)
+            | Block::HtmlBlock(lines)
+            | Block::Jsx(lines) => lines.start,
+        }
+    }
+
+    /// The number of words of prose in the block: its whitespace-separated parts
+    /// with a letter or digit in them, not counting list markers. Code, math, and
+    /// HTML have none.
+    fn word_count(&self) -> usize {
+        let count = |text: &str| {
+            text.split_whitespace()
+                .filter(|word| word.chars().any(char::is_alphanumeric))
+                .count()
+        };
+        match self {
+            Block::Heading { level, line } => {
+                count(&line.text[atx_heading_text(&line.text, *level)])
+            }
+            Block::Paragraph(lines) | Block::BlockQuote(lines) | Block::Table(lines) => {
+                count(&lines.text)
+            }
+            Block::ListGroup(lines) => lines
+                .iter()
+                .map(|line| {
+                    let text = line.text.trim_start();
+                    count(&text[marker_len(text).unwrap_or(0)..])
+                })
+                .sum(),
+            _ => 0,
+        }
+    }
+
+    /// The rule that separates the block from adjacent content by a blank line, if any.
+    fn separator(&self, config: &FormatterConfig) -> Option<EditRule> {
+        match self {
+            Block::Heading { .. } => Some(EditRule::HeadingSeparator),
+            Block::CodeFence(_) | Block::MathBlock(_) => Some(EditRule::FenceSeparator),
+            Block::ListGroup(_) => Some(EditRule::ListSeparator),
+            Block::BlockQuote(_) => Some(EditRule::QuoteSeparator),
+            Block::Table(_) if config.separate_tables_and_html => Some(EditRule::TableSeparator),
+            Block::HtmlBlock(_) if config.separate_tables_and_html => Some(EditRule::HtmlSeparator),
+            Block::DivOpen(_) | Block::DivClose(_) => Some(EditRule::DivSeparator),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed Markdown document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document<'a> {
+    /// The frontmatter, if the document starts with a `---` line.
+    pub frontmatter: Option<Frontmatter<'a>>,
+    /// The blocks after the frontmatter, in document order.
+    pub blocks: Vec<Block<'a>>,
+    /// Whether the source ends with a newline, which rendering preserves.
+    pub trailing_newline: bool,
+}
+
+impl<'a> Document<'a> {
+    /// Parse `content` into frontmatter and blocks.
+    ///
+    /// Parsing never fails: every line of `content` ends up in exactly one block.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::ast::{Block, Document};
+    ///
+    /// let document = Document::parse("# Title\nSome text\n- A\n- B\n");
+    /// assert!(document.frontmatter.is_none());
+    /// assert!(matches!(document.blocks[0], Block::Heading { level: 1, .. }));
+    /// assert!(matches!(document.blocks[1], Block::Paragraph(_)));
+    /// assert!(matches!(&document.blocks[2], Block::ListGroup(items) if items.len() == 2));
+    /// ```
+    pub fn parse(content: &'a str) -> Self {
+        Self::parse_with_mode(content, ContentMode::Markdown)
+    }
+
+    /// Parse `content` written in the given flavor of Markdown.
+    ///
+    /// In [`ContentMode::Mdx`], JSX tags and expressions become [`Block::Jsx`]
+    /// blocks instead of HTML blocks and paragraphs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::ast::{Block, Document};
+    /// use mdfmt::process_md::ContentMode;
+    ///
+    /// let document = Document::parse_with_mode("<Note>\n# Title\n</Note>\n", ContentMode::Mdx);
+    /// assert!(matches!(document.blocks[0], Block::Jsx(_)));
+    /// assert!(matches!(document.blocks[1], Block::Heading { level: 1, .. }));
+    /// ```
+    pub fn parse_with_mode(content: &'a str, mode: ContentMode) -> Self {
+        let mut cursor = Cursor::new(content);
+        Document {
+            frontmatter: parse_frontmatter(&mut cursor),
+            blocks: Blocks::new(cursor, mode).collect(),
+            trailing_newline: content.ends_with('\n'),
+        }
+    }
+
+    /// The number of headings of `level`, e.g. 2 for `##` headings.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::ast::Document;
+    ///
+    /// let document = Document::parse("# Guide\n\n## Install\n\n## Usage\n\n```sh\n## not a heading\n```\n");
+    /// assert_eq!(document.section_count(1), 1);
+    /// assert_eq!(document.section_count(2), 2);
+    /// assert_eq!(document.section_count(3), 0);
+    /// ```
+    pub fn section_count(&self, level: u8) -> usize {
+        self.blocks
+            .iter()
+            .filter(|block| matches!(block, Block::Heading { level: l, .. } if *l == usize::from(level)))
+            .count()
+    }
+
+    /// The number of words in the section of every top-level heading, which runs
+    /// up to the next top-level heading.
+    ///
+    /// Top-level headings are those of the lowest level the document has, so a
+    /// document without `#` headings is split at its `##` headings. Words are counted
+    /// in paragraphs, lists, block quotes, tables, and the headings inside the
+    /// section; code, math, and HTML are not prose and are left out, and so is
+    /// everything before the first top-level heading. Heading texts are returned
+    /// without inline formatting, as in [`crate::outline::extract_headings`].
+    ///
+    /// # Returns
+    ///
+    /// Every top-level heading text with the word count of its section, in document
+    /// order, or an empty list if the document has no headings.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::ast::Document;
+    ///
+    /// let document = Document::parse(
+    ///     "Intro\n\n## *Install*\n\nRun the installer.\n\n### From source\n\n```sh\ncargo install mdfmt\n```\n\n## Usage\n\n- Format files\n- Check files\n",
+    /// );
+    /// assert_eq!(
+    ///     document.word_count_by_section(),
+    ///     [("Install".to_string(), 5), ("Usage".to_string(), 4)]
+    /// );
+    /// ```
+    pub fn word_count_by_section(&self) -> Vec<(String, usize)> {
+        let top_level = self
+            .blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Heading { level, .. } => Some(*level),
+                _ => None,
+            })
+            .min();
+        let mut sections: Vec<(String, usize)> = Vec::new();
+        for block in &self.blocks {
+            match block {
+                Block::Heading { level, line } if Some(*level) == top_level => {
+                    let text = plain_text(&line.text[atx_heading_text(&line.text, *level)]);
+                    sections.push((text, 0));
+                }
+                _ => {
+                    if let Some((_, words)) = sections.last_mut() {
+                        *words += block.word_count();
+                    }
+                }
+            }
+        }
+        sections
+    }
+
+    /// Render the document with the blank line rules.
+    ///
+    /// Runs of blank lines collapse to one (or to
+    /// [`FormatterConfig::max_consecutive_blank_lines`]), headings, list groups, block quotes, code
+    /// fences, and math blocks are surrounded by blank lines, and code fences lose
+    /// blank lines directly inside their delimiters. Inside block quotes, runs of
+    /// blank `>` lines collapse to one. Trailing empty lines are dropped.
+    ///
+    /// Only [`FormatterConfig::tilde_math_blocks`] and
+    /// [`FormatterConfig::max_consecutive_blank_lines`] affect rendering; the content
+    /// rewriting options are applied by [`crate::process_md::format_with_config`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::ast::Document;
+    /// use mdfmt::process_md::FormatterConfig;
+    ///
+    /// let document = Document::parse("Text\n# Heading\n\n\n\nMore\n");
+    /// assert_eq!(
+    ///     document.render(&FormatterConfig::default()),
+    ///     "Text\n\n# Heading\n\nMore\n"
+    /// );
+    /// ```
+    pub fn render(&self, config: &FormatterConfig) -> String {
+        let mut rendered = Rendered::default();
+        self.render_into(config, &mut rendered);
+
+        let mut output = rendered.output;
+        if self.trailing_newline && rendered.lines > 0 {
+            output.push('\n');
+        }
+        output
+    }
+
+    /// Render the document by reporting the fate of every source line to `sink`.
+    pub(crate) fn render_into<S: LineSink>(&self, config: &FormatterConfig, sink: &mut S) {
+        render_blocks(
+            self.frontmatter.clone(),
+            self.blocks.iter().cloned(),
+            config,
+            sink,
+        );
+    }
+
+    /// Call [`Visitor::visit_block`] for every block, in document order.
+    ///
+    /// The frontmatter is not a block and is not visited.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::ast::{Document, SourceLine, Visitor};
+    /// use mdfmt::process_md::FormatterConfig;
+    ///
+    /// /// Writes every thematic break as `---`.
+    /// struct Breaks;
+    ///
+    /// impl Visitor<'_> for Breaks {
+    ///     fn visit_thematic_break(&mut self, line: &mut SourceLine<'_>) {
+    ///         line.text = "---".into();
+    ///     }
+    /// }
+    ///
+    /// let mut document = Document::parse("Text\n\n___\n\nMore\n");
+    /// document.visit_mut(&mut Breaks);
+    /// assert_eq!(
+    ///     document.render(&FormatterConfig::default()),
+    ///     "Text\n\n---\n\nMore\n"
+    /// );
+    /// ```
+    pub fn visit_mut<V: Visitor<'a> + ?Sized>(&mut self, visitor: &mut V) {
+        for block in &mut self.blocks {
+            visitor.visit_block(block);
+        }
+    }
+}
+
+/// A transformation of the blocks of a [`Document`], applied by
+/// [`Document::visit_mut`].
+///
+/// [`visit_block`](Self::visit_block) calls the method for the kind of block it is
+/// given, and all of those do nothing by default, so a visitor only implements the
+/// ones it needs. It can also replace a block with a different one by implementing
+/// `visit_block` itself.
+///
+/// [`Document::render`] writes the text of the blocks, which a visitor can replace
+/// with a slice of the source, a string literal, or a `String` of its own. The text
+/// of [`SourceLines`] includes the line endings.
+pub trait Visitor<'a> {
+    /// Visit any block, by calling the method for its kind.
+    fn visit_block(&mut self, block: &mut Block<'a>) {
+        match block {
+            Block::Heading { level, line } => self.visit_heading(*level, line),
+            Block::Paragraph(lines) => self.visit_paragraph(lines),
+            Block::CodeFence(fence) => self.visit_code_fence(fence),
+            Block::MathBlock(fence) => self.visit_math_block(fence),
+            Block::ListGroup(lines) => self.visit_list_group(lines),
+            Block::ThematicBreak(line) => self.visit_thematic_break(line),
+            Block::BlockQuote(lines) => self.visit_block_quote(lines),
+            Block::Table(lines) => self.visit_table(lines),
+            Block::BlankLine(line) => self.visit_blank_line(line),
+            Block::HtmlBlock(lines) => self.visit_html_block(lines),
+            Block::Jsx(lines) => self.visit_jsx(lines),
+            Block::DivOpen(line) => self.visit_div_open(line),
+            Block::DivClose(line) => self.visit_div_close(line),
+            Block::AttributeList(line) => self.visit_attribute_list(line),
+        }
+    }
+
+    /// Visit a [`Block::Heading`] of `level`, the number of `#` markers of its line.
+    ///
+    /// To change the level, replace the whole block in
+    /// [`visit_block`](Self::visit_block), so the level and the markers agree.
+    fn visit_heading(&mut self, _level: usize, _line: &mut SourceLine<'a>) {}
+
+    /// Visit a [`Block::Paragraph`].
+    fn visit_paragraph(&mut self, _lines: &mut SourceLines<'a>) {}
+
+    /// Visit a [`Block::CodeFence`].
+    fn visit_code_fence(&mut self, _fence: &mut FencedBlock<'a>) {}
+
+    /// Visit a [`Block::MathBlock`].
+    fn visit_math_block(&mut self, _block: &mut FencedBlock<'a>) {}
+
+    /// Visit a [`Block::ListGroup`].
+    fn visit_list_group(&mut self, _lines: &mut SourceLines<'a>) {}
+
+    /// Visit a [`Block::ThematicBreak`].
+    fn visit_thematic_break(&mut self, _line: &mut SourceLine<'a>) {}
+
+    /// Visit a [`Block::BlockQuote`].
+    fn visit_block_quote(&mut self, _lines: &mut SourceLines<'a>) {}
+
+    /// Visit a [`Block::Table`].
+    fn visit_table(&mut self, _lines: &mut SourceLines<'a>) {}
+
+    /// Visit a [`Block::BlankLine`].
+    fn visit_blank_line(&mut self, _line: &mut SourceLine<'a>) {}
+
+    /// Visit a [`Block::HtmlBlock`].
+    fn visit_html_block(&mut self, _lines: &mut SourceLines<'a>) {}
+
+    /// Visit a [`Block::Jsx`].
+    fn visit_jsx(&mut self, _lines: &mut SourceLines<'a>) {}
+
+    /// Visit a [`Block::DivOpen`].
+    fn visit_div_open(&mut self, _line: &mut SourceLine<'a>) {}
+
+    /// Visit a [`Block::DivClose`].
+    fn visit_div_close(&mut self, _line: &mut SourceLine<'a>) {}
+
+    /// Visit a [`Block::AttributeList`].
+    fn visit_attribute_list(&mut self, _line: &mut SourceLine<'a>) {}
+}
+
+/// Parse and render `content` in one go, reporting the fate of every line to `sink`.
+///
+/// This is the same as rendering [`Document::parse`], but blocks are rendered as
+/// soon as they are parsed instead of being collected first.
+pub(crate) fn render_content_into<S: LineSink>(
+    content: &str,
+    config: &FormatterConfig,
+    sink: &mut S,
+) {
+    let mut cursor = Cursor::new(content);
+    let frontmatter = parse_frontmatter(&mut cursor);
+    let blocks = Blocks::new(cursor, config.content_mode);
+    render_blocks(frontmatter, blocks, config, sink);
+}
+
+/// Parse the frontmatter, if the document starts with a `---` line.
+fn parse_frontmatter<'a>(cursor: &mut Cursor<'a>) -> Option<Frontmatter<'a>> {
+    if cursor.peek().is_none_or(|line| line.text.trim() != "---") {
+        return None;
+    }
+    let start = *cursor;
+    cursor.next();
+    let closed = cursor.any(|line| line.text.trim() == "---");
+    Some(Frontmatter {
+        lines: cursor.since(start),
+        closed,
+    })
+}
+
+/// Parses the blocks after the frontmatter, one at a time.
+struct Blocks<'a> {
+    cursor: Cursor<'a>,
+    mode: ContentMode,
+    /// The number of colons in the opening fence of each open fenced div, innermost
+    /// last.
+    divs: Vec<usize>,
+}
+
+impl<'a> Blocks<'a> {
+    fn new(cursor: Cursor<'a>, mode: ContentMode) -> Self {
+        Blocks {
+            cursor,
+            mode,
+            divs: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Iterator for Blocks<'a> {
+    type Item = Block<'a>;
+
+    fn next(&mut self) -> Option<Block<'a>> {
+        let start = self.cursor;
+        let cursor = &mut self.cursor;
+        let line = cursor.next()?;
+        let trimmed = line.text.trim();
+        if let Some(colons) = opens_div(trimmed) {
+            self.divs.push(colons);
+            return Some(Block::DivOpen(line));
+        }
+        if self
+            .divs
+            .last()
+            .is_some_and(|&colons| closes_div(trimmed, colons))
+        {
+            self.divs.pop();
+            return Some(Block::DivClose(line));
+        }
+        // Text ends at the fences of fenced divs
+        let divs = &self.divs;
+        let is_div_fence = |line: &str| {
+            let trimmed = line.trim();
+            opens_div(trimmed).is_some()
+                || divs
+                    .last()
+                    .is_some_and(|&colons| closes_div(trimmed, colons))
+        };
+        let block = if opens_math_block(trimmed, MATH_DELIMITER) {
+            Block::MathBlock(parse_fenced(line, cursor, |trimmed| {
+                closes_math_block(trimmed, MATH_DELIMITER)
+            }))
+        } else if is_fence(trimmed) {
+            // The fence is closed by a line starting with the same marker
+            let marker = fence_marker(trimmed);
+            Block::CodeFence(parse_fenced(line.clone(), cursor, |trimmed| {
+                trimmed.starts_with(marker)
+            }))
+        } else if trimmed.is_empty() {
+            Block::BlankLine(line)
+        } else if self.mode == ContentMode::Mdx && trimmed.starts_with(['<', '{']) {
+            Block::Jsx(parse_jsx(line, start, cursor))
+        } else if is_attribute_list(trimmed) {
+            Block::AttributeList(line)
+        } else if is_quote(&line.text) {
+            Block::BlockQuote(parse_quote(line, start, cursor, is_div_fence))
+        } else if is_table_row(&line.text) {
+            Block::Table(take_while(start, cursor, is_table_row))
+        } else if is_heading(&line.text) {
+            let level = trimmed.chars().take_while(|c| *c == '#').count();
+            Block::Heading { level, line }
+        } else if is_list_marker(&line.text) {
+            Block::ListGroup(take_while(start, cursor, is_list_marker))
+        } else if is_thematic_break(trimmed) {
+            Block::ThematicBreak(line)
+        } else if trimmed.starts_with('<') {
+            Block::HtmlBlock(take_while(start, cursor, |line| {
+                is_text(line) && !is_div_fence(line)
+            }))
+        } else {
+            Block::Paragraph(take_while(start, cursor, |line| {
+                continues_paragraph(line) && !is_div_fence(line)
+            }))
+        };
+        Some(block)
+    }
+}
+
+/// Apply the blank line rules between and inside `blocks`.
+///
+/// Blank lines are only ever inserted between two lines of output, never before the
+/// first line of the document or after its last one. Trailing blank lines are
+/// dropped, so whether the document ends with a newline is decided afterwards, by
+/// the caller alone.
+fn render_blocks<'a, S: LineSink>(
+    frontmatter: Option<Frontmatter<'a>>,
+    blocks: impl Iterator<Item = Block<'a>>,
+    config: &FormatterConfig,
+    sink: &mut S,
+) {
+    let mut out = Emitter {
+        sink,
+        output_len: 0,
+        last_output_is_content: false,
+        pending_empty: Vec::new(),
+    };
+
+    // The rule by which the previous block asks for a blank line before the next one
+    let mut separate_next = None;
+    if let Some(frontmatter) = frontmatter {
+        for line in frontmatter.lines.iter() {
+            out.keep(&line);
+        }
+        separate_next = frontmatter.closed.then_some(EditRule::FrontmatterSeparator);
+    }
+
+    // Runs of blank lines are held back until the block after them is known
+    let mut blank_run = Vec::new();
+    let mut previous = None;
+    for block in blocks {
+        if let Block::BlankLine(line) = block {
+            blank_run.push(line);
+            continue;
+        }
+        if let Block::AttributeList(line) = &block {
+            if blank_run.is_empty() && previous.is_some() {
+                // Part of the block above, which is still the one to separate
+                out.keep(line);
+                continue;
+            }
+        }
+
+        match heading_gap(previous.as_ref(), &block, config) {
+            // Neither removed nor inserted, so e.g. a read-more marker keeps its spacing
+            _ if protected_gap(previous.as_ref(), &block, config) => {
+                for line in &blank_run {
+                    out.keep(line);
+                }
+            }
+            Some(gap) => {
+                // The heading spacing options ask for exactly `gap` blank lines
+                render_blank_run(
+                    &blank_run,
+                    Some((gap, EditRule::HeadingSpacing)),
+                    config,
+                    &mut out,
+                );
+                for _ in blank_run.len()..gap {
+                    out.insert_blank(block.start(), EditRule::HeadingSeparator);
+                }
+            }
+            // Only the frontmatter asks for a blank line before the first block
+            None if previous.is_none() && separate_next.is_some() => {
+                // Exactly one, whatever the block is and however many there were
+                render_blank_run(&blank_run, Some((1, EditRule::BlankRun)), config, &mut out);
+                if blank_run.is_empty() {
+                    out.insert_blank(block.start(), EditRule::FrontmatterSeparator);
+                }
+            }
+            None => {
+                render_blank_run(&blank_run, None, config, &mut out);
+                let next_to_jsx =
+                    matches!(previous, Some(Block::Jsx(_))) || matches!(block, Block::Jsx(_));
+                if let Some(rule) = separate_next.or(block.separator(config)) {
+                    if out.last_output_is_content
+                        && !next_to_jsx
+                        && !inside_div(previous.as_ref(), &block)
+                    {
+                        out.insert_blank(block.start(), rule);
+                    }
+                }
+            }
+        }
+        blank_run.clear();
+        match &block {
+            Block::CodeFence(fence) if !(config.tilde_math_blocks && fence.is_tilde()) => {
+                render_code_fence(fence, &mut out)
+            }
+            Block::CodeFence(fence) | Block::MathBlock(fence) => {
+                out.keep(&fence.open);
+                for line in fence.body.iter() {
+                    out.keep(&line);
+                }
+                if let Some(close) = &fence.close {
+                    out.keep(close);
+                }
+            }
+            Block::Heading { line, .. }
+            | Block::ThematicBreak(line)
+            | Block::DivOpen(line)
+            | Block::DivClose(line)
+            | Block::AttributeList(line) => out.keep(line),
+            Block::BlockQuote(lines) => render_block_quote(lines, &mut out),
+            Block::Paragraph(lines)
+            | Block::ListGroup(lines)
+            | Block::Table(lines)
+            | Block::HtmlBlock(lines)
+            | Block::Jsx(lines) => {
+                for line in lines.iter() {
+                    out.keep(&line);
+                }
+            }
+            Block::BlankLine(_) => unreachable!("blank lines are handled above"),
+        }
+        separate_next = block.separator(config);
+        previous = Some(block);
+    }
+
+    render_blank_run(&blank_run, None, config, &mut out);
+    out.finish();
+}
+
+/// Whether `previous` and `next` are separated by the fence of a fenced div on the
+/// inside of the div, where blank lines are never inserted.
+fn inside_div(previous: Option<&Block>, next: &Block) -> bool {
+    matches!(previous, Some(Block::DivOpen(_))) || matches!(next, Block::DivClose(_))
+}
+
+/// Whether the blank lines between `previous` and `next` are kept exactly as they
+/// are, because the HTML line on either side of them is one of the
+/// [`FormatterConfig::protected_html_comments`].
+fn protected_gap(previous: Option<&Block>, next: &Block, config: &FormatterConfig) -> bool {
+    if config.protected_html_comments.is_empty() {
+        return false;
+    }
+    let is_protected = |line: Option<&str>| {
+        line.is_some_and(|line| {
+            let trimmed = line.trim();
+            config
+                .protected_html_comments
+                .iter()
+                .any(|comment| comment == trimmed)
+        })
+    };
+    let last = match previous {
+        Some(Block::HtmlBlock(lines) | Block::Jsx(lines)) => lines.text.lines().last(),
+        _ => None,
+    };
+    let first = match next {
+        Block::HtmlBlock(lines) | Block::Jsx(lines) => lines.text.lines().next(),
+        _ => None,
+    };
+    is_protected(last) || is_protected(first)
+}
+
+/// Keep the first lines of a run of blank lines: exactly as many as `exact` gives,
+/// otherwise up to [`FormatterConfig::max_consecutive_blank_lines`].
+///
+/// Lines removed only because of `exact` are reported with its rule.
+fn render_blank_run<S: LineSink>(
+    lines: &[SourceLine],
+    exact: Option<(usize, EditRule)>,
+    config: &FormatterConfig,
+    out: &mut Emitter<'_, S>,
+) {
+    let max_blank_lines = config.max_consecutive_blank_lines.max(1);
+    let (kept, rule) = exact.unwrap_or((max_blank_lines, EditRule::BlankRun));
+    for (n, line) in lines.iter().enumerate() {
+        if n < kept {
+            out.keep(line);
+        } else if n < max_blank_lines {
+            out.remove(line.index, rule);
+        } else {
+            out.remove(line.index, EditRule::BlankRun);
+        }
+    }
+}
+
+/// The exact number of blank lines the heading spacing options require between
+/// `previous` and `next`, or `None` if neither option applies to them.
+///
+/// Only headings after other blocks are spaced, so a heading at the start of the
+/// document never gets blank lines before it, and one directly after the
+/// frontmatter always gets exactly one. Likewise, headings directly inside the
+/// fences of a fenced div are not spaced, nor are attribute lists after blank lines,
+/// which would become part of the block above without them.
+/// Another block's separator on either side still asks for at least one blank line.
+fn heading_gap(previous: Option<&Block>, next: &Block, config: &FormatterConfig) -> Option<usize> {
+    if inside_div(previous, next) {
+        return None;
+    }
+    let previous = previous?;
+    if matches!(previous, Block::Jsx(_)) || matches!(next, Block::Jsx(_) | Block::AttributeList(_))
+    {
+        return None;
+    }
+    let after = match previous {
+        Block::Heading { level, .. } => config
+            .blank_lines_after_heading
+            .map(|lines| lines.for_level(*level)),
+        _ => None,
+    };
+    let before = match next {
+        Block::Heading { level, .. } => config
+            .blank_lines_before_heading
+            .map(|lines| lines.for_level(*level)),
+        _ => None,
+    };
+    if after.is_none() && before.is_none() {
+        return None;
+    }
+
+    let separated = |block: &Block, spaced: Option<usize>| {
+        usize::from(spaced.is_none() && block.separator(config).is_some())
+    };
+    let gap = [
+        after.unwrap_or(0),
+        before.unwrap_or(0),
+        separated(previous, after),
+        separated(next, before),
+    ];
+    gap.into_iter().max()
+}
+
+/// Render a code fence, dropping blank lines directly after the opening and before
+/// the closing delimiter. An unclosed fence keeps its trailing blank lines.
+fn render_code_fence<S: LineSink>(fence: &FencedBlock, out: &mut Emitter<'_, S>) {
+    let is_content = |line: &SourceLine| !line.text.trim().is_empty();
+    let first = fence.body.iter().find(is_content);
+    let last = fence.body.iter().filter(is_content).last();
+
+    out.keep(&fence.open);
+    for line in fence.body.iter() {
+        let leading = first.as_ref().is_none_or(|first| line.index < first.index);
+        let trailing =
+            fence.close.is_some() && last.as_ref().is_some_and(|last| line.index > last.index);
+        if leading || trailing {
+            out.remove(line.index, EditRule::FenceBlankLine);
+        } else {
+            out.keep(&line);
+        }
+    }
+    if let Some(close) = &fence.close {
+        out.keep(close);
+    }
+}
+
+/// Render a block quote, collapsing runs of blank `>` lines to their first line.
+///
+/// Lines of code fences inside the quote are kept as they are.
+fn render_block_quote<S: LineSink>(lines: &SourceLines, out: &mut Emitter<'_, S>) {
+    let mut open_fence = None;
+    let mut prev_was_blank = false;
+    for line in lines.iter() {
+        let quoted = unquote(&line.text).map(str::trim);
+        let blank = match (quoted, open_fence.as_deref()) {
+            (Some(quoted), Some(marker)) => {
+                if quoted.starts_with(marker) {
+                    open_fence = None;
+                }
+                false
+            }
+            (Some(quoted), None) => {
+                if is_fence(quoted) {
+                    open_fence = Some(fence_marker(quoted).to_string());
+                }
+                quoted.is_empty()
+            }
+            // Lazy continuation lines are never blank
+            (None, _) => false,
+        };
+        if blank && prev_was_blank {
+            out.remove(line.index, EditRule::QuoteBlankRun);
+        } else {
+            out.keep(&line);
+        }
+        prev_was_blank = blank;
+    }
+}
+
+/// Walks the lines of a document, split like [`str::lines`], keeping track of their
+/// byte offsets so that consecutive lines can be borrowed as one [`SourceLines`].
+#[derive(Debug, Clone, Copy)]
+struct Cursor<'a> {
+    content: &'a str,
+    offset: usize,
+    index: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(content: &'a str) -> Self {
+        Cursor {
+            content,
+            offset: 0,
+            index: 0,
+        }
+    }
+
+    /// The next line, without advancing.
+    fn peek(&self) -> Option<SourceLine<'a>> {
+        let mut cursor = *self;
+        cursor.next()
+    }
+
+    /// The lines between `start` and the current position.
+    fn since(&self, start: Cursor<'a>) -> SourceLines<'a> {
+        SourceLines {
+            start: start.index,
+            text: Cow::Borrowed(&self.content[start.offset..self.offset]),
+        }
+    }
+}
+
+impl<'a> Iterator for Cursor<'a> {
+    type Item = SourceLine<'a>;
+
+    fn next(&mut self) -> Option<SourceLine<'a>> {
+        let rest = &self.content[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        let len = rest.find('\n').map_or(rest.len(), |end| end + 1);
+        let line = &rest[..len];
+        let text = match line.strip_suffix('\n') {
+            Some(line) => line.strip_suffix('\r').unwrap_or(line),
+            None => line,
+        };
+
+        let line = SourceLine {
+            index: self.index,
+            text: Cow::Borrowed(text),
+        };
+        self.offset += len;
+        self.index += 1;
+        Some(line)
+    }
+}
+
+/// Collect the body and closing line of a fenced block opened by `open`.
+fn parse_fenced<'a>(
+    open: SourceLine<'a>,
+    cursor: &mut Cursor<'a>,
+    closes: impl Fn(&str) -> bool,
+) -> FencedBlock<'a> {
+    let start = *cursor;
+    while let Some(line) = cursor.peek() {
+        if closes(line.text.trim()) {
+            let body = cursor.since(start);
+            cursor.next();
+            return FencedBlock {
+                open,
+                body,
+                close: Some(line),
+            };
+        }
+        cursor.next();
+    }
+    FencedBlock {
+        open,
+        body: cursor.since(start),
+        close: None,
+    }
+}
+
+/// Collect a block quote starting with `first`: the `>` lines that follow it, and
+/// lazy continuation lines directly under quoted text.
+///
+/// Table rows and HTML tags can be lazy continuation lines too, so tables and HTML
+/// blocks are only recognized after the quote has ended. Lines for which
+/// `is_div_fence` holds end the quote.
+fn parse_quote<'a>(
+    first: SourceLine<'a>,
+    start: Cursor<'a>,
+    cursor: &mut Cursor<'a>,
+    is_div_fence: impl Fn(&str) -> bool,
+) -> SourceLines<'a> {
+    let continues_text = |line: &str| unquote(line).is_some_and(is_text);
+    let is_lazy = |line: &str| {
+        (is_text(line) || is_table_row(line))
+            && !is_thematic_break(line.trim())
+            && !is_div_fence(line)
+    };
+    let mut in_text = continues_text(&first.text);
+    while let Some(line) = cursor.peek() {
+        if is_quote(&line.text) {
+            in_text = continues_text(&line.text);
+        } else if !(in_text && is_lazy(&line.text)) {
+            break;
+        }
+        cursor.next();
+    }
+    cursor.since(start)
+}
+
+/// Collect a JSX block starting with `first`: up to the line that closes the tag
+/// `first` opens, or the line where the braces of an expression are balanced.
+fn parse_jsx<'a>(
+    first: SourceLine<'a>,
+    start: Cursor<'a>,
+    cursor: &mut Cursor<'a>,
+) -> SourceLines<'a> {
+    let is_expression = first.text.trim_start().starts_with('{');
+    let mut depth = 0;
+    let mut line = Some(first);
+    while let Some(current) = line {
+        depth = if is_expression {
+            let opened = current.text.matches('{').count() as isize;
+            depth + opened - current.text.matches('}').count() as isize
+        } else {
+            isize::from(!current.text.contains('>'))
+        };
+        if depth <= 0 {
+            break;
+        }
+        line = cursor.next();
+    }
+    cursor.since(start)
+}
+
+/// Advance past the lines that match `continues`, returning them together with the
+/// lines already consumed since `start`.
+fn take_while<'a>(
+    start: Cursor<'a>,
+    cursor: &mut Cursor<'a>,
+    continues: impl Fn(&str) -> bool,
+) -> SourceLines<'a> {
+    while cursor.peek().is_some_and(|line| continues(&line.text)) {
+        cursor.next();
+    }
+    cursor.since(start)
+}
+
+/// Check whether a line is part of a block quote.
+fn is_quote(line: &str) -> bool {
+    line.trim_start().starts_with('>')
+}
+
+/// The content of a quoted line after its `>` marker, or `None` if it is not quoted.
+fn unquote(line: &str) -> Option<&str> {
+    line.trim_start().strip_prefix('>')
+}
+
+/// Check whether a trimmed line is a thematic break: three or more `-`, `*`, or `_`.
+fn is_thematic_break(trimmed: &str) -> bool {
+    trimmed.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|&c| trimmed.chars().all(|ch| ch == c))
+}
+
+/// The number of colons of a trimmed line that opens a fenced div, e.g. 3 for
+/// `:::note` or `::: {.warning}`.
+///
+/// The fence needs at least three colons followed by a name or attributes.
+pub(crate) fn opens_div(trimmed: &str) -> Option<usize> {
+    let colons = trimmed.len() - trimmed.trim_start_matches(':').len();
+    let rest = trimmed[colons..].trim_start_matches([' ', '\t']);
+    (colons >= 3 && !rest.is_empty() && !rest.starts_with(':')).then_some(colons)
+}
+
+/// Check whether a trimmed line closes a fenced div opened with `colons` colons: a
+/// line of only colons, at least as many as the opening fence.
+fn closes_div(trimmed: &str, colons: usize) -> bool {
+    trimmed.len() >= colons.max(3) && trimmed.bytes().all(|b| b == b':')
+}
+
+/// Check whether a trimmed line is an attribute list: `{:` or `{#` up to a closing
+/// `}`, e.g. `{: .note}` or `{#install}`.
+pub(crate) fn is_attribute_list(trimmed: &str) -> bool {
+    (trimmed.starts_with("{:") || trimmed.starts_with("{#")) && trimmed.ends_with('}')
+}
+
+/// Check whether a line is a table row.
+fn is_table_row(line: &str) -> bool {
+    line.trim_start().starts_with('|')
+}
+
+/// Tags that start an HTML block even directly under a paragraph, as in CommonMark.
+const HTML_BLOCK_TAGS: [&str; 66] = [
+    "address",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "iframe",
+    "legend",
+    "li",
+    "link",
+    "main",
+    "menu",
+    "menuitem",
+    "nav",
+    "noframes",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "param",
+    "pre",
+    "script",
+    "search",
+    "section",
+    "style",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "textarea",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+];
+
+/// Check whether a line opens an HTML block that can interrupt a paragraph: an HTML
+/// comment, or an opening or closing block-level tag.
+fn is_html_block_start(line: &str) -> bool {
+    let Some(tag) = line.trim_start().strip_prefix('<') else {
+        return false;
+    };
+    if tag.starts_with("!--") {
+        return true;
+    }
+    let tag = tag.strip_prefix('/').unwrap_or(tag);
+    let name_len = tag
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(tag.len());
+    let (name, rest) = tag.split_at(name_len);
+    HTML_BLOCK_TAGS
+        .iter()
+        .any(|block_tag| block_tag.eq_ignore_ascii_case(name))
+        && (rest.is_empty() || rest.starts_with([' ', '\t', '>', '/']))
+}
+
+/// Check whether a line continues a paragraph.
+fn continues_paragraph(line: &str) -> bool {
+    is_text(line) && !is_html_block_start(line)
+}
+
+/// Check whether a line continues a paragraph or HTML block.
+fn is_text(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty()
+        && !is_quote(line)
+        && !is_table_row(line)
+        && !is_heading(line)
+        && !is_list_marker(line)
+        && !is_fence(trimmed)
+        && !opens_math_block(trimmed, MATH_DELIMITER)
+}
+
+/// Collects rendered lines into a string.
+#[derive(Default)]
+struct Rendered {
+    output: String,
+    lines: usize,
+}
+
+impl Rendered {
+    fn push_line(&mut self, line: &str) {
+        if self.lines > 0 {
+            self.output.push('\n');
+        }
+        self.output.push_str(line);
+        self.lines += 1;
+    }
+}
+
+impl LineSink for Rendered {
+    fn keep(&mut self, _index: usize, line: &str) {
+        self.push_line(line);
+    }
+
+    fn insert_blank(&mut self, _before: usize, _rule: EditRule) {
+        self.push_line("");
+    }
+
+    fn remove(&mut self, _index: usize, _rule: EditRule) {}
+}
+
+/// Tracks what has been emitted so far and forwards decisions to a [`LineSink`].
+///
+/// Kept lines that are exactly empty are held back until something else is emitted,
+/// because at the end of the input they may turn out to be trailing lines that are
+/// removed instead.
+struct Emitter<'s, S> {
+    sink: &'s mut S,
+    output_len: usize,
+    last_output_is_content: bool,
+    pending_empty: Vec<usize>,
+}
+
+impl<S: LineSink> Emitter<'_, S> {
+    fn flush_pending(&mut self) {
+        for index in self.pending_empty.drain(..) {
+            self.sink.keep(index, "");
+        }
+    }
+
+    fn keep(&mut self, line: &SourceLine) {
+        if line.text.is_empty() {
+            self.pending_empty.push(line.index);
+        } else {
+            self.flush_pending();
+            self.sink.keep(line.index, &line.text);
+        }
+        self.output_len += 1;
+        self.last_output_is_content = !line.text.trim().is_empty();
+    }
+
+    fn insert_blank(&mut self, before: usize, rule: EditRule) {
+        self.flush_pending();
+        self.sink.insert_blank(before, rule);
+        self.output_len += 1;
+        self.last_output_is_content = false;
+    }
+
+    fn remove(&mut self, index: usize, rule: EditRule) {
+        self.sink.remove(index, rule);
+    }
+
+    /// Preserve the original ending (newline or not): trailing empty lines would
+    /// otherwise turn into extra newlines, so drop all of them. Dropping only some would
+    /// leave work for the next run, e.g. at the end of an unclosed code fence.
+    fn finish(mut self) {
+        // At least one line of output always remains
+        let trailing_removals = self
+            .pending_empty
+            .len()
+            .min(self.output_len.saturating_sub(1));
+
+        let kept = self.pending_empty.len() - trailing_removals;
+        let removed = self.pending_empty.split_off(kept);
+        self.flush_pending();
+        for index in removed {
+            self.sink.remove(index, EditRule::TrailingBlankLine);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_md::{HeadingBlankLines, LineEnding, format_with_config};
+
+    #[test]
+    fn test_parse_frontmatter_and_fences() {
+        let input = "---\ntitle: Test\n---\n```rust\n\nfn main() {}\n```\n$$\nx^2\n$$\n~~~\nopen";
+        let document = Document::parse(input);
+
+        let frontmatter = document.frontmatter.unwrap();
+        assert!(frontmatter.closed);
+        assert_eq!(frontmatter.lines.len(), 3);
+        assert_eq!(frontmatter.lines.text, "---\ntitle: Test\n---\n");
+
+        assert_eq!(document.blocks.len(), 3);
+        let Block::CodeFence(fence) = &document.blocks[0] else {
+            panic!("expected a code fence: {:?}", document.blocks[0]);
+        };
+        assert_eq!(fence.open.index, 3);
+        assert_eq!(fence.body.len(), 2);
+        assert_eq!(fence.body.text, "\nfn main() {}\n");
+        assert_eq!(fence.close.as_ref().map(|line| &*line.text), Some("```"));
+        assert!(matches!(&document.blocks[1], Block::MathBlock(math) if math.body.len() == 1));
+        // An unclosed fence runs to the end of the document
+        assert!(matches!(&document.blocks[2], Block::CodeFence(fence) if fence.close.is_none()));
+        assert!(!document.trailing_newline);
+    }
+
+    #[test]
+    fn test_parse_text_blocks() {
+        let input = "<details>\n<summary>More</summary>\n\nTitle\n---\n\n***\n> [!NOTE]\n> Text\n1. One\n2. Two";
+        let document = Document::parse(input);
+        let kinds: Vec<&str> = document
+            .blocks
+            .iter()
+            .map(|block| match block {
+                Block::Heading { .. } => "heading",
+                Block::Paragraph(_) => "paragraph",
+                Block::CodeFence(_) => "code",
+                Block::MathBlock(_) => "math",
+                Block::ListGroup(_) => "list",
+                Block::ThematicBreak(_) => "break",
+                Block::BlockQuote(_) => "quote",
+                Block::BlankLine(_) => "blank",
+                Block::Table(_) => "table",
+                Block::HtmlBlock(_) => "html",
+                Block::Jsx(_) => "jsx",
+                Block::DivOpen(_) => "div",
+                Block::DivClose(_) => "end div",
+                Block::AttributeList(_) => "attributes",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "html",
+                "blank",
+                "paragraph",
+                "blank",
+                "break",
+                "quote",
+                "list"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mdx_keeps_jsx_unseparated() {
+        let input = "# Intro\n<Tabs items={[\n  'a',\n]}>\n## Tab\n{props.list.map((item) => (\n  <li>{item}</li>\n))}\n```js\ncode\n```\n</Tabs>\n";
+        let document = Document::parse_with_mode(input, ContentMode::Mdx);
+        let jsx: Vec<usize> = document
+            .blocks
+            .iter()
+            .filter(|block| matches!(block, Block::Jsx(_)))
+            .map(Block::start)
+            .collect();
+        assert_eq!(jsx, [1, 5, 11]);
+
+        let mdx = FormatterConfig {
+            content_mode: ContentMode::Mdx,
+            ..FormatterConfig::default()
+        };
+        assert_eq!(document.render(&mdx), input);
+        assert_eq!(
+            Document::parse(input).render(&FormatterConfig::default()),
+            "# Intro\n\n<Tabs items={[\n  'a',\n]}>\n\n## Tab\n\n{props.list.map((item) => (\n  <li>{item}</li>\n))}\n\n```js\ncode\n```\n\n</Tabs>\n"
+        );
+    }
+
+    #[test]
+    fn test_render_separates_blocks() {
+        let cases = [
+            ("", ""),
+            ("\n", "\n"),
+            ("Text\n\n\n\n", "Text\n"),
+            (
+                "---\ntitle: Test\n---\n# Heading\nText\n- A\n- B\nText\n",
+                "---\ntitle: Test\n---\n\n# Heading\n\nText\n\n- A\n- B\n\nText\n",
+            ),
+            (
+                "Text\n```\n\ncode\n\n\nmore\n\n```\nText",
+                "Text\n\n```\ncode\n\n\nmore\n```\n\nText",
+            ),
+            ("Text\n```\ncode\n\n\n", "Text\n\n```\ncode\n"),
+            (
+                "A\n$$\n\nx^2\n\n$$\nB\n> [!TIP]\n> Text\n# Heading\n",
+                "A\n\n$$\n\nx^2\n\n$$\n\nB\n\n> [!TIP]\n> Text\n\n# Heading\n",
+            ),
+            ("Line 1\r\n\r\n\r\nLine 2\r\n", "Line 1\n\nLine 2\n"),
+        ];
+        // Rendering always writes LF, which the formatter converts afterwards
+        let lf = FormatterConfig {
+            line_ending: LineEnding::Lf,
+            ..FormatterConfig::default()
+        };
+        for (input, expected) in cases {
+            let rendered = Document::parse(input).render(&lf);
+            assert_eq!(rendered, expected, "{:?}", input);
+            assert_eq!(rendered, format_with_config(input, &lf));
+        }
+    }
+
+    #[test]
+    fn test_render_block_quotes() {
+        let cases = [
+            // Runs of blank quote lines collapse, an empty line between quotes stays
+            (
+                "Text\n> One\n>\n>  \n>\n> Two\n\n\n> Other quote\n***\n",
+                "Text\n\n> One\n>\n> Two\n\n> Other quote\n\n***\n",
+            ),
+            // Lazy continuation lines belong to the quote
+            (
+                "> Quoted\ncontinued\n>\n>\n> More\n---\n",
+                "> Quoted\ncontinued\n>\n> More\n\n---\n",
+            ),
+            // Fences inside and outside quotes are left alone
+            (
+                "> ```\n>\n>\n> ```\n```\n>\n>\n```\n",
+                "> ```\n>\n>\n> ```\n\n```\n>\n>\n```\n",
+            ),
+        ];
+        for (input, expected) in cases {
+            let rendered = Document::parse(input).render(&FormatterConfig::default());
+            assert_eq!(rendered, expected, "{:?}", input);
+        }
+
+        let document = Document::parse("> Quoted\ncontinued\n# Heading\n");
+        assert!(matches!(&document.blocks[0], Block::BlockQuote(quote) if quote.len() == 2));
+    }
+
+    #[test]
+    fn test_render_tables_and_html_blocks() {
+        let input = "Text\n| A | B |\n|---|---|\n| 1 | 2 |\n\n| C |\n|---|\nMore text\n<div>\n<p>Hi</p>\n</div>\n| D |\n";
+        let document = Document::parse(input);
+        assert!(matches!(&document.blocks[1], Block::Table(table) if table.len() == 3));
+
+        // Without the option, tables and HTML blocks are left as they are
+        assert_eq!(document.render(&FormatterConfig::default()), input);
+
+        let config = FormatterConfig {
+            separate_tables_and_html: true,
+            ..FormatterConfig::default()
+        };
+        assert_eq!(
+            document.render(&config),
+            "Text\n\n| A | B |\n|---|---|\n| 1 | 2 |\n\n| C |\n|---|\n\nMore text\n\n<div>\n<p>Hi</p>\n</div>\n\n| D |\n"
+        );
+
+        // Inline tags continue a paragraph
+        let input = "Some\n<em>inline</em> text\n";
+        assert_eq!(Document::parse(input).render(&config), input);
+    }
+
+    #[test]
+    fn test_render_fenced_divs() {
+        let cases = [
+            // Blank lines go around the whole div, never inside its fences
+            (
+                "Text\n:::note\n# Title\n- A\n:::\nMore\n",
+                "Text\n\n:::note\n# Title\n\n- A\n:::\n\nMore\n",
+            ),
+            // The content is formatted like the rest of the document
+            (
+                ":::tip\nOne\n\n\n\nTwo\n```\n:::\n```\n:::\n",
+                ":::tip\nOne\n\nTwo\n\n```\n:::\n```\n:::\n",
+            ),
+            // Nested divs close with at least as many colons as they opened with
+            (
+                "::::note\n::: {.inner}\n> Quote\n:::\n> Quote\n::::\n",
+                "::::note\n::: {.inner}\n> Quote\n:::\n\n> Quote\n::::\n",
+            ),
+            // A closing fence without an open div is text
+            ("- A\n:::\n", "- A\n\n:::\n"),
+            ("> Quote\n:::\n", "> Quote\n:::\n"),
+        ];
+        for (input, expected) in cases {
+            let rendered = Document::parse(input).render(&FormatterConfig::default());
+            assert_eq!(rendered, expected, "{:?}", input);
+        }
+
+        let document = Document::parse("::::note\n:::\ntext\n::::\n");
+        assert!(matches!(document.blocks[0], Block::DivOpen(_)));
+        assert!(matches!(&document.blocks[1], Block::Paragraph(text) if text.len() == 2));
+        assert!(matches!(document.blocks[2], Block::DivClose(_)));
+    }
+
+    #[test]
+    fn test_render_attribute_lists() {
+        let cases = [
+            // Attributes stay under their block, the blank line goes after them
+            (
+                "# Title\n{: .lead}\nText\n- A\n{: .checklist}\n## Next {#next}\n",
+                "# Title\n{: .lead}\n\nText\n\n- A\n{: .checklist}\n\n## Next {#next}\n",
+            ),
+            (
+                "```sh\nls\n```\n{#listing}\n> Quote\n",
+                "```sh\nls\n```\n{#listing}\n\n> Quote\n",
+            ),
+            // Under a paragraph they are part of it, after a blank line they stand alone
+            (
+                "Text\n{: .note}\n\n{: .next}\n# Heading\n",
+                "Text\n{: .note}\n\n{: .next}\n\n# Heading\n",
+            ),
+            // Not attribute lists
+            ("# Title\n{.class}\n", "# Title\n\n{.class}\n"),
+        ];
+        for (input, expected) in cases {
+            let rendered = Document::parse(input).render(&FormatterConfig::default());
+            assert_eq!(rendered, expected, "{:?}", input);
+        }
+
+        // The heading spacing options count from the attribute list
+        let config = FormatterConfig {
+            blank_lines_after_heading: Some(HeadingBlankLines([2; 6])),
+            ..FormatterConfig::default()
+        };
+        assert_eq!(
+            Document::parse("# Title\n{: #top}\nText\n").render(&config),
+            "# Title\n{: #top}\n\n\nText\n"
+        );
+        let config = FormatterConfig {
+            blank_lines_after_heading: Some(HeadingBlankLines([0; 6])),
+            ..FormatterConfig::default()
+        };
+        assert_eq!(
+            Document::parse("# Title\n\n{: .note}\n\n    code\n").render(&config),
+            "# Title\n\n{: .note}\n\n    code\n"
+        );
+    }
+
+    #[test]
+    fn test_render_tilde_math_blocks() {
+        let document = Document::parse("Text\n~~~\n\nx^2\n\n~~~\n");
+        assert_eq!(
+            document.render(&FormatterConfig::default()),
+            "Text\n\n~~~\nx^2\n~~~\n"
+        );
+        let config = FormatterConfig {
+            tilde_math_blocks: true,
+            ..FormatterConfig::default()
+        };
+        assert_eq!(document.render(&config), "Text\n\n~~~\n\nx^2\n\n~~~\n");
+    }
+
+    #[test]
+    fn test_word_count_by_section() {
+        let document = Document::parse(
+            "# One #\n> Quoted text\n\n1. First item\n10. Tenth item\n\n| a | b |\n|---|---|\n\n<div>html</div>\n\n$$\nx + y\n$$\n\n# `Two`\n- - -\n",
+        );
+        assert_eq!(document.section_count(1), 2);
+        assert_eq!(
+            document.word_count_by_section(),
+            [("One".to_string(), 8), ("Two".to_string(), 0)]
+        );
+        assert!(
+            Document::parse("Text only\n")
+                .word_count_by_section()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_visit_mut() {
+        /// Records the kinds of blocks it sees, and turns headings into paragraphs.
+        #[derive(Default)]
+        struct Flatten(Vec<&'static str>);
+
+        impl<'a> Visitor<'a> for Flatten {
+            fn visit_block(&mut self, block: &mut Block<'a>) {
+                if let Block::Heading { line, .. } = block {
+                    let text = &line.text[line.text.find(' ').map_or(0, |i| i + 1)..];
+                    *block = Block::Paragraph(SourceLines {
+                        start: line.index,
+                        text: format!("{}\n", text).into(),
+                    });
+                }
+                match block {
+                    Block::Paragraph(lines) => self.visit_paragraph(lines),
+                    Block::CodeFence(fence) => self.visit_code_fence(fence),
+                    _ => self.0.push("other"),
+                }
+            }
+
+            fn visit_paragraph(&mut self, _lines: &mut SourceLines<'a>) {
+                self.0.push("paragraph");
+            }
+
+            fn visit_code_fence(&mut self, fence: &mut FencedBlock<'a>) {
+                fence.open.text = "~~~".into();
+                if let Some(close) = &mut fence.close {
+                    close.text = "~~~".into();
+                }
+                self.0.push("fence");
+            }
+        }
+
+        let mut document = Document::parse("# Title\n```\ncode\n```\n---\n");
+        let mut visitor = Flatten::default();
+        document.visit_mut(&mut visitor);
+        assert_eq!(visitor.0, ["paragraph", "fence", "other"]);
+        assert_eq!(
+            document.render(&FormatterConfig::default()),
+            "Title\n\n~~~\ncode\n~~~\n\n---\n"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Newline-delimited JSON protocol for formatting many documents in one process.
+//!
+//! Each input line is a request object `{"path": "...", "content": "..."}` and
+//! produces exactly one response line `{"path": "...", "content": "...", "changed": true}`,
+//! in the same order. The path is only echoed back to help the caller match
+//! responses; nothing is read from or written to the filesystem. A line that cannot
+//! be handled produces `{"path": "...", "error": "..."}` instead, and processing
+//! continues with the next line.
+//!
+//! Documents whose path ends in `.mdx` are formatted as MDX (see
+//! [`ContentMode::Mdx`]).
+
+use crate::process_md::{ContentMode, FormatterConfig, format_with_config};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+/// A single format request.
+#[derive(Debug, Deserialize)]
+struct Request {
+    path: String,
+    content: String,
+}
+
+/// The response to a single request.
+#[derive(Debug, Serialize)]
+struct Response<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Format every request read from `input` and write one response per request to `output`.
+///
+/// Blank input lines are ignored. Each response is flushed as soon as it is written,
+/// so a caller can send one request at a time and wait for its answer.
+///
+/// # Arguments
+///
+/// * `input` - Newline-delimited JSON requests
+/// * `output` - Where newline-delimited JSON responses are written
+/// * `config` - The content rules used to format each document; `.mdx` paths are
+///   formatted as MDX even if its mode is Markdown
+///
+/// # Returns
+///
+/// The number of requests that produced an error response.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::batch::run;
+/// use mdfmt::process_md::FormatterConfig;
+///
+/// let input = r##"{"path": "a.md", "content": "# Title\nText\n"}"##;
+/// let mut output = Vec::new();
+/// let errors = run(input.as_bytes(), &mut output, &FormatterConfig::default())?;
+/// assert_eq!(errors, 0);
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     "{\"path\":\"a.md\",\"content\":\"# Title\\n\\nText\\n\",\"changed\":true}\n"
+/// );
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading the input or writing the output fails.
+pub fn run<R: BufRead, W: Write>(
+    input: R,
+    mut output: W,
+    config: &FormatterConfig,
+) -> io::Result<usize> {
+    let mut errors = 0;
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let config = match ContentMode::from_path(Path::new(&request.path)) {
+                    ContentMode::Mdx if config.content_mode != ContentMode::Mdx => {
+                        Cow::Owned(FormatterConfig {
+                            content_mode: ContentMode::Mdx,
+                            ..config.clone()
+                        })
+                    }
+                    _ => Cow::Borrowed(config),
+                };
+                let formatted = format_with_config(&request.content, &config);
+                let changed = matches!(formatted, Cow::Owned(_));
+                let response = Response {
+                    path: Some(&request.path),
+                    content: Some(&formatted),
+                    changed: Some(changed),
+                    error: None,
+                };
+                serde_json::to_string(&response)
+            }
+            Err(e) => {
+                errors += 1;
+                // Echo the path back if the line is an object with a usable path
+                let value = serde_json::from_str::<serde_json::Value>(&line).ok();
+                let response = Response {
+                    path: value.as_ref().and_then(|value| value["path"].as_str()),
+                    content: None,
+                    changed: None,
+                    error: Some(format!("invalid request: {}", e)),
+                };
+                serde_json::to_string(&response)
+            }
+        };
+        writeln!(output, "{}", response.map_err(io::Error::other)?)?;
+        output.flush()?;
+    }
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_lines(input: &str) -> (Vec<serde_json::Value>, usize) {
+        let mut output = Vec::new();
+        let errors = run(input.as_bytes(), &mut output, &FormatterConfig::default()).unwrap();
+        let responses = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        (responses, errors)
+    }
+
+    #[test]
+    fn test_responses_in_request_order() {
+        let input = concat!(
+            r#"{"path": "a.md", "content": "A\n\n\n\nB\n"}"#,
+            "\n\n",
+            r#"{"path": "b.md", "content": "Clean\n"}"#,
+            "\n"
+        );
+        let (responses, errors) = run_lines(input);
+        assert_eq!(errors, 0);
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["path"], "a.md");
+        assert_eq!(responses[0]["content"], "A\n\nB\n");
+        assert_eq!(responses[0]["changed"], true);
+        assert_eq!(responses[1]["path"], "b.md");
+        assert_eq!(responses[1]["content"], "Clean\n");
+        assert_eq!(responses[1]["changed"], false);
+    }
+
+    #[test]
+    fn test_invalid_request_reports_error_and_continues() {
+        let input = concat!(
+            "not json\n",
+            r#"{"path": "missing_content.md"}"#,
+            "\n",
+            r#"{"path": "ok.md", "content": "Text\n"}"#,
+            "\n"
+        );
+        let (responses, errors) = run_lines(input);
+        assert_eq!(errors, 2);
+        assert_eq!(responses.len(), 3);
+        assert!(responses[0].get("path").is_none());
+        assert!(responses[0]["error"].is_string());
+        assert_eq!(responses[1]["path"], "missing_content.md");
+        assert!(responses[1]["error"].is_string());
+        assert_eq!(responses[2]["changed"], false);
+    }
+
+    #[test]
+    fn test_mdx_paths_keep_jsx() {
+        let content = "# Intro\n<Note>\nText\n</Note>\n";
+        let input = [("a.md", content), ("b.mdx", content)]
+            .map(|(path, content)| {
+                serde_json::json!({ "path": path, "content": content }).to_string() + "\n"
+            })
+            .concat();
+        let (responses, errors) = run_lines(&input);
+        assert_eq!(errors, 0);
+        assert_eq!(
+            responses[0]["content"],
+            "# Intro\n\n<Note>\nText\n</Note>\n"
+        );
+        assert_eq!(responses[1]["content"], content);
+        assert_eq!(responses[1]["changed"], false);
+    }
+}
@@ -0,0 +1,282 @@
+//! Content-hash cache for skipping files that were already verified clean.
+//!
+//! The cache maps each file to its size, modification time, and SHA-256 content
+//! hash as of the last run that found it formatted. A file whose metadata still
+//! matches (or whose content hash still matches, if only the mtime changed) can be
+//! skipped without reading or formatting it again. The whole cache is tied to a
+//! fingerprint of the formatting options, so changing options invalidates it.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Default location of the cache file, relative to the current directory.
+pub const DEFAULT_CACHE_FILE: &str = ".mdfmt-cache";
+
+/// Version of the on-disk cache format.
+const CACHE_FORMAT_VERSION: u32 = 1;
+
+/// The cached state of a single clean file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// File size in bytes.
+    pub size: u64,
+    /// Modification time as seconds since the Unix epoch.
+    pub mtime_secs: u64,
+    /// Sub-second part of the modification time.
+    pub mtime_nanos: u32,
+    /// Hex-encoded SHA-256 hash of the file content.
+    pub hash: String,
+}
+
+impl CacheEntry {
+    /// Build an entry from a file's current metadata and content.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the file cannot be read.
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let content = fs::read(path)?;
+        let (mtime_secs, mtime_nanos) = modification_time(&metadata);
+        Ok(CacheEntry {
+            size: metadata.len(),
+            mtime_secs,
+            mtime_nanos,
+            hash: content_hash(&content),
+        })
+    }
+}
+
+/// The result of looking a file up in the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheLookup {
+    /// The file is unchanged since it was last verified clean.
+    Hit,
+    /// The content is unchanged but the metadata moved; the entry should be refreshed.
+    HitWithUpdate(CacheEntry),
+    /// The file must be processed.
+    Miss,
+}
+
+/// On-disk representation of the cache.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    fingerprint: String,
+    files: HashMap<PathBuf, CacheEntry>,
+}
+
+/// A set of files known to be formatted under a given options fingerprint.
+#[derive(Debug, Default)]
+pub struct Cache {
+    fingerprint: String,
+    entries: HashMap<PathBuf, CacheEntry>,
+}
+
+impl Cache {
+    /// Load the cache from `path`, keeping its entries only if they were written with
+    /// the same options `fingerprint`.
+    ///
+    /// A missing, unreadable, or outdated cache file yields an empty cache rather than
+    /// an error, since the cache is purely an optimization.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use mdfmt::cache::{Cache, fingerprint};
+    /// use std::path::Path;
+    ///
+    /// let cache = Cache::load(Path::new(".mdfmt-cache"), &fingerprint(&["--delete"]));
+    /// println!("{} cached files", cache.len());
+    /// ```
+    pub fn load(path: &Path, fingerprint: &str) -> Self {
+        let entries = fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<CacheFile>(&data).ok())
+            .filter(|file| file.version == CACHE_FORMAT_VERSION && file.fingerprint == fingerprint)
+            .map(|file| file.files)
+            .unwrap_or_default();
+
+        Cache {
+            fingerprint: fingerprint.to_string(),
+            entries,
+        }
+    }
+
+    /// Save the cache to `path`.
+    ///
+    /// The cache is written to a temporary file next to `path` and then renamed over
+    /// it, so concurrent runs never observe a partially written cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the cache file cannot be written.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = CacheFile {
+            version: CACHE_FORMAT_VERSION,
+            fingerprint: self.fingerprint.clone(),
+            files: self.entries.clone(),
+        };
+        let data = serde_json::to_vec(&file).map_err(io::Error::other)?;
+
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = path.with_file_name(temp_name);
+
+        fs::write(&temp_path, data)?;
+        fs::rename(&temp_path, path).inspect_err(|_| {
+            fs::remove_file(&temp_path).ok();
+        })
+    }
+
+    /// Check whether `path` can be skipped.
+    ///
+    /// Matching size and modification time is a hit without reading the file. If only
+    /// the modification time differs, the content hash is compared instead.
+    pub fn lookup(&self, path: &Path) -> CacheLookup {
+        let Some(entry) = self.entries.get(&cache_key(path)) else {
+            return CacheLookup::Miss;
+        };
+        let Ok(metadata) = fs::metadata(path) else {
+            return CacheLookup::Miss;
+        };
+        if metadata.len() != entry.size {
+            return CacheLookup::Miss;
+        }
+        if modification_time(&metadata) == (entry.mtime_secs, entry.mtime_nanos) {
+            return CacheLookup::Hit;
+        }
+
+        match CacheEntry::from_file(path) {
+            Ok(current) if current.hash == entry.hash => CacheLookup::HitWithUpdate(current),
+            _ => CacheLookup::Miss,
+        }
+    }
+
+    /// Record `path` as clean with the given entry.
+    pub fn insert(&mut self, path: &Path, entry: CacheEntry) {
+        self.entries.insert(cache_key(path), entry);
+    }
+
+    /// Forget `path`, e.g. after it was found unformatted or deleted.
+    pub fn remove(&mut self, path: &Path) {
+        self.entries.remove(&cache_key(path));
+    }
+
+    /// Number of cached files.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Compute an options fingerprint from the crate version and the given option values.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::cache::fingerprint;
+///
+/// assert_eq!(fingerprint(&["a"]), fingerprint(&["a"]));
+/// assert_ne!(fingerprint(&["a"]), fingerprint(&["b"]));
+/// ```
+pub fn fingerprint(options: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    for option in options {
+        hasher.update([0]);
+        hasher.update(option);
+    }
+    to_hex(&hasher.finalize())
+}
+
+/// Hex-encoded SHA-256 hash of some content.
+fn content_hash(content: &[u8]) -> String {
+    to_hex(&Sha256::digest(content))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Modification time as (seconds, nanoseconds) since the Unix epoch, or zero if unknown.
+fn modification_time(metadata: &fs::Metadata) -> (u64, u32) {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| (duration.as_secs(), duration.subsec_nanos()))
+        .unwrap_or((0, 0))
+}
+
+/// Files are keyed by canonical path so different spellings share an entry.
+fn cache_key(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder;
+
+    #[test]
+    fn test_cache_round_trip_and_lookup() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
+        let file = temp_dir.join("a.md");
+        let cache_path = temp_dir.join(".mdfmt-cache");
+        fs::write(&file, "# Clean\n").unwrap();
+
+        let mut cache = Cache::load(&cache_path, "options");
+        assert!(cache.is_empty());
+        assert_eq!(cache.lookup(&file), CacheLookup::Miss);
+
+        cache.insert(&file, CacheEntry::from_file(&file).unwrap());
+        cache.save(&cache_path).unwrap();
+
+        let cache = Cache::load(&cache_path, "options");
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.lookup(&file), CacheLookup::Hit);
+
+        // Different options invalidate the whole cache
+        assert!(Cache::load(&cache_path, "other options").is_empty());
+
+        // Changed content of the same size is detected through the hash
+        fs::write(&file, "# Dirty\n").unwrap();
+        let file_handle = fs::File::options().write(true).open(&file).unwrap();
+        file_handle
+            .set_modified(UNIX_EPOCH + std::time::Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(cache.lookup(&file), CacheLookup::Miss);
+    }
+
+    #[test]
+    fn test_cache_hit_with_update_on_touched_file() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
+        let file = temp_dir.join("a.md");
+        fs::write(&file, "# Clean\n").unwrap();
+
+        let mut cache = Cache::default();
+        cache.insert(&file, CacheEntry::from_file(&file).unwrap());
+
+        // Same content, different mtime
+        let file_handle = fs::File::options().write(true).open(&file).unwrap();
+        file_handle
+            .set_modified(UNIX_EPOCH + std::time::Duration::from_secs(1))
+            .unwrap();
+        match cache.lookup(&file) {
+            CacheLookup::HitWithUpdate(entry) => assert_eq!(entry.mtime_secs, 1),
+            other => panic!("expected a hit with update, got {:?}", other),
+        }
+    }
+}
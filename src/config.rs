@@ -0,0 +1,745 @@
+//! Per-directory configuration from `.mdfmt.toml` files.
+//!
+//! Any directory can contain a `.mdfmt.toml` file that sets some of the formatting
+//! options. A file is formatted with the configuration files of its own directory and
+//! of every parent directory up to the repository root, merged from the root down, so
+//! a subdirectory only lists the options it changes:
+//!
+//! ```toml
+//! # docs/api/.mdfmt.toml
+//! fix_bare_urls = true
+//! fix_missing_alt_text = true
+//!
+//! [code_fence_language_aliases]
+//! graph = "mermaid"
+//! ```
+
+use crate::lint::LintConfig;
+use crate::process_md::{
+    DeleteStrategy, FenceStyle, FormatOptions, FormatterConfig, HeadingBlankLines, LineEnding,
+    LinkTitleStyle,
+};
+use crate::toc::TocLevels;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the configuration file looked for in every directory.
+pub const CONFIG_FILE: &str = ".mdfmt.toml";
+
+/// The options set by one configuration file, or by a merged stack of them.
+///
+/// Options that are `None` are not set, and are inherited from the parent directories
+/// or left at their defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// What to do with files that are empty or contain only frontmatter.
+    pub delete: Option<DeleteStrategy>,
+    /// Truncate whitespace-only files to zero bytes.
+    pub normalize_empty: Option<bool>,
+    /// Format files that contain only frontmatter instead of deleting or skipping them.
+    pub include_empty: Option<bool>,
+    /// Skip files larger than this many bytes.
+    pub max_size: Option<u64>,
+    /// How many milliseconds to wait for a file locked by another mdfmt run.
+    pub lock_timeout_ms: Option<u64>,
+    /// Leave files alone when only the newlines at their end would change.
+    pub ignore_final_newline_only: Option<bool>,
+    /// How many blank lines in a row are kept.
+    pub max_consecutive_blank_lines: Option<usize>,
+    /// Wrap bare URLs in angle brackets.
+    pub fix_bare_urls: Option<bool>,
+    /// Use the file name as alt text for images without any.
+    pub fix_missing_alt_text: Option<bool>,
+    /// Treat `~~~` fences as display math blocks.
+    pub tilde_math_blocks: Option<bool>,
+    /// Surround tables and HTML blocks with blank lines.
+    pub separate_tables_and_html: Option<bool>,
+    /// Rewrite code fence languages. A set map replaces the inherited one as a whole.
+    /// The table can also be written as `[fence_aliases]`.
+    #[serde(alias = "fence_aliases")]
+    pub code_fence_language_aliases: Option<BTreeMap<String, String>>,
+    /// Lowercase code fence languages and trim whitespace around info strings.
+    pub normalize_fence_languages: Option<bool>,
+    /// Also apply the built-in aliases of common short code fence languages such as
+    /// `rs` and `py`, after the `[fence_aliases]` table.
+    pub builtin_fence_aliases: Option<bool>,
+    /// Remove zero-width and soft hyphen characters outside code fences.
+    pub remove_zero_width_chars: Option<bool>,
+    /// Add this language to code fences without one.
+    pub default_fence_language: Option<String>,
+    /// Warn about code fences without a language.
+    pub require_fence_language: Option<bool>,
+    /// Rewrite code fence markers to backticks or tildes.
+    pub fence_style: Option<FenceStyle>,
+    /// Blank lines before headings: one count, or a list of counts by heading level.
+    pub blank_lines_before_heading: Option<HeadingBlankLines>,
+    /// Blank lines after headings: one count, or a list of counts by heading level.
+    pub blank_lines_after_heading: Option<HeadingBlankLines>,
+    /// Align the columns of pipe tables.
+    pub format_tables: Option<bool>,
+    /// The line endings to write, `"lf"`, `"crlf"`, or `"keep"`.
+    pub line_ending: Option<LineEnding>,
+    /// End files with a newline (`true`) or without one (`false`).
+    pub insert_final_newline: Option<bool>,
+    /// Remove trailing whitespace outside code fences.
+    pub trim_trailing_whitespace: Option<bool>,
+    /// Rewrite link titles to `"double_quote"`, `"single_quote"`, or `"parenthesis"`.
+    pub link_title_style: Option<LinkTitleStyle>,
+    /// Remove trailing periods, question marks, and exclamation marks from headings.
+    pub remove_heading_trailing_punctuation: Option<bool>,
+    /// Remove headings that have no text, only `#` markers.
+    pub remove_empty_headings: Option<bool>,
+    /// Make the underlines of setext headings as long as their text.
+    pub normalize_setext_underlines: Option<bool>,
+    /// Put one space after heading markers and collapse runs of spaces in headings.
+    pub normalize_heading_spacing: Option<bool>,
+    /// Regenerate tables of contents between `<!-- mdfmt-toc-start -->` and
+    /// `<!-- mdfmt-toc-end -->` markers.
+    pub toc: Option<bool>,
+    /// The heading levels listed in tables of contents, e.g. `"2..4"`.
+    pub toc_levels: Option<TocLevels>,
+    /// Sort the top-level keys of YAML frontmatter.
+    pub sort_frontmatter: Option<bool>,
+    /// Frontmatter keys to put first when sorting, e.g. `["title", "date"]`; the
+    /// others follow in alphabetical order.
+    pub frontmatter_key_order: Option<Vec<String>>,
+    /// Re-indent nested list items to this many spaces per nesting level.
+    pub list_indent: Option<usize>,
+    /// The number of columns a tab advances to when re-indenting lists.
+    pub tab_width: Option<usize>,
+    /// Expand leading tabs to spaces, with tab stops this many columns apart.
+    pub tabs_to_spaces: Option<usize>,
+    /// Put exactly one space between list markers and the item text.
+    pub normalize_list_marker_spacing: Option<bool>,
+    /// Wrap the lines of block quotes longer than this many characters.
+    pub wrap_blockquotes: Option<usize>,
+    /// HTML comments such as `"<!--more-->"` whose surrounding blank lines are kept
+    /// as they are. A set list replaces the inherited one as a whole.
+    pub protected_html_comments: Option<Vec<String>>,
+    /// The `[lint]` table, which turns off lint checks.
+    pub lint: Option<LintOptions>,
+}
+
+/// The `[lint]` table of a configuration file, which turns off the heading
+/// structure checks of `mdfmt --lint`:
+///
+/// ```toml
+/// [lint]
+/// allow_multiple_h1 = true
+/// ```
+///
+/// The options can also be written with dashes, e.g. `allow-multiple-h1`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LintOptions {
+    /// Allow more than one level 1 heading.
+    #[serde(alias = "allow-multiple-h1")]
+    pub allow_multiple_h1: Option<bool>,
+    /// Allow the first heading to be of another level than 1, e.g. when the title
+    /// comes from the frontmatter.
+    #[serde(alias = "allow-first-heading-not-h1")]
+    pub allow_first_heading_not_h1: Option<bool>,
+    /// Allow headings more than one level deeper than the heading before them.
+    #[serde(alias = "allow-skipped-heading-levels")]
+    pub allow_skipped_heading_levels: Option<bool>,
+}
+
+impl LintOptions {
+    /// Merge two `[lint]` tables like [`Config::merge`].
+    fn merge(base: LintOptions, override_: LintOptions) -> LintOptions {
+        LintOptions {
+            allow_multiple_h1: override_.allow_multiple_h1.or(base.allow_multiple_h1),
+            allow_first_heading_not_h1: override_
+                .allow_first_heading_not_h1
+                .or(base.allow_first_heading_not_h1),
+            allow_skipped_heading_levels: override_
+                .allow_skipped_heading_levels
+                .or(base.allow_skipped_heading_levels),
+        }
+    }
+}
+
+impl Config {
+    /// Read a configuration file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the file cannot be read, or one of kind `InvalidData`
+    /// if it is not valid TOML or sets an unknown option.
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid config file '{}': {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Merge two configurations: options set in `override_` replace those of `base`,
+    /// and options it leaves unset are inherited from `base`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::config::Config;
+    ///
+    /// let base = Config {
+    ///     fix_bare_urls: Some(true),
+    ///     tilde_math_blocks: Some(true),
+    ///     ..Config::default()
+    /// };
+    /// let override_ = Config {
+    ///     tilde_math_blocks: Some(false),
+    ///     ..Config::default()
+    /// };
+    /// let merged = Config::merge(base, override_);
+    /// assert_eq!(merged.fix_bare_urls, Some(true));
+    /// assert_eq!(merged.tilde_math_blocks, Some(false));
+    /// ```
+    pub fn merge(base: Config, override_: Config) -> Config {
+        Config {
+            delete: override_.delete.or(base.delete),
+            normalize_empty: override_.normalize_empty.or(base.normalize_empty),
+            include_empty: override_.include_empty.or(base.include_empty),
+            max_size: override_.max_size.or(base.max_size),
+            lock_timeout_ms: override_.lock_timeout_ms.or(base.lock_timeout_ms),
+            ignore_final_newline_only: override_
+                .ignore_final_newline_only
+                .or(base.ignore_final_newline_only),
+            max_consecutive_blank_lines: override_
+                .max_consecutive_blank_lines
+                .or(base.max_consecutive_blank_lines),
+            fix_bare_urls: override_.fix_bare_urls.or(base.fix_bare_urls),
+            fix_missing_alt_text: override_.fix_missing_alt_text.or(base.fix_missing_alt_text),
+            tilde_math_blocks: override_.tilde_math_blocks.or(base.tilde_math_blocks),
+            separate_tables_and_html: override_
+                .separate_tables_and_html
+                .or(base.separate_tables_and_html),
+            code_fence_language_aliases: override_
+                .code_fence_language_aliases
+                .or(base.code_fence_language_aliases),
+            normalize_fence_languages: override_
+                .normalize_fence_languages
+                .or(base.normalize_fence_languages),
+            builtin_fence_aliases: override_
+                .builtin_fence_aliases
+                .or(base.builtin_fence_aliases),
+            remove_zero_width_chars: override_
+                .remove_zero_width_chars
+                .or(base.remove_zero_width_chars),
+            default_fence_language: override_
+                .default_fence_language
+                .or(base.default_fence_language),
+            require_fence_language: override_
+                .require_fence_language
+                .or(base.require_fence_language),
+            fence_style: override_.fence_style.or(base.fence_style),
+            blank_lines_before_heading: override_
+                .blank_lines_before_heading
+                .or(base.blank_lines_before_heading),
+            blank_lines_after_heading: override_
+                .blank_lines_after_heading
+                .or(base.blank_lines_after_heading),
+            format_tables: override_.format_tables.or(base.format_tables),
+            line_ending: override_.line_ending.or(base.line_ending),
+            insert_final_newline: override_.insert_final_newline.or(base.insert_final_newline),
+            trim_trailing_whitespace: override_
+                .trim_trailing_whitespace
+                .or(base.trim_trailing_whitespace),
+            link_title_style: override_.link_title_style.or(base.link_title_style),
+            remove_heading_trailing_punctuation: override_
+                .remove_heading_trailing_punctuation
+                .or(base.remove_heading_trailing_punctuation),
+            remove_empty_headings: override_
+                .remove_empty_headings
+                .or(base.remove_empty_headings),
+            normalize_setext_underlines: override_
+                .normalize_setext_underlines
+                .or(base.normalize_setext_underlines),
+            normalize_heading_spacing: override_
+                .normalize_heading_spacing
+                .or(base.normalize_heading_spacing),
+            toc: override_.toc.or(base.toc),
+            toc_levels: override_.toc_levels.or(base.toc_levels),
+            sort_frontmatter: override_.sort_frontmatter.or(base.sort_frontmatter),
+            frontmatter_key_order: override_
+                .frontmatter_key_order
+                .or(base.frontmatter_key_order),
+            list_indent: override_.list_indent.or(base.list_indent),
+            tab_width: override_.tab_width.or(base.tab_width),
+            tabs_to_spaces: override_.tabs_to_spaces.or(base.tabs_to_spaces),
+            normalize_list_marker_spacing: override_
+                .normalize_list_marker_spacing
+                .or(base.normalize_list_marker_spacing),
+            wrap_blockquotes: override_.wrap_blockquotes.or(base.wrap_blockquotes),
+            protected_html_comments: override_
+                .protected_html_comments
+                .or(base.protected_html_comments),
+            lint: match (base.lint, override_.lint) {
+                (Some(base), Some(override_)) => Some(LintOptions::merge(base, override_)),
+                (base, override_) => override_.or(base),
+            },
+        }
+    }
+
+    /// Write the configuration as the contents of a `.mdfmt.toml` file, leaving out
+    /// the options that are not set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::config::Config;
+    /// use mdfmt::process_md::HeadingBlankLines;
+    ///
+    /// let config = Config {
+    ///     fix_bare_urls: Some(true),
+    ///     blank_lines_before_heading: Some(HeadingBlankLines([2, 1, 1, 1, 1, 1])),
+    ///     ..Config::default()
+    /// };
+    /// assert_eq!(
+    ///     config.to_toml_string(),
+    ///     "fix_bare_urls = true\nblank_lines_before_heading = [2, 1]\n"
+    /// );
+    /// ```
+    pub fn to_toml_string(&self) -> String {
+        toml::to_string(self).expect("every option can be written as TOML")
+    }
+
+    /// The lint rules of `mdfmt --lint`: every rule of [`LintConfig::default`],
+    /// except the checks turned off in the `[lint]` table, with the blank line rules
+    /// checked with the [`format_options`](Self::format_options).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::config::{Config, LintOptions};
+    ///
+    /// let config = Config {
+    ///     max_consecutive_blank_lines: Some(2),
+    ///     lint: Some(LintOptions {
+    ///         allow_multiple_h1: Some(true),
+    ///         ..LintOptions::default()
+    ///     }),
+    ///     ..Config::default()
+    /// };
+    /// let lint = config.lint_config();
+    /// assert_eq!(lint.multiple_h1, None);
+    /// assert!(lint.heading_increment.is_some());
+    /// assert_eq!(lint.formatter.max_consecutive_blank_lines, 2);
+    /// ```
+    pub fn lint_config(&self) -> LintConfig {
+        let options = self.lint.clone().unwrap_or_default();
+        let defaults = LintConfig::default();
+        let check = |allowed: Option<bool>, severity| {
+            if allowed == Some(true) {
+                None
+            } else {
+                severity
+            }
+        };
+        let format_options = self.format_options();
+        LintConfig {
+            formatter: format_options.formatter,
+            include_empty: format_options.include_empty,
+            multiple_h1: check(options.allow_multiple_h1, defaults.multiple_h1),
+            first_heading_level: check(
+                options.allow_first_heading_not_h1,
+                defaults.first_heading_level,
+            ),
+            heading_increment: check(
+                options.allow_skipped_heading_levels,
+                defaults.heading_increment,
+            ),
+            ..defaults
+        }
+    }
+
+    /// The format options with every set option applied to the defaults.
+    pub fn format_options(&self) -> FormatOptions {
+        let defaults = FormatOptions::default();
+        let formatter = defaults.formatter;
+        FormatOptions {
+            delete: self.delete.unwrap_or(defaults.delete),
+            normalize_empty: self.normalize_empty.unwrap_or(defaults.normalize_empty),
+            include_empty: self.include_empty.unwrap_or(defaults.include_empty),
+            max_size: self.max_size.or(defaults.max_size),
+            lock_timeout_ms: self.lock_timeout_ms.unwrap_or(defaults.lock_timeout_ms),
+            ignore_final_newline_only: self
+                .ignore_final_newline_only
+                .unwrap_or(defaults.ignore_final_newline_only),
+            encoding: defaults.encoding,
+            formatter: FormatterConfig {
+                max_consecutive_blank_lines: self
+                    .max_consecutive_blank_lines
+                    .unwrap_or(formatter.max_consecutive_blank_lines),
+                fix_bare_urls: self.fix_bare_urls.unwrap_or(formatter.fix_bare_urls),
+                fix_missing_alt_text: self
+                    .fix_missing_alt_text
+                    .unwrap_or(formatter.fix_missing_alt_text),
+                tilde_math_blocks: self
+                    .tilde_math_blocks
+                    .unwrap_or(formatter.tilde_math_blocks),
+                separate_tables_and_html: self
+                    .separate_tables_and_html
+                    .unwrap_or(formatter.separate_tables_and_html),
+                code_fence_language_aliases: match &self.code_fence_language_aliases {
+                    Some(aliases) => aliases.clone().into_iter().collect(),
+                    None => formatter.code_fence_language_aliases,
+                },
+                normalize_fence_languages: self
+                    .normalize_fence_languages
+                    .unwrap_or(formatter.normalize_fence_languages),
+                builtin_fence_aliases: self
+                    .builtin_fence_aliases
+                    .unwrap_or(formatter.builtin_fence_aliases),
+                remove_zero_width_chars: self
+                    .remove_zero_width_chars
+                    .unwrap_or(formatter.remove_zero_width_chars),
+                default_fence_language: self
+                    .default_fence_language
+                    .clone()
+                    .or(formatter.default_fence_language),
+                require_fence_language: self
+                    .require_fence_language
+                    .unwrap_or(formatter.require_fence_language),
+                fence_style: self.fence_style.unwrap_or(formatter.fence_style),
+                blank_lines_before_heading: self
+                    .blank_lines_before_heading
+                    .or(formatter.blank_lines_before_heading),
+                blank_lines_after_heading: self
+                    .blank_lines_after_heading
+                    .or(formatter.blank_lines_after_heading),
+                format_tables: self.format_tables.unwrap_or(formatter.format_tables),
+                line_ending: self.line_ending.unwrap_or(formatter.line_ending),
+                insert_final_newline: self.insert_final_newline.or(formatter.insert_final_newline),
+                trim_trailing_whitespace: self
+                    .trim_trailing_whitespace
+                    .unwrap_or(formatter.trim_trailing_whitespace),
+                link_title_style: self.link_title_style.or(formatter.link_title_style),
+                remove_heading_trailing_punctuation: self
+                    .remove_heading_trailing_punctuation
+                    .unwrap_or(formatter.remove_heading_trailing_punctuation),
+                remove_empty_headings: self
+                    .remove_empty_headings
+                    .unwrap_or(formatter.remove_empty_headings),
+                normalize_setext_underlines: self
+                    .normalize_setext_underlines
+                    .unwrap_or(formatter.normalize_setext_underlines),
+                normalize_heading_spacing: self
+                    .normalize_heading_spacing
+                    .unwrap_or(formatter.normalize_heading_spacing),
+                toc: match self.toc {
+                    Some(true) => Some(self.toc_levels.unwrap_or_default()),
+                    Some(false) => None,
+                    None => formatter.toc,
+                },
+                sort_frontmatter_keys: match self.sort_frontmatter {
+                    Some(true) => Some(self.frontmatter_key_order.clone().unwrap_or_default()),
+                    Some(false) => None,
+                    None => formatter.sort_frontmatter_keys,
+                },
+                list_indent: self.list_indent.or(formatter.list_indent),
+                tab_width: self.tab_width.unwrap_or(formatter.tab_width),
+                tabs_to_spaces: self.tabs_to_spaces.or(formatter.tabs_to_spaces),
+                normalize_list_marker_spacing: self
+                    .normalize_list_marker_spacing
+                    .unwrap_or(formatter.normalize_list_marker_spacing),
+                wrap_blockquotes: self.wrap_blockquotes.or(formatter.wrap_blockquotes),
+                protected_html_comments: self
+                    .protected_html_comments
+                    .clone()
+                    .unwrap_or(formatter.protected_html_comments),
+                ..formatter
+            },
+            #[cfg(feature = "preserve-mtime")]
+            preserve_mtime: defaults.preserve_mtime,
+        }
+    }
+}
+
+impl From<&FormatOptions> for Config {
+    /// The configuration that sets every option to its value in `options`, e.g. to
+    /// show the defaults together with the options that were set.
+    ///
+    /// Options whose value is to leave the content as it is, such as
+    /// `list_indent`, are left unset.
+    fn from(options: &FormatOptions) -> Self {
+        let formatter = &options.formatter;
+        Config {
+            delete: Some(options.delete),
+            normalize_empty: Some(options.normalize_empty),
+            include_empty: Some(options.include_empty),
+            max_size: options.max_size,
+            lock_timeout_ms: Some(options.lock_timeout_ms),
+            ignore_final_newline_only: Some(options.ignore_final_newline_only),
+            max_consecutive_blank_lines: Some(formatter.max_consecutive_blank_lines),
+            fix_bare_urls: Some(formatter.fix_bare_urls),
+            fix_missing_alt_text: Some(formatter.fix_missing_alt_text),
+            tilde_math_blocks: Some(formatter.tilde_math_blocks),
+            separate_tables_and_html: Some(formatter.separate_tables_and_html),
+            code_fence_language_aliases: Some(
+                formatter
+                    .code_fence_language_aliases
+                    .clone()
+                    .into_iter()
+                    .collect(),
+            ),
+            normalize_fence_languages: Some(formatter.normalize_fence_languages),
+            builtin_fence_aliases: Some(formatter.builtin_fence_aliases),
+            remove_zero_width_chars: Some(formatter.remove_zero_width_chars),
+            default_fence_language: formatter.default_fence_language.clone(),
+            require_fence_language: Some(formatter.require_fence_language),
+            fence_style: Some(formatter.fence_style),
+            blank_lines_before_heading: formatter.blank_lines_before_heading,
+            blank_lines_after_heading: formatter.blank_lines_after_heading,
+            format_tables: Some(formatter.format_tables),
+            line_ending: Some(formatter.line_ending),
+            insert_final_newline: formatter.insert_final_newline,
+            trim_trailing_whitespace: Some(formatter.trim_trailing_whitespace),
+            link_title_style: formatter.link_title_style,
+            remove_heading_trailing_punctuation: Some(
+                formatter.remove_heading_trailing_punctuation,
+            ),
+            remove_empty_headings: Some(formatter.remove_empty_headings),
+            normalize_setext_underlines: Some(formatter.normalize_setext_underlines),
+            normalize_heading_spacing: Some(formatter.normalize_heading_spacing),
+            toc: Some(formatter.toc.is_some()),
+            toc_levels: formatter.toc,
+            sort_frontmatter: Some(formatter.sort_frontmatter_keys.is_some()),
+            frontmatter_key_order: formatter.sort_frontmatter_keys.clone(),
+            list_indent: formatter.list_indent,
+            tab_width: Some(formatter.tab_width),
+            tabs_to_spaces: formatter.tabs_to_spaces,
+            normalize_list_marker_spacing: Some(formatter.normalize_list_marker_spacing),
+            wrap_blockquotes: formatter.wrap_blockquotes,
+            protected_html_comments: Some(formatter.protected_html_comments.clone()),
+            lint: None,
+        }
+    }
+}
+
+/// Find the configuration files that apply to files in `dir`.
+///
+/// The search walks upward from `dir` and stops at the repository root, the first
+/// directory that contains `.git`, or at the filesystem root outside a repository.
+///
+/// # Arguments
+///
+/// * `dir` - The directory of the files being formatted
+///
+/// # Returns
+///
+/// The paths of all `.mdfmt.toml` files found, root first and nearest last.
+pub fn config_files(dir: &Path) -> Vec<PathBuf> {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let mut files = Vec::new();
+    for ancestor in dir.ancestors() {
+        let path = ancestor.join(CONFIG_FILE);
+        if path.is_file() {
+            files.push(path);
+        }
+        if ancestor.join(".git").exists() {
+            break;
+        }
+    }
+    files.reverse();
+    files
+}
+
+/// Load and merge the configuration for files in `dir`, from the repository root
+/// down to `dir` itself (see [`config_files`]).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::config;
+/// use std::path::Path;
+///
+/// let options = config::resolve(Path::new("docs/api"))?.format_options();
+/// println!("bare URLs are fixed: {}", options.formatter.fix_bare_urls);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` if one of the configuration files cannot be read or parsed.
+pub fn resolve(dir: &Path) -> io::Result<Config> {
+    config_files(dir)
+        .iter()
+        .try_fold(Config::default(), |base, path| {
+            debug!("{}: using {}", dir.display(), path.display());
+            Ok(Config::merge(base, Config::from_file(path)?))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder;
+
+    #[test]
+    fn test_merge_inherits_unset_options() {
+        let base = Config {
+            delete: Some(DeleteStrategy::Remove),
+            code_fence_language_aliases: Some(BTreeMap::from([(
+                "py".to_string(),
+                "python".to_string(),
+            )])),
+            ..Config::default()
+        };
+        let override_ = Config {
+            delete: Some(DeleteStrategy::Keep),
+            tilde_math_blocks: Some(false),
+            ..Config::default()
+        };
+        let merged = Config::merge(base.clone(), override_);
+        assert_eq!(merged.delete, Some(DeleteStrategy::Keep));
+        assert_eq!(merged.tilde_math_blocks, Some(false));
+        assert_eq!(
+            merged.code_fence_language_aliases,
+            base.code_fence_language_aliases
+        );
+        assert_eq!(Config::merge(base.clone(), Config::default()), base);
+    }
+
+    #[test]
+    fn test_resolve_merges_from_repository_root_down() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let outer = temp.path();
+        let root = outer.join("repo");
+        let api = root.join("docs").join("api");
+        fs::create_dir_all(&api).unwrap();
+        fs::create_dir(root.join(".git")).unwrap();
+        // Above the repository root, so never used
+        fs::write(outer.join(CONFIG_FILE), "tilde_math_blocks = true\n").unwrap();
+        fs::write(
+            root.join(CONFIG_FILE),
+            "delete = \"trash\"\nfix_bare_urls = true\n",
+        )
+        .unwrap();
+        fs::write(
+            api.join(CONFIG_FILE),
+            "fix_bare_urls = false\nmax_consecutive_blank_lines = 2\n",
+        )
+        .unwrap();
+
+        assert_eq!(config_files(&api).len(), 2);
+        let options = resolve(&api).unwrap().format_options();
+        assert_eq!(options.delete, DeleteStrategy::Trash);
+        assert!(!options.formatter.fix_bare_urls);
+        assert!(!options.formatter.tilde_math_blocks);
+        assert_eq!(options.formatter.max_consecutive_blank_lines, 2);
+
+        let options = resolve(&root.join("docs")).unwrap().format_options();
+        assert!(options.formatter.fix_bare_urls);
+        assert_eq!(options.formatter.max_consecutive_blank_lines, 1);
+    }
+
+    #[test]
+    fn test_fence_aliases_table() {
+        let config: Config = toml::from_str(
+            "normalize_fence_languages = true\n\n[fence_aliases]\nrs = \"rust\"\nyml = \"yaml\"\n",
+        )
+        .unwrap();
+        let formatter = config.format_options().formatter;
+        assert!(formatter.normalize_fence_languages);
+        assert!(!formatter.builtin_fence_aliases);
+        assert_eq!(formatter.code_fence_language_aliases["yml"], "yaml");
+
+        let config: Config = toml::from_str("builtin_fence_aliases = true\n").unwrap();
+        assert!(config.format_options().formatter.builtin_fence_aliases);
+    }
+
+    #[test]
+    fn test_heading_blank_lines() {
+        let config: Config =
+            toml::from_str("blank_lines_before_heading = [2, 1]\nblank_lines_after_heading = 1\n")
+                .unwrap();
+        let formatter = config.format_options().formatter;
+        let before = formatter.blank_lines_before_heading.unwrap();
+        assert_eq!(before.for_level(1), 2);
+        assert_eq!(before.for_level(4), 1);
+        assert_eq!(
+            formatter.blank_lines_after_heading,
+            Some(HeadingBlankLines::all(1))
+        );
+
+        let err = toml::from_str::<Config>("blank_lines_before_heading = []\n").unwrap_err();
+        assert!(err.to_string().contains("blank_lines_before_heading"));
+    }
+
+    #[test]
+    fn test_to_toml_string_round_trips() {
+        let config: Config = toml::from_str(
+            "delete = \"trash\"\nblank_lines_after_heading = [2, 1, 0]\ntoc = true\n\
+             toc_levels = \"2..3\"\nlink_title_style = \"single_quote\"\n\n\
+             [fence_aliases]\nsh = \"bash\"\n",
+        )
+        .unwrap();
+        let written = config.to_toml_string();
+        assert!(written.contains("[code_fence_language_aliases]\nsh = \"bash\"\n"));
+        assert_eq!(toml::from_str::<Config>(&written).unwrap(), config);
+
+        // With every option set, the file formats exactly like the options
+        let options = config.format_options();
+        let all = Config::from(&options);
+        assert_eq!(all.fix_bare_urls, Some(false));
+        assert_eq!(all.list_indent, None);
+        let written = all.to_toml_string();
+        let read: Config = toml::from_str(&written).unwrap();
+        assert_eq!(read, all);
+        assert_eq!(read.format_options().formatter, options.formatter);
+    }
+
+    #[test]
+    fn test_lint_table() {
+        let base: Config = toml::from_str(
+            "[lint]\nallow-multiple-h1 = true\nallow_skipped_heading_levels = true\n",
+        )
+        .unwrap();
+        let override_: Config = toml::from_str(
+            "fix_bare_urls = true\n\n[lint]\nallow_skipped_heading_levels = false\n",
+        )
+        .unwrap();
+        let merged = Config::merge(base, override_);
+        assert_eq!(
+            merged.lint,
+            Some(LintOptions {
+                allow_multiple_h1: Some(true),
+                allow_first_heading_not_h1: None,
+                allow_skipped_heading_levels: Some(false),
+            })
+        );
+        assert!(
+            merged.to_toml_string().ends_with(
+                "[lint]\nallow_multiple_h1 = true\nallow_skipped_heading_levels = false\n"
+            )
+        );
+
+        let lint = merged.lint_config();
+        assert_eq!(lint.multiple_h1, None);
+        assert!(lint.first_heading_level.is_some());
+        assert!(lint.heading_increment.is_some());
+        assert!(lint.formatter.fix_bare_urls);
+
+        let err = toml::from_str::<Config>("[lint]\nallow_h2 = true\n").unwrap_err();
+        assert!(err.to_string().contains("allow_h2"));
+    }
+
+    #[test]
+    fn test_invalid_config_is_an_error() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let path = temp.path().join(CONFIG_FILE);
+        fs::write(&path, "max_blank_lines = 2\n").unwrap();
+
+        let err = Config::from_file(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("max_blank_lines"));
+    }
+}
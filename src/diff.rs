@@ -0,0 +1,565 @@
+//! Unified diff support for restricting formatting to changed line ranges and
+//! previewing formatter edits.
+//!
+//! This module reads the output of `git diff` (or any unified diff) and extracts,
+//! for each file, the line ranges of the new version that were touched by a hunk.
+//! It can also render the formatter's line edits, or the difference between a
+//! file and its formatted content, as unified diff hunks.
+
+use crate::process_md::LineEdit;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+/// The changed line ranges of a single file in a unified diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffFile {
+    /// Path of the new version of the file, with the `b/` prefix stripped.
+    pub path: PathBuf,
+    /// 1-based, inclusive line ranges of the new file covered by hunks.
+    pub ranges: Vec<RangeInclusive<usize>>,
+}
+
+/// Parse a unified diff into the changed line ranges of each file.
+///
+/// Files deleted by the diff (`+++ /dev/null`) and pure deletion hunks (which have no
+/// lines in the new file) are skipped. A leading `b/` on the new path, as produced by
+/// `git diff`, is stripped.
+///
+/// # Arguments
+///
+/// * `diff` - The unified diff text
+///
+/// # Returns
+///
+/// One `DiffFile` per file that has at least one hunk with new lines, in diff order.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::diff::parse_unified_diff;
+/// use std::path::Path;
+///
+/// let diff = "--- a/README.md\n+++ b/README.md\n@@ -10,2 +10,4 @@\n";
+/// let files = parse_unified_diff(diff);
+/// assert_eq!(files[0].path, Path::new("README.md"));
+/// assert_eq!(files[0].ranges, vec![10..=13]);
+/// ```
+pub fn parse_unified_diff(diff: &str) -> Vec<DiffFile> {
+    let mut files: Vec<DiffFile> = Vec::new();
+    let mut current: Option<DiffFile> = None;
+
+    for line in diff.lines() {
+        if let Some(new_path) = line.strip_prefix("+++ ") {
+            if let Some(file) = current.take().filter(|f| !f.ranges.is_empty()) {
+                files.push(file);
+            }
+            // Drop a trailing tab-separated timestamp, as written by `diff -u`
+            let new_path = new_path.split('\t').next().unwrap_or(new_path).trim_end();
+            if new_path != "/dev/null" {
+                let new_path = new_path.strip_prefix("b/").unwrap_or(new_path);
+                current = Some(DiffFile {
+                    path: PathBuf::from(new_path),
+                    ranges: Vec::new(),
+                });
+            }
+        } else if line.starts_with("@@ ") {
+            if let (Some(file), Some(range)) = (current.as_mut(), parse_hunk_header(line)) {
+                file.ranges.push(range);
+            }
+        }
+    }
+
+    if let Some(file) = current.filter(|f| !f.ranges.is_empty()) {
+        files.push(file);
+    }
+
+    files
+}
+
+/// Parse the new-file range from a hunk header like `@@ -1,3 +4,5 @@ context`.
+fn parse_hunk_header(line: &str) -> Option<RangeInclusive<usize>> {
+    let new_range = line
+        .split_whitespace()
+        .find_map(|part| part.strip_prefix('+'))?;
+    let (start, count) = match new_range.split_once(',') {
+        Some((start, count)) => (start.parse::<usize>().ok()?, count.parse::<usize>().ok()?),
+        None => (new_range.parse::<usize>().ok()?, 1),
+    };
+
+    if count == 0 {
+        return None;
+    }
+
+    Some(start..=start + count - 1)
+}
+
+/// One line of a rendered diff.
+enum DiffLine<'a> {
+    Keep(&'a str),
+    Remove(&'a str),
+    Insert(&'a str),
+}
+
+/// The largest number of removed and inserted lines [`render_diff`] looks for a
+/// shortest diff with; beyond that, the rest of the changed region is shown as
+/// removed and inserted as a whole, which keeps time and memory bounded.
+const MAX_EDIT_DISTANCE: usize = 1000;
+
+/// Render line edits (as produced by [`blank_line_edits`](crate::process_md::blank_line_edits))
+/// as unified diff hunks.
+///
+/// # Arguments
+///
+/// * `content` - The original content the edits apply to
+/// * `edits` - The edits, sorted by line index
+/// * `context` - Number of unchanged lines to show around each change
+/// * `max_hunks` - Maximum number of hunks to render, or `None` for all of them
+///
+/// # Returns
+///
+/// The hunks, each starting with an `@@ -a,b +c,d @@` header. If hunks were left out
+/// because of `max_hunks`, a final line says how many.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::diff::render_line_edits;
+/// use mdfmt::process_md::blank_line_edits;
+///
+/// let content = "Text\n# Heading\n";
+/// let hunks = render_line_edits(content, &blank_line_edits(content), 1, None);
+/// assert_eq!(hunks, "@@ -1,2 +1,3 @@\n Text\n+\n # Heading\n");
+/// ```
+pub fn render_line_edits(
+    content: &str,
+    edits: &[LineEdit],
+    context: usize,
+    max_hunks: Option<usize>,
+) -> String {
+    let mut lines = Vec::new();
+    let mut pending = edits.iter().peekable();
+
+    for (i, line) in content.lines().enumerate() {
+        let mut removed = false;
+        while let Some(edit) = pending.next_if(|edit| edit.line() == i) {
+            match edit {
+                LineEdit::InsertBlankBefore(_) => lines.push(DiffLine::Insert("")),
+                LineEdit::Remove(_) => removed = true,
+            }
+        }
+        lines.push(if removed {
+            DiffLine::Remove(line)
+        } else {
+            DiffLine::Keep(line)
+        });
+    }
+    for edit in pending {
+        if let LineEdit::InsertBlankBefore(_) = edit {
+            lines.push(DiffLine::Insert(""));
+        }
+    }
+
+    render_hunks(&lines, context, max_hunks)
+}
+
+/// Render the difference between `old` and `new` as unified diff hunks, e.g. to show
+/// how formatting changes a file.
+///
+/// Lines that occur exactly once in both contents are matched first, as in
+/// `git diff --patience`, so a text line is never shown as moved just to line up
+/// the blank lines around it. Lines are compared with their line endings, so a
+/// line whose ending changed is shown as removed and inserted again.
+///
+/// # Arguments
+///
+/// * `old` - The original content
+/// * `new` - The changed content
+/// * `context` - Number of unchanged lines to show around each change
+/// * `max_hunks` - Maximum number of hunks to render, or `None` for all of them
+///
+/// # Returns
+///
+/// The hunks, like [`render_line_edits`], or an empty string if the contents are
+/// the same.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::diff::render_diff;
+///
+/// let hunks = render_diff("# Title\nSee https://a.com\n", "# Title\n\nSee <https://a.com>\n", 1, None);
+/// assert_eq!(hunks, "@@ -1,2 +1,3 @@\n # Title\n-See https://a.com\n+\n+See <https://a.com>\n");
+/// ```
+pub fn render_diff(old: &str, new: &str, context: usize, max_hunks: Option<usize>) -> String {
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let mut lines = Vec::new();
+    diff_lines(&old, &new, &mut lines);
+    for line in &mut lines {
+        let (DiffLine::Keep(text) | DiffLine::Remove(text) | DiffLine::Insert(text)) = line;
+        *text = text.trim_end_matches(['\n', '\r']);
+    }
+
+    render_hunks(&lines, context, max_hunks)
+}
+
+/// Append a diff from `old` to `new` to `lines`: the longest run of lines unique
+/// to both is kept, and the parts between them are diffed with [`myers_diff`].
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str], lines: &mut Vec<DiffLine<'a>>) {
+    let (mut i, mut j) = (0, 0);
+    for (anchor_i, anchor_j) in unique_matches(old, new) {
+        myers_diff(&old[i..anchor_i], &new[j..anchor_j], lines);
+        lines.push(DiffLine::Keep(old[anchor_i]));
+        (i, j) = (anchor_i + 1, anchor_j + 1);
+    }
+    myers_diff(&old[i..], &new[j..], lines);
+}
+
+/// The positions in `old` and `new` of the longest increasing run of lines that
+/// occur exactly once in each, found by patience sorting.
+fn unique_matches(old: &[&str], new: &[&str]) -> Vec<(usize, usize)> {
+    // How often each line occurs in `old` and `new`, and where it last did
+    let mut counts: HashMap<&str, (usize, usize, usize, usize)> = HashMap::new();
+    for (i, &line) in old.iter().enumerate() {
+        let entry = counts.entry(line).or_default();
+        entry.0 += 1;
+        entry.2 = i;
+    }
+    for (j, &line) in new.iter().enumerate() {
+        if let Some(entry) = counts.get_mut(line) {
+            entry.1 += 1;
+            entry.3 = j;
+        }
+    }
+    let mut pairs: Vec<(usize, usize)> = counts
+        .into_values()
+        .filter(|&(in_old, in_new, _, _)| in_old == 1 && in_new == 1)
+        .map(|(_, _, i, j)| (i, j))
+        .collect();
+    pairs.sort_unstable();
+
+    // The top of each pile, and the pair below each pair in the pile to its left
+    let mut piles: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = Vec::with_capacity(pairs.len());
+    for (k, &(_, j)) in pairs.iter().enumerate() {
+        let pile = piles.partition_point(|&top| pairs[top].1 < j);
+        previous.push(pile.checked_sub(1).map(|left| piles[left]));
+        if pile == piles.len() {
+            piles.push(k);
+        } else {
+            piles[pile] = k;
+        }
+    }
+    let mut matches = Vec::with_capacity(piles.len());
+    let mut next = piles.last().copied();
+    while let Some(k) = next {
+        matches.push(pairs[k]);
+        next = previous[k];
+    }
+    matches.reverse();
+    matches
+}
+
+/// Append a shortest diff from `old` to `new` to `lines`, found with Myers'
+/// algorithm after keeping the lines both start and end with, or a diff that
+/// removes and inserts everything in between if it needs more than
+/// [`MAX_EDIT_DISTANCE`] changes.
+fn myers_diff<'a>(old: &[&'a str], new: &[&'a str], lines: &mut Vec<DiffLine<'a>>) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    lines.extend(old[..prefix].iter().map(|&line| DiffLine::Keep(line)));
+    shortest_diff(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+        lines,
+    );
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|&line| DiffLine::Keep(line)),
+    );
+}
+
+/// Append a shortest diff from `old` to `new` to `lines` (see [`myers_diff`]).
+fn shortest_diff<'a>(old: &[&'a str], new: &[&'a str], lines: &mut Vec<DiffLine<'a>>) {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let offset = n + m + 1;
+    // The furthest `x` reached on each diagonal `k = x - y`, at index `k + offset`
+    let mut v = vec![0isize; (2 * offset + 1) as usize];
+    // The reached `x` of diagonals `-d..=d` after each round `d`
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=(n + m).min(MAX_EDIT_DISTANCE as isize) {
+        for k in (-d..=d).step_by(2) {
+            let at = |k: isize| v[(k + offset) as usize];
+            let mut x = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                at(k + 1)
+            } else {
+                at(k - 1) + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(k + offset) as usize] = x;
+            if x >= n && y >= m {
+                trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+                backtrack(old, new, &trace, lines);
+                return;
+            }
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+    }
+
+    lines.extend(old.iter().map(|&line| DiffLine::Remove(line)));
+    lines.extend(new.iter().map(|&line| DiffLine::Insert(line)));
+}
+
+/// Append the diff that the rounds in `trace` found, from the end back to the
+/// start, to `lines`.
+fn backtrack<'a>(
+    old: &[&'a str],
+    new: &[&'a str],
+    trace: &[Vec<isize>],
+    lines: &mut Vec<DiffLine<'a>>,
+) {
+    let mut reversed = Vec::new();
+    let (mut x, mut y) = (old.len() as isize, new.len() as isize);
+    for d in (1..trace.len() as isize).rev() {
+        let at = |k: isize| trace[d as usize - 1][(k + d - 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let (prev_x, prev_y) = (at(prev_k), at(prev_k) - prev_k);
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            reversed.push(DiffLine::Keep(old[x as usize]));
+        }
+        if x == prev_x {
+            reversed.push(DiffLine::Insert(new[prev_y as usize]));
+        } else {
+            reversed.push(DiffLine::Remove(old[prev_x as usize]));
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    while x > 0 {
+        x -= 1;
+        reversed.push(DiffLine::Keep(old[x as usize]));
+    }
+    lines.extend(reversed.into_iter().rev());
+}
+
+/// Render `lines` as unified diff hunks with `context` unchanged lines around each
+/// change, showing at most `max_hunks` of them.
+fn render_hunks(lines: &[DiffLine], context: usize, max_hunks: Option<usize>) -> String {
+    // Group changed lines into hunks, merging hunks whose context overlaps
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (k, line) in lines.iter().enumerate() {
+        if matches!(line, DiffLine::Keep(_)) {
+            continue;
+        }
+        let start = k.saturating_sub(context);
+        let end = (k + 1 + context).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let total_hunks = hunks.len();
+    let shown_hunks = max_hunks.map_or(total_hunks, |max| max.min(total_hunks));
+    let mut output = String::new();
+    let mut old_line = 0;
+    let mut new_line = 0;
+    let mut position = 0;
+
+    for &(start, end) in &hunks[..shown_hunks] {
+        // Advance the line counters to the start of the hunk
+        for line in &lines[position..start] {
+            match line {
+                DiffLine::Keep(_) => {
+                    old_line += 1;
+                    new_line += 1;
+                }
+                DiffLine::Remove(_) => old_line += 1,
+                DiffLine::Insert(_) => new_line += 1,
+            }
+        }
+
+        let hunk = &lines[start..end];
+        let old_count = hunk
+            .iter()
+            .filter(|l| !matches!(l, DiffLine::Insert(_)))
+            .count();
+        let new_count = hunk
+            .iter()
+            .filter(|l| !matches!(l, DiffLine::Remove(_)))
+            .count();
+        let old_start = if old_count == 0 {
+            old_line
+        } else {
+            old_line + 1
+        };
+        let new_start = if new_count == 0 {
+            new_line
+        } else {
+            new_line + 1
+        };
+        output.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_count, new_start, new_count
+        ));
+
+        for line in hunk {
+            match line {
+                DiffLine::Keep(text) => output.push_str(&format!(" {}\n", text)),
+                DiffLine::Remove(text) => output.push_str(&format!("-{}\n", text)),
+                DiffLine::Insert(text) => output.push_str(&format!("+{}\n", text)),
+            }
+        }
+
+        old_line += old_count;
+        new_line += new_count;
+        position = end;
+    }
+
+    if shown_hunks < total_hunks {
+        let remaining = total_hunks - shown_hunks;
+        output.push_str(&format!(
+            "... {} more hunk{}\n",
+            remaining,
+            if remaining == 1 { "" } else { "s" }
+        ));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_unified_diff_multiple_files() {
+        let diff = "\
+diff --git a/docs/a.md b/docs/a.md
+--- a/docs/a.md
++++ b/docs/a.md
+@@ -1,3 +1,4 @@
+ # A
++new line
+@@ -20 +21 @@ context
+-old
++new
+diff --git a/gone.md b/gone.md
+--- a/gone.md
++++ /dev/null
+@@ -1,2 +0,0 @@
+-bye
+diff --git a/b.md b/b.md
+--- a/b.md
++++ b/b.md
+@@ -5,2 +4,0 @@
+-removed
+";
+        let files = parse_unified_diff(diff);
+        assert_eq!(
+            files,
+            vec![DiffFile {
+                path: PathBuf::from("docs/a.md"),
+                ranges: vec![1..=4, 21..=21],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_render_line_edits_multiple_hunks() {
+        let content = "a\n\n\nb\nc\nd\ne\nf\n# H\ng\n";
+        let edits = crate::process_md::blank_line_edits(content);
+        let expected = "\
+@@ -2,3 +2,2 @@
+ 
+-
+ b
+@@ -8,3 +7,5 @@
+ f
++
+ # H
++
+ g
+";
+        assert_eq!(render_line_edits(content, &edits, 1, None), expected);
+    }
+
+    #[test]
+    fn test_render_line_edits_max_hunks() {
+        let content = "a\n\n\nb\nc\nd\ne\nf\n# H\ng\n";
+        let edits = crate::process_md::blank_line_edits(content);
+        let expected = "@@ -2,3 +2,2 @@\n \n-\n b\n... 1 more hunk\n";
+        assert_eq!(render_line_edits(content, &edits, 1, Some(1)), expected);
+        assert_eq!(render_line_edits("a\n", &[], 1, None), "");
+    }
+
+    #[test]
+    fn test_render_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\n";
+        let expected = "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -7,1 +7,2 @@\n g\n+h\n";
+        assert_eq!(render_diff(old, new, 1, None), expected);
+        assert_eq!(render_diff(old, old, 1, None), "");
+        // Only changed line endings are shown too
+        assert_eq!(
+            render_diff("a\r\nb\r\n", "a\r\nb\n", 0, None),
+            "@@ -2,1 +2,1 @@\n-b\n+b\n"
+        );
+        // Text lines are kept in place rather than blank lines
+        assert_eq!(
+            render_diff(
+                "# Title\nText\n\n\n\nMore\n",
+                "# Title\n\nText\n\nMore\n",
+                0,
+                None
+            ),
+            "@@ -1,0 +2,1 @@\n+\n@@ -4,2 +4,0 @@\n-\n-\n"
+        );
+        // Moved lines are removed in one place and inserted in the other
+        assert_eq!(
+            render_diff("x\ny\nz\n", "y\nz\nx\n", 0, None),
+            "@@ -1,1 +0,0 @@\n-x\n@@ -3,0 +3,1 @@\n+x\n"
+        );
+    }
+
+    #[test]
+    fn test_render_diff_beyond_max_edit_distance() {
+        let old = "a\n".repeat(MAX_EDIT_DISTANCE);
+        let new = "b\n".repeat(MAX_EDIT_DISTANCE);
+        assert_eq!(
+            render_diff(&old, &new, 0, None),
+            format!(
+                "@@ -1,{0} +1,{0} @@\n{1}{2}",
+                MAX_EDIT_DISTANCE,
+                "-a\n".repeat(MAX_EDIT_DISTANCE),
+                "+b\n".repeat(MAX_EDIT_DISTANCE)
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_unified_diff_plain_paths() {
+        let diff = "--- old.md\t2024-01-01\n+++ new.md\t2024-01-02\n@@ -1 +1,2 @@\n";
+        let files = parse_unified_diff(diff);
+        assert_eq!(files[0].path, PathBuf::from("new.md"));
+        assert_eq!(files[0].ranges, vec![1..=2]);
+    }
+}
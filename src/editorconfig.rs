@@ -0,0 +1,170 @@
+//! EditorConfig support.
+//!
+//! The `.editorconfig` properties that apply to a file are mapped onto the matching
+//! formatting options, so mdfmt writes files the way editors in the project do:
+//!
+//! | EditorConfig property      | mdfmt option               |
+//! |----------------------------|----------------------------|
+//! | `end_of_line`              | `line_ending`              |
+//! | `insert_final_newline`     | `insert_final_newline`     |
+//! | `trim_trailing_whitespace` | `trim_trailing_whitespace` |
+//! | `tab_width`, `indent_size` | `tab_width`                |
+//! | `indent_style = space`     | `tabs_to_spaces`           |
+//!
+//! Properties are looked up per file, so files matched by different sections get
+//! different options. `.mdfmt.toml` files and command line flags take precedence.
+
+use crate::config::Config;
+use crate::process_md::LineEnding;
+use ec4rs::property::{EndOfLine, FinalNewline, IndentStyle, TabWidth, TrimTrailingWs};
+use std::io;
+use std::path::Path;
+
+/// Read the EditorConfig properties that apply to the file at `path`.
+///
+/// `.editorconfig` files are read from the directory of the file upward, until one
+/// of them sets `root = true`. Properties mdfmt has no equivalent for, including
+/// `end_of_line = cr`, are ignored.
+///
+/// `tab_width`, or `indent_size` where `tab_width` is not set, gives the width of
+/// tabs, from 1 to 16 columns. `indent_style = space` expands leading tabs to spaces
+/// with that width, or with 4 columns where neither is set.
+///
+/// # Arguments
+///
+/// * `path` - The file being formatted; it does not have to exist
+///
+/// # Returns
+///
+/// A [`Config`] with only `line_ending`, `insert_final_newline`,
+/// `trim_trailing_whitespace`, `tab_width`, and `tabs_to_spaces` set, and only where
+/// a section sets them.
+///
+/// # Errors
+///
+/// Returns an `io::Error` of kind `InvalidData` if one of the `.editorconfig` files
+/// cannot be read or parsed.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::editorconfig;
+/// use std::path::Path;
+///
+/// let config = editorconfig::resolve(Path::new("docs/guide.md"))?;
+/// let options = config.format_options();
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn resolve(path: &Path) -> io::Result<Config> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut properties = ec4rs::properties_of(&path).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid .editorconfig: {}", e),
+        )
+    })?;
+    // Fills in `tab_width` from `indent_size`
+    properties.use_fallbacks();
+    let tab_width = match properties.get::<TabWidth>() {
+        Ok(TabWidth::Value(width)) if (1..=16).contains(&width) => Some(width),
+        _ => None,
+    };
+    Ok(Config {
+        line_ending: match properties.get::<EndOfLine>() {
+            Ok(EndOfLine::Lf) => Some(LineEnding::Lf),
+            Ok(EndOfLine::CrLf) => Some(LineEnding::Crlf),
+            Ok(EndOfLine::Cr) | Err(_) => None,
+        },
+        insert_final_newline: match properties.get::<FinalNewline>() {
+            Ok(FinalNewline::Value(insert)) => Some(insert),
+            Err(_) => None,
+        },
+        trim_trailing_whitespace: match properties.get::<TrimTrailingWs>() {
+            Ok(TrimTrailingWs::Value(trim)) => Some(trim),
+            Err(_) => None,
+        },
+        tab_width,
+        tabs_to_spaces: match properties.get::<IndentStyle>() {
+            Ok(IndentStyle::Spaces) => Some(tab_width.unwrap_or(4)),
+            Ok(IndentStyle::Tabs) | Err(_) => None,
+        },
+        ..Config::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    #[test]
+    fn test_resolve_per_section() {
+        let temp = Builder::new()
+            .prefix("mdfmt_test_editorconfig_")
+            .tempdir()
+            .unwrap();
+        let root = temp.path();
+        fs::create_dir(root.join("notes")).unwrap();
+        fs::write(
+            root.join(".editorconfig"),
+            "root = true\n\n[*]\nend_of_line = lf\n\n[*.md]\nend_of_line = crlf\ninsert_final_newline = true\ntrim_trailing_whitespace = true\n\n[CHANGELOG.md]\ntrim_trailing_whitespace = false\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("notes/.editorconfig"),
+            "[*.md]\nend_of_line = cr\ninsert_final_newline = unset\n",
+        )
+        .unwrap();
+
+        let readme = resolve(&root.join("README.md")).unwrap();
+        assert_eq!(readme.line_ending, Some(LineEnding::Crlf));
+        assert_eq!(readme.insert_final_newline, Some(true));
+        assert_eq!(readme.trim_trailing_whitespace, Some(true));
+        assert_eq!(readme.fix_bare_urls, None);
+
+        let changelog = resolve(&root.join("CHANGELOG.md")).unwrap();
+        assert_eq!(changelog.trim_trailing_whitespace, Some(false));
+
+        // Nearer files override, and values mdfmt cannot use leave the option unset
+        let note = resolve(&root.join("notes/todo.md")).unwrap();
+        assert_eq!(note.line_ending, None);
+        assert_eq!(note.insert_final_newline, None);
+        assert_eq!(note.trim_trailing_whitespace, Some(true));
+
+        let text = resolve(&root.join("notes.txt")).unwrap();
+        assert_eq!(text.line_ending, Some(LineEnding::Lf));
+        assert_eq!(text.trim_trailing_whitespace, None);
+    }
+
+    #[test]
+    fn test_resolve_indentation() {
+        let temp = Builder::new()
+            .prefix("mdfmt_test_editorconfig_")
+            .tempdir()
+            .unwrap();
+        let root = temp.path();
+        fs::write(
+            root.join(".editorconfig"),
+            "root = true\n\n[*]\nindent_style = space\n\n[*.md]\nindent_size = 2\n\n[tabs.md]\nindent_style = tab\ntab_width = 8\n\n[wide.md]\nindent_size = 40\n",
+        )
+        .unwrap();
+
+        let readme = resolve(&root.join("README.md")).unwrap();
+        assert_eq!(readme.tab_width, Some(2));
+        assert_eq!(readme.tabs_to_spaces, Some(2));
+
+        let tabs = resolve(&root.join("tabs.md")).unwrap();
+        assert_eq!(tabs.tab_width, Some(8));
+        assert_eq!(tabs.tabs_to_spaces, None);
+
+        // Widths mdfmt does not accept fall back to 4 columns
+        let wide = resolve(&root.join("wide.md")).unwrap();
+        assert_eq!(wide.tab_width, None);
+        assert_eq!(wide.tabs_to_spaces, Some(4));
+
+        let text = resolve(&root.join("notes.txt")).unwrap();
+        assert_eq!(text.tab_width, None);
+        assert_eq!(text.tabs_to_spaces, Some(4));
+    }
+}
@@ -1,10 +1,18 @@
 //! File discovery functionality for finding markdown files in directories.
 //!
 //! This module provides utilities to recursively search for markdown files
-//! in directory structures.
+//! in directory structures and to filter them with include/exclude patterns.
 
-use glob::glob;
-use std::path::{Path, PathBuf};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::{debug, trace, warn};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::time::SystemTime;
 
 /// Find all markdown files recursively in the given directory.
 ///
@@ -12,6 +20,13 @@ use std::path::{Path, PathBuf};
 /// directory and its subdirectories. It only returns regular files, excluding
 /// directories that might end with `.md`.
 ///
+/// The directories are walked rather than matched with a glob pattern, so any path
+/// the platform accepts works, including paths with pattern characters and, on
+/// Windows, verbatim (`\\?\C:\docs`) and UNC (`\\server\share\docs`) paths.
+/// The returned paths start with `search_dir` and use native separators below it.
+/// Directories that cannot be read are skipped with a warning logged through the
+/// [`log`] facade.
+///
 /// # Arguments
 ///
 /// * `search_dir` - The directory to search for markdown files
@@ -31,67 +46,554 @@ use std::path::{Path, PathBuf};
 /// println!("Found {} markdown files", md_files.len());
 /// ```
 ///
-/// # Panics
-///
-/// Panics if the glob pattern is invalid (which should never happen with our static pattern).
 pub fn find_md_files(search_dir: &Path) -> Vec<PathBuf> {
+    find_md_files_filtered(search_dir, &FileFilter::default())
+}
+
+/// Find the markdown files in the given directory that pass `filter`.
+///
+/// This finds the same files as [`find_md_files`] followed by [`filter_files`], but
+/// checks each directory before descending into it: a directory excluded by the
+/// filter (see [`FileFilter::excludes_dir`]) is skipped without being read, so
+/// excluding `node_modules` or `vendor` does not cost a walk through them.
+///
+/// # Arguments
+///
+/// * `search_dir` - The directory to search for markdown files
+/// * `filter` - The include/exclude patterns, relative to `search_dir`
+///
+/// # Returns
+///
+/// A vector of `PathBuf` containing the markdown files that pass the filter.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::find_md_files::{FileFilter, find_md_files_filtered};
+/// use std::path::Path;
+///
+/// let filter = FileFilter::new(&[], &["node_modules".to_string()])?;
+/// let md_files = find_md_files_filtered(Path::new("."), &filter);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn find_md_files_filtered(search_dir: &Path, filter: &FileFilter) -> Vec<PathBuf> {
     let mut files = Vec::new();
-    let pattern = format!("{}/**/*.md", search_dir.display());
+    let mut pending = vec![search_dir.to_path_buf()];
+    let relative = |path: &'_ Path| path.strip_prefix(search_dir).unwrap_or(path).to_path_buf();
 
-    match glob(&pattern) {
-        Ok(entries) => {
-            for entry in entries {
-                match entry {
-                    Ok(path) => {
-                        // Only include regular files, skip directories that might end with .md
-                        if path.is_file() {
-                            files.push(path);
+    while let Some(dir) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            // A missing search directory has no files, like one removed during the walk
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                warn!("Error reading directory '{}': {}", dir.display(), e);
+                continue;
+            }
+        };
+        for entry in entries {
+            match entry {
+                Ok(entry) => {
+                    let path = entry.path();
+                    if path.is_dir() {
+                        if !filter.is_empty() && filter.excludes_dir(&relative(&path)) {
+                            debug!("skipping excluded directory {}", path.display());
+                            continue;
                         }
-                    }
-                    Err(e) => {
-                        eprintln!("Warning: Error reading path in glob: {}", e);
+                        pending.push(path);
+                    } else if path.is_file()
+                        && entry.file_name().as_encoded_bytes().ends_with(b".md")
+                        && (filter.is_empty() || filter.is_match(&relative(&path)))
+                    {
+                        // Only include regular files, skip directories that might end with .md
+                        trace!("found {}", path.display());
+                        files.push(path);
                     }
                 }
+                Err(e) => warn!("Error reading directory '{}': {}", dir.display(), e),
             }
         }
-        Err(e) => {
-            eprintln!("Error: Failed to create glob pattern '{}': {}", pattern, e);
-        }
     }
 
     // Sort files for consistent output
     files.sort();
+    debug!(
+        "{}: found {} markdown files",
+        search_dir.display(),
+        files.len()
+    );
     files
 }
 
+/// The path to report for `path`: relative to `base` when it is inside it, with
+/// native separators, and on Windows without a verbatim `\\?\` prefix.
+///
+/// Paths outside `base` stay absolute, and `base` itself becomes `.`. Verbatim
+/// prefixes, as returned by [`Path::canonicalize`] on Windows, are turned into the
+/// drive (`C:`) or UNC (`\\server\share`) prefix they stand for. `.` components
+/// are dropped.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::find_md_files::display_path;
+/// use std::path::Path;
+///
+/// let base = Path::new("/home/ann/project");
+/// assert_eq!(
+///     display_path(Path::new("/home/ann/project/docs/./guide.md"), base),
+///     Path::new("docs/guide.md")
+/// );
+/// assert_eq!(display_path(Path::new("/tmp/notes.md"), base), Path::new("/tmp/notes.md"));
+/// assert_eq!(display_path(Path::new("./guide.md"), base), Path::new("guide.md"));
+/// ```
+pub fn display_path(path: &Path, base: &Path) -> PathBuf {
+    let path = native_path(path);
+    let path = match path.strip_prefix(native_path(base)) {
+        Ok(relative) => relative.to_path_buf(),
+        _ => path,
+    };
+    if path.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        path
+    }
+}
+
+/// Rebuild `path` from its components, so it has native separators and no `.`
+/// components, and replace a verbatim prefix with the prefix it stands for.
+fn native_path(path: &Path) -> PathBuf {
+    let mut native = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::VerbatimDisk(disk) => native.push(format!("{}:", char::from(disk))),
+                Prefix::VerbatimUNC(server, share) => {
+                    let mut unc = OsString::from(r"\\");
+                    unc.push(server);
+                    unc.push(r"\");
+                    unc.push(share);
+                    native.push(unc);
+                }
+                _ => native.push(prefix.as_os_str()),
+            },
+            Component::CurDir => {}
+            component => native.push(component),
+        }
+    }
+    native
+}
+
+/// The order in which [`find_md_files_with`] returns files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TraversalOrder {
+    /// Shallowest files first: the files of the search directory, then those one
+    /// level down, and so on, in path order within each level.
+    BreadthFirst,
+    /// Path order, where every directory comes before its subdirectories; this is
+    /// the order of [`find_md_files`].
+    #[default]
+    DepthFirstPreorder,
+    /// Deepest files first, in path order within each level, so files in
+    /// subdirectories always come before the files of their parent directories.
+    DepthFirstPostorder,
+}
+
+/// Options for [`find_md_files_with`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FindOptions {
+    /// The order of the returned files.
+    pub order: TraversalOrder,
+}
+
+/// Find all markdown files recursively in the given directory, in the order given
+/// by `options`.
+///
+/// This finds the same files as [`find_md_files`]. With
+/// [`TraversalOrder::DepthFirstPostorder`], a `README.md` that summarizes the
+/// `README.md` files of its subdirectories comes after all of them.
+///
+/// # Arguments
+///
+/// * `search_dir` - The directory to search for markdown files
+/// * `options` - How to order the files
+///
+/// # Returns
+///
+/// A vector of `PathBuf` containing all found markdown files.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::find_md_files::{FindOptions, TraversalOrder, find_md_files_with};
+/// use std::path::Path;
+///
+/// let options = FindOptions {
+///     order: TraversalOrder::DepthFirstPostorder,
+/// };
+/// // Children before parents, e.g. docs/api/README.md before docs/README.md
+/// let md_files = find_md_files_with(Path::new("."), &options);
+/// ```
+pub fn find_md_files_with(search_dir: &Path, options: &FindOptions) -> Vec<PathBuf> {
+    let mut files = find_md_files(search_dir);
+    // The sorts are stable, so each level stays in path order
+    match options.order {
+        TraversalOrder::BreadthFirst => {
+            sort_md_files_by(&mut files, |path| path.components().count())
+        }
+        TraversalOrder::DepthFirstPreorder => {}
+        TraversalOrder::DepthFirstPostorder => {
+            sort_md_files_by(&mut files, |path| Reverse(path.components().count()))
+        }
+    }
+    files
+}
+
+/// Find all markdown files recursively in the given directory, ordered by a sort key.
+///
+/// This finds the same files as [`find_md_files`], then sorts them by `key_fn`.
+/// The sort is stable, so files with equal keys stay in path order. Ready-made key
+/// functions are [`sort_key_name`], [`sort_key_path`], [`sort_key_modified`], and
+/// [`sort_key_size`].
+///
+/// # Arguments
+///
+/// * `search_dir` - The directory to search for markdown files
+/// * `key_fn` - Computes the sort key of each file; called once per file
+///
+/// # Returns
+///
+/// A vector of `PathBuf` containing all found markdown files, sorted by key.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::find_md_files::{find_md_files_sorted_by, sort_key_modified};
+/// use std::path::Path;
+///
+/// // Most recently modified files first
+/// let md_files = find_md_files_sorted_by(Path::new("."), sort_key_modified);
+/// ```
+pub fn find_md_files_sorted_by<K: Ord, F: Fn(&Path) -> K>(
+    search_dir: &Path,
+    key_fn: F,
+) -> Vec<PathBuf> {
+    let mut files = find_md_files(search_dir);
+    sort_md_files_by(&mut files, key_fn);
+    files
+}
+
+/// Sort files in place by a sort key, keeping files with equal keys in their order.
+///
+/// # Examples
+///
+/// ```rust
+/// use mdfmt::find_md_files::{sort_key_name, sort_md_files_by};
+/// use std::path::PathBuf;
+///
+/// let mut files = vec![PathBuf::from("b/a.md"), PathBuf::from("a/b.md")];
+/// sort_md_files_by(&mut files, sort_key_name);
+/// assert_eq!(files, vec![PathBuf::from("b/a.md"), PathBuf::from("a/b.md")]);
+/// ```
+pub fn sort_md_files_by<K: Ord, F: Fn(&Path) -> K>(files: &mut [PathBuf], key_fn: F) {
+    files.sort_by_cached_key(|path| key_fn(path));
+}
+
+/// Sort key for the file name only, ignoring the directory.
+pub fn sort_key_name(path: &Path) -> OsString {
+    path.file_name().map(OsString::from).unwrap_or_default()
+}
+
+/// Sort key for the full path; this is the order [`find_md_files`] returns.
+pub fn sort_key_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// Sort key for the modification time, newest first.
+///
+/// Files whose modification time cannot be read sort last.
+pub fn sort_key_modified(path: &Path) -> Reverse<Option<SystemTime>> {
+    Reverse(
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok(),
+    )
+}
+
+/// Sort key for the file size, largest first.
+///
+/// Files whose size cannot be read sort last.
+pub fn sort_key_size(path: &Path) -> Reverse<u64> {
+    Reverse(fs::metadata(path).map_or(0, |metadata| metadata.len()))
+}
+
+/// Check whether a path has the markdown (`.md`) extension.
+///
+/// # Examples
+///
+/// ```rust
+/// use mdfmt::find_md_files::is_md_file;
+/// use std::path::Path;
+///
+/// assert!(is_md_file(Path::new("README.md")));
+/// assert!(!is_md_file(Path::new("notes.txt")));
+/// ```
+pub fn is_md_file(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()) == Some("md")
+}
+
+/// Include and exclude patterns for narrowing down discovered files.
+///
+/// Patterns are globs matched against paths relative to the searched directory.
+/// A pattern without a `/` matches at any depth, like in `.gitignore`.
+///
+/// Precedence follows `.gitignore` semantics:
+///
+/// 1. If any include patterns are given, a file must match at least one of them.
+/// 2. Exclude patterns then apply in order, and the last one that matches decides.
+/// 3. An exclude pattern starting with `!` is negated: a file it matches is
+///    re-included, even if an earlier exclude pattern matched it.
+///
+/// Exclude patterns also match directories, and a pattern that matches a directory
+/// matches every file in it, so `node_modules` excludes everything below any
+/// `node_modules` directory.
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    /// Whether each exclude pattern (by index in `exclude`) is negated
+    negated: Vec<bool>,
+}
+
+impl FileFilter {
+    /// Build a filter from include and exclude patterns.
+    ///
+    /// # Arguments
+    ///
+    /// * `include` - Patterns a file must match; empty to include everything
+    /// * `exclude` - Patterns to exclude, where a leading `!` re-includes matches
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mdfmt::find_md_files::FileFilter;
+    /// use std::path::Path;
+    ///
+    /// let exclude = ["*.md".to_string(), "!CHANGELOG.md".to_string()];
+    /// let filter = FileFilter::new(&[], &exclude)?;
+    /// assert!(!filter.is_match(Path::new("docs/guide.md")));
+    /// assert!(filter.is_match(Path::new("CHANGELOG.md")));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` of kind `InvalidInput` if a pattern is not a valid glob.
+    pub fn new(include: &[String], exclude: &[String]) -> io::Result<Self> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in include {
+                builder.add(build_glob(pattern)?);
+            }
+            Some(build_set(builder)?)
+        };
+
+        let mut builder = GlobSetBuilder::new();
+        let mut negated = Vec::with_capacity(exclude.len());
+        for pattern in exclude {
+            let (pattern, negate) = match pattern.strip_prefix('!') {
+                Some(rest) => (rest, true),
+                None => (pattern.as_str(), false),
+            };
+            builder.add(build_glob(pattern)?);
+            negated.push(negate);
+        }
+
+        Ok(FileFilter {
+            include,
+            exclude: build_set(builder)?,
+            negated,
+        })
+    }
+
+    /// Whether a path relative to the searched directory passes the filter.
+    pub fn is_match(&self, relative: &Path) -> bool {
+        if let Some(include) = &self.include {
+            if !include.is_match(relative) {
+                return false;
+            }
+        }
+
+        // The last exclude pattern that matches the file or one of its directories wins
+        let last = relative
+            .ancestors()
+            .filter(|path| !path.as_os_str().is_empty())
+            .filter_map(|path| self.exclude.matches(path).into_iter().max())
+            .max();
+        match last {
+            Some(index) => self.negated[index],
+            None => true,
+        }
+    }
+
+    /// Whether a directory relative to the searched directory is excluded with
+    /// everything in it.
+    ///
+    /// That is the case when the last exclude pattern that matches the directory
+    /// is not negated, and no negated pattern comes after it, since that could
+    /// re-include files in the directory.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mdfmt::find_md_files::FileFilter;
+    /// use std::path::Path;
+    ///
+    /// let exclude = ["drafts".to_string(), "!drafts/keep.md".to_string(), "vendor".to_string()];
+    /// let filter = FileFilter::new(&[], &exclude)?;
+    /// assert!(filter.excludes_dir(Path::new("lib/vendor")));
+    /// assert!(!filter.is_match(Path::new("lib/vendor/README.md")));
+    /// // A later `!` pattern re-includes files in the directory, so it is still read
+    /// assert!(!filter.excludes_dir(Path::new("drafts")));
+    /// assert!(!filter.is_match(Path::new("drafts/todo.md")));
+    /// assert!(filter.is_match(Path::new("drafts/keep.md")));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn excludes_dir(&self, relative: &Path) -> bool {
+        self.exclude
+            .matches(relative)
+            .into_iter()
+            .max()
+            .is_some_and(|index| !self.negated[index..].contains(&true))
+    }
+
+    /// Whether the filter has no patterns at all.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_none() && self.negated.is_empty()
+    }
+}
+
+/// Keep only the files under `base` that pass `filter`.
+///
+/// # Arguments
+///
+/// * `files` - The discovered files, rooted at `base`
+/// * `base` - The searched directory that patterns are relative to
+/// * `filter` - The include/exclude patterns to apply
+///
+/// # Returns
+///
+/// The files that pass the filter, in their original order.
+pub fn filter_files(files: Vec<PathBuf>, base: &Path, filter: &FileFilter) -> Vec<PathBuf> {
+    if filter.is_empty() {
+        return files;
+    }
+
+    files
+        .into_iter()
+        .filter(|file| filter.is_match(file.strip_prefix(base).unwrap_or(file)))
+        .collect()
+}
+
+/// Group files that have exactly the same content.
+///
+/// Files are compared by the SHA-256 hash of their content, so every file is read
+/// once. Files that cannot be read are left out.
+///
+/// # Arguments
+///
+/// * `files` - The files to compare
+///
+/// # Returns
+///
+/// The groups of two or more files with the same content, each in the order of
+/// `files`, ordered by their first file.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::find_md_files::{find_duplicate_content, find_md_files};
+/// use std::path::Path;
+///
+/// for group in find_duplicate_content(&find_md_files(Path::new("docs"))) {
+///     println!("{} files have the same content as {}", group.len(), group[0].display());
+/// }
+/// ```
+pub fn find_duplicate_content(files: &[PathBuf]) -> Vec<Vec<PathBuf>> {
+    find_duplicate_content_by(files, |path| fs::read(path))
+}
+
+/// Group files whose content, as returned by `content`, is exactly the same.
+///
+/// This works like [`find_duplicate_content`], but compares what `content` returns
+/// for each file instead of the raw file content, e.g. the formatted content.
+/// Files for which `content` fails are left out.
+pub fn find_duplicate_content_by<F>(files: &[PathBuf], content: F) -> Vec<Vec<PathBuf>>
+where
+    F: Fn(&Path) -> io::Result<Vec<u8>>,
+{
+    let mut groups: HashMap<[u8; 32], Vec<PathBuf>> = HashMap::new();
+    // The hash of each distinct content, in the order it was first seen
+    let mut order = Vec::new();
+    for path in files {
+        let Ok(content) = content(path) else {
+            continue;
+        };
+        let hash: [u8; 32] = Sha256::digest(&content).into();
+        let group = groups.entry(hash).or_insert_with(|| {
+            order.push(hash);
+            Vec::new()
+        });
+        group.push(path.clone());
+    }
+
+    order
+        .iter()
+        .filter_map(|hash| groups.remove(hash))
+        .filter(|group| group.len() >= 2)
+        .collect()
+}
+
+/// Compile one glob, anchoring patterns without a `/` at any depth. A trailing `/`,
+/// as in `node_modules/`, is dropped, so the pattern matches the directory.
+fn build_glob(pattern: &str) -> io::Result<Glob> {
+    let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+    let pattern = pattern.strip_suffix('/').unwrap_or(pattern);
+    let pattern = if pattern.contains('/') {
+        pattern.to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+    Glob::new(&pattern).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn build_set(builder: GlobSetBuilder) -> io::Result<GlobSet> {
+    builder
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
     use std::fs;
+    use tempfile::Builder;
 
     #[test]
     fn test_find_md_files_empty_directory() {
-        // Use a temporary directory in the system temp location
-        let temp_dir = env::temp_dir().join("mdfmt_test_empty");
-        if temp_dir.exists() {
-            fs::remove_dir_all(&temp_dir).ok();
-        }
-        fs::create_dir_all(&temp_dir).unwrap();
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
 
-        let files = find_md_files(&temp_dir);
+        let files = find_md_files(temp_dir);
         assert!(files.is_empty());
-
-        // Cleanup
-        fs::remove_dir_all(&temp_dir).ok();
     }
 
     #[test]
     fn test_find_md_files_with_files() {
-        let temp_dir = env::temp_dir().join("mdfmt_test_with_files");
-        if temp_dir.exists() {
-            fs::remove_dir_all(&temp_dir).ok();
-        }
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
 
         // Create test files
         let subdir = temp_dir.join("subdir");
@@ -104,12 +606,263 @@ mod tests {
         fs::write(&txt_file, "Not markdown").unwrap();
         fs::write(subdir.join("nested.md"), "# Nested").unwrap();
 
-        let files = find_md_files(&temp_dir);
+        let files = find_md_files(temp_dir);
         assert_eq!(files.len(), 2);
         assert!(files.iter().any(|p| p.file_name().unwrap() == "test.md"));
         assert!(files.iter().any(|p| p.file_name().unwrap() == "nested.md"));
+    }
+
+    #[test]
+    fn test_find_md_files_in_directory_with_glob_characters() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let dir = temp.path().join("docs[v2] x");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("guide.md"), "# Guide").unwrap();
+        // Would match `docs[v2] x` as a pattern, but is not inside it
+        fs::create_dir(temp.path().join("docs2 x")).unwrap();
+        fs::write(temp.path().join("docs2 x").join("other.md"), "# Other").unwrap();
+
+        assert_eq!(find_md_files(&dir), [dir.join("guide.md")]);
+    }
+
+    #[test]
+    fn test_display_path() {
+        let base = Path::new("/work/repo");
+        assert_eq!(display_path(base, base), Path::new("."));
+        assert_eq!(
+            display_path(Path::new("/work/repo2/a.md"), base),
+            Path::new("/work/repo2/a.md")
+        );
+        assert_eq!(
+            display_path(Path::new("docs/../a.md"), base),
+            Path::new("docs/../a.md")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_display_path_on_windows() {
+        let base = Path::new(r"C:\work\repo");
+        for (path, expected) in [
+            (r"\\?\C:\work\repo\docs\a.md", r"docs\a.md"),
+            (r"C:/work/repo/docs/a.md", r"docs\a.md"),
+            (r"\\?\D:\notes\b.md", r"D:\notes\b.md"),
+            (r"\\?\UNC\server\share\c.md", r"\\server\share\c.md"),
+            (r"docs/sub dir/d.md", r"docs\sub dir\d.md"),
+        ] {
+            assert_eq!(display_path(Path::new(path), base), Path::new(expected));
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_find_md_files_with_windows_verbatim_path() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let dir = temp.path().join("my docs");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("guide.md"), "# Guide").unwrap();
+
+        let verbatim = dir.canonicalize().unwrap();
+        assert!(verbatim.as_os_str().to_string_lossy().starts_with(r"\\?\"));
+        assert_eq!(find_md_files(&verbatim), [verbatim.join("guide.md")]);
+    }
+
+    #[test]
+    fn test_find_md_files_with_traversal_order() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
+        for file in [
+            "README.md",
+            "a/README.md",
+            "a/x/deep.md",
+            "b/README.md",
+            "z.md",
+        ] {
+            let path = temp_dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "# Test").unwrap();
+        }
+        let find = |order| {
+            let files = find_md_files_with(temp_dir, &FindOptions { order });
+            files
+                .iter()
+                .map(|path| {
+                    path.strip_prefix(temp_dir)
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            find(TraversalOrder::DepthFirstPreorder),
+            [
+                "README.md",
+                "a/README.md",
+                "a/x/deep.md",
+                "b/README.md",
+                "z.md"
+            ]
+        );
+        assert_eq!(
+            find(TraversalOrder::BreadthFirst),
+            [
+                "README.md",
+                "z.md",
+                "a/README.md",
+                "b/README.md",
+                "a/x/deep.md"
+            ]
+        );
+        assert_eq!(
+            find(TraversalOrder::DepthFirstPostorder),
+            [
+                "a/x/deep.md",
+                "a/README.md",
+                "b/README.md",
+                "README.md",
+                "z.md"
+            ]
+        );
+        assert_eq!(
+            FindOptions::default().order,
+            TraversalOrder::DepthFirstPreorder
+        );
+    }
+
+    #[test]
+    fn test_find_md_files_sorted_by() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
+        fs::create_dir_all(temp_dir.join("a")).unwrap();
+        fs::write(temp_dir.join("a/z.md"), "# Largest file").unwrap();
+        fs::write(temp_dir.join("b.md"), "# B").unwrap();
+        fs::write(temp_dir.join("c.md"), "# C file").unwrap();
+
+        let names = |files: Vec<PathBuf>| -> Vec<String> {
+            files
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(
+            names(find_md_files_sorted_by(temp_dir, sort_key_path)),
+            vec!["z.md", "b.md", "c.md"]
+        );
+        assert_eq!(
+            names(find_md_files_sorted_by(temp_dir, sort_key_name)),
+            vec!["b.md", "c.md", "z.md"]
+        );
+        assert_eq!(
+            names(find_md_files_sorted_by(temp_dir, sort_key_size)),
+            vec!["z.md", "c.md", "b.md"]
+        );
+    }
+
+    #[test]
+    fn test_find_duplicate_content() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
+        let files: Vec<PathBuf> = ["a.md", "b.md", "c.md", "d.md", "e.md", "missing.md"]
+            .iter()
+            .map(|name| temp_dir.join(name))
+            .collect();
+        fs::write(&files[0], "# Copy\n").unwrap();
+        fs::write(&files[1], "# Other\n").unwrap();
+        fs::write(&files[2], "# Copy\n").unwrap();
+        fs::write(&files[3], "# Other\n").unwrap();
+        fs::write(&files[4], "# Copy\n\n").unwrap();
+
+        assert_eq!(
+            find_duplicate_content(&files),
+            vec![
+                vec![files[0].clone(), files[2].clone()],
+                vec![files[1].clone(), files[3].clone()],
+            ]
+        );
+
+        // Trailing blank lines are ignored when comparing trimmed content
+        let groups = find_duplicate_content_by(&files, |path| {
+            Ok(fs::read_to_string(path)?.trim_end().as_bytes().to_vec())
+        });
+        assert_eq!(
+            groups[0],
+            vec![files[0].clone(), files[2].clone(), files[4].clone()]
+        );
+        assert!(find_duplicate_content(&files[..2]).is_empty());
+    }
+
+    #[test]
+    fn test_filter_files_with_negated_exclude() {
+        let base = Path::new("docs");
+        let files = vec![
+            base.join("CHANGELOG.md"),
+            base.join("guide.md"),
+            base.join("drafts/todo.md"),
+            base.join("drafts/keep.md"),
+        ];
+        let include = vec!["**/*.md".to_string()];
+        let exclude = vec![
+            "CHANGELOG.md".to_string(),
+            "drafts/**".to_string(),
+            "!drafts/keep.md".to_string(),
+        ];
+        let filter = FileFilter::new(&include, &exclude).unwrap();
+
+        let kept = filter_files(files, base, &filter);
+        assert_eq!(
+            kept,
+            vec![base.join("guide.md"), base.join("drafts/keep.md")]
+        );
+    }
+
+    #[test]
+    fn test_find_md_files_filtered_skips_excluded_directories() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let dir = temp.path();
+        for file in [
+            "a.md",
+            "node_modules/pkg/README.md",
+            "lib/vendor/x.md",
+            "docs/guide.md",
+            "docs/drafts/todo.md",
+            "docs/drafts/keep.md",
+        ] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "# Title\n").unwrap();
+        }
+        // Directories excluded before a `!` pattern are still read, as it could
+        // re-include files in them
+        let exclude = ["drafts", "!**/keep.md", "node_modules/", "vendor"].map(String::from);
+        let filter = FileFilter::new(&[], &exclude).unwrap();
+
+        assert!(filter.excludes_dir(Path::new("node_modules")));
+        assert!(filter.excludes_dir(Path::new("lib/vendor")));
+        assert!(!filter.excludes_dir(Path::new("docs/drafts")));
+        let expected = vec![
+            dir.join("a.md"),
+            dir.join("docs/drafts/keep.md"),
+            dir.join("docs/guide.md"),
+        ];
+        assert_eq!(find_md_files_filtered(dir, &filter), expected);
+        assert_eq!(filter_files(find_md_files(dir), dir, &filter), expected);
+    }
+
+    #[test]
+    fn test_filter_last_matching_exclude_wins() {
+        let exclude = vec!["!CHANGELOG.md".to_string(), "*.md".to_string()];
+        let filter = FileFilter::new(&[], &exclude).unwrap();
+        assert!(!filter.is_match(Path::new("CHANGELOG.md")));
+
+        let include = vec!["docs/**".to_string()];
+        let filter = FileFilter::new(&include, &[]).unwrap();
+        assert!(filter.is_match(Path::new("docs/a/b.md")));
+        assert!(!filter.is_match(Path::new("README.md")));
 
-        // Cleanup
-        fs::remove_dir_all(&temp_dir).ok();
+        let err = FileFilter::new(&["a[".to_string()], &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
     }
 }
@@ -0,0 +1,303 @@
+//! YAML frontmatter: sorting its top-level keys and checking that it parses.
+//!
+//! Frontmatter is the block between a `---` on the first line of a document and the
+//! next `---` line. [`sort_frontmatter_keys`] reorders its top-level keys without
+//! reformatting anything else, and [`crate::lint::lint_frontmatter_yaml`] reports
+//! frontmatter that YAML parsers reject. TOML frontmatter, delimited by `+++`, is
+//! left alone.
+
+use std::ops::Range;
+use yaml_rust2::yaml::Hash;
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// The range of lines between the frontmatter delimiters, if the document has
+/// frontmatter with a closing delimiter.
+fn frontmatter_lines(lines: &[&str]) -> Option<Range<usize>> {
+    if lines.first()?.trim() != "---" {
+        return None;
+    }
+    let end = lines.iter().skip(1).position(|line| line.trim() == "---")? + 1;
+    Some(1..end)
+}
+
+/// Parse `yaml` as a single document with a mapping at the top.
+fn parse_mapping(yaml: &str) -> Option<Hash> {
+    let mut documents = YamlLoader::load_from_str(yaml).ok()?;
+    match documents.pop() {
+        Some(Yaml::Hash(mapping)) if documents.is_empty() => Some(mapping),
+        _ => None,
+    }
+}
+
+/// The name a top-level key is sorted by.
+fn key_name(key: &Yaml) -> Option<String> {
+    match key {
+        Yaml::String(name) | Yaml::Real(name) => Some(name.clone()),
+        Yaml::Integer(number) => Some(number.to_string()),
+        Yaml::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// The lines of a frontmatter block, split into its top-level entries.
+#[derive(Default)]
+struct Entries<'a> {
+    /// Comments and blank lines before the first key, which stay first.
+    preamble: Vec<&'a str>,
+    /// Each key with the comments directly above it and everything nested in it.
+    entries: Vec<Vec<&'a str>>,
+    /// The blank lines between two entries, which stay where they are when the
+    /// entries move. `gaps[i]` comes before the entry at position `i + 1`.
+    gaps: Vec<Vec<&'a str>>,
+    /// Comments and blank lines after the last entry, which stay last.
+    postamble: Vec<&'a str>,
+}
+
+impl<'a> Entries<'a> {
+    fn split(lines: &[&'a str]) -> Self {
+        let mut split = Entries::default();
+        // Comments and blank lines not yet known to belong to an entry
+        let mut pending: Vec<&str> = Vec::new();
+        for &line in lines {
+            let trimmed = line.trim_end();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                pending.push(line);
+                continue;
+            }
+            if line.starts_with([' ', '\t']) {
+                // Nested values and block scalars, with the blank lines inside them
+                let current = split.entries.last_mut().unwrap_or(&mut split.preamble);
+                current.append(&mut pending);
+                current.push(line);
+                continue;
+            }
+            // Comments directly above a key describe it and move with it
+            let attached = pending
+                .iter()
+                .rposition(|line| line.trim().is_empty())
+                .map_or(0, |blank| blank + 1);
+            let mut entry = pending.split_off(attached);
+            let separator = pending
+                .iter()
+                .rposition(|line| !line.trim().is_empty())
+                .map_or(0, |comment| comment + 1);
+            let gap = pending.split_off(separator);
+            match split.entries.last_mut() {
+                Some(previous) => {
+                    previous.append(&mut pending);
+                    split.gaps.push(gap);
+                }
+                None => {
+                    split.preamble.append(&mut pending);
+                    split.preamble.extend(gap);
+                }
+            }
+            entry.push(line);
+            split.entries.push(entry);
+        }
+        split.postamble = pending;
+        split
+    }
+}
+
+/// Sort the top-level keys of YAML frontmatter.
+///
+/// Keys listed in `key_order` come first, in that order, followed by the other keys
+/// in alphabetical order, ignoring case. Each key moves together with the comments
+/// directly above it and everything nested in it, so comments, block scalars, and
+/// nested mappings and lists are kept as they are. Blank lines between keys stay
+/// where they are.
+///
+/// Frontmatter is only changed when it parses as a YAML mapping both before and
+/// after sorting, with every key keeping its value. Frontmatter that does not
+/// parse, and keys that cannot be moved on their own, e.g. an anchor used by a
+/// later key, leave the document unchanged. The Markdown after the frontmatter is
+/// never changed.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content whose frontmatter to sort
+/// * `key_order` - Keys to put first; empty to sort every key alphabetically
+///
+/// # Returns
+///
+/// The content with the frontmatter keys sorted.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::frontmatter::sort_frontmatter_keys;
+///
+/// let input = "---\ntags:\n  - rust\n# Shown in the browser tab\ntitle: Setup\ndate: 2024-05-01\n---\n\n# Setup\n";
+/// assert_eq!(
+///     sort_frontmatter_keys(input, &[]),
+///     "---\ndate: 2024-05-01\ntags:\n  - rust\n# Shown in the browser tab\ntitle: Setup\n---\n\n# Setup\n"
+/// );
+/// assert_eq!(
+///     sort_frontmatter_keys(input, &["title".to_string()]),
+///     "---\n# Shown in the browser tab\ntitle: Setup\ndate: 2024-05-01\ntags:\n  - rust\n---\n\n# Setup\n"
+/// );
+/// ```
+pub fn sort_frontmatter_keys(content: &str, key_order: &[String]) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let Some(range) = frontmatter_lines(&lines) else {
+        return content.to_string();
+    };
+    let body = &lines[range.clone()];
+    let Some(mapping) = parse_mapping(&body.concat()) else {
+        return content.to_string();
+    };
+    let split = Entries::split(body);
+    if split.entries.len() != mapping.len() {
+        // Keys spread over lines in ways the split does not follow, e.g. `? key`
+        return content.to_string();
+    }
+    let Some(names) = mapping.keys().map(key_name).collect::<Option<Vec<_>>>() else {
+        return content.to_string();
+    };
+
+    let rank = |name: &String| {
+        key_order
+            .iter()
+            .position(|key| key == name)
+            .unwrap_or(key_order.len())
+    };
+    let mut order: Vec<usize> = (0..names.len()).collect();
+    order.sort_by_key(|&i| (rank(&names[i]), names[i].to_lowercase(), names[i].clone()));
+    if order.iter().enumerate().all(|(position, &i)| position == i) {
+        return content.to_string();
+    }
+
+    let mut sorted: Vec<&str> = split.preamble.clone();
+    for (position, &i) in order.iter().enumerate() {
+        if position > 0 {
+            sorted.extend(&split.gaps[position - 1]);
+        }
+        sorted.extend(&split.entries[i]);
+    }
+    sorted.extend(&split.postamble);
+
+    let sorted = sorted.concat();
+    let unchanged = parse_mapping(&sorted).is_some_and(|sorted| {
+        sorted.len() == mapping.len()
+            && mapping
+                .iter()
+                .all(|(key, value)| sorted.get(key) == Some(value))
+    });
+    if !unchanged {
+        return content.to_string();
+    }
+    let mut result = lines[..range.start].concat();
+    result.push_str(&sorted);
+    result.push_str(&lines[range.end..].concat());
+    result
+}
+
+/// Where the frontmatter of `content` fails to parse as YAML.
+///
+/// # Returns
+///
+/// The 1-based line and column in `content`, and the parser's description of the
+/// problem, or `None` if there is no frontmatter or it parses.
+pub(crate) fn yaml_error(content: &str) -> Option<(usize, usize, String)> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let range = frontmatter_lines(&lines)?;
+    let error = YamlLoader::load_from_str(&lines[range.clone()].concat()).err()?;
+    // The marker counts lines from 1 and columns from 0, within the frontmatter. An
+    // error at the end of the input is reported on the closing delimiter
+    let line = range.start + error.marker().line();
+    Some((
+        line.min(range.end + 1),
+        error.marker().col() + 1,
+        error.info().to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(frontmatter: &str) -> String {
+        let content = format!("---\n{}---\n\nBody: text\n", frontmatter);
+        let result = sort_frontmatter_keys(&content, &[]);
+        let body = result
+            .strip_prefix("---\n")
+            .and_then(|result| result.strip_suffix("---\n\nBody: text\n"))
+            .expect("only the frontmatter changes");
+        body.to_string()
+    }
+
+    #[test]
+    fn test_sort_frontmatter_keys() {
+        // Nested values, block scalars, and comments move with their key
+        assert_eq!(
+            sorted(
+                "# Site metadata\n\ntitle: Guide\nsummary: |\n  First line.\n\n  Second paragraph.\nauthor:\n  name: Ann\n  # Optional\n  email: ann@example.com\n"
+            ),
+            "# Site metadata\n\nauthor:\n  name: Ann\n  # Optional\n  email: ann@example.com\nsummary: |\n  First line.\n\n  Second paragraph.\ntitle: Guide\n"
+        );
+        // Blank lines between keys stay in place, comments after one stay with it
+        assert_eq!(
+            sorted("b: 1\n# about b\n\n# about a\na: 2\n\nC: 3\n# end\n"),
+            "# about a\na: 2\n\nb: 1\n# about b\n\nC: 3\n# end\n"
+        );
+        // Sorted input, and input that is not a mapping or not YAML, is unchanged
+        for unchanged in [
+            "a: 1\nb: 2\n",
+            "- b\n- a\n",
+            "b: [1\na: 2\n",
+            // The alias would come before its anchor
+            "b: &shared 1\na: *shared\n",
+            "? b\n: 1\na: 2\n",
+        ] {
+            assert_eq!(sorted(unchanged), unchanged);
+        }
+
+        let content = "---\r\nb: 1\r\na: 2\r\n---\r\n\r\nb: 1\r\na: 2\r\n";
+        assert_eq!(
+            sort_frontmatter_keys(content, &[]),
+            "---\r\na: 2\r\nb: 1\r\n---\r\n\r\nb: 1\r\na: 2\r\n"
+        );
+        for unchanged in [
+            "b: 1\na: 2\n",
+            "---\nb: 1\na: 2\n",
+            "+++\nb = 1\na = 2\n+++\n",
+        ] {
+            assert_eq!(sort_frontmatter_keys(unchanged, &[]), unchanged);
+        }
+    }
+
+    #[test]
+    fn test_sort_frontmatter_keys_with_key_order() {
+        let content = "---\ntags: [a]\nzeta: 1\ndate: 2024-01-01\nAlpha: 2\ntitle: T\n---\n";
+        let order = [
+            "title".to_string(),
+            "date".to_string(),
+            "missing".to_string(),
+        ];
+        assert_eq!(
+            sort_frontmatter_keys(content, &order),
+            "---\ntitle: T\ndate: 2024-01-01\nAlpha: 2\ntags: [a]\nzeta: 1\n---\n"
+        );
+    }
+
+    #[test]
+    fn test_yaml_error() {
+        assert_eq!(yaml_error("# Title\n"), None);
+        assert_eq!(yaml_error("---\ntitle: Fine\n---\n"), None);
+        assert_eq!(yaml_error("---\ntitle: [open\n"), None);
+
+        let (line, column, message) =
+            yaml_error("---\ntitle: Guide\ntags: [a, b\n---\n\nText\n").unwrap();
+        assert_eq!(line, 4);
+        assert_eq!(column, 1);
+        assert!(!message.is_empty());
+
+        let (line, column, _) = yaml_error("---\ntitle: Guide\n  bad: indent\n---\n").unwrap();
+        assert_eq!((line, column), (3, 6));
+
+        let (line, _, message) = yaml_error("---\ntitle: A\ntitle: B\n---\n").unwrap();
+        assert_eq!(line, 3);
+        assert!(message.contains("duplicated key"));
+    }
+}
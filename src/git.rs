@@ -0,0 +1,349 @@
+//! Git integration for restricting formatting to changed files.
+//!
+//! This module shells out to the `git` binary to discover markdown files that are
+//! modified, added, renamed, or untracked, either relative to the index/working tree
+//! or relative to another ref (useful for CI on pull requests), or that are staged
+//! for the next commit (useful in pre-commit hooks).
+
+use crate::find_md_files::is_md_file;
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Find markdown files under `search_dir` that git reports as changed.
+///
+/// Without `since`, this returns files that are modified, added, renamed, or untracked
+/// according to `git status`. With `since`, it returns files that differ between the
+/// merge base of `since` and `HEAD` and the working tree, plus untracked files.
+///
+/// `search_dir` may be any directory inside the repository; only files beneath it are
+/// returned. Deleted files are never returned, and renamed files are reported by their
+/// new path.
+///
+/// # Arguments
+///
+/// * `search_dir` - The directory to restrict results to
+/// * `since` - An optional git ref to diff against instead of the index
+///
+/// # Returns
+///
+/// A sorted vector of `PathBuf` rooted at `search_dir`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::git::changed_md_files;
+/// use std::path::Path;
+///
+/// let files = changed_md_files(Path::new("docs"), Some("origin/main"))?;
+/// println!("{} changed markdown files", files.len());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` if `git` cannot be run, if `search_dir` is not inside a git
+/// repository, or if `since` is not a valid ref.
+pub fn changed_md_files(search_dir: &Path, since: Option<&str>) -> io::Result<Vec<PathBuf>> {
+    let root = repository_root(search_dir)?;
+    let relative_paths = match since {
+        Some(reference) => {
+            let base = run_git(&root, &["merge-base", reference, "HEAD"]).map_err(|e| {
+                io::Error::other(format!("failed to resolve ref '{}': {}", reference, e))
+            })?;
+            let diff = run_git(
+                &root,
+                &[
+                    "diff",
+                    "--name-only",
+                    "-z",
+                    "--diff-filter=ACMR",
+                    base.trim(),
+                ],
+            )?;
+            let mut paths = split_nul(&diff);
+            let untracked = run_git(&root, &["ls-files", "--others", "--exclude-standard", "-z"])?;
+            paths.extend(split_nul(&untracked));
+            paths
+        }
+        None => {
+            let status = run_git(
+                &root,
+                &["status", "--porcelain=v1", "-z", "--untracked-files=all"],
+            )?;
+            parse_porcelain_status(&status)
+        }
+    };
+    md_files_under(search_dir, &root, relative_paths)
+}
+
+/// Find markdown files under `search_dir` that are staged for the next commit.
+///
+/// These are the files `git diff --cached --diff-filter=ACMR` reports: added,
+/// copied, modified, or renamed in the index, by their new path. Deleted files are
+/// never returned.
+///
+/// # Arguments
+///
+/// * `search_dir` - The directory to restrict results to
+///
+/// # Returns
+///
+/// A sorted vector of `PathBuf` rooted at `search_dir`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::git::staged_md_files;
+/// use std::path::Path;
+///
+/// let files = staged_md_files(Path::new("."))?;
+/// println!("{} staged markdown files", files.len());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` if `git` cannot be run or if `search_dir` is not inside a
+/// git repository.
+pub fn staged_md_files(search_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let root = repository_root(search_dir)?;
+    let staged = run_git(
+        &root,
+        &[
+            "diff",
+            "--cached",
+            "--name-only",
+            "-z",
+            "--diff-filter=ACMR",
+        ],
+    )?;
+    md_files_under(search_dir, &root, split_nul(&staged))
+}
+
+/// Find markdown files under `search_dir` whose working tree differs from the
+/// index, i.e. that have changes `git add` would stage.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if `git` cannot be run or if `search_dir` is not inside a
+/// git repository.
+pub fn unstaged_md_files(search_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let root = repository_root(search_dir)?;
+    let unstaged = run_git(&root, &["diff", "--name-only", "-z"])?;
+    md_files_under(search_dir, &root, split_nul(&unstaged))
+}
+
+/// Stage the current content of `files` with `git add`, e.g. after formatting
+/// staged files in a pre-commit hook. Files that no longer exist are staged as
+/// deleted.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if `git` cannot be run or refuses to add the files, e.g.
+/// because they are outside the repository.
+pub fn stage_files(files: &[PathBuf]) -> io::Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    let mut args = vec!["add".as_ref(), "--".as_ref()];
+    args.extend(files.iter().map(|file| file.as_os_str()));
+    run_git(Path::new("."), &args).map(drop)
+}
+
+/// The canonical path of the top-level directory of the repository `dir` is in.
+fn repository_root(dir: &Path) -> io::Result<PathBuf> {
+    let root_output = run_git(dir, &["rev-parse", "--show-toplevel"]).map_err(|e| {
+        if e.kind() == io::ErrorKind::NotFound {
+            return e;
+        }
+        io::Error::other(format!(
+            "'{}' is not inside a git repository",
+            dir.display()
+        ))
+    })?;
+    PathBuf::from(root_output.trim_end_matches(['\n', '\r'])).canonicalize()
+}
+
+/// The markdown files among `relative_paths`, which are relative to the repository
+/// `root`, that exist beneath `search_dir`, sorted and rooted at `search_dir`.
+fn md_files_under(
+    search_dir: &Path,
+    root: &Path,
+    mut relative_paths: Vec<String>,
+) -> io::Result<Vec<PathBuf>> {
+    let search_root = search_dir.canonicalize()?;
+    relative_paths.sort();
+    relative_paths.dedup();
+
+    let mut files: Vec<PathBuf> = relative_paths
+        .into_iter()
+        .filter_map(|relative| {
+            let absolute = root.join(&relative);
+            let rest = absolute.strip_prefix(&search_root).ok()?;
+            Some(search_dir.join(rest))
+        })
+        .filter(|path| path.is_file() && is_md_file(path))
+        .collect();
+
+    files.sort();
+    Ok(files)
+}
+
+/// Run a git command in `dir` and return its stdout, or an error carrying its stderr.
+fn run_git<S: AsRef<OsStr>>(dir: &Path, args: &[S]) -> io::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(
+                e.kind(),
+                "git is not installed or not on PATH; it is needed to find changed or staged files",
+            ),
+            _ => io::Error::new(e.kind(), format!("failed to run git: {}", e)),
+        })?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Split NUL-separated git output into its non-empty fields.
+fn split_nul(output: &str) -> Vec<String> {
+    output
+        .split('\0')
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parse `git status --porcelain=v1 -z` output into the paths of changed files.
+///
+/// Rename and copy entries are followed by their original path, which is skipped so
+/// only the new path is reported.
+fn parse_porcelain_status(output: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut fields = output.split('\0').filter(|s| !s.is_empty());
+
+    while let Some(entry) = fields.next() {
+        if entry.len() < 4 {
+            continue;
+        }
+        let (status, path) = entry.split_at(3);
+        if status.contains('R') || status.contains('C') {
+            // Skip the original path of the rename/copy
+            fields.next();
+        }
+        paths.push(path.to_string());
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args([
+                "-c",
+                "user.name=mdfmt",
+                "-c",
+                "user.email=mdfmt@example.com",
+            ])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(status.status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn test_parse_porcelain_status_with_rename() {
+        let output = " M modified.md\0R  new.md\0old.md\0?? untracked.md\0";
+        assert_eq!(
+            parse_porcelain_status(output),
+            vec!["modified.md", "new.md", "untracked.md"]
+        );
+    }
+
+    #[test]
+    fn test_changed_md_files_not_a_repository() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
+
+        let err = changed_md_files(temp_dir, None).unwrap_err();
+        assert!(err.to_string().contains("not inside a git repository"));
+    }
+
+    #[test]
+    fn test_changed_md_files_in_subdirectory() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
+        let docs = temp_dir.join("docs");
+        fs::create_dir_all(&docs).unwrap();
+
+        git(temp_dir, &["init", "-q"]);
+        fs::write(temp_dir.join("root.md"), "# Root").unwrap();
+        fs::write(docs.join("clean.md"), "# Clean").unwrap();
+        fs::write(docs.join("modified.md"), "# Modified").unwrap();
+        fs::write(docs.join("old.md"), "# Renamed").unwrap();
+        git(temp_dir, &["add", "."]);
+        git(temp_dir, &["commit", "-q", "-m", "initial"]);
+
+        fs::write(temp_dir.join("root.md"), "# Root\nchanged").unwrap();
+        fs::write(docs.join("modified.md"), "# Modified\nchanged").unwrap();
+        fs::write(docs.join("untracked.md"), "# Untracked").unwrap();
+        fs::write(docs.join("notes.txt"), "not markdown").unwrap();
+        git(temp_dir, &["mv", "docs/old.md", "docs/new.md"]);
+
+        let files = changed_md_files(&docs, None).unwrap();
+        let names: Vec<_> = files
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["modified.md", "new.md", "untracked.md"]);
+        assert!(files.iter().all(|p| p.starts_with(&docs)));
+
+        let since_head = changed_md_files(&docs, Some("HEAD")).unwrap();
+        assert_eq!(since_head, files);
+    }
+
+    #[test]
+    fn test_staged_md_files() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
+
+        git(temp_dir, &["init", "-q"]);
+        fs::write(temp_dir.join("committed.md"), "# Committed").unwrap();
+        git(temp_dir, &["add", "."]);
+        git(temp_dir, &["commit", "-q", "-m", "initial"]);
+
+        fs::write(temp_dir.join("committed.md"), "# Committed\nchanged").unwrap();
+        fs::write(temp_dir.join("added.md"), "# Added").unwrap();
+        fs::write(temp_dir.join("untracked.md"), "# Untracked").unwrap();
+        fs::write(temp_dir.join("notes.txt"), "not markdown").unwrap();
+        git(temp_dir, &["add", "added.md", "notes.txt"]);
+        fs::write(temp_dir.join("added.md"), "# Added\nchanged").unwrap();
+
+        assert_eq!(
+            staged_md_files(temp_dir).unwrap(),
+            [temp_dir.join("added.md")]
+        );
+        assert_eq!(
+            unstaged_md_files(temp_dir).unwrap(),
+            [temp_dir.join("added.md"), temp_dir.join("committed.md")]
+        );
+    }
+}
@@ -23,11 +23,46 @@
 //!
 //! ## Modules
 //!
+//! - [`ast`] - Block-level document representation the formatter is built on
+//! - [`batch`] - Newline-delimited JSON protocol for formatting documents over stdin
+//! - [`cache`] - Content-hash cache for skipping files that are already formatted
+//! - [`config`] - Per-directory `.mdfmt.toml` configuration files
+//! - [`diff`] - Unified diff parsing for formatting only changed lines
+//! - [`editorconfig`] - Formatting options from `.editorconfig` files
+//! - [`frontmatter`] - Sorting and checking YAML frontmatter
 //! - [`find_md_files`] - Functions for finding Markdown files in directories
+//! - [`git`] - Git integration for formatting only changed files
+//! - [`lists`] - Nested list structure and list indentation
+//! - [`lint`] - Lint rules that report problems without changing files
+//! - [`lsp`] - Language server for formatting documents from editors
+//! - [`outline`] - The headings of a document with their anchors
+//! - [`output`] - Terminal colors for the human readable report
 //! - [`process_md`] - Core formatting and processing functions
+//! - [`stats`] - Statistics about the content of a document
+//! - [`toc`] - Tables of contents maintained between marker comments
+//! - [`wrap`] - Wrapping long lines
 
+pub mod ast;
+pub mod batch;
+pub mod cache;
+pub mod config;
+pub mod diff;
+pub mod editorconfig;
 pub mod find_md_files;
+pub mod frontmatter;
+pub mod git;
+pub mod lint;
+pub mod lists;
+pub mod lsp;
+pub mod outline;
+pub mod output;
 pub mod process_md;
+pub mod stats;
+pub mod toc;
+pub mod wrap;
 
 pub use find_md_files::find_md_files;
-pub use process_md::{process_md_file, remove_multiple_blank_lines};
+pub use process_md::{
+    DeleteStrategy, FormatOptions, FormatResult, format_content, is_formatted, process_md_file,
+    remove_multiple_blank_lines,
+};
@@ -0,0 +1,1851 @@
+//! Lint rules that report problems the blank line formatter does not fix.
+//!
+//! Each rule scans the content outside code fences (and, unless it says otherwise,
+//! outside frontmatter) and returns [`LintWarning`]s with 1-based line and column
+//! numbers. Some rules also come with an opt-in auto-fix function that is wired into
+//! [`FormatterConfig`](crate::process_md::FormatterConfig).
+//!
+//! [`lint_all`] runs every enabled rule in one pass, together with the blank line
+//! rules of the formatter, and returns [`RuleViolation`]s with a severity and a rule
+//! code (see [`RULE_CODES`]), without changing anything.
+
+use crate::ast;
+use crate::frontmatter;
+use crate::lists::indented_code_lines;
+use crate::process_md::{
+    ContentKind, EditRule, FormatterConfig, LineRegion, LineSink, MATH_DELIMITER, atx_heading_text,
+    atx_headings, fence_marker, is_fence, is_heading, is_list_marker, line_regions,
+    opens_math_block, remove_zero_width_characters, setext_underlines,
+};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// A problem found by a lint rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintWarning {
+    /// Identifier of the rule that produced the warning, e.g. `bare-url`.
+    pub rule: &'static str,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column (in characters) where the problem starts.
+    pub column: usize,
+    /// Human readable description of the problem.
+    pub message: String,
+}
+
+/// How serious a [`RuleViolation`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    /// A problem that must be fixed; `mdfmt --lint` exits with 1 when it finds one.
+    Error,
+    /// A problem that should be fixed.
+    Warning,
+    /// A hint that is safe to ignore.
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        })
+    }
+}
+
+/// A problem found by [`lint_all`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleViolation {
+    /// Identifier of the rule that was violated, e.g. `bare-url`.
+    pub rule_id: &'static str,
+    /// How serious the problem is, as configured in [`LintConfig`].
+    pub severity: Severity,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column (in characters) where the problem starts, if it has one.
+    pub col: Option<usize>,
+    /// Human readable description of the problem.
+    pub message: String,
+    /// The line as the rule's auto-fix would rewrite it, if the rule has one.
+    pub suggestion: Option<String>,
+}
+
+impl RuleViolation {
+    /// The stable code of the violated rule, e.g. `MB001` (see [`RULE_CODES`]).
+    pub fn code(&self) -> &'static str {
+        RULE_CODES
+            .iter()
+            .find(|(_, rule_id)| *rule_id == self.rule_id)
+            .map_or("MB000", |(code, _)| code)
+    }
+}
+
+/// The code of every rule reported by [`lint_all`], with its rule id.
+///
+/// Codes starting with `MB0` are blank line problems that the formatter fixes, and
+/// codes starting with `MB1` come from the lint rules.
+pub const RULE_CODES: [(&str, &str); 24] = [
+    ("MB001", "blank-run"),
+    ("MB002", "trailing-blank-line"),
+    ("MB003", "heading-blank-line"),
+    ("MB004", "heading-spacing"),
+    ("MB005", "fence-blank-line"),
+    ("MB006", "fence-separator"),
+    ("MB007", "list-separator"),
+    ("MB008", "frontmatter-separator"),
+    ("MB009", "quote-separator"),
+    ("MB010", "quote-blank-run"),
+    ("MB011", "table-separator"),
+    ("MB012", "html-separator"),
+    ("MB013", "div-separator"),
+    ("MB101", "bare-url"),
+    ("MB102", "missing-alt-text"),
+    ("MB103", "fence-language"),
+    ("MB104", "zero-width-character"),
+    ("MB105", "heading-punctuation"),
+    ("MB106", "frontmatter-yaml"),
+    ("MB107", "fence-style"),
+    ("MB108", "empty-heading"),
+    ("MB109", "multiple-h1"),
+    ("MB110", "first-heading-level"),
+    ("MB111", "heading-increment"),
+];
+
+/// The rules run by [`lint_all`] and the severity of their violations.
+///
+/// Rules set to `None` are not run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintConfig {
+    /// Severity of the blank line problems the formatter would fix.
+    pub formatting: Option<Severity>,
+    /// The formatter options the blank line rules are checked with, e.g.
+    /// [`FormatterConfig::blank_lines_before_heading`].
+    pub formatter: FormatterConfig,
+    /// Check files that contain only frontmatter for blank line problems too, as
+    /// the formatter formats them with
+    /// [`FormatOptions::include_empty`](crate::process_md::FormatOptions::include_empty).
+    /// Otherwise they have none, like empty and whitespace-only files.
+    pub include_empty: bool,
+    /// Severity of [`lint_bare_urls`] violations.
+    pub bare_urls: Option<Severity>,
+    /// Severity of [`lint_missing_alt_text`] violations.
+    pub missing_alt_text: Option<Severity>,
+    /// Severity of [`lint_missing_fence_language`] violations.
+    pub missing_fence_language: Option<Severity>,
+    /// Severity of [`lint_zero_width_characters`] violations.
+    pub zero_width_characters: Option<Severity>,
+    /// Severity of [`lint_heading_trailing_punctuation`] violations.
+    pub heading_punctuation: Option<Severity>,
+    /// Severity of [`lint_frontmatter_yaml`] violations.
+    pub frontmatter_yaml: Option<Severity>,
+    /// Severity of [`lint_inconsistent_fence_style`] violations.
+    pub fence_style: Option<Severity>,
+    /// Severity of [`lint_empty_headings`] violations.
+    pub empty_headings: Option<Severity>,
+    /// Severity of [`lint_multiple_h1`] violations.
+    pub multiple_h1: Option<Severity>,
+    /// Severity of [`lint_first_heading_level`] violations.
+    pub first_heading_level: Option<Severity>,
+    /// Severity of [`lint_heading_increment`] violations.
+    pub heading_increment: Option<Severity>,
+}
+
+impl Default for LintConfig {
+    /// Every rule is run. Blank line problems are errors, like with `--check`, and
+    /// so are images without alt text, since they make a document inaccessible, and
+    /// frontmatter that is not valid YAML, which site generators refuse to build.
+    /// Everything else is a warning.
+    fn default() -> Self {
+        LintConfig {
+            formatting: Some(Severity::Error),
+            formatter: FormatterConfig::default(),
+            include_empty: false,
+            bare_urls: Some(Severity::Warning),
+            missing_alt_text: Some(Severity::Error),
+            missing_fence_language: Some(Severity::Warning),
+            zero_width_characters: Some(Severity::Warning),
+            heading_punctuation: Some(Severity::Warning),
+            frontmatter_yaml: Some(Severity::Error),
+            fence_style: Some(Severity::Warning),
+            empty_headings: Some(Severity::Warning),
+            multiple_h1: Some(Severity::Warning),
+            first_heading_level: Some(Severity::Warning),
+            heading_increment: Some(Severity::Warning),
+        }
+    }
+}
+
+/// A lint rule, with the auto-fix that goes with it.
+type Rule = (fn(&str) -> Vec<LintWarning>, Option<fn(&str) -> String>);
+
+/// Run every rule enabled in `config` without modifying anything.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to check
+/// * `config` - The rules to run and the severity of their violations
+///
+/// # Returns
+///
+/// The violations of all rules, sorted by line and column. Rules with an auto-fix
+/// suggest the fixed line. Blank line problems have no column, and are reported
+/// once for every run of blank lines.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::{LintConfig, Severity, lint_all};
+///
+/// let input = "![](chart.png)\nSee https://example.com\n# Links\n";
+/// let violations = lint_all(input, &LintConfig::default());
+/// assert_eq!(violations.len(), 3);
+/// assert_eq!(violations[0].rule_id, "missing-alt-text");
+/// assert_eq!(violations[0].severity, Severity::Error);
+/// assert_eq!(violations[1].col, Some(5));
+/// assert_eq!(violations[1].suggestion.as_deref(), Some("See <https://example.com>"));
+/// assert_eq!(violations[2].code(), "MB003");
+/// assert_eq!(violations[2].message, "missing blank line before heading");
+/// ```
+pub fn lint_all(content: &str, config: &LintConfig) -> Vec<RuleViolation> {
+    let rules: [(Option<Severity>, Rule); 11] = [
+        (config.bare_urls, (lint_bare_urls, Some(fix_bare_urls))),
+        (
+            config.missing_alt_text,
+            (lint_missing_alt_text, Some(fix_missing_alt_text)),
+        ),
+        (
+            config.missing_fence_language,
+            (lint_missing_fence_language, None),
+        ),
+        (
+            config.zero_width_characters,
+            (
+                lint_zero_width_characters,
+                Some(|content: &str| remove_zero_width_characters(content)),
+            ),
+        ),
+        (
+            config.heading_punctuation,
+            (
+                lint_heading_trailing_punctuation,
+                Some(fix_heading_punctuation),
+            ),
+        ),
+        (config.frontmatter_yaml, (lint_frontmatter_yaml, None)),
+        (
+            config.fence_style,
+            (
+                |content: &str| lint_inconsistent_fence_style(content).into_iter().collect(),
+                None,
+            ),
+        ),
+        // The fix removes lines, so it cannot suggest a replacement for one
+        (config.empty_headings, (lint_empty_headings, None)),
+        (config.multiple_h1, (lint_multiple_h1, None)),
+        (config.first_heading_level, (lint_first_heading_level, None)),
+        (config.heading_increment, (lint_heading_increment, None)),
+    ];
+    let lines: Vec<&str> = content.lines().collect();
+    let mut violations = Vec::new();
+
+    // Files without a body are deleted or skipped instead of formatted
+    let formatted =
+        ContentKind::with_config(content, &config.formatter).is_formatted(config.include_empty);
+    if let Some(severity) = config.formatting.filter(|_| formatted) {
+        let mut findings = FormattingFindings {
+            lines: &lines,
+            severity,
+            violations: &mut violations,
+            last_removal: None,
+        };
+        ast::render_content_into(content, &config.formatter, &mut findings);
+    }
+
+    for (severity, (lint, fix)) in rules {
+        let Some(severity) = severity else {
+            continue;
+        };
+        let warnings = lint(content);
+        if warnings.is_empty() {
+            continue;
+        }
+        // The fixes rewrite lines in place, so line numbers still match
+        let fixed = fix.map(|fix| fix(content)).unwrap_or_default();
+        let fixed_lines: Vec<&str> = fixed.lines().collect();
+        violations.extend(warnings.into_iter().map(|warning| {
+            let index = warning.line - 1;
+            RuleViolation {
+                rule_id: warning.rule,
+                severity,
+                line: warning.line,
+                col: Some(warning.column),
+                message: warning.message,
+                suggestion: fixed_lines
+                    .get(index)
+                    .filter(|fixed| lines.get(index) != Some(fixed))
+                    .map(|fixed| fixed.to_string()),
+            }
+        }));
+    }
+
+    violations.sort_by_key(|violation| (violation.line, violation.col));
+    violations
+}
+
+/// Turns the blank line edits of the formatter into violations.
+struct FormattingFindings<'a> {
+    lines: &'a [&'a str],
+    severity: Severity,
+    violations: &'a mut Vec<RuleViolation>,
+    /// The input line of the last removal and the index of the violation it was
+    /// reported with, so that a run of removed lines is reported once
+    last_removal: Option<(usize, usize)>,
+}
+
+impl FormattingFindings<'_> {
+    fn report(&mut self, rule_id: &'static str, line: usize, message: String) {
+        self.violations.push(RuleViolation {
+            rule_id,
+            severity: self.severity,
+            line,
+            col: None,
+            message,
+            suggestion: None,
+        });
+    }
+}
+
+impl LineSink for FormattingFindings<'_> {
+    fn keep(&mut self, _index: usize, _line: &str) {}
+
+    fn insert_blank(&mut self, before: usize, rule: EditRule) {
+        let (rule_id, block) = match rule {
+            EditRule::HeadingSeparator => ("heading-blank-line", "heading"),
+            EditRule::FenceSeparator => ("fence-separator", "code block"),
+            EditRule::ListSeparator => ("list-separator", "list"),
+            EditRule::FrontmatterSeparator => ("frontmatter-separator", "frontmatter"),
+            EditRule::QuoteSeparator => ("quote-separator", "block quote"),
+            EditRule::TableSeparator => ("table-separator", "table"),
+            EditRule::HtmlSeparator => ("html-separator", "HTML block"),
+            EditRule::DivSeparator => ("div-separator", "fenced div"),
+            EditRule::BlankRun
+            | EditRule::HeadingSpacing
+            | EditRule::QuoteBlankRun
+            | EditRule::FenceBlankLine
+            | EditRule::TrailingBlankLine => return,
+        };
+        // A blank line goes between two lines; report the one the block starts or
+        // ends on
+        let next = self.lines.get(before).map_or("", |line| line.trim());
+        let starts_block = match rule {
+            EditRule::HeadingSeparator => is_heading(next),
+            EditRule::FenceSeparator => is_fence(next) || opens_math_block(next, MATH_DELIMITER),
+            EditRule::ListSeparator => is_list_marker(next),
+            EditRule::QuoteSeparator => next.starts_with('>'),
+            EditRule::TableSeparator => next.starts_with('|'),
+            EditRule::HtmlSeparator => next.starts_with('<'),
+            EditRule::DivSeparator => ast::opens_div(next).is_some(),
+            _ => false,
+        };
+        let (line, side) = if starts_block {
+            (before + 1, "before")
+        } else {
+            (before.max(1), "after")
+        };
+        self.report(
+            rule_id,
+            line,
+            format!("missing blank line {} {}", side, block),
+        );
+    }
+
+    fn remove(&mut self, index: usize, rule: EditRule) {
+        let (rule_id, message) = match rule {
+            EditRule::BlankRun => ("blank-run", "multiple consecutive blank lines"),
+            EditRule::TrailingBlankLine => ("trailing-blank-line", "blank lines at end of file"),
+            EditRule::HeadingSpacing => ("heading-spacing", "too many blank lines around heading"),
+            EditRule::FenceBlankLine => {
+                ("fence-blank-line", "blank line directly inside code fence")
+            }
+            EditRule::QuoteBlankRun => (
+                "quote-blank-run",
+                "multiple consecutive blank lines in block quote",
+            ),
+            EditRule::HeadingSeparator
+            | EditRule::FenceSeparator
+            | EditRule::ListSeparator
+            | EditRule::FrontmatterSeparator
+            | EditRule::QuoteSeparator
+            | EditRule::TableSeparator
+            | EditRule::HtmlSeparator
+            | EditRule::DivSeparator => return,
+        };
+        match self.last_removal {
+            Some((last, reported)) if last + 1 == index => {
+                self.last_removal = Some((index, reported));
+            }
+            // The end of a trailing run is collapsed like any other run first
+            Some((last, reported)) if last == index + 1 => {
+                self.last_removal = Some((index, reported));
+                let violation = &mut self.violations[reported];
+                violation.rule_id = rule_id;
+                violation.line = index + 1;
+                violation.message = message.to_string();
+            }
+            _ => {
+                self.last_removal = Some((index, self.violations.len()));
+                self.report(rule_id, index + 1, message.to_string());
+            }
+        }
+    }
+}
+
+/// Conservative pattern for HTTP(S) URLs; parentheses and quotes end the match.
+static URL_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"https?://[^\s<>\[\]()"'`]+"#).expect("URL pattern is valid"));
+
+/// Reference definitions like `[ref]: https://example.com`.
+static REFERENCE_DEFINITION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^ {0,3}\[[^\]]+\]:").expect("reference definition pattern is valid")
+});
+
+/// Reference definitions with their destination and optional title, e.g.
+/// `[logo]: images/logo.png "Project logo"`.
+static REFERENCE_DEFINITION_PARTS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^ {0,3}\[([^\]]+)\]:\s*(<[^>]*>|\S+)(?:\s+("[^"]*"|'[^']*'|\([^)]*\)))?\s*$"#)
+        .expect("reference definition parts pattern is valid")
+});
+
+/// Inline images `![alt](src "title")` and reference images `![alt][label]`.
+static IMAGE_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"!\[(?P<alt>[^\]]*)\](?:\((?P<src><[^>]*>|[^)\s]*)(?:\s+(?:"[^"]*"|'[^']*'))?\s*\)|\[(?P<label>[^\]]*)\])"#,
+    )
+    .expect("image pattern is valid")
+});
+
+/// Find HTTP/HTTPS URLs that are not wrapped in angle brackets or link syntax.
+///
+/// URLs inside frontmatter, code fences, inline code spans, link text, link
+/// destinations, reference definitions, and HTML attributes are ignored.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to check
+///
+/// # Returns
+///
+/// One `LintWarning` per bare URL, in document order.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::lint_bare_urls;
+///
+/// let warnings = lint_bare_urls("See https://example.com and <https://example.org>.");
+/// assert_eq!(warnings.len(), 1);
+/// assert_eq!(warnings[0].line, 1);
+/// assert_eq!(warnings[0].column, 5);
+/// ```
+pub fn lint_bare_urls(content: &str) -> Vec<LintWarning> {
+    let lines: Vec<&str> = content.lines().collect();
+    let regions = line_regions(&lines);
+    let mut warnings = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        if regions[i] != LineRegion::Text {
+            continue;
+        }
+        for range in bare_url_ranges(line) {
+            warnings.push(LintWarning {
+                rule: "bare-url",
+                line: i + 1,
+                column: line[..range.start].chars().count() + 1,
+                message: format!(
+                    "bare URL `{}` should be wrapped in angle brackets or a link",
+                    &line[range]
+                ),
+            });
+        }
+    }
+
+    warnings
+}
+
+/// Wrap bare HTTP/HTTPS URLs in angle brackets.
+///
+/// This fixes every URL reported by [`lint_bare_urls`] and leaves the rest of the
+/// content, including line endings, untouched.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::fix_bare_urls;
+///
+/// let output = fix_bare_urls("Visit https://example.com.\n");
+/// assert_eq!(output, "Visit <https://example.com>.\n");
+/// ```
+pub fn fix_bare_urls(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        let ranges = if regions[i] == LineRegion::Text {
+            bare_url_ranges(trimmed[i])
+        } else {
+            Vec::new()
+        };
+        let mut last = 0;
+        for range in ranges {
+            output.push_str(&line[last..range.start]);
+            output.push('<');
+            output.push_str(&line[range.clone()]);
+            output.push('>');
+            last = range.end;
+        }
+        output.push_str(&line[last..]);
+    }
+
+    output
+}
+
+/// Byte ranges of bare URLs within a single line of text.
+fn bare_url_ranges(line: &str) -> Vec<Range<usize>> {
+    if REFERENCE_DEFINITION.is_match(line) {
+        return Vec::new();
+    }
+
+    let code_spans = code_span_ranges(line);
+    let mut ranges = Vec::new();
+
+    for found in URL_PATTERN.find_iter(line) {
+        let start = found.start();
+        // Trailing sentence punctuation is not part of the URL
+        let end = start
+            + found
+                .as_str()
+                .trim_end_matches(['.', ',', ';', ':', '!', '?', '*', '_', '~'])
+                .len();
+        let before = &line[..start];
+
+        let in_code_span = code_spans.iter().any(|span| span.contains(&start));
+        let is_autolink = before.ends_with('<');
+        let is_link_destination = before.ends_with("](") || before.ends_with("]: ");
+        let is_attribute = before.ends_with(['"', '\'', '=']);
+        let in_link_text = before.matches('[').count() > before.matches(']').count();
+
+        if !(in_code_span || is_autolink || is_link_destination || is_attribute || in_link_text) {
+            ranges.push(start..end);
+        }
+    }
+
+    ranges
+}
+
+/// Find images whose alt text is empty or only whitespace.
+///
+/// Inline images like `![](image.png)` or `![ ](image.png)` are always reported.
+/// Reference images like `![][logo]` are reported when the label is empty, the
+/// reference is not defined, or its definition has no title that could describe the
+/// image. Images inside frontmatter, code fences, and inline code spans are ignored.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to check
+///
+/// # Returns
+///
+/// One `LintWarning` per image, in document order, quoting the raw image syntax.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::lint_missing_alt_text;
+///
+/// let warnings = lint_missing_alt_text("![](diagram.png) and ![Logo](logo.png)");
+/// assert_eq!(warnings.len(), 1);
+/// assert_eq!(warnings[0].column, 1);
+/// assert!(warnings[0].message.contains("`![](diagram.png)`"));
+/// ```
+pub fn lint_missing_alt_text(content: &str) -> Vec<LintWarning> {
+    let lines: Vec<&str> = content.lines().collect();
+    let regions = line_regions(&lines);
+    let definitions = reference_definitions(&lines, &regions);
+    let mut warnings = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        if regions[i] != LineRegion::Text {
+            continue;
+        }
+        for image in missing_alt_images(line, &definitions) {
+            warnings.push(missing_alt_warning(line, i, &image));
+        }
+    }
+
+    warnings
+}
+
+/// Fill in missing alt text with the image file name, without its extension.
+///
+/// This fixes the images reported by [`lint_missing_alt_text`] whose source is
+/// known, e.g. `![](images/build-status.png)` becomes
+/// `![build-status](images/build-status.png)`. Images without a usable file name
+/// are left unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::fix_missing_alt_text;
+///
+/// let output = fix_missing_alt_text("![](img/chart.svg \"Sales\")\n");
+/// assert_eq!(output, "![chart](img/chart.svg \"Sales\")\n");
+/// ```
+pub fn fix_missing_alt_text(content: &str) -> String {
+    fix_missing_alt_text_with(content, |_, fallback| fallback.map(str::to_string))
+}
+
+/// Fill in missing alt text with text chosen by `alt_text`.
+///
+/// `alt_text` is called for every image reported by [`lint_missing_alt_text`], with
+/// the warning and the file name fallback used by [`fix_missing_alt_text`]. It
+/// returns the alt text to insert, or `None` to leave the image unchanged. This is
+/// how an interactive frontend can prompt for a description.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::fix_missing_alt_text_with;
+///
+/// let output = fix_missing_alt_text_with("![](chart.png)", |warning, fallback| {
+///     assert_eq!(warning.line, 1);
+///     assert_eq!(fallback, Some("chart"));
+///     Some("Monthly sales chart".to_string())
+/// });
+/// assert_eq!(output, "![Monthly sales chart](chart.png)");
+/// ```
+pub fn fix_missing_alt_text_with<F>(content: &str, mut alt_text: F) -> String
+where
+    F: FnMut(&LintWarning, Option<&str>) -> Option<String>,
+{
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let definitions = reference_definitions(&trimmed, &regions);
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        let images = if regions[i] == LineRegion::Text {
+            missing_alt_images(trimmed[i], &definitions)
+        } else {
+            Vec::new()
+        };
+        let mut last = 0;
+        for image in images {
+            let warning = missing_alt_warning(trimmed[i], i, &image);
+            let fallback = image.source.as_deref().and_then(file_stem);
+            if let Some(text) = alt_text(&warning, fallback) {
+                // Keep `![`, replace the blank alt text, and keep everything from `]`
+                output.push_str(&line[last..image.alt.start]);
+                output.push_str(&text.replace('[', "\\[").replace(']', "\\]"));
+                last = image.alt.end;
+            }
+        }
+        output.push_str(&line[last..]);
+    }
+
+    output
+}
+
+/// An image with blank alt text found in a line.
+struct MissingAltImage {
+    /// Byte range of the whole image syntax.
+    syntax: Range<usize>,
+    /// Byte range of the (blank) alt text between the brackets.
+    alt: Range<usize>,
+    /// The image source, from the image itself or its reference definition.
+    source: Option<String>,
+}
+
+/// A reference definition's destination and title.
+struct ReferenceDefinition {
+    destination: String,
+    has_title: bool,
+}
+
+/// Reference definitions in the text regions, keyed by normalized label.
+///
+/// The first definition of a label wins, as in CommonMark.
+fn reference_definitions(
+    lines: &[&str],
+    regions: &[LineRegion],
+) -> HashMap<String, ReferenceDefinition> {
+    let mut definitions = HashMap::new();
+    for (line, region) in lines.iter().zip(regions) {
+        if *region != LineRegion::Text {
+            continue;
+        }
+        if let Some(captures) = REFERENCE_DEFINITION_PARTS.captures(line) {
+            let destination = captures[2].trim_start_matches('<').trim_end_matches('>');
+            definitions
+                .entry(normalize_label(&captures[1]))
+                .or_insert(ReferenceDefinition {
+                    destination: destination.to_string(),
+                    has_title: captures.get(3).is_some(),
+                });
+        }
+    }
+    definitions
+}
+
+/// Reference labels match case-insensitively, with runs of whitespace collapsed.
+fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Images with blank alt text within a single line of text.
+fn missing_alt_images(
+    line: &str,
+    definitions: &HashMap<String, ReferenceDefinition>,
+) -> Vec<MissingAltImage> {
+    if REFERENCE_DEFINITION.is_match(line) {
+        return Vec::new();
+    }
+
+    let code_spans = code_span_ranges(line);
+    let mut images = Vec::new();
+
+    for captures in IMAGE_PATTERN.captures_iter(line) {
+        let syntax = captures.get(0).expect("whole match is present");
+        let alt = captures.name("alt").expect("alt group always participates");
+        if !alt.as_str().trim().is_empty()
+            || code_spans.iter().any(|span| span.contains(&syntax.start()))
+        {
+            continue;
+        }
+
+        let source = if let Some(src) = captures.name("src") {
+            Some(src.as_str().trim_start_matches('<').trim_end_matches('>'))
+        } else {
+            let label = captures.name("label").map_or("", |label| label.as_str());
+            match definitions.get(&normalize_label(label)) {
+                // A titled definition already describes the image
+                Some(definition) if !label.trim().is_empty() && definition.has_title => continue,
+                Some(definition) if !label.trim().is_empty() => {
+                    Some(definition.destination.as_str())
+                }
+                _ => None,
+            }
+        };
+
+        images.push(MissingAltImage {
+            syntax: syntax.range(),
+            alt: alt.range(),
+            source: source
+                .filter(|source| !source.is_empty())
+                .map(str::to_string),
+        });
+    }
+
+    images
+}
+
+/// Find code fences without a language in their info string (markdownlint MD040).
+///
+/// Such fences render without syntax highlighting. Only opening fences are checked,
+/// so closing fences and fences shown inside a longer fence are never reported.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to check
+///
+/// # Returns
+///
+/// One `LintWarning` per bare opening fence, in document order.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::lint_missing_fence_language;
+///
+/// let warnings = lint_missing_fence_language("```\nls\n```\n\n```sh\nls\n```\n");
+/// assert_eq!(warnings.len(), 1);
+/// assert_eq!(warnings[0].line, 1);
+/// assert_eq!(warnings[0].rule, "fence-language");
+/// ```
+pub fn lint_missing_fence_language(content: &str) -> Vec<LintWarning> {
+    let lines: Vec<&str> = content.lines().collect();
+    let regions = line_regions(&lines);
+
+    lines
+        .iter()
+        .enumerate()
+        .filter(|&(i, line)| {
+            regions[i] == LineRegion::CodeFenceOpening && fence_info(line).is_empty()
+        })
+        .map(|(i, line)| LintWarning {
+            rule: "fence-language",
+            line: i + 1,
+            column: line.len() - line.trim_start().len() + 1,
+            message: "code fence has no language".to_string(),
+        })
+        .collect()
+}
+
+/// Find a file that mixes ```` ``` ```` and `~~~` code fences.
+///
+/// Both styles are valid, but one file should stick to one of them. The fences of
+/// the style used less often are the odd ones out, or the fences that differ from
+/// the first one when both styles are used equally often. Fences shown inside other
+/// fences are not counted. [`FormatterConfig::fence_style`] rewrites the fences to
+/// one style.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to check
+///
+/// # Returns
+///
+/// A `LintWarning` pointing at the first fence of the odd style, or `None` if all
+/// fences use the same style.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::lint_inconsistent_fence_style;
+///
+/// let input = "```sh\nls\n```\n\n~~~sh\npwd\n~~~\n\n```sh\ncd\n```\n";
+/// let warning = lint_inconsistent_fence_style(input).unwrap();
+/// assert_eq!(warning.line, 5);
+/// assert_eq!(warning.rule, "fence-style");
+/// assert!(lint_inconsistent_fence_style("```\nls\n```\n").is_none());
+/// ```
+pub fn lint_inconsistent_fence_style(content: &str) -> Option<LintWarning> {
+    let lines: Vec<&str> = content.lines().collect();
+    let regions = line_regions(&lines);
+    let openings: Vec<(usize, &str)> = lines
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| regions[i] == LineRegion::CodeFenceOpening)
+        .map(|(i, line)| (i, *line))
+        .collect();
+    let is_tilde = |line: &str| line.trim_start().starts_with('~');
+    let tildes = openings.iter().filter(|(_, line)| is_tilde(line)).count();
+    let backticks = openings.len() - tildes;
+    if tildes == 0 || backticks == 0 {
+        return None;
+    }
+    let odd_tilde = tildes < backticks || tildes == backticks && !is_tilde(openings[0].1);
+    let (marker, other, style) = if odd_tilde {
+        ("~~~", "```", "backtick")
+    } else {
+        ("```", "~~~", "tilde")
+    };
+    let &(i, line) = openings
+        .iter()
+        .find(|(_, line)| is_tilde(line) == odd_tilde)?;
+    Some(LintWarning {
+        rule: "fence-style",
+        line: i + 1,
+        column: line.len() - line.trim_start().len() + 1,
+        message: format!(
+            "code fence uses `{}` but the other fences in this file use `{}`; run with `--fence-style {}` to make them consistent",
+            marker, other, style
+        ),
+    })
+}
+
+/// The info string of an opening fence line, without surrounding whitespace.
+pub(crate) fn fence_info(line: &str) -> &str {
+    let trimmed = line.trim();
+    trimmed[fence_marker(trimmed).len()..].trim()
+}
+
+/// Invisible characters removed by
+/// [`remove_zero_width_characters`](crate::process_md::remove_zero_width_characters),
+/// with their names.
+pub(crate) const ZERO_WIDTH_CHARACTERS: [(char, &str); 4] = [
+    ('\u{200B}', "zero-width space"),
+    ('\u{200C}', "zero-width non-joiner"),
+    ('\u{200D}', "zero-width joiner"),
+    ('\u{AD}', "soft hyphen"),
+];
+
+/// Whether a line in `region` may have its zero-width characters removed. Code
+/// fences and math blocks are left alone, since the characters may be intentional
+/// there.
+pub(crate) fn strips_zero_width(region: LineRegion) -> bool {
+    matches!(region, LineRegion::Text | LineRegion::Frontmatter)
+}
+
+/// Find zero-width and soft hyphen characters outside code fences and math blocks.
+///
+/// These characters are invisible when rendered, and usually come from text copied
+/// out of browsers or word processors. Frontmatter is checked too.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to check
+///
+/// # Returns
+///
+/// One `LintWarning` per line that contains such characters, pointing at the first
+/// one and naming every kind found on the line.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::lint_zero_width_characters;
+///
+/// let warnings = lint_zero_width_characters("Hello\u{200B}world\n```\na\u{200B}b\n```\n");
+/// assert_eq!(warnings.len(), 1);
+/// assert_eq!(warnings[0].column, 6);
+/// assert_eq!(warnings[0].message, "invisible zero-width space (U+200B)");
+/// ```
+pub fn lint_zero_width_characters(content: &str) -> Vec<LintWarning> {
+    let lines: Vec<&str> = content.lines().collect();
+    let regions = line_regions(&lines);
+    let mut warnings = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        if !strips_zero_width(regions[i]) {
+            continue;
+        }
+        let Some(first) = line.chars().position(is_zero_width) else {
+            continue;
+        };
+        let found: Vec<String> = ZERO_WIDTH_CHARACTERS
+            .iter()
+            .filter(|(c, _)| line.contains(*c))
+            .map(|(c, name)| format!("{} (U+{:04X})", name, u32::from(*c)))
+            .collect();
+        warnings.push(LintWarning {
+            rule: "zero-width-character",
+            line: i + 1,
+            column: first + 1,
+            message: format!("invisible {}", found.join(", ")),
+        });
+    }
+
+    warnings
+}
+
+/// Find frontmatter that is not valid YAML.
+///
+/// Only frontmatter delimited by `---` lines is checked. Frontmatter without a
+/// closing `---` is not reported, since the formatter does not treat it as
+/// frontmatter either.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to check
+///
+/// # Returns
+///
+/// At most one `LintWarning`, pointing at the line and column where the YAML parser
+/// gave up.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::lint_frontmatter_yaml;
+///
+/// let warnings = lint_frontmatter_yaml("---\ntitle: Setup\ntags: [a, b\n---\n\n# Setup\n");
+/// assert_eq!(warnings.len(), 1);
+/// assert_eq!(warnings[0].line, 4);
+/// assert!(warnings[0].message.starts_with("frontmatter is not valid YAML: "));
+/// assert!(lint_frontmatter_yaml("---\ntitle: Setup\n---\n").is_empty());
+/// ```
+pub fn lint_frontmatter_yaml(content: &str) -> Vec<LintWarning> {
+    frontmatter::yaml_error(content)
+        .map(|(line, column, problem)| LintWarning {
+            rule: "frontmatter-yaml",
+            line,
+            column,
+            message: format!("frontmatter is not valid YAML: {}", problem),
+        })
+        .into_iter()
+        .collect()
+}
+
+/// Whether `c` is one of the [`ZERO_WIDTH_CHARACTERS`].
+pub(crate) fn is_zero_width(c: char) -> bool {
+    ZERO_WIDTH_CHARACTERS
+        .iter()
+        .any(|&(zero_width, _)| zero_width == c)
+}
+
+/// Find ATX and setext headings that end with a period, question mark, or
+/// exclamation mark.
+///
+/// Style guides such as the Microsoft Writing Style Guide and the Google developer
+/// documentation style guide leave this punctuation off headings. An ellipsis
+/// (`...`), a mixed run such as `?!`, escaped punctuation, and punctuation inside
+/// inline code or link text are not reported. Headings in frontmatter, code fences,
+/// and math blocks are ignored.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to check
+///
+/// # Returns
+///
+/// One `LintWarning` per heading, pointing at the first trailing punctuation
+/// character.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::lint_heading_trailing_punctuation;
+///
+/// let warnings = lint_heading_trailing_punctuation("# Setup.\n\n## What next?!\n");
+/// assert_eq!(warnings.len(), 1);
+/// assert_eq!(warnings[0].line, 1);
+/// assert_eq!(warnings[0].column, 8);
+/// assert_eq!(warnings[0].message, "heading ends with punctuation `.`");
+/// ```
+pub fn lint_heading_trailing_punctuation(content: &str) -> Vec<LintWarning> {
+    let lines: Vec<&str> = content.lines().collect();
+    heading_punctuation(&lines)
+        .into_iter()
+        .map(|(i, range)| LintWarning {
+            rule: "heading-punctuation",
+            line: i + 1,
+            column: lines[i][..range.start].chars().count() + 1,
+            message: format!(
+                "heading ends with punctuation `{}`",
+                lines[i][range].trim_start()
+            ),
+        })
+        .collect()
+}
+
+/// Remove trailing periods, question marks, and exclamation marks from headings.
+///
+/// The headings reported by [`lint_heading_trailing_punctuation`] lose their
+/// trailing punctuation, along with whitespace before it. The closing `#` sequence
+/// of ATX headings and the underline of setext headings are kept, and so are line
+/// endings.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to fix
+///
+/// # Returns
+///
+/// The content with punctuation removed from heading ends.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::fix_heading_punctuation;
+///
+/// let input = "# Overview.\n\nWhy bother?\n---\n\n## Wait... ##\n";
+/// assert_eq!(
+///     fix_heading_punctuation(input),
+///     "# Overview\n\nWhy bother\n---\n\n## Wait... ##\n"
+/// );
+/// ```
+pub fn fix_heading_punctuation(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let mut fixes = heading_punctuation(&trimmed).into_iter().peekable();
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        match fixes.next_if(|(heading, _)| *heading == i) {
+            Some((_, range)) => {
+                output.push_str(&line[..range.start]);
+                output.push_str(&line[range.end..]);
+            }
+            None => output.push_str(line),
+        }
+    }
+
+    output
+}
+
+/// The 0-based line numbers of headings that end with punctuation, and the byte
+/// range of that punctuation and the whitespace before it, in document order.
+fn heading_punctuation(lines: &[&str]) -> Vec<(usize, Range<usize>)> {
+    let mut headings: Vec<(usize, Range<usize>)> = atx_headings(lines)
+        .map(|(i, level)| (i, atx_heading_text(lines[i], level)))
+        .collect();
+    for i in setext_underlines(lines) {
+        let title = lines[i - 1];
+        let start = title.len() - title.trim_start().len();
+        headings.push((i - 1, start..title.trim_end().len()));
+    }
+    headings.sort_by_key(|(i, _)| *i);
+
+    headings
+        .into_iter()
+        .filter_map(|(i, text)| {
+            trailing_punctuation(lines[i], text.clone()).map(|range| (i, range))
+        })
+        .collect()
+}
+
+/// The byte range of the trailing punctuation of the heading text at `text` in
+/// `line`, including whitespace before it, if the punctuation should be removed.
+fn trailing_punctuation(line: &str, text: Range<usize>) -> Option<Range<usize>> {
+    let heading = &line[text.clone()];
+    // Spaced runs such as `? ?` are removed whole, so a second pass finds nothing
+    let words = heading.trim_end_matches(['.', '?', '!', ' ', '\t']);
+    let run = &heading[words.len()..];
+    let kept = !run.contains(['.', '?', '!'])
+        || run.contains("...")
+        || (run.contains('?') && run.contains('!'))
+        || words.is_empty()
+        || words.ends_with('\\');
+    if kept {
+        return None;
+    }
+    let start = text.start + words.len();
+    let before = &line[..start];
+    let in_code_span = code_span_ranges(line)
+        .iter()
+        .any(|span| span.contains(&start));
+    let in_link_text = before.matches('[').count() > before.matches(']').count()
+        || before.matches('<').count() > before.matches('>').count();
+    if in_code_span || in_link_text {
+        return None;
+    }
+    Some(start..text.end)
+}
+
+/// Find headings without text, such as a lone `###`, and headings without content,
+/// which are followed directly by a heading of the same or a higher level, or by
+/// the end of the document.
+///
+/// A heading followed by a lower-level heading has content: its subsections. The
+/// only heading of a document is never reported for missing content, so a file
+/// with nothing but a title is fine. Headings in frontmatter, code fences, math
+/// blocks, and indented code are ignored.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to check
+///
+/// # Returns
+///
+/// One `LintWarning` per heading, pointing at its first `#` or the start of its
+/// text.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::lint_empty_headings;
+///
+/// let input = "# Guide\n\n## Setup\n\n## Troubleshooting\n\n##\n\n## Usage\n\nRun it.\n";
+/// let warnings = lint_empty_headings(input);
+/// let found: Vec<_> = warnings.iter().map(|w| (w.line, w.message.as_str())).collect();
+/// assert_eq!(
+///     found,
+///     [
+///         (3, "heading has no content"),
+///         (5, "heading has no content"),
+///         (7, "heading has no text"),
+///     ]
+/// );
+/// ```
+pub fn lint_empty_headings(content: &str) -> Vec<LintWarning> {
+    let lines: Vec<&str> = content.lines().collect();
+    let headings = heading_spans(&lines);
+    let mut warnings = Vec::new();
+
+    for (k, heading) in headings.iter().enumerate() {
+        let message = if heading.empty {
+            "heading has no text"
+        } else if headings.len() > 1 && !has_content(&lines, &headings, k) {
+            "heading has no content"
+        } else {
+            continue;
+        };
+        warnings.push(heading_warning(
+            "empty-heading",
+            &lines,
+            heading,
+            message.to_string(),
+        ));
+    }
+
+    warnings
+}
+
+/// Find level 1 headings after the first one.
+///
+/// A document has one title, and tools that build a table of contents or a page
+/// title from it expect a single level 1 heading. Headings in frontmatter, code
+/// fences, math blocks, and indented code are ignored; setext `===` headings count.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to check
+///
+/// # Returns
+///
+/// One `LintWarning` per level 1 heading after the first.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::lint_multiple_h1;
+///
+/// let warnings = lint_multiple_h1("# Guide\n\nText\n\nAppendix\n========\n");
+/// assert_eq!(warnings.len(), 1);
+/// assert_eq!(warnings[0].line, 5);
+/// assert_eq!(
+///     warnings[0].message,
+///     "more than one level 1 heading; the first is on line 1"
+/// );
+/// ```
+pub fn lint_multiple_h1(content: &str) -> Vec<LintWarning> {
+    let lines: Vec<&str> = content.lines().collect();
+    let titles: Vec<HeadingSpan> = heading_spans(&lines)
+        .into_iter()
+        .filter(|heading| heading.level == 1)
+        .collect();
+    let Some(first) = titles.first() else {
+        return Vec::new();
+    };
+    titles[1..]
+        .iter()
+        .map(|heading| {
+            let message = format!(
+                "more than one level 1 heading; the first is on line {}",
+                first.start + 1
+            );
+            heading_warning("multiple-h1", &lines, heading, message)
+        })
+        .collect()
+}
+
+/// Find a first heading that is not level 1, which leaves the document without a
+/// title at the top of its outline.
+///
+/// Documents whose title comes from elsewhere, such as the frontmatter of a static
+/// site, can turn this check off (see [`LintConfig::first_heading_level`]).
+/// Headings are found as in [`lint_multiple_h1`].
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to check
+///
+/// # Returns
+///
+/// At most one `LintWarning`, pointing at the first heading.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::lint_first_heading_level;
+///
+/// let warnings = lint_first_heading_level("```\n# Code\n```\n\n## Install\n");
+/// assert_eq!(warnings.len(), 1);
+/// assert_eq!(warnings[0].line, 5);
+/// assert_eq!(warnings[0].message, "first heading is level 2, not level 1");
+/// assert!(lint_first_heading_level("No headings\n").is_empty());
+/// ```
+pub fn lint_first_heading_level(content: &str) -> Vec<LintWarning> {
+    let lines: Vec<&str> = content.lines().collect();
+    heading_spans(&lines)
+        .first()
+        .filter(|heading| heading.level != 1)
+        .map(|heading| {
+            let message = format!("first heading is level {}, not level 1", heading.level);
+            heading_warning("first-heading-level", &lines, heading, message)
+        })
+        .into_iter()
+        .collect()
+}
+
+/// Find headings more than one level deeper than the heading before them, e.g. a
+/// `####` heading directly under a `##` section, which skips a level of the
+/// outline (markdownlint MD001).
+///
+/// Going back up any number of levels is fine. Headings are found as in
+/// [`lint_multiple_h1`].
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to check
+///
+/// # Returns
+///
+/// One `LintWarning` per heading that skips levels.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::lint_heading_increment;
+///
+/// let warnings = lint_heading_increment("# Guide\n\n## Setup\n\n#### Linux\n\n## Usage\n");
+/// assert_eq!(warnings.len(), 1);
+/// assert_eq!(warnings[0].line, 5);
+/// assert_eq!(warnings[0].message, "heading level jumps from 2 to 4");
+/// ```
+pub fn lint_heading_increment(content: &str) -> Vec<LintWarning> {
+    let lines: Vec<&str> = content.lines().collect();
+    heading_spans(&lines)
+        .windows(2)
+        .filter(|pair| pair[1].level > pair[0].level + 1)
+        .map(|pair| {
+            let message = format!(
+                "heading level jumps from {} to {}",
+                pair[0].level, pair[1].level
+            );
+            heading_warning("heading-increment", &lines, &pair[1], message)
+        })
+        .collect()
+}
+
+/// The warning of `rule` for `heading`, pointing at its first `#` or the start of
+/// its text.
+fn heading_warning(
+    rule: &'static str,
+    lines: &[&str],
+    heading: &HeadingSpan,
+    message: String,
+) -> LintWarning {
+    let line = lines[heading.start];
+    LintWarning {
+        rule,
+        line: heading.start + 1,
+        column: line.chars().take_while(|c| c.is_whitespace()).count() + 1,
+        message,
+    }
+}
+
+/// Remove ATX headings that have no text at all, such as a lone `##` or `## ##`.
+///
+/// The blank lines after a removed heading go with it, so no run of blank lines is
+/// left where it was. A blank line takes its place when it stood directly between
+/// two other lines, which would otherwise run into one paragraph. The only heading
+/// of a document is kept, and so are headings in frontmatter, code fences, math
+/// blocks, and indented code. Headings with text but no content are only reported
+/// by [`lint_empty_headings`], since they may be placeholders for sections to come.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to fix
+///
+/// # Returns
+///
+/// The content without its empty headings.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lint::remove_empty_headings;
+///
+/// let input = "# Guide\n\n###\n\nIntro\n##\nMore\n";
+/// assert_eq!(remove_empty_headings(input), "# Guide\n\nIntro\n\nMore\n");
+/// assert_eq!(remove_empty_headings("#\n\nText\n"), "#\n\nText\n");
+/// ```
+pub fn remove_empty_headings(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let headings = heading_spans(&trimmed);
+    if headings.len() < 2 {
+        return content.to_string();
+    }
+    let mut removed = headings
+        .iter()
+        .filter(|heading| heading.empty)
+        .map(|heading| heading.start)
+        .peekable();
+    let mut output = String::with_capacity(content.len());
+    // Whether the last line written is blank, or nothing was written yet
+    let mut after_blank = true;
+    let mut i = 0;
+
+    while i < lines.len() {
+        if removed.next_if_eq(&i).is_none() {
+            output.push_str(lines[i]);
+            after_blank = trimmed[i].trim().is_empty();
+            i += 1;
+            continue;
+        }
+        let mut next = i + 1;
+        while next < lines.len() && trimmed[next].trim().is_empty() {
+            next += 1;
+        }
+        if !after_blank && next < lines.len() {
+            output.push_str(&lines[i][trimmed[i].len()..]);
+            after_blank = true;
+        }
+        i = next;
+    }
+
+    output
+}
+
+/// A heading found by [`heading_spans`].
+struct HeadingSpan {
+    /// The 0-based line the heading starts on: the text of a setext heading.
+    start: usize,
+    /// The 0-based line the heading ends on: the underline of a setext heading.
+    end: usize,
+    level: usize,
+    /// Whether the heading has no text, only `#` markers.
+    empty: bool,
+}
+
+/// The ATX and setext headings of regular text outside indented code, in document
+/// order.
+fn heading_spans(lines: &[&str]) -> Vec<HeadingSpan> {
+    let code = indented_code_lines(lines, &line_regions(lines), 4);
+    let mut headings: Vec<HeadingSpan> = atx_headings(lines)
+        .map(|(i, level)| HeadingSpan {
+            start: i,
+            end: i,
+            level,
+            empty: lines[i][atx_heading_text(lines[i], level)]
+                .trim()
+                .is_empty(),
+        })
+        .chain(setext_underlines(lines).map(|i| HeadingSpan {
+            start: i - 1,
+            end: i,
+            level: if lines[i].trim_start().starts_with('=') {
+                1
+            } else {
+                2
+            },
+            empty: false,
+        }))
+        .filter(|heading| !code[heading.start])
+        .collect();
+    headings.sort_by_key(|heading| heading.start);
+    headings
+}
+
+/// Whether the `k`th of `headings` is followed by anything before the next heading
+/// of the same or a higher level.
+fn has_content(lines: &[&str], headings: &[HeadingSpan], k: usize) -> bool {
+    let heading = &headings[k];
+    let Some(next) = (heading.end + 1..lines.len()).find(|&i| !lines[i].trim().is_empty()) else {
+        return false;
+    };
+    !headings
+        .get(k + 1)
+        .is_some_and(|following| following.start == next && following.level <= heading.level)
+}
+
+/// The warning reported for an image with blank alt text on the 0-based line `index`.
+fn missing_alt_warning(line: &str, index: usize, image: &MissingAltImage) -> LintWarning {
+    LintWarning {
+        rule: "missing-alt-text",
+        line: index + 1,
+        column: line[..image.syntax.start].chars().count() + 1,
+        message: format!("image `{}` has no alt text", &line[image.syntax.clone()]),
+    }
+}
+
+/// The file name of an image source without its extension, query, or fragment.
+fn file_stem(source: &str) -> Option<&str> {
+    let path = source.split(['?', '#']).next().unwrap_or(source);
+    Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .filter(|stem| !stem.is_empty())
+}
+
+/// Byte ranges of inline code spans (including their backtick delimiters) in a line.
+///
+/// A code span opens with a run of backticks and closes with the next run of the
+/// same length; unmatched runs are literal text.
+pub(crate) fn code_span_ranges(line: &str) -> Vec<Range<usize>> {
+    let bytes = line.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'`' {
+            i += 1;
+            continue;
+        }
+        let open_start = i;
+        while i < bytes.len() && bytes[i] == b'`' {
+            i += 1;
+        }
+        let run_length = i - open_start;
+
+        // Look for a closing run of exactly the same length
+        let mut j = i;
+        let mut close_end = None;
+        while j < bytes.len() {
+            if bytes[j] == b'`' {
+                let run_start = j;
+                while j < bytes.len() && bytes[j] == b'`' {
+                    j += 1;
+                }
+                if j - run_start == run_length {
+                    close_end = Some(j);
+                    break;
+                }
+            } else {
+                j += 1;
+            }
+        }
+
+        if let Some(end) = close_end {
+            spans.push(open_start..end);
+            i = end;
+        }
+    }
+
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_bare_urls_ignores_wrapped_urls() {
+        let input = "\
+[link](https://a.example)
+<https://b.example>
+[https://c.example](https://c.example)
+[ref]: https://d.example
+![image](https://e.example/x.png)
+<a href=\"https://f.example\">f</a>
+Use `https://g.example` here
+";
+        assert!(lint_bare_urls(input).is_empty());
+    }
+
+    #[test]
+    fn test_lint_bare_urls_skips_protected_regions() {
+        let input = "---\nurl: https://a.example\n---\n\n```\nhttps://b.example\n```\n\nhttps://c.example\n";
+        let warnings = lint_bare_urls(input);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].line, 9);
+        assert_eq!(warnings[0].rule, "bare-url");
+    }
+
+    #[test]
+    fn test_fix_bare_urls() {
+        let input =
+            "See https://a.example/path?q=1, then http://b.example.\r\n`https://c.example`\n";
+        let expected =
+            "See <https://a.example/path?q=1>, then <http://b.example>.\r\n`https://c.example`\n";
+        assert_eq!(fix_bare_urls(input), expected);
+        // Fixing is idempotent
+        assert_eq!(fix_bare_urls(expected), expected);
+    }
+
+    #[test]
+    fn test_lint_missing_alt_text() {
+        let input = "\
+![](a.png) ![ ](b.png \"B\") ![Described](c.png)
+![][untitled] ![][titled] ![][missing] ![][]
+`![](code.png)`
+
+```
+![](fenced.png)
+```
+
+[untitled]: images/untitled.png
+[Titled]: images/titled.png \"A titled image\"
+";
+        let warnings = lint_missing_alt_text(input);
+        let found: Vec<_> = warnings
+            .iter()
+            .map(|warning| (warning.line, warning.column))
+            .collect();
+        assert_eq!(found, vec![(1, 1), (1, 12), (2, 1), (2, 27), (2, 40)]);
+        assert_eq!(warnings[0].rule, "missing-alt-text");
+        assert_eq!(warnings[2].message, "image `![][untitled]` has no alt text");
+    }
+
+    #[test]
+    fn test_fix_missing_alt_text() {
+        let input = "![](img/a-chart.png?raw=1) ![][logo] ![][]\r\n\n[logo]: <img/logo.svg>\n";
+        let expected =
+            "![a-chart](img/a-chart.png?raw=1) ![logo][logo] ![][]\r\n\n[logo]: <img/logo.svg>\n";
+        assert_eq!(fix_missing_alt_text(input), expected);
+        // Fixing is idempotent
+        assert_eq!(fix_missing_alt_text(expected), expected);
+    }
+
+    #[test]
+    fn test_fix_missing_alt_text_with_callback() {
+        let mut asked = Vec::new();
+        let output = fix_missing_alt_text_with("![](a.png) ![](b.png)\n", |warning, _| {
+            asked.push(warning.column);
+            (warning.column == 1).then(|| "First".to_string())
+        });
+        assert_eq!(output, "![First](a.png) ![](b.png)\n");
+        assert_eq!(asked, vec![1, 12]);
+    }
+
+    #[test]
+    fn test_lint_missing_fence_language() {
+        let input = "````markdown\n```\nx\n```\n````\n\n  ~~~ \ny\n~~~\n\n```rust\n```\n";
+        let warnings = lint_missing_fence_language(input);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].line, warnings[0].column), (7, 3));
+    }
+
+    #[test]
+    fn test_lint_inconsistent_fence_style() {
+        // Fences inside other fences do not count
+        let input = "~~~~md\n```\nx\n```\n~~~~\n\n  ```rust\n  ```\n\n~~~\n~~~\n";
+        let warning = lint_inconsistent_fence_style(input).unwrap();
+        assert_eq!((warning.line, warning.column), (7, 3));
+        assert!(warning.message.contains("`--fence-style tilde`"));
+        // A tie reports the fences that differ from the first one
+        let warning = lint_inconsistent_fence_style("```\n```\n~~~\n~~~\n").unwrap();
+        assert_eq!(warning.line, 3);
+        assert!(warning.message.contains("`--fence-style backtick`"));
+        assert_eq!(lint_inconsistent_fence_style(""), None);
+        assert_eq!(
+            lint_inconsistent_fence_style("~~~\n~~~\n$$\n```\n$$\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_lint_zero_width_characters() {
+        let input =
+            "---\ntitle: A\u{AD}B\n---\n\nplain\nx\u{200D}y\u{200C}z\u{200D}\n\n$$\n\u{200B}\n$$\n";
+        let warnings = lint_zero_width_characters(input);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!((warnings[0].line, warnings[0].column), (2, 9));
+        assert_eq!(warnings[0].message, "invisible soft hyphen (U+00AD)");
+        assert_eq!((warnings[1].line, warnings[1].column), (6, 2));
+        assert_eq!(
+            warnings[1].message,
+            "invisible zero-width non-joiner (U+200C), zero-width joiner (U+200D)"
+        );
+    }
+
+    #[test]
+    fn test_heading_trailing_punctuation() {
+        let input = concat!(
+            "---\ntitle: Draft.\n---\n\n",
+            "# Intro.\r\n",
+            "## Why? ##\n",
+            "### Wait...\n",
+            "#### Really?!\n",
+            "##### Version 2\\.\n",
+            "###### Use `x.`\n",
+            "# See [the docs!\n",
+            "#hashtag.\n",
+            "# Done ! !\n",
+            "# ?\n\n",
+            "Is it done?\n===\n\n",
+            "- Not a title.\n---\n\n",
+            "```\n# Code.\n```\n",
+        );
+        let found: Vec<_> = lint_heading_trailing_punctuation(input)
+            .into_iter()
+            .map(|w| (w.line, w.column, w.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (5, 8, "heading ends with punctuation `.`".to_string()),
+                (6, 7, "heading ends with punctuation `?`".to_string()),
+                (13, 7, "heading ends with punctuation `! !`".to_string()),
+                (16, 11, "heading ends with punctuation `?`".to_string()),
+            ]
+        );
+
+        let fixed = fix_heading_punctuation(input);
+        assert!(fixed.contains("# Intro\r\n## Why ##\n### Wait...\n"));
+        assert!(fixed.contains("\n# Done\n# ?\n\nIs it done\n===\n"));
+        assert!(fixed.starts_with("---\ntitle: Draft.\n"));
+        assert!(fixed.ends_with("- Not a title.\n---\n\n```\n# Code.\n```\n"));
+        assert!(lint_heading_trailing_punctuation(&fixed).is_empty());
+    }
+
+    #[test]
+    fn test_empty_headings() {
+        // Setext headings, lower-level subsections, and fences count as content
+        let input = "Title\n=====\n\n## A\n\n### A.1\n\n```\n# code\n```\n\n  ## ##\n\nB\n---\n";
+        let found: Vec<_> = lint_empty_headings(input)
+            .into_iter()
+            .map(|w| (w.line, w.column, w.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (12, 3, "heading has no text".to_string()),
+                (14, 1, "heading has no content".to_string()),
+            ]
+        );
+        assert!(lint_empty_headings("# Title\n").is_empty());
+        assert_eq!(lint_empty_headings("#\n    ##\n").len(), 1);
+
+        let cases = [
+            // Adjacent empty headings leave one blank line between their neighbours
+            ("# A\nText\n##\n###\nMore\n", "# A\nText\n\nMore\n"),
+            ("# A\r\n\r\n##\r\n\r\n\r\nText\r\n", "# A\r\n\r\nText\r\n"),
+            // At the end of the document nothing takes their place
+            ("# A\n\nText\n#", "# A\n\nText\n"),
+            // The only heading, and headings in code and frontmatter, are kept
+            ("---\n#\n---\n##\n", "---\n#\n---\n##\n"),
+            (
+                "# A\n\n    ##\n~~~\n##\n~~~\n",
+                "# A\n\n    ##\n~~~\n##\n~~~\n",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(remove_empty_headings(input), expected, "input: {:?}", input);
+            assert_eq!(remove_empty_headings(expected), expected);
+        }
+    }
+
+    #[test]
+    fn test_heading_structure() {
+        let lines = |warnings: Vec<LintWarning>| -> Vec<usize> {
+            warnings.into_iter().map(|warning| warning.line).collect()
+        };
+        // Frontmatter, fences, and indented code have no headings; setext ones count
+        let input = "---\n# title\n---\n\n```\n# Code\n```\n\n    # Indented\n\nGuide\n=====\n\n### Deep\n\n# Again\n\n## Back\n\n#### Skip\n";
+        assert_eq!(lines(lint_multiple_h1(input)), [16]);
+        assert!(lint_first_heading_level(input).is_empty());
+        let warnings = lint_heading_increment(input);
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|w| (w.line, w.column, w.message.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (14, 1, "heading level jumps from 1 to 3"),
+                (20, 1, "heading level jumps from 2 to 4"),
+            ]
+        );
+
+        let input = "Intro\n\nSetup\n-----\n\n  #### Next\n\n# Title\n";
+        assert_eq!(lines(lint_first_heading_level(input)), [3]);
+        assert_eq!(lint_heading_increment(input)[0].column, 3);
+        assert!(lint_multiple_h1(input).is_empty());
+    }
+
+    #[test]
+    fn test_lint_all() {
+        let input = "---\nlink: https://example.com\n---\n\n```\nhttps://example.com\n```\n\n![](a.png) https://example.com\u{200B}\n";
+        let violations = lint_all(input, &LintConfig::default());
+        let found: Vec<_> = violations
+            .iter()
+            .map(|v| (v.rule_id, v.severity, v.line, v.col))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("fence-language", Severity::Warning, 5, Some(1)),
+                ("missing-alt-text", Severity::Error, 9, Some(1)),
+                ("bare-url", Severity::Warning, 9, Some(12)),
+                ("zero-width-character", Severity::Warning, 9, Some(31)),
+            ]
+        );
+        assert_eq!(violations[0].suggestion, None);
+        assert_eq!(
+            violations[1].suggestion.as_deref(),
+            Some("![a](a.png) https://example.com\u{200B}")
+        );
+
+        let config = LintConfig {
+            missing_alt_text: Some(Severity::Info),
+            missing_fence_language: None,
+            ..LintConfig::default()
+        };
+        let violations = lint_all(input, &config);
+        assert_eq!(violations.len(), 3);
+        assert_eq!(violations[0].severity, Severity::Info);
+        assert!(lint_all("# Clean\n", &LintConfig::default()).is_empty());
+
+        let violations = lint_all("---\ntags: [a\n---\n", &LintConfig::default());
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].code(), "MB106");
+        assert_eq!(violations[0].severity, Severity::Error);
+        assert_eq!(violations[0].line, 3);
+    }
+
+    #[test]
+    fn test_lint_all_reports_blank_line_problems() {
+        let input = "---\ntitle: T\n---\nText\n\n\n\n- item\n> quote\n\n```\n\ncode\n```\n\n\n";
+        let config = LintConfig {
+            missing_fence_language: None,
+            ..LintConfig::default()
+        };
+        let found: Vec<_> = lint_all(input, &config)
+            .into_iter()
+            .map(|v| (v.code(), v.line, v.message))
+            .collect();
+        let expected = [
+            ("MB008", 3, "missing blank line after frontmatter"),
+            ("MB001", 6, "multiple consecutive blank lines"),
+            ("MB007", 8, "missing blank line after list"),
+            ("MB005", 12, "blank line directly inside code fence"),
+            ("MB002", 15, "blank lines at end of file"),
+        ];
+        assert_eq!(
+            found,
+            expected.map(|(code, line, message)| (code, line, message.to_string()))
+        );
+
+        let config = LintConfig {
+            formatting: None,
+            ..config
+        };
+        assert!(lint_all(input, &config).is_empty());
+    }
+
+    #[test]
+    fn test_lint_all_skips_files_without_a_body() {
+        // The formatter leaves these alone, so --check finds nothing to change
+        for input in ["---\ntitle: t\n---\n\n\n", "\n\n\n", ""] {
+            assert!(
+                lint_all(input, &LintConfig::default()).is_empty(),
+                "{:?}",
+                input
+            );
+        }
+
+        // Unless it formats frontmatter-only files too
+        let config = LintConfig {
+            include_empty: true,
+            ..LintConfig::default()
+        };
+        let found: Vec<_> = lint_all("---\ntitle: t\n---\n\n\n", &config)
+            .into_iter()
+            .map(|v| (v.code(), v.line))
+            .collect();
+        assert_eq!(found, [("MB002", 4)]);
+    }
+
+    #[test]
+    fn test_code_span_ranges() {
+        assert_eq!(code_span_ranges("a `b` c"), vec![2..5]);
+        assert_eq!(code_span_ranges("``a ` b`` `c"), vec![0..9]);
+        assert!(code_span_ranges("no `spans").is_empty());
+    }
+}
@@ -0,0 +1,445 @@
+//! Nested list structure and list indentation.
+//!
+//! Lists are followed the way CommonMark nests them: an item is nested in the item
+//! above it when its marker starts at or after the column where that item's
+//! content starts, and a line belongs to the deepest open item whose content it is
+//! indented to. [`reindent_lists`] uses that structure to give every nesting level
+//! the same indentation, whatever the original columns were.
+
+use crate::process_md::{LineRegion, line_regions};
+
+/// An item whose content later lines can still belong to.
+#[derive(Debug, Clone, Copy)]
+struct OpenItem {
+    /// Column where the content of the item starts in the input.
+    content_column: usize,
+    /// Column of the marker in the output.
+    new_column: usize,
+    /// Column where the content of the item starts in the output.
+    new_content_column: usize,
+}
+
+impl OpenItem {
+    /// The column a line of this item at `column` in the input moves to.
+    fn shift(&self, column: usize) -> usize {
+        (column + self.new_content_column).saturating_sub(self.content_column)
+    }
+}
+
+/// Split `line` into the width of its indentation, with tabs expanded to the next
+/// multiple of `tab_width`, and the rest.
+pub(crate) fn indentation(line: &str, tab_width: usize) -> (usize, &str) {
+    let mut column = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            ' ' => column += 1,
+            '\t' => column += tab_width - column % tab_width,
+            _ => return (column, &line[i..]),
+        }
+    }
+    (column, "")
+}
+
+/// The length of the list marker `text` starts with, e.g. 1 for `- item` and 3 for
+/// `10. item`, or `None` if `text` does not start with a list item.
+pub(crate) fn marker_len(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let len = match bytes.first()? {
+        b'-' | b'*' | b'+' => 1,
+        b'0'..=b'9' => {
+            let digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+            if digits > 9 || !matches!(bytes.get(digits), Some(b'.' | b')')) {
+                return None;
+            }
+            digits + 1
+        }
+        _ => return None,
+    };
+    match bytes.get(len) {
+        None | Some(b' ' | b'\t') => Some(len),
+        _ => None,
+    }
+}
+
+/// Check whether `text` is a thematic break written with spaces, e.g. `* * *`,
+/// which starts like a list item.
+fn is_spaced_thematic_break(text: &str) -> bool {
+    let marker = text.as_bytes()[0];
+    text.bytes().filter(|&b| b == marker).count() >= 3
+        && text.bytes().all(|b| b == marker || b == b' ' || b == b'\t')
+}
+
+/// Re-indent nested list items to `indent` spaces per nesting level.
+///
+/// Each item's level comes from the structure of the list, not from its column, so
+/// lists indented by 2, 3, or 4 spaces, or by tabs of `tab_width` columns, all end
+/// up the same. Top-level items keep their column. Nested items are never
+/// indented less than the content of their parent item, since they would not be
+/// nested anymore, so items under `1.` get at least 3 spaces.
+///
+/// Continuation lines, including code fences in list items, move together with
+/// their item, so the content of a fence keeps its indentation relative to the
+/// fence. A line indented less than the content of every open item ends the list,
+/// even right below an item, since the formatter separates it from the list with a
+/// blank line anyway. Everything outside lists, and frontmatter and math blocks, is
+/// kept.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to re-indent
+/// * `indent` - The number of spaces per nesting level
+/// * `tab_width` - The number of columns a tab in the indentation advances to
+///
+/// # Returns
+///
+/// The content with its lists re-indented; line endings are kept.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lists::reindent_lists;
+///
+/// let input = "- a\n   - b\n\n     More b\n\t- c\n1. d\n   1. e\n";
+/// assert_eq!(
+///     reindent_lists(input, 4, 4),
+///     "- a\n    - b\n\n      More b\n    - c\n1. d\n    1. e\n"
+/// );
+/// assert_eq!(reindent_lists("1. a\n    - b\n", 2, 4), "1. a\n   - b\n");
+/// ```
+pub fn reindent_lists(content: &str, indent: usize, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let mut open: Vec<OpenItem> = Vec::new();
+    // How far the code fence being passed through moved
+    let mut fence_shift: Option<(usize, usize)> = None;
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        let text = trimmed[i];
+        let (column, rest) = indentation(text, tab_width);
+        let new_column = match regions[i] {
+            LineRegion::Frontmatter | LineRegion::MathBlock => None,
+            LineRegion::CodeFence => {
+                match fence_shift {
+                    Some((from, to)) if !rest.is_empty() => {
+                        // Lines keep their indentation relative to the fence, and
+                        // lines indented less than the fence are never moved right
+                        let spaces = text.len() - text.trim_start_matches(' ').len();
+                        let new_spaces = if spaces >= from {
+                            spaces - from + to
+                        } else {
+                            spaces.min(to)
+                        };
+                        output.push_str(&" ".repeat(new_spaces));
+                        output.push_str(&line[spaces..]);
+                        continue;
+                    }
+                    _ => None,
+                }
+            }
+            _ if rest.is_empty() => None,
+            region => {
+                let item = marker_len(rest)
+                    .filter(|_| !is_spaced_thematic_break(rest))
+                    .filter(|_| region == LineRegion::Text);
+                let new_column = match item {
+                    Some(len) => {
+                        while open.last().is_some_and(|item| column < item.content_column) {
+                            open.pop();
+                        }
+                        if open.is_empty() && column >= 4 {
+                            // An indented code block, or text continuing a paragraph
+                            None
+                        } else {
+                            let new_column = open.last().map_or(column, |parent| {
+                                (parent.new_column + indent).max(parent.new_content_column)
+                            });
+                            let (after, content) = indentation(&rest[len..], tab_width);
+                            let spacing = if content.is_empty() || after > 4 {
+                                1
+                            } else {
+                                after
+                            };
+                            open.push(OpenItem {
+                                content_column: column + len + spacing,
+                                new_column,
+                                new_content_column: new_column + len + spacing,
+                            });
+                            Some(new_column)
+                        }
+                    }
+                    // The line belongs to the deepest item it is indented to
+                    None => match open.iter().rposition(|item| column >= item.content_column) {
+                        Some(index) => {
+                            // It must stay out of the deeper items it ends
+                            let new_column =
+                                open.get(index + 1)
+                                    .map_or(open[index].shift(column), |closed| {
+                                        open[index].shift(column).min(closed.new_content_column - 1)
+                                    });
+                            open.truncate(index + 1);
+                            Some(new_column)
+                        }
+                        None => {
+                            open.clear();
+                            None
+                        }
+                    },
+                };
+                if region == LineRegion::CodeFenceOpening {
+                    fence_shift = new_column.map(|to| (column, to));
+                }
+                new_column
+            }
+        };
+
+        match new_column {
+            Some(new_column) => {
+                output.push_str(&" ".repeat(new_column));
+                output.push_str(&line[text.len() - rest.len()..]);
+            }
+            None => output.push_str(line),
+        }
+    }
+
+    output
+}
+
+/// Find the lines of indented code blocks, following the list items they are
+/// nested in.
+///
+/// A line is indented code when it is indented 4 or more columns past the content
+/// of the item it belongs to, or past the margin outside lists, and it does not
+/// continue a paragraph. Lines outside [`LineRegion::Text`] are never indented
+/// code.
+pub(crate) fn indented_code_lines(
+    lines: &[&str],
+    regions: &[LineRegion],
+    tab_width: usize,
+) -> Vec<bool> {
+    let tab_width = tab_width.max(1);
+    // Where the content of each open item starts
+    let mut open: Vec<usize> = Vec::new();
+    let mut in_paragraph = false;
+    let mut code = Vec::with_capacity(lines.len());
+
+    for (line, region) in lines.iter().zip(regions) {
+        let (column, rest) = indentation(line, tab_width);
+        if rest.is_empty() || *region != LineRegion::Text {
+            in_paragraph = false;
+            code.push(false);
+            continue;
+        }
+        let container = open
+            .iter()
+            .rposition(|&content_column| column >= content_column);
+        open.truncate(container.map_or(0, |index| index + 1));
+        if !in_paragraph && column >= open.last().copied().unwrap_or(0) + 4 {
+            code.push(true);
+            continue;
+        }
+        if let Some(len) = marker_len(rest).filter(|_| !is_spaced_thematic_break(rest)) {
+            let (after, content) = indentation(&rest[len..], tab_width);
+            let spacing = if content.is_empty() || after > 4 {
+                1
+            } else {
+                after
+            };
+            open.push(column + len + spacing);
+        }
+        in_paragraph = !rest.starts_with('#');
+        code.push(false);
+    }
+
+    code
+}
+
+/// Put exactly one space between a list marker and the item text.
+///
+/// Lines starting with a bullet (`-`, `*`, or `+`) or an ordered marker (`1.` or
+/// `1)`), after any indentation, get a single space after the marker, replacing
+/// spaces and tabs. Items without text are left as they are, and so are items
+/// whose text is 5 or more columns away, since they start with an indented code
+/// block, and thematic breaks like `* * *`. Other lines are returned unchanged.
+///
+/// # Arguments
+///
+/// * `line` - A line without its line ending
+///
+/// # Returns
+///
+/// The line with one space after its list marker.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::lists::normalize_list_marker_spacing;
+///
+/// assert_eq!(normalize_list_marker_spacing("-   item"), "- item");
+/// assert_eq!(normalize_list_marker_spacing("  10)\titem"), "  10) item");
+/// assert_eq!(normalize_list_marker_spacing("-  "), "-  ");
+/// assert_eq!(normalize_list_marker_spacing("-item"), "-item");
+/// ```
+pub fn normalize_list_marker_spacing(line: &str) -> String {
+    let rest = line.trim_start_matches([' ', '\t']);
+    let Some(len) = marker_len(rest).filter(|_| !is_spaced_thematic_break(rest)) else {
+        return line.to_string();
+    };
+    let (after, text) = indentation(&rest[len..], 4);
+    if text.is_empty() || after > 4 || rest[len..].starts_with(' ') && after == 1 {
+        return line.to_string();
+    }
+    let marker_end = line.len() - rest.len() + len;
+    format!("{} {}", &line[..marker_end], text)
+}
+
+/// Apply [`normalize_list_marker_spacing`] to every line of regular text.
+pub(crate) fn normalize_list_markers(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        let text = trimmed[i];
+        if regions[i] == LineRegion::Text {
+            output.push_str(&normalize_list_marker_spacing(text));
+            output.push_str(&line[text.len()..]);
+        } else {
+            output.push_str(line);
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reindent_lists() {
+        let cases = [
+            // Mixed indentation becomes one width per level
+            (
+                "- a\n  - b\n     - c\n  - d\n- e\n",
+                "- a\n    - b\n        - c\n    - d\n- e\n",
+            ),
+            // Tabs count up to the next tab stop
+            ("- a\n\t- b\n\t\t- c\n", "- a\n    - b\n        - c\n"),
+            // Items under ordered items need room for the marker, and an item
+            // indented less than that is not nested at all
+            (
+                "1. a\n   - b\n10. c\n    - d\n11. e\n  - f\n",
+                "1. a\n    - b\n10. c\n    - d\n11. e\n  - f\n",
+            ),
+            // Continuation paragraphs move with their item, and less indented
+            // lines end the list
+            (
+                "- a\n  - b\n    b2\n\n    More b\n\n  More a\nlazy\n  - c\n",
+                "- a\n    - b\n      b2\n\n      More b\n\n  More a\nlazy\n  - c\n",
+            ),
+            // Text after the list and indented code blocks are not list items
+            (
+                "- a\n   - b\n\nText\n    - code\n",
+                "- a\n    - b\n\nText\n    - code\n",
+            ),
+            (
+                "- a\n  # Heading\n# Top\n  Text\n",
+                "- a\n  # Heading\n# Top\n  Text\n",
+            ),
+            ("* * *\n  - a\n   - b\n", "* * *\n  - a\n   - b\n"),
+            // Lines that end a nested item stay out of it
+            ("- a\n      - b\n     c\n", "- a\n    - b\n     c\n"),
+            (
+                "---\n- a\n  - b\n---\n\n$$\n  - x\n$$\n",
+                "---\n- a\n  - b\n---\n\n$$\n  - x\n$$\n",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(reindent_lists(input, 4, 4), expected, "{:?}", input);
+            assert_eq!(reindent_lists(expected, 4, 4), expected, "{:?}", expected);
+        }
+    }
+
+    #[test]
+    fn test_reindent_lists_moves_code_fences() {
+        let input = "- a\n  - b\n\n    ```rust\n    fn main() {\n        run();\n    }\n    ```\r\n- c\n```\n  - x\n```\n";
+        let expected = "- a\n   - b\n\n     ```rust\n     fn main() {\n         run();\n     }\n     ```\r\n- c\n```\n  - x\n```\n";
+        assert_eq!(reindent_lists(input, 3, 4), expected);
+        assert_eq!(reindent_lists(expected, 2, 4), input);
+    }
+
+    #[test]
+    fn test_indented_code_lines() {
+        let lines = [
+            "Text",
+            "    continued",
+            "",
+            "    code",
+            "\tcode",
+            "- item",
+            "",
+            "      code in the item",
+            "  item text",
+            "",
+            "      code in the item",
+            "# Heading",
+            "    code",
+            "```",
+            "    fenced",
+            "```",
+        ];
+        let regions = line_regions(&lines);
+        let code: Vec<usize> = indented_code_lines(&lines, &regions, 4)
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &code)| code.then_some(i))
+            .collect();
+        assert_eq!(code, [3, 4, 7, 10, 12]);
+    }
+
+    #[test]
+    fn test_normalize_list_marker_spacing() {
+        for (line, expected) in [
+            ("-  item", "- item"),
+            ("*\titem", "* item"),
+            ("+    four spaces", "+ four spaces"),
+            ("    1.  nested", "    1. nested"),
+            ("2)  two", "2) two"),
+            ("- already", "- already"),
+            // Indented code in an item, blank items, and things that are no items
+            ("-      code", "-      code"),
+            ("-", "-"),
+            ("1.   ", "1.   "),
+            ("*  *  *", "*  *  *"),
+            ("*emphasis*  text", "*emphasis*  text"),
+            ("1.5  km", "1.5  km"),
+        ] {
+            assert_eq!(normalize_list_marker_spacing(line), expected, "{:?}", line);
+        }
+        assert_eq!(
+            normalize_list_markers("-  a\r\n```\n-  code\n```\n>  -  quote\n"),
+            "- a\r\n```\n-  code\n```\n>  -  quote\n"
+        );
+    }
+
+    #[test]
+    fn test_indentation() {
+        assert_eq!(indentation("  \t- a", 4), (4, "- a"));
+        assert_eq!(indentation("\t \t", 2), (4, ""));
+        assert_eq!(marker_len("- a"), Some(1));
+        assert_eq!(marker_len("12) a"), Some(3));
+        assert_eq!(marker_len("-"), Some(1));
+        assert_eq!(marker_len("-a"), None);
+        assert_eq!(marker_len("1.5 km"), None);
+    }
+}
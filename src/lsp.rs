@@ -0,0 +1,724 @@
+//! A language server that formats documents for editors.
+//!
+//! [`run`] speaks the Language Server Protocol over a pair of streams, normally
+//! stdin and stdout, and implements only `textDocument/formatting` and
+//! `textDocument/rangeFormatting`. Editors send the text of open documents with
+//! `textDocument/didOpen` and `textDocument/didChange` (full document sync), and the
+//! formatting requests are answered from that text, so unsaved changes are
+//! formatted and the files on disk are never read or written.
+//!
+//! Each request is formatted with the options of the file its URI points to, like
+//! `mdfmt` would format that file. The `FormattingOptions` of the request give the
+//! defaults those options are applied to:
+//!
+//! | `FormattingOptions`      | mdfmt option                      |
+//! |--------------------------|-----------------------------------|
+//! | `tabSize`                | `tab_width`                       |
+//! | `insertFinalNewline`     | `insert_final_newline` when true  |
+//! | `trimTrailingWhitespace` | `trim_trailing_whitespace` when true |
+//!
+//! Responses are lists of `TextEdit`s that replace only the lines that changed.
+
+use crate::config::Config;
+use crate::process_md::{ContentMode, format_range_edit, format_with_config};
+use log::debug;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// The JSON-RPC error code for a message that is not valid JSON.
+const PARSE_ERROR: i64 = -32700;
+/// The JSON-RPC error code for a message that is not a valid request.
+const INVALID_REQUEST: i64 = -32600;
+/// The JSON-RPC error code for a method the server does not implement.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// The JSON-RPC error code for parameters that do not fit the method.
+const INVALID_PARAMS: i64 = -32602;
+/// The JSON-RPC error code for a request that failed.
+const INTERNAL_ERROR: i64 = -32603;
+/// The LSP error code for a request sent before `initialize`.
+const SERVER_NOT_INITIALIZED: i64 = -32002;
+
+/// Documents longer than this many lines, multiplied, are diffed as a whole.
+const MAX_DIFF_CELLS: usize = 1_000_000;
+
+/// A JSON-RPC error response.
+type RpcError = (i64, String);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextDocumentIdentifier {
+    uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextDocumentItem {
+    uri: String,
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidOpenParams {
+    text_document: TextDocumentItem,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContentChange {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidChangeParams {
+    text_document: TextDocumentIdentifier,
+    content_changes: Vec<ContentChange>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidCloseParams {
+    text_document: TextDocumentIdentifier,
+}
+
+/// The editor settings sent with formatting requests.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FormattingOptions {
+    tab_size: u32,
+    insert_final_newline: Option<bool>,
+    trim_trailing_whitespace: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FormattingParams {
+    text_document: TextDocumentIdentifier,
+    options: FormattingOptions,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Position {
+    line: usize,
+    character: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Range {
+    start: Position,
+    end: Position,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RangeFormattingParams {
+    text_document: TextDocumentIdentifier,
+    range: Range,
+    options: FormattingOptions,
+}
+
+/// Serve LSP requests read from `input` until the client sends `exit` or closes
+/// the stream, writing the responses to `output`.
+///
+/// # Arguments
+///
+/// * `input` - Messages from the client, each with a `Content-Length` header
+/// * `output` - Where responses are written, each flushed as soon as it is written
+/// * `resolve` - The configuration of the file at the given path, or of documents
+///   that are not files (such as unsaved buffers) with `None`
+///
+/// # Returns
+///
+/// Whether the client asked the server to shut down before it exited, which is
+/// what makes the exit a clean one.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::config::Config;
+/// use mdfmt::lsp::run;
+///
+/// let messages = [
+///     r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+///     r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+///     r#"{"jsonrpc":"2.0","method":"exit"}"#,
+/// ];
+/// let input: String = messages
+///     .iter()
+///     .map(|message| format!("Content-Length: {}\r\n\r\n{}", message.len(), message))
+///     .collect();
+/// let mut output = Vec::new();
+/// let clean = run(input.as_bytes(), &mut output, |_| Ok(Config::default()))?;
+/// assert!(clean);
+/// assert!(String::from_utf8(output).unwrap().contains("documentFormattingProvider"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading the input or writing the output fails, or a
+/// message header is invalid. Invalid messages and failed requests get an error
+/// response instead.
+pub fn run<R, W, F>(mut input: R, mut output: W, resolve: F) -> io::Result<bool>
+where
+    R: BufRead,
+    W: Write,
+    F: FnMut(Option<&Path>) -> io::Result<Config>,
+{
+    let mut server = Server {
+        documents: HashMap::new(),
+        resolve,
+        initialized: false,
+        shutdown: false,
+    };
+    while let Some(body) = read_message(&mut input)? {
+        let message: Value = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                let error = (PARSE_ERROR, format!("invalid JSON: {}", e));
+                write_message(&mut output, &response(Value::Null, Err(error)))?;
+                continue;
+            }
+        };
+        // Messages without a method are responses, and the server sends no requests
+        let Some(method) = message["method"].as_str() else {
+            continue;
+        };
+        debug!("lsp: {}", method);
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        match message.get("id") {
+            Some(id) => {
+                let result = server.request(method, params);
+                write_message(&mut output, &response(id.clone(), result))?;
+            }
+            None if method == "exit" => return Ok(server.shutdown),
+            None => server.notification(method, params),
+        }
+    }
+    Ok(server.shutdown)
+}
+
+/// The state of a running server.
+struct Server<F> {
+    /// The text of every open document, by URI.
+    documents: HashMap<String, String>,
+    resolve: F,
+    initialized: bool,
+    shutdown: bool,
+}
+
+impl<F: FnMut(Option<&Path>) -> io::Result<Config>> Server<F> {
+    /// Answer a request.
+    fn request(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        if method == "initialize" {
+            self.initialized = true;
+            return Ok(json!({
+                "capabilities": {
+                    // Full document sync
+                    "textDocumentSync": 1,
+                    "documentFormattingProvider": true,
+                    "documentRangeFormattingProvider": true,
+                },
+                "serverInfo": {
+                    "name": "mdfmt",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            }));
+        }
+        if !self.initialized {
+            return Err((SERVER_NOT_INITIALIZED, "server not initialized".to_string()));
+        }
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "server is shutting down".to_string()));
+        }
+        match method {
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/formatting" => {
+                let params: FormattingParams = parse_params(params)?;
+                let uri = params.text_document.uri;
+                let formatter = self.options(&uri, &params.options)?.formatter;
+                let text = self.document(&uri)?;
+                let formatted = format_with_config(text, &formatter);
+                let old: Vec<&str> = text.split_inclusive('\n').collect();
+                let new: Vec<&str> = formatted.split_inclusive('\n').collect();
+                Ok(Value::Array(text_edits(&old, 0, &old, &new)))
+            }
+            "textDocument/rangeFormatting" => {
+                let params: RangeFormattingParams = parse_params(params)?;
+                let uri = params.text_document.uri;
+                let options = self.options(&uri, &params.options)?;
+                let text = self.document(&uri)?;
+                // A selection ending at the start of a line does not include that line
+                let Range { start, end } = params.range;
+                let end_line = if end.character == 0 && end.line > start.line {
+                    end.line
+                } else {
+                    end.line + 1
+                };
+                let result = format_range_edit(text, start.line..end_line, &options);
+                let lines: Vec<&str> = text.split_inclusive('\n').collect();
+                let old = &lines[result.lines.clone()];
+                let new: Vec<&str> = result.replacement.split_inclusive('\n').collect();
+                Ok(Value::Array(text_edits(
+                    &lines,
+                    result.lines.start,
+                    old,
+                    &new,
+                )))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method: {}", method))),
+        }
+    }
+
+    /// Handle a notification, which gets no response.
+    fn notification(&mut self, method: &str, params: Value) {
+        if !self.initialized {
+            return;
+        }
+        match method {
+            "textDocument/didOpen" => {
+                if let Ok(params) = serde_json::from_value::<DidOpenParams>(params) {
+                    let document = params.text_document;
+                    self.documents.insert(document.uri, document.text);
+                }
+            }
+            "textDocument/didChange" => {
+                if let Ok(params) = serde_json::from_value::<DidChangeParams>(params) {
+                    if let Some(change) = params.content_changes.into_iter().last() {
+                        self.documents.insert(params.text_document.uri, change.text);
+                    }
+                }
+            }
+            "textDocument/didClose" => {
+                if let Ok(params) = serde_json::from_value::<DidCloseParams>(params) {
+                    self.documents.remove(&params.text_document.uri);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The text of the open document at `uri`.
+    fn document(&self, uri: &str) -> Result<&str, RpcError> {
+        self.documents
+            .get(uri)
+            .map(String::as_str)
+            .ok_or_else(|| (INVALID_PARAMS, format!("document is not open: {}", uri)))
+    }
+
+    /// The options to format the document at `uri` with: the editor settings,
+    /// overridden by the configuration of the file.
+    fn options(
+        &mut self,
+        uri: &str,
+        editor: &FormattingOptions,
+    ) -> Result<crate::process_md::FormatOptions, RpcError> {
+        let path = uri_to_path(uri);
+        let config = (self.resolve)(path.as_deref()).map_err(|e| {
+            let file = path.as_deref().map_or(uri.into(), Path::to_string_lossy);
+            (INTERNAL_ERROR, format!("{}: {}", file, e))
+        })?;
+        let editor = Config {
+            tab_width: usize::try_from(editor.tab_size)
+                .ok()
+                .filter(|width| (1..=16).contains(width)),
+            insert_final_newline: editor.insert_final_newline.filter(|&insert| insert),
+            trim_trailing_whitespace: editor.trim_trailing_whitespace.filter(|&trim| trim),
+            ..Config::default()
+        };
+        let mut options = Config::merge(editor, config).format_options();
+        options.formatter.content_mode = path
+            .as_deref()
+            .map_or(ContentMode::Markdown, ContentMode::from_path);
+        Ok(options)
+    }
+}
+
+/// Parse the parameters of a request.
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, format!("invalid params: {}", e)))
+}
+
+/// The response to the request with `id`.
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }),
+    }
+}
+
+/// Read the body of the next message, or `None` at the end of the input.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let line = header.trim_end();
+        if line.is_empty() {
+            // Blank lines between messages
+            if length.is_none() {
+                continue;
+            }
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// Write `message` with its `Content-Length` header and flush it.
+fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// The path of a `file:` URI, or `None` for other schemes.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    // Only the local host; `file://localhost/path` is the same as `file:///path`
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // `file:///C:/docs` is the Windows path `C:/docs`
+    let is_drive = |path: &str| path.as_bytes().get(2) == Some(&b':');
+    if cfg!(windows) && is_drive(&path) {
+        return Some(PathBuf::from(&path[1..]));
+    }
+    Some(PathBuf::from(path))
+}
+
+/// The `TextEdit`s that turn the lines `old` of `document`, starting at line
+/// `offset`, into `new`.
+///
+/// Lines are compared with their line breaks. Each run of lines that differ is
+/// replaced by one edit, so blank lines that were added or removed give small
+/// edits. Very long runs of changes are replaced as a whole.
+fn text_edits(document: &[&str], offset: usize, old: &[&str], new: &[&str]) -> Vec<Value> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_changed = &old[prefix..old.len() - suffix];
+    let new_changed = &new[prefix..new.len() - suffix];
+
+    let hunks = if old_changed.len() * new_changed.len() > MAX_DIFF_CELLS {
+        vec![(0..old_changed.len(), 0..new_changed.len())]
+    } else {
+        diff_hunks(old_changed, new_changed)
+    };
+    hunks
+        .into_iter()
+        .map(|(old_lines, new_lines)| {
+            let start = offset + prefix + old_lines.start;
+            let end = offset + prefix + old_lines.end;
+            json!({
+                "range": {
+                    "start": position(document, start),
+                    "end": position(document, end),
+                },
+                "newText": new_changed[new_lines].concat(),
+            })
+        })
+        .collect()
+}
+
+/// The runs of lines that differ between `old` and `new`, as the lines of each to
+/// replace with the other.
+///
+/// The lines both keep are a longest common subsequence in which lines with text
+/// count twice, so when blank lines are added or removed, the lines around them
+/// are kept rather than removed and added again.
+fn diff_hunks(old: &[&str], new: &[&str]) -> Vec<(std::ops::Range<usize>, std::ops::Range<usize>)> {
+    let weight = |line: &str| if line.trim().is_empty() { 1 } else { 2 };
+    // `common[i][j]` is the weight of the longest common subsequence of `old[i..]`
+    // and `new[j..]`
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            let skip = common[i + 1][j].max(common[i][j + 1]);
+            common[i][j] = if old[i] == new[j] {
+                skip.max(common[i + 1][j + 1] + weight(old[i]))
+            } else {
+                skip
+            };
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let (mut hunk_i, mut hunk_j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        let keep = i < old.len()
+            && j < new.len()
+            && old[i] == new[j]
+            && common[i][j] == common[i + 1][j + 1] + weight(old[i]);
+        if keep {
+            if (hunk_i, hunk_j) != (i, j) {
+                hunks.push((hunk_i..i, hunk_j..j));
+            }
+            i += 1;
+            j += 1;
+            (hunk_i, hunk_j) = (i, j);
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    if (hunk_i, hunk_j) != (i, j) {
+        hunks.push((hunk_i..i, hunk_j..j));
+    }
+    hunks
+}
+
+/// The position of the start of line `line` of `document`, or of its end for the
+/// line after the last one.
+fn position(document: &[&str], line: usize) -> Value {
+    match document.last() {
+        // There is no line after a last line without a line break
+        Some(last) if line == document.len() && !last.ends_with('\n') => json!({
+            "line": line - 1,
+            "character": last.encode_utf16().count(),
+        }),
+        _ => json!({ "line": line, "character": 0 }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run the server on `messages` and return its clean exit flag and responses.
+    fn serve(messages: &[Value]) -> (bool, Vec<Value>) {
+        let input: String = messages
+            .iter()
+            .map(|message| {
+                let body = message.to_string();
+                format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+            })
+            .collect();
+        let mut output = Vec::new();
+        let clean = run(input.as_bytes(), &mut output, |_| Ok(Config::default())).unwrap();
+        let mut output = output.as_slice();
+        let mut responses = Vec::new();
+        while let Some(body) = read_message(&mut output).unwrap() {
+            responses.push(serde_json::from_slice(&body).unwrap());
+        }
+        (clean, responses)
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn notification(method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "method": method, "params": params })
+    }
+
+    fn open(uri: &str, text: &str) -> Value {
+        notification(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": uri, "languageId": "markdown", "version": 1, "text": text },
+            }),
+        )
+    }
+
+    #[test]
+    fn test_formatting_returns_small_edits() {
+        let options = json!({ "tabSize": 4, "insertSpaces": true });
+        let (clean, responses) = serve(&[
+            request(1, "initialize", json!({})),
+            notification("initialized", json!({})),
+            open(
+                "untitled:Untitled-1",
+                "# A\nText\n\n\n\nMore\n```\ncode\n```",
+            ),
+            request(
+                2,
+                "textDocument/formatting",
+                json!({ "textDocument": { "uri": "untitled:Untitled-1" }, "options": options }),
+            ),
+            request(3, "shutdown", Value::Null),
+            notification("exit", Value::Null),
+        ]);
+        assert!(clean);
+        assert_eq!(responses.len(), 3);
+        assert_eq!(
+            responses[1]["result"],
+            json!([
+                {
+                    "range": { "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 0 } },
+                    "newText": "\n",
+                },
+                {
+                    "range": { "start": { "line": 3, "character": 0 }, "end": { "line": 5, "character": 0 } },
+                    "newText": "",
+                },
+                {
+                    "range": { "start": { "line": 6, "character": 0 }, "end": { "line": 6, "character": 0 } },
+                    "newText": "\n",
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_range_formatting_uses_the_latest_text() {
+        let uri = "file:///tmp/mdfmt%20docs/guide.md";
+        let (clean, responses) = serve(&[
+            request(1, "initialize", json!({})),
+            open(uri, "Old\n"),
+            notification(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": uri, "version": 2 },
+                    "contentChanges": [{ "text": "A\n\n\n\nB\n```\n\n\ncode\n```\n# End" }],
+                }),
+            ),
+            // Starts inside the fence, and ends at the start of line 9
+            request(
+                2,
+                "textDocument/rangeFormatting",
+                json!({
+                    "textDocument": { "uri": uri },
+                    "range": { "start": { "line": 6, "character": 0 }, "end": { "line": 9, "character": 0 } },
+                    "options": { "tabSize": 2, "insertSpaces": true, "insertFinalNewline": true },
+                }),
+            ),
+        ]);
+        assert!(!clean);
+        assert_eq!(
+            responses[1]["result"],
+            json!([
+                {
+                    "range": { "start": { "line": 5, "character": 0 }, "end": { "line": 5, "character": 0 } },
+                    "newText": "\n",
+                },
+                {
+                    "range": { "start": { "line": 6, "character": 0 }, "end": { "line": 8, "character": 0 } },
+                    "newText": "",
+                },
+                {
+                    "range": { "start": { "line": 10, "character": 0 }, "end": { "line": 10, "character": 0 } },
+                    "newText": "\n",
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let format = |id, uri: &str| {
+            request(
+                id,
+                "textDocument/formatting",
+                json!({ "textDocument": { "uri": uri }, "options": { "tabSize": 4, "insertSpaces": true } }),
+            )
+        };
+        let (clean, responses) = serve(&[
+            format(1, "file:///a.md"),
+            request(2, "initialize", json!({})),
+            format(3, "file:///a.md"),
+            request(4, "textDocument/hover", json!({})),
+            request(5, "textDocument/formatting", json!({})),
+            request(6, "shutdown", Value::Null),
+            format(7, "file:///a.md"),
+            notification("exit", Value::Null),
+        ]);
+        assert!(clean);
+        let codes: Vec<&Value> = responses
+            .iter()
+            .map(|response| &response["error"]["code"])
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                &json!(SERVER_NOT_INITIALIZED),
+                &Value::Null,
+                &json!(INVALID_PARAMS),
+                &json!(METHOD_NOT_FOUND),
+                &json!(INVALID_PARAMS),
+                &Value::Null,
+                &json!(INVALID_REQUEST),
+            ]
+        );
+        assert_eq!(
+            responses[2]["error"]["message"],
+            "document is not open: file:///a.md"
+        );
+    }
+
+    #[test]
+    fn test_text_edits_at_end_without_line_break() {
+        let old = ["A\n", "B"];
+        let edits = text_edits(&old, 0, &old, &["A\n", "B\n"]);
+        assert_eq!(
+            edits,
+            [json!({
+                "range": { "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 1 } },
+                "newText": "B\n",
+            })]
+        );
+        assert!(text_edits(&old, 0, &old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_uri_to_path() {
+        assert_eq!(
+            uri_to_path("file:///home/me/My%20Docs/a.md"),
+            Some(PathBuf::from("/home/me/My Docs/a.md"))
+        );
+        assert_eq!(
+            uri_to_path("file://localhost/a%2"),
+            Some(PathBuf::from("/a%2"))
+        );
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+    }
+}
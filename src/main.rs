@@ -1,155 +1,2545 @@
-use clap::Parser;
-use mdfmt::{find_md_files, process_md};
+use clap::{Parser, ValueEnum};
+use encoding_rs::{Encoding, UTF_8};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{Level, LevelFilter, Log, Metadata, Record, info};
+use mdfmt::cache::{self, Cache, CacheEntry, CacheLookup};
+use mdfmt::config::{self, Config};
+use mdfmt::find_md_files::{
+    FileFilter, display_path, filter_files, find_md_files_filtered, is_md_file, sort_key_modified,
+    sort_key_name, sort_key_path, sort_key_size, sort_md_files_by,
+};
+use mdfmt::lint::{self, LintConfig, LintWarning, Severity};
+use mdfmt::output::{ColorChoice, Colors, Style};
+use mdfmt::process_md::{
+    ContentKind, ContentMode, DeleteStrategy, EditCounts, FenceStyle, FileChanges, FileOutcome,
+    FileTimings, FormatOptions, FormatterConfig, HeadingBlankLines, LineEnding,
+};
+use mdfmt::toc::TocLevels;
+use mdfmt::{batch, diff, editorconfig, find_md_files, git, lsp, outline, process_md};
 use rayon::prelude::*;
-use std::path::Path;
+use serde_json::json;
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Exit code when no files needed changes.
+const EXIT_CLEAN: i32 = 0;
+/// Exit code when files were changed (or would be changed, with `--check`).
+const EXIT_CHANGED: i32 = 1;
+/// Exit code when errors occurred.
+const EXIT_ERROR: i32 = 2;
 
 /// A Markdown formatter that removes multiple consecutive blank lines and handles empty files.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    after_help = "Exit codes:\n  0  No changes were needed\n  1  Files were changed (or would be changed, with --check)\n  2  Errors occurred",
+    args_conflicts_with_subcommands = true,
+    disable_help_subcommand = true
+)]
 struct Args {
-    /// Path to a markdown file or directory to process
-    #[arg(value_name = "PATH")]
-    path: Option<String>,
+    /// Packaging helpers; without a subcommand, mdfmt formats the given paths
+    #[command(subcommand)]
+    command: Option<Subcommand>,
 
-    /// Show verbose output
-    #[arg(short, long)]
-    verbose: bool,
+    /// Paths to markdown files or directories to process (defaults to the current directory)
+    #[arg(value_name = "PATH", num_args(0..))]
+    paths: Vec<String>,
+
+    /// Show verbose output; -vv also logs debug details, and -vvv which rule changed
+    /// each line
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only print errors
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Do not show a progress bar while processing files
+    #[arg(long)]
+    no_progress: bool,
 
     /// Dry run - show what would be done without making changes
-    #[arg(short = 'n', long)]
+    #[arg(short = 'n', long, conflicts_with = "diff_filter")]
     dry_run: bool,
 
     /// Allow deletion of empty files
     #[arg(long)]
     delete: bool,
+
+    /// Move empty files to the system trash instead of deleting them permanently
+    #[arg(long)]
+    delete_to_trash: bool,
+
+    /// Truncate files that contain only whitespace to zero bytes instead of deleting
+    /// or skipping them
+    #[arg(long)]
+    normalize_empty: bool,
+
+    /// Format files that contain only frontmatter instead of deleting or skipping them
+    #[arg(long)]
+    include_empty: bool,
+
+    /// Print a warning on stderr for every file that contains only frontmatter and is
+    /// kept
+    #[arg(long)]
+    warn_empty: bool,
+
+    /// Write the paths of deleted files (or files that would be deleted), one per
+    /// line, to this file
+    #[arg(long, value_name = "FILE")]
+    deleted_list: Option<PathBuf>,
+
+    /// Check whether files are formatted without modifying them
+    #[arg(long, conflicts_with_all = ["dry_run", "diff_filter"])]
+    check: bool,
+
+    /// Write the formatted files below DIR instead of modifying them, with their
+    /// paths relative to the searched directories; clean files are copied, and files
+    /// that would be deleted for being empty are not written
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["check", "dry_run", "list_different", "lint", "find_duplicates", "extract_headings", "diff_filter", "line_range", "format_only_staged", "stdin_json", "print_config"])]
+    out_dir: Option<PathBuf>,
+
+    /// Report the blank line problems formatting would fix and the lint rule
+    /// violations (bare URLs, images without alt text, code fences without a
+    /// language, zero-width characters, headings ending with punctuation) as
+    /// `path:line: CODE message`, without
+    /// modifying files; exits with 1 if any of them is an error
+    #[arg(long, conflicts_with_all = ["check", "dry_run", "diff_filter", "line_range", "stdin_json"])]
+    lint: bool,
+
+    /// Only print the paths of files that would be changed, one per line and
+    /// relative to the current directory, without modifying them
+    #[arg(short = 'l', long, conflicts_with_all = ["check", "dry_run", "lint", "diff_filter", "stdin_json", "output_format"])]
+    list_different: bool,
+
+    /// With --check, only print the absolute paths of files that would be changed,
+    /// one per line, or as a JSON array of strings with --output-format json
+    #[arg(long, requires = "check", conflicts_with_all = ["list_different", "report_unchanged"])]
+    list_files: bool,
+
+    /// Report groups of files with the same content after formatting, instead of
+    /// formatting them; exits with 1 if any are found
+    #[arg(long, conflicts_with_all = ["check", "dry_run", "lint", "list_different", "diff_filter", "stdin_json", "line_range"])]
+    find_duplicates: bool,
+
+    /// Print the headings of every file with their line numbers and anchors, as an
+    /// indented outline or as JSON with --output-format json, without modifying files
+    #[arg(long, conflicts_with_all = ["check", "dry_run", "lint", "list_different", "find_duplicates", "diff_filter", "stdin_json", "line_range"])]
+    extract_headings: bool,
+
+    /// Exit with 0 even when files were changed (or would be changed, with --check)
+    #[arg(long)]
+    no_error_on_unformatted: bool,
+
+    /// With --check, also list the files that are already formatted; with --quiet,
+    /// only their number is printed
+    #[arg(long, requires = "check")]
+    report_unchanged: bool,
+
+    /// Only format markdown files that git reports as modified, added, or untracked
+    #[arg(long)]
+    changed: bool,
+
+    /// Only format markdown files changed since the given git ref (implies --changed)
+    #[arg(long, value_name = "REF")]
+    changed_since: Option<String>,
+
+    /// Only format markdown files staged for commit, and stage the formatted files
+    /// again unless they also have unstaged changes, e.g. in a git pre-commit hook
+    #[arg(long, conflicts_with_all = ["changed", "changed_since"])]
+    format_only_staged: bool,
+
+    /// With --format-only-staged, leave the formatted files unstaged to review them
+    /// before committing
+    #[arg(long, requires = "format_only_staged")]
+    no_restage: bool,
+
+    /// Only process files in searched directories that match this glob (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    include: Vec<String>,
+
+    /// Skip files and directories in searched directories that match this glob;
+    /// prefix with `!` to re-include files excluded by an earlier pattern (repeatable)
+    #[arg(long, value_name = "PATTERN", allow_hyphen_values = true)]
+    exclude: Vec<String>,
+
+    /// Read a unified diff from stdin and only format the lines it changed
+    #[arg(long, conflicts_with_all = ["changed", "changed_since", "format_only_staged"])]
+    diff_filter: bool,
+
+    /// Only format lines START to END (1-based, inclusive) of a single file, e.g. an
+    /// editor selection; the rest of the file is left as is
+    #[arg(long, value_name = "START:END", value_parser = parse_line_range, conflicts_with_all = ["diff_filter", "demote_headings", "promote_headings"])]
+    line_range: Option<RangeInclusive<usize>>,
+
+    /// Read newline-delimited JSON requests `{"path", "content"}` from stdin and write
+    /// one formatted response per request to stdout, without touching the filesystem
+    #[arg(long, conflicts_with_all = ["paths", "check", "dry_run", "diff_filter", "line_range", "changed", "changed_since", "format_only_staged"])]
+    stdin_json: bool,
+
+    /// Format every --stdin-json request as this flavor of Markdown; requests for
+    /// `.mdx` paths are always formatted as MDX
+    #[arg(long, value_enum, value_name = "FORMAT", requires = "stdin_json")]
+    stdin_format: Option<StdinFormat>,
+
+    /// Format --stdin-json requests with the options of the file at PATH, from its
+    /// config files and `.editorconfig`, and as MDX if it is an `.mdx` file; the
+    /// file itself is never read or written and does not have to exist
+    #[arg(long, value_name = "PATH", requires = "stdin_json")]
+    stdin_filepath: Option<PathBuf>,
+
+    /// Run a language server on stdin and stdout that formats the documents open
+    /// in an editor, with the options of their files
+    #[arg(long, conflicts_with_all = ["paths", "check", "dry_run", "list_different", "lint", "diff_filter", "line_range", "changed", "changed_since", "format_only_staged", "stdin_json", "print_config"])]
+    lsp: bool,
+
+    /// Keep the modification times of formatted files, so build tools that compare
+    /// timestamps do not rebuild them
+    #[cfg(feature = "preserve-mtime")]
+    #[arg(long, conflicts_with_all = ["diff_filter", "line_range", "stdin_json"])]
+    preserve_mtime: bool,
+
+    /// Skip files larger than this size, e.g. 500K or 5M (binary units)
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// Wait up to this many milliseconds for a file that another mdfmt run is
+    /// formatting, then skip it with an error [default: 5000]
+    #[arg(long, value_name = "MS")]
+    lock_timeout: Option<u64>,
+
+    /// Read files in this encoding, e.g. latin1 or shift_jis, and write them back in
+    /// it; files whose formatted content it cannot represent are skipped with an
+    /// error [default: utf-8]
+    #[arg(long, value_name = "NAME", value_parser = parse_encoding, conflicts_with_all = ["line_range", "extract_headings", "stdin_json", "lsp"])]
+    encoding: Option<&'static Encoding>,
+
+    /// Demote every heading by N levels, e.g. `#` to `##` for 1; headings stop at
+    /// level 6
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=5), conflicts_with_all = ["promote_headings", "diff_filter", "stdin_json"])]
+    demote_headings: Option<u8>,
+
+    /// Promote every heading by N levels, e.g. `##` to `#` for 1; files with headings
+    /// that would go past level 1 are skipped with an error
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=5), conflicts_with_all = ["diff_filter", "stdin_json"])]
+    promote_headings: Option<u8>,
+
+    /// Promote headings that would go past level 1 to level 1 instead of skipping the
+    /// file
+    #[arg(long, requires = "promote_headings")]
+    clamp_headings: bool,
+
+    /// Rewrite code fence markers; fences whose content would close the converted fence
+    /// are kept
+    #[arg(long, value_enum, value_name = "STYLE", conflicts_with_all = ["diff_filter", "stdin_json"])]
+    fence_style: Option<FenceMarker>,
+
+    /// Lowercase code fence languages and trim whitespace around info strings, e.g.
+    /// "``` Rust" to "```rust"; aliases come from the `[fence_aliases]` config table
+    #[arg(long, conflicts_with_all = ["diff_filter", "stdin_json"])]
+    normalize_fence_lang: bool,
+
+    /// Add this language to code fences without one, e.g. `text`
+    #[arg(long, value_name = "LANG", value_parser = clap::builder::NonEmptyStringValueParser::new(), conflicts_with_all = ["diff_filter", "stdin_json"])]
+    default_fence_lang: Option<String>,
+
+    /// Warn on stderr about code fences without a language (markdownlint MD040)
+    #[arg(long, conflicts_with_all = ["diff_filter", "stdin_json"])]
+    require_fence_lang: bool,
+
+    /// Require exactly N blank lines before headings; a list like 2,1 sets the count
+    /// by heading level, the last one repeating for deeper levels
+    #[arg(long, value_name = "N[,N...]", conflicts_with_all = ["diff_filter", "stdin_json"])]
+    blank_lines_before_heading: Option<HeadingBlankLines>,
+
+    /// Require exactly N blank lines after headings, set like
+    /// --blank-lines-before-heading
+    #[arg(long, value_name = "N[,N...]", conflicts_with_all = ["diff_filter", "stdin_json"])]
+    blank_lines_after_heading: Option<HeadingBlankLines>,
+
+    /// Regenerate the table of contents between `<!-- mdfmt-toc-start -->` and
+    /// `<!-- mdfmt-toc-end -->` markers; files without them are not changed
+    #[arg(long, conflicts_with_all = ["diff_filter", "stdin_json"])]
+    toc: bool,
+
+    /// The heading levels listed in tables of contents, e.g. 2..4
+    #[arg(long, value_name = "MIN..MAX", requires = "toc")]
+    toc_levels: Option<TocLevels>,
+
+    /// Remove headings that have no text, only `#` markers, unless they are the only
+    /// heading of the file
+    #[arg(long, conflicts_with_all = ["diff_filter", "stdin_json"])]
+    remove_empty_headings: bool,
+
+    /// Sort the top-level keys of YAML frontmatter alphabetically, or with the keys
+    /// from `frontmatter_key_order` in a config file first
+    #[arg(long, conflicts_with_all = ["diff_filter", "stdin_json"])]
+    sort_frontmatter: bool,
+
+    /// Re-indent nested list items to N spaces per nesting level, e.g. 2 or 4
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=8), conflicts_with_all = ["diff_filter", "stdin_json"])]
+    list_indent: Option<u8>,
+
+    /// The number of columns a tab in list indentation advances to
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=16), requires = "list_indent")]
+    tab_width: Option<u8>,
+
+    /// Put exactly one space between list markers and the item text, e.g. "-   item"
+    /// to "- item"
+    #[arg(long, conflicts_with_all = ["diff_filter", "stdin_json"])]
+    normalize_list_spacing: bool,
+
+    /// Wrap the lines of block quotes longer than WIDTH characters, keeping their
+    /// `>` markers on every line
+    #[arg(long, value_name = "WIDTH", value_parser = clap::value_parser!(u16).range(1..), conflicts_with_all = ["diff_filter", "stdin_json"])]
+    wrap_blockquotes: Option<u16>,
+
+    /// Expand tabs in the indentation of lines to spaces, with tab stops WIDTH
+    /// columns apart; code blocks and tabs within lines are kept
+    #[arg(long, value_name = "WIDTH", num_args = 0..=1, require_equals = true, default_missing_value = "4", value_parser = clap::value_parser!(u8).range(1..=16), conflicts_with_all = ["diff_filter", "stdin_json"])]
+    tabs_to_spaces: Option<u8>,
+
+    /// Write files with these line endings, also inside code fences and frontmatter;
+    /// overrides `end_of_line` in `.editorconfig`
+    #[arg(long, visible_alias = "line-ending", value_enum, value_name = "STYLE", conflicts_with_all = ["diff_filter", "stdin_json"])]
+    end_of_line: Option<LineEndingStyle>,
+
+    /// End every file with a newline
+    #[arg(long, conflicts_with_all = ["diff_filter", "stdin_json"])]
+    final_newline: bool,
+
+    /// Remove the newline at the end of every file
+    #[arg(long, conflicts_with_all = ["final_newline", "diff_filter", "stdin_json"])]
+    no_final_newline: bool,
+
+    /// Leave files alone when only the newlines at their end would change; files
+    /// with other changes still get them fixed
+    #[arg(long)]
+    ignore_final_newline_only: bool,
+
+    /// Remove trailing whitespace outside code fences, including hard line breaks
+    /// written as two trailing spaces
+    #[arg(long, conflicts_with_all = ["diff_filter", "stdin_json"])]
+    trim_trailing_whitespace: bool,
+
+    /// Keep trailing whitespace, even if .editorconfig or .mdfmt.toml trims it
+    #[arg(long, conflicts_with_all = ["trim_trailing_whitespace", "diff_filter", "stdin_json"])]
+    keep_trailing_whitespace: bool,
+
+    /// Output format for the report
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, alias = "output")]
+    output_format: OutputFormat,
+
+    /// When to color the report and error messages; `auto` colors terminals unless
+    /// the NO_COLOR environment variable is set
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorWhen::Auto)]
+    color: ColorWhen,
+
+    /// Order in which files are processed and listed [default: the order of the
+    /// paths given, each directory sorted by path]
+    #[arg(long, value_enum, value_name = "KEY")]
+    sort_by: Option<SortBy>,
+
+    /// Print per-file results in input order after all files are processed, instead of
+    /// as each file finishes
+    #[arg(long)]
+    sorted_output: bool,
+
+    /// Measure per-file read, format, and write times and report the slowest files
+    #[arg(long)]
+    timings: bool,
+
+    /// Skip files that were already verified clean by a previous run
+    #[arg(long)]
+    cache: bool,
+
+    /// Do not use the cache, even if --cache or --cache-location is given
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Location of the cache file, relative to the current directory (implies --cache)
+    /// [default: .mdfmt-cache]
+    #[arg(long, value_name = "PATH")]
+    cache_location: Option<PathBuf>,
+
+    /// Ignore `.mdfmt.toml` config files
+    #[arg(long)]
+    no_config: bool,
+
+    /// Ignore `.editorconfig` files, which otherwise set the line endings, final
+    /// newline, trailing whitespace, and tab options
+    #[arg(long)]
+    no_editorconfig: bool,
+
+    /// Print the options that apply to files in PATH as TOML and exit: the defaults,
+    /// overridden by the config files, `.editorconfig` (when PATH is a file), and
+    /// the command line
+    #[arg(long, conflicts_with = "stdin_json")]
+    print_config: bool,
+
+    /// Print the completion script for SHELL and exit; the older spelling of
+    /// `mdfmt completions SHELL`, kept for existing scripts
+    #[cfg(feature = "completions")]
+    #[arg(
+        long,
+        value_name = "SHELL",
+        value_enum,
+        ignore_case = true,
+        hide = true
+    )]
+    generate_completions: Option<clap_complete::Shell>,
+}
+
+/// Subcommands that generate files for packaging. They are hidden, so a directory
+/// with the same name can still be formatted as `./completions` or `./manpage`.
+#[derive(clap::Subcommand, Debug)]
+enum Subcommand {
+    /// Print the completion script for SHELL to stdout
+    #[cfg(feature = "completions")]
+    #[command(hide = true)]
+    Completions {
+        /// The shell to complete for
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Print the man page in roff format to stdout
+    #[cfg(feature = "manpage")]
+    #[command(hide = true)]
+    Manpage,
+}
+
+/// How the run is reported on stdout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human readable status lines and summary
+    Text,
+    /// A single JSON document with per-file results and the summary
+    Json,
+}
+
+/// When to use colors, for `--color`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ColorWhen {
+    /// Color output to terminals, unless NO_COLOR is set
+    Auto,
+    /// Always color output, even when piped
+    Always,
+    /// Never color output
+    Never,
+}
+
+/// Marker character for `--fence-style`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum FenceMarker {
+    /// Backtick fences (```)
+    Backtick,
+    /// Tilde fences (~~~)
+    Tilde,
+    /// Leave fence markers as they are
+    Keep,
+}
+
+/// Line endings for `--end-of-line`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum LineEndingStyle {
+    /// Unix line endings (\n)
+    Lf,
+    /// Windows line endings (\r\n)
+    Crlf,
+    /// The line endings most lines of each file already have
+    Keep,
+}
+
+/// Markdown flavor for `--stdin-format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum StdinFormat {
+    /// Plain Markdown
+    Markdown,
+    /// MDX; blank lines are never inserted next to JSX tags and expressions
+    Mdx,
+}
+
+/// Sort key for the files of a run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum SortBy {
+    /// File name only, ignoring the directory
+    Name,
+    /// Full path
+    Path,
+    /// Modification time, newest first
+    Modified,
+    /// File size, largest first
+    Size,
 }
 
 fn main() {
     let cli = Args::parse();
 
-    // Handle input path logic
-    let (search_dir, specific_file) = if let Some(p) = cli.path {
-        let input_path = Path::new(&p);
-        if input_path.is_file() {
-            // If a specific file is provided, we'll process only that file
-            (None, Some(input_path.to_path_buf()))
-        } else if input_path.is_dir() {
-            // If a directory is provided, search within it
-            (Some(input_path.to_path_buf()), None)
+    if let Some(command) = cli.command {
+        run_subcommand(command);
+    }
+    #[cfg(feature = "completions")]
+    if let Some(shell) = cli.generate_completions {
+        run_subcommand(Subcommand::Completions { shell });
+    }
+
+    // Each stream is colored only if it goes to a terminal, so pipes get plain text
+    let colors = Colors::detect(match cli.color {
+        ColorWhen::Auto => ColorChoice::Auto,
+        ColorWhen::Always => ColorChoice::Always,
+        ColorWhen::Never => ColorChoice::Never,
+    });
+    log::set_logger(Box::leak(Box::new(StderrLogger { colors })))
+        .expect("the logger is only set once");
+    log::set_max_level(log_level(cli.verbose, cli.quiet));
+    install_panic_hook();
+
+    // clap waives `requires` when the required flag conflicts with a given one, as
+    // --stdin-json does with paths
+    if !cli.stdin_json && (cli.stdin_filepath.is_some() || cli.stdin_format.is_some()) {
+        let flag = if cli.stdin_filepath.is_some() {
+            "--stdin-filepath"
         } else {
-            eprintln!("Error: Path '{}' does not exist or is not accessible", p);
-            process::exit(1);
+            "--stdin-format"
+        };
+        print_error(colors, &format!("Error: {} requires --stdin-json", flag));
+        process::exit(EXIT_ERROR);
+    }
+
+    // Batch mode serves format requests over stdin and never reads or writes the files
+    // they are for; with --stdin-filepath, it only reads the config files of one
+    if cli.stdin_json {
+        let mut config = match &cli.stdin_filepath {
+            Some(path) => match resolve_config(path, &cli, !cli.no_editorconfig) {
+                Ok(config) => config.format_options().formatter,
+                Err(e) => {
+                    print_error(colors, &format!("Error: {}: {}", path.display(), e));
+                    process::exit(EXIT_ERROR);
+                }
+            },
+            None => FormatterConfig::default(),
+        };
+        config.content_mode = match (cli.stdin_format, &cli.stdin_filepath) {
+            (Some(StdinFormat::Mdx), _) => ContentMode::Mdx,
+            (Some(StdinFormat::Markdown), _) | (None, None) => ContentMode::Markdown,
+            (None, Some(path)) => ContentMode::from_path(path),
+        };
+        match batch::run(io::stdin().lock(), io::stdout().lock(), &config) {
+            Ok(0) => process::exit(EXIT_CLEAN),
+            Ok(_) => process::exit(EXIT_ERROR),
+            Err(e) => {
+                print_error(
+                    colors,
+                    &format!("Error: Failed to process JSON requests: {}", e),
+                );
+                process::exit(EXIT_ERROR);
+            }
         }
-    } else {
+    }
+
+    // The language server formats the text editors send, and only reads config files
+    if cli.lsp {
+        let current_dir = std::env::current_dir().unwrap_or_default();
+        let resolve = |path: Option<&Path>| {
+            resolve_config(path.unwrap_or(&current_dir), &cli, !cli.no_editorconfig)
+        };
+        match lsp::run(io::stdin().lock(), io::stdout().lock(), resolve) {
+            Ok(true) => process::exit(EXIT_CLEAN),
+            // The protocol asks for 1 when the client exits without a shutdown
+            Ok(false) => process::exit(1),
+            Err(e) => {
+                print_error(colors, &format!("Error: Language server failed: {}", e));
+                process::exit(EXIT_ERROR);
+            }
+        }
+    }
+
+    // Handle input path logic
+    let input_paths: Vec<PathBuf> = if cli.paths.is_empty() {
         // No path provided, use current directory
         match std::env::current_dir() {
-            Ok(dir) => (Some(dir), None),
+            Ok(dir) => vec![dir],
             Err(e) => {
-                eprintln!("Error: Failed to get current directory: {}", e);
-                process::exit(1);
+                print_error(
+                    colors,
+                    &format!("Error: Failed to get current directory: {}", e),
+                );
+                process::exit(EXIT_ERROR);
             }
         }
+    } else {
+        cli.paths.iter().map(PathBuf::from).collect()
     };
+    if cli.line_range.is_some() && !(input_paths.len() == 1 && input_paths[0].is_file()) {
+        print_error(colors, "Error: --line-range requires a single file");
+        process::exit(EXIT_ERROR);
+    }
 
-    let md_files = if let Some(file) = specific_file {
-        // Process only the specific file if it's a markdown file
-        if cli.verbose {
-            println!("Processing specific file: {}", file.display());
+    // The config is resolved for the path itself, without looking for files
+    if cli.print_config {
+        if input_paths.len() != 1 {
+            print_error(colors, "Error: --print-config takes a single path");
+            process::exit(EXIT_ERROR);
         }
+        process::exit(run_print_config(&input_paths[0], &cli, colors));
+    }
 
-        // Check if the file has .md extension
-        if file.extension().and_then(|ext| ext.to_str()) == Some("md") {
-            vec![file]
-        } else {
-            eprintln!(
-                "Error: File '{}' is not a markdown file (.md)",
-                file.display()
+    let filter = match FileFilter::new(&cli.include, &cli.exclude) {
+        Ok(filter) => filter,
+        Err(e) => {
+            print_error(
+                colors,
+                &format!("Error: Invalid --include/--exclude pattern: {}", e),
             );
-            process::exit(1);
+            process::exit(EXIT_ERROR);
         }
-    } else if let Some(dir) = search_dir {
-        // Search for markdown files in the directory
-        if cli.verbose {
-            println!("Searching for markdown files in: {}", dir.display());
+    };
+
+    // The diff is read once from stdin and resolved against every searched directory
+    let diff_files = if cli.diff_filter {
+        let mut diff_text = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut diff_text) {
+            print_error(
+                colors,
+                &format!("Error: Failed to read diff from stdin: {}", e),
+            );
+            process::exit(EXIT_ERROR);
         }
-        find_md_files(&dir)
+        diff::parse_unified_diff(&diff_text)
     } else {
-        // This should never happen, but handle it gracefully
-        eprintln!("Error: No valid path specified");
-        process::exit(1);
+        Vec::new()
     };
 
-    if md_files.is_empty() {
-        println!("No markdown files found");
-        return;
+    // Files are reported relative to the current directory where they are inside it
+    let current_dir = std::env::current_dir().unwrap_or_default();
+
+    // Changed line ranges per file, only populated in --diff-filter mode
+    let mut line_ranges: HashMap<PathBuf, Vec<RangeInclusive<usize>>> = HashMap::new();
+    // Files found by --format-only-staged, and those of them with unstaged changes
+    let mut staged_files = HashSet::new();
+    let mut partially_staged = HashSet::new();
+    let mut md_files = Vec::new();
+    let mut seen = HashSet::new();
+    let mut out_dir = cli.out_dir.as_deref().map(OutDir::new);
+
+    for input_path in input_paths {
+        // Files keep their path below the searched directory in --out-dir
+        let root = if input_path.is_file() {
+            input_path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default()
+        } else {
+            input_path.clone()
+        };
+        // Copies written into the searched directory by an earlier run are skipped
+        let nested_out_root = match &out_dir {
+            Some(out_dir) => out_dir.nested_in(&input_path).unwrap_or_else(|e| {
+                print_error(colors, &format!("Error: {}", e));
+                process::exit(EXIT_ERROR);
+            }),
+            None => None,
+        };
+        let files = if input_path.is_file() {
+            if cli.diff_filter {
+                print_error(
+                    colors,
+                    "Error: --diff-filter requires a directory, not a file",
+                );
+                process::exit(EXIT_ERROR);
+            }
+
+            // Process only the specific file if it's a markdown file
+            if cli.verbose > 0 && cli.output_format == OutputFormat::Text {
+                println!("Processing specific file: {}", input_path.display());
+            }
+
+            // Check if the file has .md extension
+            if is_md_file(&input_path) {
+                vec![input_path]
+            } else {
+                print_error(
+                    colors,
+                    &format!(
+                        "Error: File '{}' is not a markdown file (.md)",
+                        input_path.display()
+                    ),
+                );
+                process::exit(EXIT_ERROR);
+            }
+        } else if input_path.is_dir() {
+            let dir = input_path;
+            // Include/exclude patterns are relative to the searched directory
+            if cli.diff_filter {
+                // Paths in the diff are resolved relative to the search directory
+                let mut files = Vec::new();
+                for diff_file in &diff_files {
+                    let path = dir.join(&diff_file.path);
+                    if path.is_file() && is_md_file(&path) {
+                        line_ranges
+                            .insert(display_path(&path, &current_dir), diff_file.ranges.clone());
+                        files.push(path);
+                    }
+                }
+                files.sort();
+                filter_files(files, &dir, &filter)
+            } else if cli.changed || cli.changed_since.is_some() {
+                // Restrict to files git reports as changed
+                if cli.verbose > 0 && cli.output_format == OutputFormat::Text {
+                    println!("Searching for changed markdown files in: {}", dir.display());
+                }
+                match git::changed_md_files(&dir, cli.changed_since.as_deref()) {
+                    Ok(files) => filter_files(files, &dir, &filter),
+                    Err(e) => {
+                        print_error(colors, &format!("Error: {}", e));
+                        process::exit(EXIT_ERROR);
+                    }
+                }
+            } else if cli.format_only_staged {
+                if cli.verbose > 0 && cli.output_format == OutputFormat::Text {
+                    println!("Searching for staged markdown files in: {}", dir.display());
+                }
+                // Files with unstaged changes are formatted, but staging them again
+                // would commit those changes too
+                let staged = git::staged_md_files(&dir).and_then(|staged| {
+                    let unstaged = git::unstaged_md_files(&dir)?;
+                    Ok((staged, unstaged))
+                });
+                match staged {
+                    Ok((staged, unstaged)) => {
+                        staged_files.extend(staged.iter().filter_map(|file| file_key(file)));
+                        partially_staged.extend(unstaged.iter().filter_map(|file| file_key(file)));
+                        filter_files(staged, &dir, &filter)
+                    }
+                    Err(e) => {
+                        print_error(colors, &format!("Error: {}", e));
+                        process::exit(EXIT_ERROR);
+                    }
+                }
+            } else {
+                // Search for markdown files in the directory
+                if cli.verbose > 0 && cli.output_format == OutputFormat::Text {
+                    println!("Searching for markdown files in: {}", dir.display());
+                }
+                // Excluded directories are skipped without being read
+                find_md_files_filtered(&dir, &filter)
+            }
+        } else {
+            print_error(
+                colors,
+                &format!(
+                    "Error: Path '{}' does not exist or is not accessible",
+                    input_path.display()
+                ),
+            );
+            process::exit(EXIT_ERROR);
+        };
+
+        // Deduplicate files reached through more than one path argument
+        for file in files {
+            let key = file.canonicalize().unwrap_or_else(|_| file.clone());
+            if nested_out_root
+                .as_ref()
+                .is_some_and(|out_root| key.starts_with(out_root))
+            {
+                continue;
+            }
+            if seen.insert(key) {
+                let path = display_path(&file, &current_dir);
+                if let Some(Err(e)) = out_dir
+                    .as_mut()
+                    .map(|out_dir| out_dir.add(&root, &file, &path))
+                {
+                    print_error(colors, &format!("Error: {}", e));
+                    process::exit(EXIT_ERROR);
+                }
+                md_files.push(path);
+            }
+        }
+    }
+    if let Some(Err(e)) = out_dir
+        .as_ref()
+        .map(|out_dir| out_dir.check_overwrites(&seen))
+    {
+        print_error(colors, &format!("Error: {}", e));
+        process::exit(EXIT_ERROR);
     }
 
-    println!(
-        "Found {} markdown file{}",
+    match cli.sort_by {
+        Some(SortBy::Name) => sort_md_files_by(&mut md_files, sort_key_name),
+        Some(SortBy::Path) => sort_md_files_by(&mut md_files, sort_key_path),
+        Some(SortBy::Modified) => sort_md_files_by(&mut md_files, sort_key_modified),
+        Some(SortBy::Size) => sort_md_files_by(&mut md_files, sort_key_size),
+        None => {}
+    }
+
+    let preview = cli.check || cli.dry_run || cli.list_different;
+    // Options given on the command line override those from config files
+    let cli_config = cli_config(&cli);
+    let heading_shift = match (cli.demote_headings, cli.promote_headings) {
+        (Some(levels), _) => levels as i8,
+        (_, Some(levels)) => -(levels as i8),
+        (None, None) => 0,
+    };
+
+    // Config files are resolved once per directory, from the repository root down.
+    // EditorConfig sections match file names, so they are resolved for every file and
+    // give way to the config files.
+    let mut dir_configs: HashMap<&Path, Config> = HashMap::new();
+    let mut file_options: HashMap<&Path, FormatOptions> = HashMap::new();
+    let mut lint_configs: HashMap<&Path, LintConfig> = HashMap::new();
+    let mut configs = BTreeSet::new();
+    for path in &md_files {
+        let dir = file_dir(path);
+        if !dir_configs.contains_key(dir) {
+            let config = if cli.no_config {
+                Config::default()
+            } else {
+                match config::resolve(dir) {
+                    Ok(config) => config,
+                    Err(e) => {
+                        print_error(colors, &format!("Error: {}", e));
+                        process::exit(EXIT_ERROR);
+                    }
+                }
+            };
+            dir_configs.insert(dir, config);
+        }
+        let editor_config = if cli.no_editorconfig {
+            Config::default()
+        } else {
+            match editorconfig::resolve(path) {
+                Ok(config) => config,
+                Err(e) => {
+                    print_error(colors, &format!("Error: {}", e));
+                    process::exit(EXIT_ERROR);
+                }
+            }
+        };
+        let config = Config::merge(
+            Config::merge(editor_config, dir_configs[dir].clone()),
+            cli_config.clone(),
+        );
+        let mut options = config.format_options();
+        options.formatter.heading_shift = heading_shift;
+        options.formatter.clamp_headings = cli.clamp_headings;
+        if let Some(encoding) = cli.encoding {
+            options.encoding = encoding;
+        }
+        #[cfg(feature = "preserve-mtime")]
+        {
+            options.preserve_mtime = cli.preserve_mtime;
+        }
+        if cli.lint {
+            let mut lint_config = config.lint_config();
+            lint_config.formatter = options.formatter.clone();
+            lint_configs.insert(path, lint_config);
+        }
+        configs.insert(format!("{:?}", config));
+        file_options.insert(path, options);
+    }
+    info!(
+        "{} markdown files with {} distinct configurations",
         md_files.len(),
-        if md_files.len() == 1 { "" } else { "s" }
+        configs.len()
     );
 
-    if cli.dry_run {
-        println!("Dry run mode - no files will be modified");
-        for file in &md_files {
-            println!("Would process: {}", file.display());
+    // Outlines are read from the files as they are, so nothing is formatted
+    if cli.extract_headings {
+        process::exit(run_extract_headings(
+            &md_files,
+            cli.sort_by.is_none(),
+            cli.output_format,
+            cli.quiet,
+            colors,
+        ));
+    }
+
+    // Duplicates are found without writing anything, comparing the formatted content
+    if cli.find_duplicates {
+        process::exit(run_find_duplicates(
+            &md_files,
+            &file_options,
+            cli.output_format,
+            cli.quiet,
+            colors,
+        ));
+    }
+
+    // Lint mode only reports problems, so nothing is formatted or deleted
+    if cli.lint {
+        process::exit(run_lint(
+            &md_files,
+            &file_options,
+            &lint_configs,
+            cli.sort_by.is_none(),
+            cli.output_format,
+            cli.quiet,
+            colors,
+        ));
+    }
+
+    // The list of different files is the only output of --list-different and
+    // --list-files
+    let text_output = !cli.quiet
+        && !cli.list_different
+        && !cli.list_files
+        && cli.output_format == OutputFormat::Text;
+
+    if md_files.is_empty() {
+        if text_output {
+            println!("No markdown files found");
+        } else if cli.list_files {
+            print_file_list(&[], cli.output_format);
+        } else if cli.output_format == OutputFormat::Json {
+            let use_cache = !cli.no_cache && (cli.cache || cli.cache_location.is_some());
+            print_json_report(
+                &[],
+                &[],
+                cli.report_unchanged.then_some(&[]),
+                &Summary::default(),
+                JsonSections {
+                    timings: cli.timings,
+                    cache: use_cache,
+                    too_large: cli.max_size.is_some(),
+                    normalized: cli.normalize_empty,
+                    omitted: cli.out_dir.is_some(),
+                },
+            );
+        }
+        // Still leave an (empty) list behind for scripts that read it
+        if let Some(list_path) = &cli.deleted_list {
+            if let Err(e) = write_deleted_list(list_path, &[]) {
+                print_error(
+                    colors,
+                    &format!(
+                        "Error: Failed to write deleted list '{}': {}",
+                        list_path.display(),
+                        e
+                    ),
+                );
+                process::exit(EXIT_ERROR);
+            }
         }
         return;
     }
 
-    let results: Vec<_> = md_files
-        .par_iter()
-        .map(|path| {
-            let result = match process_md::process_md_file(path, cli.delete) {
-                Ok((deleted, modified)) => {
-                    if deleted {
-                        Ok("deleted (empty body with frontmatter or completely empty)".to_string())
-                    } else if modified {
-                        Ok("modified (removed multiple blank lines)".to_string())
-                    } else {
-                        Ok("no changes needed".to_string())
+    if text_output {
+        println!(
+            "Found {} markdown file{}",
+            md_files.len(),
+            if md_files.len() == 1 { "" } else { "s" }
+        );
+    }
+
+    if cli.dry_run && text_output {
+        println!("Dry run mode - no files will be modified");
+    }
+
+    // The progress bar draws on stderr, so it never mixes with the report on stdout,
+    // but it would with the log lines of -vv
+    let progress = if !text_output
+        || cli.no_progress
+        || log::max_level() >= LevelFilter::Debug
+        || !io::stderr().is_terminal()
+    {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(md_files.len() as u64).with_style(
+            ProgressStyle::with_template("[{bar:40}] {pos}/{len} files processed (est {eta})")
+                .expect("progress bar template is valid")
+                .progress_chars("=> "),
+        )
+    };
+
+    let normalize_empty = file_options.values().any(|options| options.normalize_empty);
+    let limits_size = file_options
+        .values()
+        .any(|options| options.max_size.is_some());
+    let started = Instant::now();
+
+    // Workers only read the cache; new entries are collected and saved at the end.
+    // Diff-filtered and line range runs only format part of each file, and --out-dir
+    // runs write every file, so they never use the cache.
+    let cache_path = (!cli.no_cache
+        && !cli.diff_filter
+        && cli.line_range.is_none()
+        && cli.out_dir.is_none()
+        && (cli.cache || cli.cache_location.is_some()))
+    .then(|| {
+        cli.cache_location
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(cache::DEFAULT_CACHE_FILE))
+    });
+    let mut file_cache = match &cache_path {
+        Some(path) => {
+            // Every distinct config in use, so editing a config file invalidates the cache
+            let headings = format!(
+                "heading-shift={}{}",
+                heading_shift,
+                if cli.clamp_headings { ",clamp" } else { "" }
+            );
+            let encoding = format!("encoding={}", cli.encoding.unwrap_or(UTF_8).name());
+            let mut fingerprint: Vec<&str> = configs.iter().map(String::as_str).collect();
+            fingerprint.push(&headings);
+            fingerprint.push(&encoding);
+            let cache = Cache::load(path, &cache::fingerprint(&fingerprint));
+            info!("{} files in the cache at {}", cache.len(), path.display());
+            cache
+        }
+        None => Cache::default(),
+    };
+
+    let mut summary = Summary {
+        processed: md_files.len(),
+        ..Summary::default()
+    };
+    // Reports are kept only when they are needed after processing: for JSON output,
+    // for --sorted-output, and (just the timings) for the slowest files list
+    let keep_reports = cli.output_format == OutputFormat::Json || cli.sorted_output;
+    let mut results = Vec::new();
+    let mut timed = Vec::new();
+    let mut cache_updates = Vec::new();
+    let mut deleted = Vec::new();
+    let mut unchanged = Vec::new();
+    let mut different = Vec::new();
+    let mut restage = Vec::new();
+
+    // Workers send each report over a channel as soon as the file is done, and the
+    // main thread prints it right away, so output is not held back by the slowest file
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            md_files
+                .par_iter()
+                .enumerate()
+                .for_each_with(sender, |sender, (index, path)| {
+                    // Files verified clean by an earlier run are skipped without being read
+                    let cached = match cache_path.as_ref().map(|_| file_cache.lookup(path)) {
+                        Some(CacheLookup::Hit) => Some(None),
+                        Some(CacheLookup::HitWithUpdate(entry)) => Some(Some(entry)),
+                        Some(CacheLookup::Miss) | None => None,
+                    };
+                    // Each report is rendered here in full, so it is printed in one piece
+                    let render = |report: &FileReport| {
+                        render_file_report(report, text_output, cli.verbose > 0, preview, colors)
+                    };
+                    if let Some(cache_entry) = cached {
+                        let mut report = FileReport {
+                            index,
+                            path,
+                            result: Ok((FileStatus::Cached, None)),
+                            edits: EditCounts::default(),
+                            warnings: Vec::new(),
+                            warn_empty: false,
+                            timings: None,
+                            cache_entry,
+                            panicked: false,
+                            output: ReportOutput::default(),
+                        };
+                        report.output = render(&report);
+                        sender.send(report).ok();
+                        return;
                     }
+
+                    let mut timings = cli.timings.then(FileTimings::default);
+                    let options = &file_options[path.as_path()];
+                    let changes = catch_panic(|| match (line_ranges.get(path), &cli.line_range) {
+                        (Some(ranges), _) => {
+                            process_file_in_ranges(path, ranges, options).map(FileChanges::from)
+                        }
+                        (None, Some(range)) => {
+                            process_file_in_line_range(path, range, options, preview)
+                                .map(FileChanges::from)
+                        }
+                        (None, None) => process_file(
+                            path,
+                            options,
+                            out_dir.as_ref().map(|out_dir| out_dir.path(path)),
+                            preview,
+                            timings.as_mut(),
+                        ),
+                    });
+                    // A file that crashes the formatter is reported like any other error
+                    let panicked = changes.is_err();
+                    let changes = changes.unwrap_or_else(|message| Err(io::Error::other(message)));
+                    let (edits, warnings) = match &changes {
+                        Ok(changes) => (changes.edits, changes.warnings.clone()),
+                        Err(_) => (EditCounts::default(), Vec::new()),
+                    };
+                    let warn_empty = cli.warn_empty
+                        && changes.as_ref().is_ok_and(|changes| {
+                            changes.kind == Some(ContentKind::FrontmatterOnly)
+                                && !matches!(
+                                    changes.outcome,
+                                    FileOutcome::Deleted | FileOutcome::Trashed
+                                )
+                        });
+                    let result = match changes {
+                        Ok(changes) => {
+                            let status = match changes.outcome {
+                                FileOutcome::Unchanged => FileStatus::Unchanged,
+                                FileOutcome::Modified => FileStatus::Modified,
+                                // Empty files are left out of the output directory
+                                FileOutcome::Deleted | FileOutcome::Trashed
+                                    if cli.out_dir.is_some() =>
+                                {
+                                    FileStatus::Omitted
+                                }
+                                FileOutcome::Deleted => FileStatus::Deleted,
+                                FileOutcome::Trashed => FileStatus::Trashed,
+                                FileOutcome::TooLarge => FileStatus::TooLarge,
+                                FileOutcome::Normalized => FileStatus::Normalized,
+                            };
+                            // Show what a dry run would change, limited to the first few hunks
+                            let hunks = if cli.dry_run
+                                && cli.line_range.is_none()
+                                && cli.verbose > 0
+                                && text_output
+                                && status == FileStatus::Modified
+                            {
+                                process_md::read_file_with_encoding(path, options.encoding)
+                                    .ok()
+                                    .map(|content| {
+                                        let formatted =
+                                            process_md::format_content(&content, options);
+                                        diff::render_diff(&content, &formatted.content, 1, Some(3))
+                                    })
+                            } else {
+                                None
+                            };
+                            Ok((status, hunks))
+                        }
+                        Err(e) => Err(format!("error: {}", e)),
+                    };
+                    // Remember files that are clean now, including ones just formatted in place.
+                    // Files to warn about are read again, so the warnings are repeated.
+                    let cache_entry = match &result {
+                        _ if warn_empty || !warnings.is_empty() => None,
+                        Ok((FileStatus::Unchanged, _)) if cache_path.is_some() => {
+                            CacheEntry::from_file(path).ok()
+                        }
+                        Ok((FileStatus::Modified | FileStatus::Normalized, _))
+                            if cache_path.is_some() && !preview =>
+                        {
+                            CacheEntry::from_file(path).ok()
+                        }
+                        _ => None,
+                    };
+                    let mut report = FileReport {
+                        index,
+                        path,
+                        result,
+                        edits,
+                        warnings,
+                        warn_empty,
+                        timings,
+                        cache_entry,
+                        panicked,
+                        output: ReportOutput::default(),
+                    };
+                    report.output = render(&report);
+                    sender.send(report).ok();
+                });
+        });
+
+        for report in receiver {
+            progress.inc(1);
+            if let Some(timings) = report.timings {
+                summary.bytes += timings.bytes;
+                if cli.timings {
+                    timed.push((report.path, timings));
                 }
-                Err(e) => Err(format!("error: {}", e)),
-            };
-            (path, result)
+            }
+            match &report.result {
+                Ok((status, _)) => {
+                    if (cli.list_different || cli.list_files) && status.is_change() {
+                        different.push((report.index, report.path));
+                    }
+                    if cli.format_only_staged && !cli.no_restage && !preview && status.is_change() {
+                        restage.push((report.index, report.path));
+                    }
+                    match status {
+                        FileStatus::Deleted | FileStatus::Trashed => {
+                            summary.deleted += 1;
+                            deleted.push((report.index, report.path));
+                        }
+                        FileStatus::Modified => summary.modified += 1,
+                        FileStatus::Normalized => summary.normalized += 1,
+                        FileStatus::Omitted => summary.omitted += 1,
+                        FileStatus::Cached => summary.cached += 1,
+                        FileStatus::TooLarge => summary.too_large += 1,
+                        FileStatus::Unchanged => {}
+                    }
+                    // Cached files were clean when they were cached and have not changed
+                    if cli.report_unchanged
+                        && matches!(status, FileStatus::Unchanged | FileStatus::Cached)
+                    {
+                        unchanged.push((report.index, report.path));
+                    }
+                    if report.cache_entry.is_some() || *status != FileStatus::Cached {
+                        cache_updates.push((report.path, report.cache_entry.clone()));
+                    }
+                }
+                Err(_) => {
+                    summary.errors += 1;
+                    if report.panicked {
+                        summary.panicked += 1;
+                    }
+                    cache_updates.push((report.path, None));
+                }
+            }
+
+            if keep_reports {
+                results.push(report);
+            } else {
+                progress.suspend(|| report.output.print());
+            }
+        }
+    });
+    progress.finish_and_clear();
+    summary.elapsed = started.elapsed();
+
+    // Keep the report deterministic where it is read as a whole
+    results.sort_by_key(|report| report.index);
+    for report in &results {
+        report.output.print();
+    }
+    deleted.sort_by_key(|(index, _)| *index);
+    let deleted: Vec<&PathBuf> = deleted.into_iter().map(|(_, path)| path).collect();
+    unchanged.sort_by_key(|(index, _)| *index);
+    let unchanged: Vec<&PathBuf> = unchanged.into_iter().map(|(_, path)| path).collect();
+
+    if cli.list_different {
+        different.sort_by_key(|(index, _)| *index);
+        // Relative paths can be passed straight back to mdfmt or git
+        for (_, path) in &different {
+            println!("{}", path.display());
+        }
+        if !cli.quiet {
+            eprintln!(
+                "{} file{} would be changed",
+                different.len(),
+                if different.len() == 1 { "" } else { "s" }
+            );
+        }
+    }
+    if cli.list_files {
+        different.sort_by_key(|(index, _)| *index);
+        let paths: Vec<PathBuf> = different
+            .iter()
+            .map(|(_, path)| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+            .collect();
+        print_file_list(&paths, cli.output_format);
+    }
+
+    if !restage.is_empty() {
+        restage.sort_by_key(|(index, _)| *index);
+        let mut files = Vec::new();
+        for (_, path) in restage {
+            let key = file_key(path);
+            if key
+                .as_ref()
+                .is_some_and(|key| partially_staged.contains(key))
+            {
+                eprintln!(
+                    "{}: warning: not staged again because it has unstaged changes",
+                    path.display()
+                );
+            } else if key.is_some_and(|key| staged_files.contains(&key)) {
+                files.push(path.clone());
+            }
+        }
+        if let Err(e) = git::stage_files(&files) {
+            print_error(
+                colors,
+                &format!("Error: Failed to stage formatted files: {}", e),
+            );
+            summary.errors += 1;
+        }
+    }
+
+    if let Some(list_path) = &cli.deleted_list {
+        if let Err(e) = write_deleted_list(list_path, &deleted) {
+            print_error(
+                colors,
+                &format!(
+                    "Error: Failed to write deleted list '{}': {}",
+                    list_path.display(),
+                    e
+                ),
+            );
+            summary.errors += 1;
+        }
+    }
+
+    for (path, entry) in cache_updates {
+        match entry {
+            Some(entry) => file_cache.insert(path, entry),
+            None => file_cache.remove(path),
+        }
+    }
+
+    if let Some(path) = &cache_path {
+        if let Err(e) = file_cache.save(path) {
+            eprintln!("Warning: Failed to write cache '{}': {}", path.display(), e);
+        }
+    }
+
+    // Print summary
+    if cli.list_files {
+        // The list is the whole report
+    } else if cli.output_format == OutputFormat::Json {
+        print_json_report(
+            &results,
+            &deleted,
+            cli.report_unchanged.then_some(&unchanged),
+            &summary,
+            JsonSections {
+                timings: cli.timings,
+                cache: cache_path.is_some(),
+                too_large: limits_size,
+                normalized: normalize_empty,
+                omitted: cli.out_dir.is_some(),
+            },
+        );
+    } else if text_output {
+        if cli.report_unchanged && !unchanged.is_empty() {
+            println!();
+            println!("Files already formatted:");
+            for path in &unchanged {
+                println!("  {}", path.display());
+            }
+        }
+        if !deleted.is_empty() {
+            println!();
+            if preview {
+                println!("Files that would be deleted:");
+            } else {
+                println!("Deleted files:");
+            }
+            for path in &deleted {
+                let path = path.display().to_string();
+                println!("  {}", colors.stdout.paint(Style::Deleted, &path));
+            }
+        }
+
+        let count = |count: usize| {
+            colors
+                .stdout
+                .paint(Style::Count, &count.to_string())
+                .into_owned()
+        };
+        println!();
+        println!("Summary:");
+        println!("  Files processed: {}", count(summary.processed));
+        if preview {
+            println!(
+                "  Files that would be modified: {}",
+                count(summary.modified)
+            );
+            println!("  Files that would be deleted: {}", count(summary.deleted));
+            if normalize_empty {
+                println!(
+                    "  Files that would be normalized: {}",
+                    count(summary.normalized)
+                );
+            }
+        } else {
+            println!("  Files modified: {}", count(summary.modified));
+            if cli.out_dir.is_some() {
+                println!("  Files not written (empty): {}", count(summary.omitted));
+            } else {
+                println!("  Files deleted: {}", count(summary.deleted));
+            }
+            if normalize_empty {
+                println!("  Files normalized: {}", count(summary.normalized));
+            }
+        }
+        if cache_path.is_some() {
+            println!("  Files skipped (cached): {}", count(summary.cached));
+        }
+        if limits_size {
+            println!("  Files skipped (too large): {}", count(summary.too_large));
+        }
+        if cli.report_unchanged {
+            println!("  Files already formatted: {}", count(unchanged.len()));
+        }
+        println!("  Errors: {}", count(summary.errors));
+        if summary.panicked > 0 {
+            println!("  Panicked: {}", count(summary.panicked));
+        }
+
+        if cli.timings {
+            println!("  Total time: {:.2?}", summary.elapsed);
+            println!("  Bytes processed: {}", summary.bytes);
+            print_slowest_files(timed);
+        }
+    } else if cli.report_unchanged && cli.quiet {
+        println!(
+            "{} file{} already formatted",
+            unchanged.len(),
+            if unchanged.len() == 1 { "" } else { "s" }
+        );
+    }
+
+    if summary.errors > 0 {
+        process::exit(EXIT_ERROR);
+    } else if summary.modified + summary.deleted + summary.normalized + summary.omitted > 0
+        && !cli.dry_run
+        && !cli.no_error_on_unformatted
+    {
+        process::exit(EXIT_CHANGED);
+    }
+    process::exit(EXIT_CLEAN);
+}
+
+/// The outcome of processing a single file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileStatus {
+    Unchanged,
+    Modified,
+    Deleted,
+    /// Moved to the system trash instead of being deleted
+    Trashed,
+    /// Skipped because the cache shows it was already clean
+    Cached,
+    /// Skipped because it is larger than --max-size
+    TooLarge,
+    /// Truncated to zero bytes because it contained only whitespace
+    Normalized,
+    /// Not written to --out-dir because it would have been deleted for being empty
+    Omitted,
+}
+
+impl FileStatus {
+    /// Machine readable name used in JSON output.
+    fn as_str(self) -> &'static str {
+        match self {
+            FileStatus::Unchanged => "unchanged",
+            FileStatus::Modified => "modified",
+            FileStatus::Deleted => "deleted",
+            FileStatus::Trashed => "trashed",
+            FileStatus::Cached => "cached",
+            FileStatus::TooLarge => "too_large",
+            FileStatus::Normalized => "normalized",
+            FileStatus::Omitted => "omitted",
+        }
+    }
+
+    /// Whether the file was (or would be) changed.
+    fn is_change(self) -> bool {
+        matches!(
+            self,
+            FileStatus::Modified
+                | FileStatus::Deleted
+                | FileStatus::Trashed
+                | FileStatus::Normalized
+                | FileStatus::Omitted
+        )
+    }
+
+    /// The color of the status line, if it has one.
+    fn style(self) -> Option<Style> {
+        match self {
+            FileStatus::Modified | FileStatus::Normalized => Some(Style::Modified),
+            FileStatus::Deleted | FileStatus::Trashed | FileStatus::Omitted => Some(Style::Deleted),
+            FileStatus::Unchanged | FileStatus::Cached | FileStatus::TooLarge => None,
+        }
+    }
+
+    /// Human readable status line; `preview` selects the "would be" wording.
+    fn message(self, preview: bool) -> &'static str {
+        match (self, preview) {
+            (FileStatus::Unchanged, _) => "no changes needed",
+            (FileStatus::Cached, _) => "skipped (cached)",
+            (FileStatus::TooLarge, _) => "skipped (too large)",
+            (FileStatus::Normalized, false) => "normalized (whitespace only, truncated to empty)",
+            (FileStatus::Normalized, true) => {
+                "would be normalized (whitespace only, truncated to empty)"
+            }
+            (FileStatus::Modified, false) => "modified (removed multiple blank lines)",
+            (FileStatus::Modified, true) => {
+                "would be modified (multiple blank lines or missing spacing)"
+            }
+            (FileStatus::Deleted, false) => {
+                "deleted (empty body with frontmatter or completely empty)"
+            }
+            (FileStatus::Deleted, true) => {
+                "would be deleted (empty body with frontmatter or completely empty)"
+            }
+            (FileStatus::Trashed, false) => {
+                "trashed (empty body with frontmatter or completely empty)"
+            }
+            (FileStatus::Trashed, true) => {
+                "would be trashed (empty body with frontmatter or completely empty)"
+            }
+            (FileStatus::Omitted, _) => {
+                "not written (empty body with frontmatter or completely empty)"
+            }
+        }
+    }
+}
+
+/// Everything reported about a single processed file.
+struct FileReport<'a> {
+    /// Position of the file in the input order
+    index: usize,
+    path: &'a PathBuf,
+    /// The status and, for verbose dry runs, the rendered hunks; or an error message
+    result: Result<(FileStatus, Option<String>), String>,
+    /// The edits made by each rule, when they are known
+    edits: EditCounts,
+    /// Line-numbered warnings from the enabled checks
+    warnings: Vec<LintWarning>,
+    /// Whether to warn that the file contains only frontmatter (--warn-empty)
+    warn_empty: bool,
+    timings: Option<FileTimings>,
+    /// A fresh cache entry if the file is known to be clean after this run
+    cache_entry: Option<CacheEntry>,
+    /// Whether the error is a panic caught while processing the file
+    panicked: bool,
+    /// The report as printed in the text output
+    output: ReportOutput,
+}
+
+/// What the report of a file prints on stdout and stderr.
+#[derive(Debug, Default)]
+struct ReportOutput {
+    stdout: String,
+    stderr: String,
+}
+
+impl ReportOutput {
+    /// Print both streams, each with a single write, so the reports of files
+    /// processed in parallel never interleave.
+    fn print(&self) {
+        print!("{}", self.stdout);
+        eprint!("{}", self.stderr);
+    }
+}
+
+/// Aggregate counts for the summary block.
+#[derive(Debug, Default)]
+struct Summary {
+    processed: usize,
+    modified: usize,
+    normalized: usize,
+    deleted: usize,
+    /// Files left out of --out-dir for being empty
+    omitted: usize,
+    cached: usize,
+    too_large: usize,
+    /// Every error, including the panics
+    errors: usize,
+    panicked: usize,
+    elapsed: Duration,
+    bytes: u64,
+}
+
+/// Run a packaging subcommand, which writes its output to stdout, and exit.
+#[cfg_attr(
+    not(any(feature = "completions", feature = "manpage")),
+    allow(unreachable_code)
+)]
+fn run_subcommand(command: Subcommand) -> ! {
+    #[cfg(any(feature = "completions", feature = "manpage"))]
+    use clap::CommandFactory;
+
+    match command {
+        #[cfg(feature = "completions")]
+        Subcommand::Completions { shell } => {
+            clap_complete::generate(shell, &mut Args::command(), "mdfmt", &mut io::stdout());
+        }
+        #[cfg(feature = "manpage")]
+        Subcommand::Manpage => {
+            if let Err(e) = clap_mangen::Man::new(Args::command()).render(&mut io::stdout()) {
+                eprintln!("Error: Failed to write the man page: {}", e);
+                process::exit(EXIT_ERROR);
+            }
+        }
+    }
+    process::exit(EXIT_CLEAN);
+}
+
+/// The directory a file is in, which is `.` for bare file names.
+fn file_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+/// A path that identifies a file however it was reached, even after the file was
+/// deleted: its canonical directory joined with its name.
+fn file_key(path: &Path) -> Option<PathBuf> {
+    Some(file_dir(path).canonicalize().ok()?.join(path.file_name()?))
+}
+
+/// Where `--out-dir` writes the formatted copies of the files, which keep their
+/// path below the searched directory they were found in.
+struct OutDir<'a> {
+    /// The output directory as given on the command line.
+    dir: &'a Path,
+    /// Its canonical path, which only exists if an earlier run created it.
+    root: Option<PathBuf>,
+    /// Where each file is written.
+    paths: HashMap<PathBuf, PathBuf>,
+    /// Which file each output path is for.
+    sources: HashMap<PathBuf, PathBuf>,
+}
+
+impl<'a> OutDir<'a> {
+    fn new(dir: &'a Path) -> Self {
+        OutDir {
+            dir,
+            root: dir.canonicalize().ok(),
+            paths: HashMap::new(),
+            sources: HashMap::new(),
+        }
+    }
+
+    /// The canonical output directory if it is inside the searched `input_path`, so
+    /// the copies an earlier run wrote there can be skipped, or an error if it is
+    /// `input_path` itself.
+    fn nested_in(&self, input_path: &Path) -> Result<Option<PathBuf>, String> {
+        let Some(root) = &self.root else {
+            return Ok(None);
+        };
+        let Some(dir) = input_path
+            .canonicalize()
+            .ok()
+            .filter(|_| input_path.is_dir())
+        else {
+            return Ok(None);
+        };
+        if *root == dir {
+            return Err("--out-dir must not be one of the searched directories".to_string());
+        }
+        Ok(root.starts_with(&dir).then(|| root.clone()))
+    }
+
+    /// Plan where `file`, found below `root` and reported as `path`, is written, or
+    /// return an error if another file would be written there too.
+    fn add(&mut self, root: &Path, file: &Path, path: &Path) -> Result<(), String> {
+        let out_path = self.dir.join(file.strip_prefix(root).unwrap_or(file));
+        if let Some(other) = self.sources.insert(out_path.clone(), path.to_path_buf()) {
+            return Err(format!(
+                "--out-dir would write both '{}' and '{}' to '{}'",
+                other.display(),
+                path.display(),
+                out_path.display()
+            ));
+        }
+        self.paths.insert(path.to_path_buf(), out_path);
+        Ok(())
+    }
+
+    /// Check that no copy lands on one of the files being formatted, given by their
+    /// canonical paths, since the originals are never modified.
+    fn check_overwrites(&self, files: &HashSet<PathBuf>) -> Result<(), String> {
+        for (out_path, source) in &self.sources {
+            if out_path
+                .canonicalize()
+                .is_ok_and(|out_path| files.contains(&out_path))
+            {
+                return Err(format!(
+                    "--out-dir would overwrite '{}' with the formatted '{}'",
+                    out_path.display(),
+                    source.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the file reported as `path` is written.
+    fn path(&self, path: &Path) -> &Path {
+        &self.paths[path]
+    }
+}
+
+/// Format a file in place, or into `destination` for `--out-dir`, or check whether
+/// formatting would change it with `preview`.
+fn process_file(
+    path: &Path,
+    options: &FormatOptions,
+    destination: Option<&Path>,
+    preview: bool,
+    timings: Option<&mut FileTimings>,
+) -> io::Result<FileChanges> {
+    match destination {
+        Some(out_path) => process_md::process_md_file_to(path, out_path, options, timings),
+        None if preview => process_md::check_md_file_with_options(path, options, timings),
+        None => process_md::process_md_file_with_options(path, options, timings),
+    }
+}
+
+/// Format only the given line ranges of a file, unless it is larger than the size
+/// limit of `options`.
+fn process_file_in_ranges(
+    path: &PathBuf,
+    ranges: &[RangeInclusive<usize>],
+    options: &FormatOptions,
+) -> io::Result<FileOutcome> {
+    if let Some(max_size) = options.max_size {
+        if fs::metadata(path)?.len() > max_size {
+            return Ok(FileOutcome::TooLarge);
+        }
+    }
+    let modified = process_md::process_md_file_in_ranges_with_options(path, ranges, options)?;
+    Ok(if modified {
+        FileOutcome::Modified
+    } else {
+        FileOutcome::Unchanged
+    })
+}
+
+/// Print the groups of files with the same formatted content, one path per line and
+/// a blank line between groups.
+///
+/// Returns the exit code: 2 if a file could not be read, 1 if any duplicates were
+/// found, and 0 otherwise.
+fn run_find_duplicates(
+    md_files: &[PathBuf],
+    file_options: &HashMap<&Path, FormatOptions>,
+    output_format: OutputFormat,
+    quiet: bool,
+    colors: Colors,
+) -> i32 {
+    let failed = Cell::new(false);
+    let groups = find_md_files::find_duplicate_content_by(md_files, |path| {
+        let options = &file_options[path];
+        let content =
+            process_md::read_file_with_encoding(path, options.encoding).inspect_err(|e| {
+                print_error(colors, &format!("{}: error: {}", path.display(), e));
+                failed.set(true);
+            })?;
+        Ok(process_md::format_content(&content, options)
+            .content
+            .into_owned()
+            .into_bytes())
+    });
+
+    if output_format == OutputFormat::Json {
+        let report = json!({
+            "duplicates": groups,
+            "summary": {
+                "files": md_files.len(),
+                "groups": groups.len(),
+            },
+        });
+        println!("{}", report);
+    } else {
+        for (i, group) in groups.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            for path in group {
+                println!("{}", path.display());
+            }
+        }
+        if !quiet {
+            if !groups.is_empty() {
+                println!();
+            }
+            println!(
+                "{} group{} of duplicate files in {} file{}",
+                groups.len(),
+                if groups.len() == 1 { "" } else { "s" },
+                md_files.len(),
+                if md_files.len() == 1 { "" } else { "s" }
+            );
+        }
+    }
+
+    if failed.get() {
+        EXIT_ERROR
+    } else if groups.is_empty() {
+        EXIT_CLEAN
+    } else {
+        EXIT_CHANGED
+    }
+}
+
+/// Print the headings of every file, ordered by path (unless `sort_by_path` is off).
+///
+/// In text, each file's path is followed by its headings, indented by level and
+/// preceded by their line numbers, with a blank line between files.
+///
+/// Returns the exit code: 2 if a file could not be read, and 0 otherwise.
+fn run_extract_headings(
+    md_files: &[PathBuf],
+    sort_by_path: bool,
+    output_format: OutputFormat,
+    quiet: bool,
+    colors: Colors,
+) -> i32 {
+    let mut files: Vec<&PathBuf> = md_files.iter().collect();
+    if sort_by_path {
+        files.sort();
+    }
+    let results: Vec<_> = files
+        .par_iter()
+        .map(|path| {
+            catch_panic(|| {
+                process_md::read_file(path).map(|content| outline::extract_headings(&content))
+            })
+            .unwrap_or_else(|message| Err(io::Error::other(message)))
         })
         .collect();
 
-    let mut deleted_count = 0;
-    let mut modified_count = 0;
-    let mut error_count = 0;
+    let mut exit_code = EXIT_CLEAN;
+    let mut outlines = Vec::new();
+    for (path, result) in files.iter().zip(results) {
+        match result {
+            Ok(headings) => outlines.push((*path, headings)),
+            Err(e) => {
+                print_error(colors, &format!("{}: error: {}", path.display(), e));
+                exit_code = EXIT_ERROR;
+            }
+        }
+    }
+    let count: usize = outlines.iter().map(|(_, headings)| headings.len()).sum();
+
+    if output_format == OutputFormat::Json {
+        let files: Vec<_> = outlines
+            .iter()
+            .map(|(path, headings)| {
+                let headings: Vec<_> = headings
+                    .iter()
+                    .map(|heading| {
+                        json!({
+                            "level": heading.level,
+                            "text": heading.text,
+                            "line": heading.line,
+                            "slug": heading.slug,
+                        })
+                    })
+                    .collect();
+                json!({ "path": path, "headings": headings })
+            })
+            .collect();
+        let report = json!({
+            "files": files,
+            "summary": {
+                "files": outlines.len(),
+                "headings": count,
+            },
+        });
+        println!("{}", report);
+        return exit_code;
+    }
+
+    for (i, (path, headings)) in outlines.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}", path.display());
+        let width = headings
+            .last()
+            .map_or(0, |heading| heading.line.to_string().len());
+        for heading in headings {
+            println!(
+                "{:>width$}  {}{} (#{})",
+                heading.line,
+                "  ".repeat(usize::from(heading.level) - 1),
+                heading.text,
+                heading.slug,
+                width = width
+            );
+        }
+    }
+    if !quiet {
+        if !outlines.is_empty() {
+            println!();
+        }
+        println!(
+            "{} heading{} in {} file{}",
+            count,
+            if count == 1 { "" } else { "s" },
+            outlines.len(),
+            if outlines.len() == 1 { "" } else { "s" }
+        );
+    }
+    exit_code
+}
+
+/// Lint every file and print the violations as `path:line[:col]: CODE message`,
+/// ordered by path (unless `sort_by_path` is off) and then by line.
+///
+/// Returns the exit code: 2 if a file could not be read, 1 if any violation is an
+/// error, and 0 otherwise.
+fn run_lint(
+    md_files: &[PathBuf],
+    file_options: &HashMap<&Path, FormatOptions>,
+    lint_configs: &HashMap<&Path, LintConfig>,
+    sort_by_path: bool,
+    output_format: OutputFormat,
+    quiet: bool,
+    colors: Colors,
+) -> i32 {
+    let mut files: Vec<&PathBuf> = md_files.iter().collect();
+    if sort_by_path {
+        files.sort();
+    }
+    let results: Vec<_> = files
+        .par_iter()
+        .map(|path| {
+            let encoding = file_options[path.as_path()].encoding;
+            let config = &lint_configs[path.as_path()];
+            catch_panic(|| {
+                process_md::read_file_with_encoding(path, encoding)
+                    .map(|content| lint::lint_all(&content, config))
+            })
+            .unwrap_or_else(|message| Err(io::Error::other(message)))
+        })
+        .collect();
 
-    for (path, result) in results {
+    let mut exit_code = EXIT_CLEAN;
+    let mut found = Vec::new();
+    for (path, result) in files.iter().zip(results) {
         match result {
-            Ok(status) => {
-                if cli.verbose || !status.contains("no changes needed") {
-                    println!("{}: {}", path.display(), status);
+            Ok(violations) => found.extend(violations.into_iter().map(|v| (*path, v))),
+            Err(e) => {
+                print_error(colors, &format!("{}: error: {}", path.display(), e));
+                exit_code = EXIT_ERROR;
+            }
+        }
+    }
+    let errors = found
+        .iter()
+        .filter(|(_, violation)| violation.severity == Severity::Error)
+        .count();
+    if errors > 0 && exit_code == EXIT_CLEAN {
+        exit_code = EXIT_CHANGED;
+    }
+
+    if output_format == OutputFormat::Json {
+        let violations: Vec<_> = found
+            .iter()
+            .map(|(path, violation)| {
+                json!({
+                    "path": path,
+                    "line": violation.line,
+                    "col": violation.col,
+                    "code": violation.code(),
+                    "rule": violation.rule_id,
+                    "severity": violation.severity.to_string(),
+                    "message": violation.message,
+                    "suggestion": violation.suggestion,
+                })
+            })
+            .collect();
+        let report = json!({
+            "violations": violations,
+            "summary": {
+                "files": files.len(),
+                "problems": found.len(),
+                "errors": errors,
+            },
+        });
+        println!("{}", report);
+        return exit_code;
+    }
+
+    for (path, violation) in &found {
+        let location = match violation.col {
+            Some(col) => format!("{}:{}:{}", path.display(), violation.line, col),
+            None => format!("{}:{}", path.display(), violation.line),
+        };
+        println!("{}: {} {}", location, violation.code(), violation.message);
+    }
+    if !quiet {
+        println!(
+            "{} problem{} ({} error{}) in {} file{}",
+            found.len(),
+            if found.len() == 1 { "" } else { "s" },
+            errors,
+            if errors == 1 { "" } else { "s" },
+            files.len(),
+            if files.len() == 1 { "" } else { "s" }
+        );
+    }
+    exit_code
+}
+
+/// Format only lines `range` of a file, or check whether that would change it with
+/// `preview`, unless it is larger than the size limit of `options`.
+fn process_file_in_line_range(
+    path: &PathBuf,
+    range: &RangeInclusive<usize>,
+    options: &FormatOptions,
+    preview: bool,
+) -> io::Result<FileOutcome> {
+    if let Some(max_size) = options.max_size {
+        if fs::metadata(path)?.len() > max_size {
+            return Ok(FileOutcome::TooLarge);
+        }
+    }
+    let (start, end) = (*range.start(), *range.end());
+    let modified = if preview {
+        let content = process_md::read_file(path)?;
+        process_md::format_range(&content, start, end, &options.formatter) != content
+    } else {
+        process_md::process_md_file_in_range(path, start, end, &options.formatter)?
+    };
+    Ok(if modified {
+        FileOutcome::Modified
+    } else {
+        FileOutcome::Unchanged
+    })
+}
+
+/// Parse a 1-based, inclusive line range such as `10:20`.
+fn parse_line_range(value: &str) -> Result<RangeInclusive<usize>, String> {
+    let (start, end) = value
+        .split_once(':')
+        .ok_or_else(|| format!("expected START:END, got '{}'", value))?;
+    let parse = |line: &str| match line.trim().parse::<usize>() {
+        Ok(line) if line > 0 => Ok(line),
+        _ => Err(format!("invalid line number '{}'", line)),
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start > end {
+        return Err(format!("start line {} is after end line {}", start, end));
+    }
+    Ok(start..=end)
+}
+
+/// Parse a byte count with an optional binary unit, e.g. `512`, `64K`, `5M` or `1GiB`.
+fn parse_size(value: &str) -> Result<u64, String> {
+    let upper = value.trim().to_ascii_uppercase();
+    let (digits, unit) = upper.split_at(
+        upper
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(upper.len()),
+    );
+    let multiplier: u64 = match unit {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return Err(format!("unknown unit '{}', expected K, M or G", unit)),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| format!("'{}' is not a valid size", value))
+}
+
+/// Parse the name of an encoding, e.g. `utf-8`, `latin1` or `windows-1252`, as the
+/// WHATWG Encoding Standard labels them.
+fn parse_encoding(value: &str) -> Result<&'static Encoding, String> {
+    let encoding = Encoding::for_label(value.trim().as_bytes())
+        .ok_or_else(|| format!("unknown encoding '{}'", value))?;
+    // UTF-16 and the replacement encoding cannot be written back
+    if encoding.output_encoding() != encoding {
+        return Err(format!("files cannot be written as {}", encoding.name()));
+    }
+    Ok(encoding)
+}
+
+/// Write the deleted paths to `list_path`, one per line.
+fn write_deleted_list(list_path: &Path, deleted: &[&PathBuf]) -> io::Result<()> {
+    let list: String = deleted
+        .iter()
+        .map(|path| format!("{}\n", path.display()))
+        .collect();
+    fs::write(list_path, list)
+}
+
+/// Print the options that apply to files in `path` as TOML, with every option that
+/// is not set at its default, so the output is a complete `.mdfmt.toml` file.
+///
+/// Returns the exit code: 2 if a config file could not be read, and 0 otherwise.
+fn run_print_config(path: &Path, cli: &Args, colors: Colors) -> i32 {
+    match resolve_config(path, cli, !cli.no_editorconfig && path.is_file()) {
+        Ok(config) => {
+            let printed = Config {
+                lint: config.lint.clone(),
+                ..Config::from(&config.format_options())
+            };
+            print!("{}", printed.to_toml_string());
+            EXIT_CLEAN
+        }
+        Err(e) => {
+            print_error(colors, &format!("Error: {}", e));
+            EXIT_ERROR
+        }
+    }
+}
+
+/// The options for `path`, which does not have to exist: the defaults, overridden by
+/// the config files that apply to it, by `.editorconfig` if `use_editorconfig` is
+/// set, and by the command line.
+///
+/// The config files of a directory are those of the files in it.
+fn resolve_config(path: &Path, cli: &Args, use_editorconfig: bool) -> io::Result<Config> {
+    let dir = if path.is_dir() { path } else { file_dir(path) };
+    let config = if cli.no_config {
+        Config::default()
+    } else {
+        config::resolve(dir)?
+    };
+    let editor_config = if use_editorconfig {
+        editorconfig::resolve(path)?
+    } else {
+        Config::default()
+    };
+    Ok(Config::merge(
+        Config::merge(editor_config, config),
+        cli_config(cli),
+    ))
+}
+
+/// The options given on the command line, which override those from config files.
+fn cli_config(cli: &Args) -> Config {
+    Config {
+        delete: if cli.delete_to_trash {
+            Some(DeleteStrategy::Trash)
+        } else if cli.delete {
+            Some(DeleteStrategy::Remove)
+        } else {
+            None
+        },
+        normalize_empty: cli.normalize_empty.then_some(true),
+        include_empty: cli.include_empty.then_some(true),
+        max_size: cli.max_size,
+        lock_timeout_ms: cli.lock_timeout,
+        ignore_final_newline_only: cli.ignore_final_newline_only.then_some(true),
+        fence_style: cli.fence_style.map(|marker| match marker {
+            FenceMarker::Backtick => FenceStyle::Backtick,
+            FenceMarker::Tilde => FenceStyle::Tilde,
+            FenceMarker::Keep => FenceStyle::Keep,
+        }),
+        normalize_fence_languages: cli.normalize_fence_lang.then_some(true),
+        default_fence_language: cli.default_fence_lang.clone(),
+        require_fence_language: cli.require_fence_lang.then_some(true),
+        blank_lines_before_heading: cli.blank_lines_before_heading,
+        blank_lines_after_heading: cli.blank_lines_after_heading,
+        toc: cli.toc.then_some(true),
+        toc_levels: cli.toc_levels,
+        remove_empty_headings: cli.remove_empty_headings.then_some(true),
+        sort_frontmatter: cli.sort_frontmatter.then_some(true),
+        list_indent: cli.list_indent.map(usize::from),
+        tab_width: cli.tab_width.map(usize::from),
+        tabs_to_spaces: cli.tabs_to_spaces.map(usize::from),
+        normalize_list_marker_spacing: cli.normalize_list_spacing.then_some(true),
+        wrap_blockquotes: cli.wrap_blockquotes.map(usize::from),
+        line_ending: cli.end_of_line.map(|style| match style {
+            LineEndingStyle::Lf => LineEnding::Lf,
+            LineEndingStyle::Crlf => LineEnding::Crlf,
+            LineEndingStyle::Keep => LineEnding::Keep,
+        }),
+        insert_final_newline: if cli.final_newline {
+            Some(true)
+        } else if cli.no_final_newline {
+            Some(false)
+        } else {
+            None
+        },
+        trim_trailing_whitespace: if cli.trim_trailing_whitespace {
+            Some(true)
+        } else if cli.keep_trailing_whitespace {
+            Some(false)
+        } else {
+            None
+        },
+        ..Config::default()
+    }
+}
+
+/// Print an error message on stderr, in bright red if stderr is colored.
+fn print_error(colors: Colors, message: &str) {
+    eprintln!("{}", colors.stderr.paint(Style::Error, message));
+}
+
+thread_local! {
+    /// Whether panics on this thread are caught by [`catch_panic`].
+    static CATCHING_PANICS: Cell<bool> = const { Cell::new(false) };
+    /// Where the last panic caught on this thread happened.
+    static PANIC_LOCATION: Cell<Option<String>> = const { Cell::new(None) };
+}
+
+/// Install a panic hook that keeps panics caught by [`catch_panic`] off stderr,
+/// since they are reported as errors of the file they happened in, and prints
+/// every other panic as usual.
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if CATCHING_PANICS.get() {
+            PANIC_LOCATION.set(info.location().map(ToString::to_string));
+        } else {
+            default_hook(info);
+        }
+    }));
+}
+
+/// Run `f`, turning a panic into an error message such as
+/// `panicked at src/lists.rs:10:5: index out of bounds`, so a file that crashes the
+/// formatter does not stop the files processed next to it.
+fn catch_panic<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    let catching = CATCHING_PANICS.replace(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING_PANICS.set(catching);
+    result.map_err(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown cause");
+        match PANIC_LOCATION.take() {
+            Some(location) => format!("panicked at {}: {}", location, message),
+            None => format!("panicked: {}", message),
+        }
+    })
+}
+
+/// Writes the log records of mdfmt itself to stderr: errors and warnings like the
+/// other messages, and the more detailed levels with their level and module.
+struct StderrLogger {
+    colors: Colors,
+}
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target().split("::").next() == Some("mdfmt")
+            && metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Error => print_error(self.colors, &format!("Error: {}", record.args())),
+            Level::Warn => eprintln!("Warning: {}", record.args()),
+            level => eprintln!("[{} {}] {}", level, record.target(), record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// The most detailed level to log: warnings by default, only errors with --quiet,
+/// and info, debug, or trace for -v, -vv, or -vvv.
+///
+/// `RUST_LOG` overrides the flags with a level such as `debug`, or with an
+/// `mdfmt=debug` directive; directives for other crates are ignored, and so are
+/// the modules within mdfmt.
+fn log_level(verbose: u8, quiet: bool) -> LevelFilter {
+    let from_env = std::env::var("RUST_LOG").ok().and_then(|value| {
+        value
+            // The last directive wins
+            .rsplit(',')
+            .find_map(|directive| match directive.split_once('=') {
+                None => directive.trim().parse().ok(),
+                Some((target, level)) if target.trim().split("::").next() == Some("mdfmt") => {
+                    level.trim().parse().ok()
                 }
-                if status.contains("deleted") {
-                    deleted_count += 1;
-                } else if status.contains("modified") {
-                    modified_count += 1;
+                Some(_) => None,
+            })
+    });
+    from_env.unwrap_or(match (quiet, verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    })
+}
+
+/// Print the paths of --list-files, one per line or as a JSON array of strings.
+fn print_file_list(paths: &[PathBuf], output_format: OutputFormat) {
+    if output_format == OutputFormat::Json {
+        println!("{}", json!(paths));
+    } else {
+        for path in paths {
+            println!("{}", path.display());
+        }
+    }
+}
+
+/// Render one file's status line (and dry run hunks) for stdout, or its error for
+/// stderr.
+///
+/// Errors and warnings are always rendered. Status lines only appear with
+/// `show_status`, and for unchanged or skipped files only with `verbose`.
+fn render_file_report(
+    report: &FileReport,
+    show_status: bool,
+    verbose: bool,
+    preview: bool,
+    colors: Colors,
+) -> ReportOutput {
+    let mut output = ReportOutput::default();
+    let (stdout, stderr) = (&mut output.stdout, &mut output.stderr);
+    match &report.result {
+        Ok((status, hunks)) => {
+            if show_status && (verbose || status.is_change()) {
+                // Verbose output says exactly what changed, where that is known
+                let line = if verbose && *status == FileStatus::Modified && !report.edits.is_empty()
+                {
+                    let verb = if preview {
+                        "would be modified"
+                    } else {
+                        "modified"
+                    };
+                    format!(
+                        "{}: {} ({})",
+                        report.path.display(),
+                        verb,
+                        describe_edits(&report.edits)
+                    )
+                } else {
+                    format!("{}: {}", report.path.display(), status.message(preview))
+                };
+                match status.style() {
+                    Some(style) => writeln!(stdout, "{}", colors.stdout.paint(style, &line)),
+                    None => writeln!(stdout, "{}", line),
                 }
+                .ok();
             }
-            Err(error) => {
-                eprintln!("{}: {}", path.display(), error);
-                error_count += 1;
+            if let Some(hunks) = hunks {
+                stdout.push_str(&colors.stdout.diff(hunks));
+            }
+            for warning in &report.warnings {
+                writeln!(
+                    stderr,
+                    "{}:{}: warning: {}",
+                    report.path.display(),
+                    warning.line,
+                    warning.message
+                )
+                .ok();
+            }
+            if report.warn_empty {
+                writeln!(
+                    stderr,
+                    "{}: warning: file contains only frontmatter",
+                    report.path.display()
+                )
+                .ok();
             }
         }
+        Err(error) => {
+            let message = format!("{}: {}", report.path.display(), error);
+            writeln!(stderr, "{}", colors.stderr.paint(Style::Error, &message)).ok();
+        }
     }
+    output
+}
+
+/// Describe the edits of each rule, e.g. "3 blank runs collapsed, 2 heading separators added".
+fn describe_edits(edits: &EditCounts) -> String {
+    let counted = [
+        (edits.blank_runs_collapsed, "blank run", "collapsed"),
+        (edits.heading_separators, "heading separator", "added"),
+        (edits.fence_separators, "fence separator", "added"),
+        (edits.list_separators, "list separator", "added"),
+        (
+            edits.frontmatter_separators,
+            "frontmatter separator",
+            "added",
+        ),
+        (edits.quote_separators, "quote separator", "added"),
+        (edits.table_separators, "table separator", "added"),
+        (edits.html_separators, "HTML separator", "added"),
+        (edits.div_separators, "div separator", "added"),
+        (
+            edits.heading_blank_lines_removed,
+            "heading blank line",
+            "removed",
+        ),
+        (
+            edits.quote_blank_runs_collapsed,
+            "blank quote run",
+            "collapsed",
+        ),
+        (
+            edits.fence_blank_lines_removed,
+            "blank line",
+            "removed inside fences",
+        ),
+        (
+            edits.trailing_blank_lines_removed,
+            "trailing blank line",
+            "removed",
+        ),
+        (edits.lines_rewritten, "line", "rewritten"),
+    ];
+    let mut parts: Vec<String> = counted
+        .iter()
+        .filter(|(count, _, _)| *count > 0)
+        .map(|(count, noun, verb)| {
+            let plural = if *count == 1 { "" } else { "s" };
+            format!("{} {}{} {}", count, noun, plural, verb)
+        })
+        .collect();
+    if edits.line_endings_normalized {
+        parts.push("line endings normalized".to_string());
+    }
+    if edits.final_newline_fixed {
+        parts.push("final newline fixed".to_string());
+    }
+    parts.join(", ")
+}
+
+/// Print the ten slowest files by total processing time.
+fn print_slowest_files(mut timed: Vec<(&PathBuf, FileTimings)>) {
+    timed.sort_by_key(|(_, timings)| std::cmp::Reverse(timings.total()));
 
-    // Print summary
     println!();
-    println!("Summary:");
-    println!("  Files processed: {}", md_files.len());
-    println!("  Files modified: {}", modified_count);
-    println!("  Files deleted: {}", deleted_count);
-    println!("  Errors: {}", error_count);
-
-    if error_count > 0 {
-        process::exit(1);
+    println!("Slowest files:");
+    for (path, timings) in timed.iter().take(10) {
+        println!(
+            "  {:>10.2?}  {} (read {:.2?}, format {:.2?}, write {:.2?})",
+            timings.total(),
+            path.display(),
+            timings.read,
+            timings.format,
+            timings.write
+        );
+    }
+}
+
+/// Milliseconds as a float, for JSON output.
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// The optional parts of the JSON report, which are only included when the options
+/// that produce them are in use.
+#[derive(Debug, Clone, Copy)]
+struct JsonSections {
+    /// Per-file timings and the totals in the summary
+    timings: bool,
+    /// The number of files skipped by the cache
+    cache: bool,
+    /// The number of files skipped for their size
+    too_large: bool,
+    /// The number of files normalized to empty
+    normalized: bool,
+    /// The number of files left out of --out-dir
+    omitted: bool,
+}
+
+/// Print the whole run as a single JSON document on stdout.
+///
+/// `unchanged` lists the files that are already formatted, for --report-unchanged.
+fn print_json_report(
+    results: &[FileReport],
+    deleted: &[&PathBuf],
+    unchanged: Option<&[&PathBuf]>,
+    summary: &Summary,
+    sections: JsonSections,
+) {
+    let files: Vec<_> = results
+        .iter()
+        .map(|report| {
+            let mut entry = match &report.result {
+                Ok((FileStatus::Modified, _)) if !report.edits.is_empty() => json!({
+                    "path": report.path,
+                    "status": FileStatus::Modified.as_str(),
+                    "edits": report.edits,
+                }),
+                Ok((status, _)) => json!({
+                    "path": report.path,
+                    "status": status.as_str(),
+                }),
+                Err(error) => json!({
+                    "path": report.path,
+                    "status": "error",
+                    "error": error,
+                }),
+            };
+            if let (true, Some(timings)) = (sections.timings, report.timings) {
+                entry["timings"] = json!({
+                    "read_ms": millis(timings.read),
+                    "format_ms": millis(timings.format),
+                    "write_ms": millis(timings.write),
+                    "total_ms": millis(timings.total()),
+                    "bytes": timings.bytes,
+                });
+            }
+            entry
+        })
+        .collect();
+
+    let mut summary_json = json!({
+        "processed": summary.processed,
+        "modified": summary.modified,
+        "deleted": summary.deleted,
+        "errors": summary.errors,
+        "panicked": summary.panicked,
+    });
+    if sections.cache {
+        summary_json["cached"] = json!(summary.cached);
+    }
+    if sections.normalized {
+        summary_json["normalized"] = json!(summary.normalized);
+    }
+    if sections.omitted {
+        summary_json["omitted"] = json!(summary.omitted);
+    }
+    if sections.too_large {
+        summary_json["too_large"] = json!(summary.too_large);
+    }
+    if let Some(unchanged) = unchanged {
+        summary_json["unchanged"] = json!(unchanged.len());
+    }
+    if sections.timings {
+        summary_json["elapsed_ms"] = json!(millis(summary.elapsed));
+        summary_json["bytes"] = json!(summary.bytes);
+    }
+
+    let mut report = json!({
+        "files": files,
+        "deleted": deleted,
+        "summary": summary_json,
+    });
+    if let Some(unchanged) = unchanged {
+        report["unchanged"] = json!(unchanged);
+    }
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).expect("JSON report is serializable")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catch_panic() {
+        install_panic_hook();
+        assert_eq!(catch_panic(|| 1), Ok(1));
+        let content = "é";
+        let error = catch_panic(|| content[..1].len()).unwrap_err();
+        assert!(
+            error.starts_with("panicked at src/main.rs:")
+                && error.contains("byte index 1 is not a char boundary"),
+            "{}",
+            error
+        );
+        let error = catch_panic(|| panic!("{} files", 2)).unwrap_err();
+        assert!(error.ends_with(": 2 files"), "{}", error);
+        // Each location is reported with its own panic only
+        assert!(PANIC_LOCATION.take().is_none());
     }
 }
@@ -0,0 +1,117 @@
+//! The outline of a document: its headings with their levels and anchors.
+//!
+//! [`extract_headings`] lists the ATX headings of a document the way they read when
+//! rendered, with the anchors GitHub generates for them, for navigation menus,
+//! checking links to headings, or sitemaps. Tables of contents (see
+//! [`crate::toc`]) use the same anchors.
+
+use crate::process_md::{atx_heading_text, atx_headings};
+use crate::toc::{plain_text, slugify};
+use std::collections::HashMap;
+
+/// A heading of a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    /// The level of the heading, from 1 for `#` to 6 for `######`.
+    pub level: u8,
+    /// The text of the heading without inline formatting, e.g. `Install mdfmt` for
+    /// ``## Install `mdfmt` ``.
+    pub text: String,
+    /// The 1-based line number of the heading.
+    pub line: usize,
+    /// The anchor GitHub generates for the heading, without the `#`.
+    pub slug: String,
+}
+
+/// List the ATX headings of `content` in document order.
+///
+/// Headings in frontmatter, code fences, and math blocks are skipped. Link, image,
+/// emphasis, and code span markup is removed from the text. Slugs follow GitHub:
+/// the text is lowercased, its punctuation removed, and spaces turned into hyphens,
+/// and the second heading with the same slug gets `-1` appended, the third `-2`,
+/// and so on.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to outline
+///
+/// # Returns
+///
+/// The headings of the document, or an empty list if it has none.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::outline::extract_headings;
+///
+/// let input = "---\ntitle: Guide\n---\n\n# Guide\n\n## Install `mdfmt` ##\n\n```sh\n# Not a heading\n```\n\n## *Usage*\n\n## Usage\n";
+/// let headings = extract_headings(input);
+/// let outline: Vec<_> = headings
+///     .iter()
+///     .map(|heading| (heading.level, heading.text.as_str(), heading.line, heading.slug.as_str()))
+///     .collect();
+/// assert_eq!(
+///     outline,
+///     [
+///         (1, "Guide", 5, "guide"),
+///         (2, "Install mdfmt", 7, "install-mdfmt"),
+///         (2, "Usage", 13, "usage"),
+///         (2, "Usage", 15, "usage-1"),
+///     ]
+/// );
+/// ```
+pub fn extract_headings(content: &str) -> Vec<Heading> {
+    let lines: Vec<&str> = content
+        .split_inclusive('\n')
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let mut slugs: HashMap<String, usize> = HashMap::new();
+    atx_headings(&lines)
+        .map(|(i, level)| {
+            let text = plain_text(&lines[i][atx_heading_text(lines[i], level)]);
+            let slug = slugify(&text);
+            let count = slugs.entry(slug.clone()).or_insert(0);
+            let slug = match *count {
+                0 => slug,
+                n => format!("{}-{}", slug, n),
+            };
+            *count += 1;
+            Heading {
+                level: level as u8,
+                text,
+                line: i + 1,
+                slug,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_headings() {
+        let headings = extract_headings(
+            "#tag\n# [Docs](index.md) for _mdfmt_\r\n###### Deep #\n$$\n# x\n$$\n",
+        );
+        assert_eq!(
+            headings,
+            [
+                Heading {
+                    level: 1,
+                    text: "Docs for mdfmt".to_string(),
+                    line: 2,
+                    slug: "docs-for-mdfmt".to_string(),
+                },
+                Heading {
+                    level: 6,
+                    text: "Deep".to_string(),
+                    line: 3,
+                    slug: "deep".to_string(),
+                },
+            ]
+        );
+        assert!(extract_headings("Text\n").is_empty());
+    }
+}
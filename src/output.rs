@@ -0,0 +1,225 @@
+//! Terminal colors for the human readable report.
+//!
+//! Colors are decided once per output stream: `--color always` and `--color never`
+//! force them on or off, and `--color auto` only uses them when the stream is a
+//! terminal and the `NO_COLOR` environment variable is unset or empty. A disabled
+//! [`Painter`] hands text back unchanged, and the JSON report never goes through one,
+//! so machine readable output never contains ANSI escape sequences.
+
+use std::borrow::Cow;
+use std::env;
+use std::io::{self, IsTerminal};
+
+/// When to use colors, as given by `--color`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Use colors when writing to a terminal, unless `NO_COLOR` is set
+    #[default]
+    Auto,
+    /// Always use colors, even when piped or with `NO_COLOR` set
+    Always,
+    /// Never use colors
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether a stream should be colored.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_terminal` - Whether the stream is a terminal
+    /// * `no_color` - Whether the `NO_COLOR` environment variable is set to a
+    ///   non-empty value
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::output::ColorChoice;
+    ///
+    /// assert!(ColorChoice::Auto.enabled(true, false));
+    /// assert!(!ColorChoice::Auto.enabled(false, false));
+    /// assert!(ColorChoice::Always.enabled(false, true));
+    /// ```
+    pub fn enabled(self, is_terminal: bool, no_color: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal && !no_color,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// What a piece of output is, which decides its color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// Files that were (or would be) modified or normalized, in yellow
+    Modified,
+    /// Files that were (or would be) deleted or trashed, in red
+    Deleted,
+    /// Error messages, in bright red
+    Error,
+    /// Numbers in the summary, in bold
+    Count,
+    /// Lines added by a diff hunk, in green
+    Added,
+    /// Lines removed by a diff hunk, in red
+    Removed,
+    /// Diff hunk headers, in cyan
+    HunkHeader,
+}
+
+impl Style {
+    /// The ANSI SGR parameters of the style.
+    fn code(self) -> &'static str {
+        match self {
+            Style::Modified => "33",
+            Style::Deleted | Style::Removed => "31",
+            Style::Error => "91",
+            Style::Count => "1",
+            Style::Added => "32",
+            Style::HunkHeader => "36",
+        }
+    }
+}
+
+/// Applies [`Style`]s to text written to one output stream, if colors are enabled
+/// for it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Painter {
+    enabled: bool,
+}
+
+impl Painter {
+    /// A painter that colors text if `enabled`.
+    pub fn new(enabled: bool) -> Self {
+        Painter { enabled }
+    }
+
+    /// Whether this painter adds colors.
+    pub fn is_enabled(self) -> bool {
+        self.enabled
+    }
+
+    /// Wrap `text` in the escape sequences of `style`.
+    ///
+    /// # Returns
+    ///
+    /// The colored text, or `text` itself when colors are disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::output::{Painter, Style};
+    ///
+    /// assert_eq!(Painter::new(true).paint(Style::Error, "oops"), "\x1b[91moops\x1b[0m");
+    /// assert_eq!(Painter::new(false).paint(Style::Error, "oops"), "oops");
+    /// ```
+    pub fn paint<'a>(self, style: Style, text: &'a str) -> Cow<'a, str> {
+        if self.enabled && !text.is_empty() {
+            Cow::Owned(format!("\x1b[{}m{}\x1b[0m", style.code(), text))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    /// Color the lines of unified diff hunks: added lines green, removed lines red,
+    /// and hunk headers cyan. Context lines are left as they are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::output::Painter;
+    ///
+    /// let hunks = "@@ -1,2 +1,1 @@\n Text\n-\n";
+    /// assert_eq!(
+    ///     Painter::new(true).diff(hunks),
+    ///     "\x1b[36m@@ -1,2 +1,1 @@\x1b[0m\n Text\n\x1b[31m-\x1b[0m\n"
+    /// );
+    /// ```
+    pub fn diff(self, hunks: &str) -> Cow<'_, str> {
+        if !self.enabled {
+            return Cow::Borrowed(hunks);
+        }
+        let mut output = String::with_capacity(hunks.len());
+        for line in hunks.split_inclusive('\n') {
+            let text = line.trim_end_matches(['\n', '\r']);
+            let style = if text.starts_with("@@") {
+                Some(Style::HunkHeader)
+            } else if text.starts_with('+') {
+                Some(Style::Added)
+            } else if text.starts_with('-') {
+                Some(Style::Removed)
+            } else {
+                None
+            };
+            match style {
+                Some(style) => {
+                    output.push_str(&self.paint(style, text));
+                    output.push_str(&line[text.len()..]);
+                }
+                None => output.push_str(line),
+            }
+        }
+        Cow::Owned(output)
+    }
+}
+
+/// The painters for stdout and stderr, which are colored independently, so piping
+/// one of them keeps the other colored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Colors {
+    /// For the report on stdout
+    pub stdout: Painter,
+    /// For errors and warnings on stderr
+    pub stderr: Painter,
+}
+
+impl Colors {
+    /// Decide colors for both streams of this process, checking whether each is a
+    /// terminal and reading `NO_COLOR` (see [`ColorChoice::enabled`]).
+    pub fn detect(choice: ColorChoice) -> Self {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        Colors {
+            stdout: Painter::new(choice.enabled(io::stdout().is_terminal(), no_color)),
+            stderr: Painter::new(choice.enabled(io::stderr().is_terminal(), no_color)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_choice() {
+        // (terminal, NO_COLOR) for auto, always, and never
+        let cases = [
+            ((true, false), [true, true, false]),
+            ((true, true), [false, true, false]),
+            ((false, false), [false, true, false]),
+            ((false, true), [false, true, false]),
+        ];
+        for ((is_terminal, no_color), expected) in cases {
+            let enabled = [ColorChoice::Auto, ColorChoice::Always, ColorChoice::Never]
+                .map(|choice| choice.enabled(is_terminal, no_color));
+            assert_eq!(enabled, expected, "{:?}", (is_terminal, no_color));
+        }
+    }
+
+    #[test]
+    fn test_painter() {
+        let painter = Painter::new(true);
+        assert_eq!(painter.paint(Style::Count, "3"), "\x1b[1m3\x1b[0m");
+        assert_eq!(painter.paint(Style::Modified, ""), "");
+        let hunks = "@@ -1,3 +1,2 @@\n # Title\n-\n+\r\nText";
+        assert_eq!(
+            painter.diff(hunks),
+            "\x1b[36m@@ -1,3 +1,2 @@\x1b[0m\n # Title\n\x1b[31m-\x1b[0m\n\x1b[32m+\x1b[0m\r\nText"
+        );
+
+        let plain = Painter::default();
+        assert!(!plain.is_enabled());
+        assert!(matches!(plain.diff(hunks), Cow::Borrowed(_)));
+        assert!(matches!(plain.paint(Style::Error, "x"), Cow::Borrowed(_)));
+    }
+}
@@ -3,9 +3,33 @@
 //! This module provides functions to process markdown files by removing excessive blank lines
 //! while preserving important formatting like frontmatter and code blocks.
 
-use std::fs;
-use std::io;
-use std::path::Path;
+use crate::ast::{self, is_attribute_list};
+use crate::frontmatter::sort_frontmatter_keys;
+use crate::lint::{
+    LintWarning, code_span_ranges, fence_info, fix_bare_urls, fix_heading_punctuation,
+    fix_missing_alt_text, is_zero_width, lint_missing_fence_language, lint_zero_width_characters,
+    remove_empty_headings, strips_zero_width,
+};
+use crate::lists::{
+    indentation, indented_code_lines, marker_len, normalize_list_markers, reindent_lists,
+};
+use crate::toc::{TocLevels, update_toc};
+use crate::wrap::wrap_blockquote_lines;
+use encoding_rs::{EncoderResult, Encoding, UTF_8};
+use log::{Level, debug, log_enabled, trace};
+use rayon::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::LazyLock;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 /// Process a markdown file to remove multiple consecutive blank lines and handle empty files.
 ///
@@ -13,22 +37,22 @@ use std::path::Path;
 /// and optionally deletes empty files. It preserves frontmatter content and code fence blocks.
 ///
 /// Returns a tuple of (deleted, modified) where:
-/// - `deleted` indicates if the file was deleted (empty body with only frontmatter)
+/// - `deleted` indicates if the file was deleted or moved to the trash (empty body with only frontmatter)
 /// - `modified` indicates if the file was modified (removed multiple blank lines)
 ///
 /// # Arguments
 ///
 /// * `path` - Path to the markdown file to process
-/// * `allow_delete` - Whether to allow deletion of empty files
+/// * `delete` - Whether and how to delete empty files
 ///
 /// # Examples
 ///
 /// ```rust,no_run
-/// use mdfmt::process_md::process_md_file;
+/// use mdfmt::process_md::{DeleteStrategy, process_md_file};
 /// use std::path::Path;
 ///
 /// // Process a file without allowing deletion
-/// let (deleted, modified) = process_md_file(Path::new("example.md"), false)?;
+/// let (deleted, modified) = process_md_file(Path::new("example.md"), DeleteStrategy::Keep)?;
 /// if modified {
 ///     println!("File was modified");
 /// }
@@ -37,376 +61,5402 @@ use std::path::Path;
 ///
 /// # Errors
 ///
+/// Returns an `io::Error` if the file cannot be read or written, or if it keeps
+/// changing on disk while being formatted (it is never overwritten in that case).
+pub fn process_md_file<P: AsRef<Path>>(
+    path: P,
+    delete: DeleteStrategy,
+) -> io::Result<(bool, bool)> {
+    run_md_file(path.as_ref(), &FormatOptions::deleting(delete), true, None)
+        .map(|changes| changes.outcome.flags())
+}
+
+/// Check whether a markdown file would be changed by [`process_md_file`], without
+/// writing or deleting anything.
+///
+/// Returns a tuple of (would_delete, would_modify) with the same meaning as the
+/// result of [`process_md_file`].
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::process_md::{DeleteStrategy, check_md_file};
+/// use std::path::Path;
+///
+/// let (would_delete, would_modify) = check_md_file(Path::new("example.md"), DeleteStrategy::Keep)?;
+/// if would_modify {
+///     println!("File is not formatted");
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file cannot be read.
+pub fn check_md_file<P: AsRef<Path>>(path: P, delete: DeleteStrategy) -> io::Result<(bool, bool)> {
+    run_md_file(path.as_ref(), &FormatOptions::deleting(delete), false, None)
+        .map(|changes| changes.outcome.flags())
+}
+
+/// Wall-clock time spent in each stage of processing a file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileTimings {
+    /// Time spent reading the file.
+    pub read: Duration,
+    /// Time spent formatting the content.
+    pub format: Duration,
+    /// Time spent writing or deleting the file (zero when nothing was written).
+    pub write: Duration,
+    /// Size of the file content in bytes.
+    pub bytes: u64,
+}
+
+impl FileTimings {
+    /// Total time spent across all stages.
+    pub fn total(&self) -> Duration {
+        self.read + self.format + self.write
+    }
+}
+
+/// Like [`process_md_file`], but also records how long each stage took.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::process_md::{DeleteStrategy, FileTimings, process_md_file_with_timings};
+/// use std::path::Path;
+///
+/// let mut timings = FileTimings::default();
+/// process_md_file_with_timings(Path::new("example.md"), DeleteStrategy::Keep, &mut timings)?;
+/// println!("formatting took {:?}", timings.format);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
 /// Returns an `io::Error` if the file cannot be read or written.
-pub fn process_md_file<P: AsRef<Path>>(path: P, allow_delete: bool) -> io::Result<(bool, bool)> {
-    let path = path.as_ref();
-    let original_content = fs::read_to_string(path)?;
-
-    if original_content.trim().is_empty() {
-        // Delete completely empty files only if deletion is allowed
-        if allow_delete {
-            fs::remove_file(path)?;
-            return Ok((true, false));
-        } else {
-            // Skip processing but don't delete
-            return Ok((false, false));
+pub fn process_md_file_with_timings<P: AsRef<Path>>(
+    path: P,
+    delete: DeleteStrategy,
+    timings: &mut FileTimings,
+) -> io::Result<(bool, bool)> {
+    run_md_file(
+        path.as_ref(),
+        &FormatOptions::deleting(delete),
+        true,
+        Some(timings),
+    )
+    .map(|changes| changes.outcome.flags())
+}
+
+/// Like [`check_md_file`], but also records how long each stage took.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file cannot be read.
+pub fn check_md_file_with_timings<P: AsRef<Path>>(
+    path: P,
+    delete: DeleteStrategy,
+    timings: &mut FileTimings,
+) -> io::Result<(bool, bool)> {
+    run_md_file(
+        path.as_ref(),
+        &FormatOptions::deleting(delete),
+        false,
+        Some(timings),
+    )
+    .map(|changes| changes.outcome.flags())
+}
+
+/// The outcome of processing a single markdown file with [`FormatOptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileOutcome {
+    /// The file was already formatted.
+    Unchanged,
+    /// The file was (or would be) rewritten.
+    Modified,
+    /// The file was (or would be) deleted for being empty.
+    Deleted,
+    /// The file was (or would be) moved to the trash for being empty.
+    Trashed,
+    /// The file contained only whitespace and was (or would be) truncated to zero
+    /// bytes, see [`FormatOptions::normalize_empty`].
+    Normalized,
+    /// The file was skipped without being read because it exceeds
+    /// [`FormatOptions::max_size`].
+    TooLarge,
+}
+
+impl FileOutcome {
+    /// The (deleted, modified) tuple returned by [`process_md_file`].
+    fn flags(self) -> (bool, bool) {
+        let deleted = matches!(self, FileOutcome::Deleted | FileOutcome::Trashed);
+        let modified = matches!(self, FileOutcome::Modified | FileOutcome::Normalized);
+        (deleted, modified)
+    }
+}
+
+/// Why the formatter inserted or removed a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EditRule {
+    /// An extra blank line in a run of blank lines was removed.
+    BlankRun,
+    /// A blank line was inserted before or after a heading.
+    HeadingSeparator,
+    /// A blank line before or after a heading was removed to match the heading
+    /// spacing options.
+    HeadingSpacing,
+    /// A blank line was inserted before or after a code fence or math block.
+    FenceSeparator,
+    /// A blank line was inserted before or after a list group.
+    ListSeparator,
+    /// A blank line was inserted after the frontmatter.
+    FrontmatterSeparator,
+    /// A blank line was inserted before or after a block quote.
+    QuoteSeparator,
+    /// A blank line was inserted before or after a table.
+    TableSeparator,
+    /// A blank line was inserted before or after an HTML block.
+    HtmlSeparator,
+    /// A blank line was inserted before or after a fenced div.
+    DivSeparator,
+    /// An extra blank `>` line in a run inside a block quote was removed.
+    QuoteBlankRun,
+    /// A blank line directly inside a code fence was removed.
+    FenceBlankLine,
+    /// An empty line at the end of the document was removed.
+    TrailingBlankLine,
+}
+
+/// How many edits each formatting rule made to a document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct EditCounts {
+    /// Runs of blank lines collapsed to a single blank line.
+    pub blank_runs_collapsed: usize,
+    /// Blank lines inserted before or after headings.
+    pub heading_separators: usize,
+    /// Blank lines removed before or after headings to match
+    /// [`FormatterConfig::blank_lines_before_heading`] and
+    /// [`FormatterConfig::blank_lines_after_heading`].
+    pub heading_blank_lines_removed: usize,
+    /// Blank lines inserted before or after code fences and math blocks.
+    pub fence_separators: usize,
+    /// Blank lines inserted before or after list groups.
+    pub list_separators: usize,
+    /// Blank lines inserted after the frontmatter.
+    pub frontmatter_separators: usize,
+    /// Blank lines inserted before or after block quotes.
+    pub quote_separators: usize,
+    /// Blank lines inserted before or after tables.
+    pub table_separators: usize,
+    /// Blank lines inserted before or after HTML blocks.
+    pub html_separators: usize,
+    /// Blank lines inserted before or after fenced divs.
+    pub div_separators: usize,
+    /// Runs of blank `>` lines inside block quotes collapsed to a single line.
+    pub quote_blank_runs_collapsed: usize,
+    /// Blank lines removed directly after the opening or before the closing fence.
+    pub fence_blank_lines_removed: usize,
+    /// Empty lines removed from the end of the document.
+    pub trailing_blank_lines_removed: usize,
+    /// Lines changed by the content rules of [`FormatterConfig`].
+    pub lines_rewritten: usize,
+    /// Whether line endings were converted to [`FormatterConfig::line_ending`].
+    pub line_endings_normalized: bool,
+    /// Whether a final newline was added or removed for
+    /// [`FormatterConfig::insert_final_newline`].
+    pub final_newline_fixed: bool,
+}
+
+impl EditCounts {
+    /// Whether no rule made any edit.
+    pub fn is_empty(&self) -> bool {
+        *self == EditCounts::default()
+    }
+}
+
+/// Counts edits by rule, as a [`LineSink`], and logs each edit at the trace level.
+#[derive(Debug, Default)]
+struct EditCounter {
+    counts: EditCounts,
+    /// The last line removed from a blank run, to tell the runs apart
+    last_blank_run_removal: Option<usize>,
+    /// The last line removed from a run of blank quote lines
+    last_quote_blank_run_removal: Option<usize>,
+}
+
+/// Count `index` as the start of a new run unless it directly follows `last`.
+fn count_run(count: &mut usize, last: &mut Option<usize>, index: usize) {
+    // The removed lines of one run are consecutive
+    if index == 0 || *last != Some(index - 1) {
+        *count += 1;
+    }
+    *last = Some(index);
+}
+
+impl LineSink for EditCounter {
+    fn keep(&mut self, _index: usize, _line: &str) {}
+
+    fn insert_blank(&mut self, before: usize, rule: EditRule) {
+        trace!(
+            "inserted a blank line before line {} ({:?})",
+            before + 1,
+            rule
+        );
+        let count = match rule {
+            EditRule::HeadingSeparator => &mut self.counts.heading_separators,
+            EditRule::FenceSeparator => &mut self.counts.fence_separators,
+            EditRule::ListSeparator => &mut self.counts.list_separators,
+            EditRule::FrontmatterSeparator => &mut self.counts.frontmatter_separators,
+            EditRule::QuoteSeparator => &mut self.counts.quote_separators,
+            EditRule::TableSeparator => &mut self.counts.table_separators,
+            EditRule::HtmlSeparator => &mut self.counts.html_separators,
+            EditRule::DivSeparator => &mut self.counts.div_separators,
+            EditRule::BlankRun
+            | EditRule::HeadingSpacing
+            | EditRule::QuoteBlankRun
+            | EditRule::FenceBlankLine
+            | EditRule::TrailingBlankLine => {
+                return;
+            }
+        };
+        *count += 1;
+    }
+
+    fn remove(&mut self, index: usize, rule: EditRule) {
+        trace!("removed line {} ({:?})", index + 1, rule);
+        match rule {
+            EditRule::BlankRun => count_run(
+                &mut self.counts.blank_runs_collapsed,
+                &mut self.last_blank_run_removal,
+                index,
+            ),
+            EditRule::QuoteBlankRun => count_run(
+                &mut self.counts.quote_blank_runs_collapsed,
+                &mut self.last_quote_blank_run_removal,
+                index,
+            ),
+            EditRule::HeadingSpacing => self.counts.heading_blank_lines_removed += 1,
+            EditRule::FenceBlankLine => self.counts.fence_blank_lines_removed += 1,
+            EditRule::TrailingBlankLine => self.counts.trailing_blank_lines_removed += 1,
+            EditRule::HeadingSeparator
+            | EditRule::FenceSeparator
+            | EditRule::ListSeparator
+            | EditRule::FrontmatterSeparator
+            | EditRule::QuoteSeparator
+            | EditRule::TableSeparator
+            | EditRule::HtmlSeparator
+            | EditRule::DivSeparator => {}
+        }
+    }
+}
+
+/// The result of processing a single markdown file with [`FormatOptions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChanges {
+    /// What happened to the file.
+    pub outcome: FileOutcome,
+    /// The edits made to the content, if it was (or would be) rewritten.
+    pub edits: EditCounts,
+    /// What the file consisted of, or `None` if it was not classified, e.g. because
+    /// it was skipped without being read.
+    pub kind: Option<ContentKind>,
+    /// Warnings from the enabled checks, see [`FormatResult::warnings`].
+    pub warnings: Vec<LintWarning>,
+}
+
+impl From<FileOutcome> for FileChanges {
+    fn from(outcome: FileOutcome) -> Self {
+        FileChanges {
+            outcome,
+            edits: EditCounts::default(),
+            kind: None,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// Process a markdown file with the given options.
+///
+/// Unlike [`process_md_file`], this can skip files over a size limit and apply the
+/// optional content rules of [`FormatOptions::formatter`]. Stage timings are recorded
+/// when `timings` is provided.
+///
+/// # Arguments
+///
+/// * `path` - Path to the markdown file to process
+/// * `options` - File-level and formatting options
+/// * `timings` - Where to record stage timings, if wanted
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::process_md::{FileOutcome, FormatOptions, process_md_file_with_options};
+/// use std::path::Path;
+///
+/// let options = FormatOptions {
+///     max_size: Some(5 * 1024 * 1024),
+///     ..FormatOptions::default()
+/// };
+/// let changes = process_md_file_with_options(Path::new("example.md"), &options, None)?;
+/// if changes.outcome == FileOutcome::TooLarge {
+///     println!("Skipped large file");
+/// } else if changes.outcome == FileOutcome::Modified {
+///     println!("Collapsed {} blank runs", changes.edits.blank_runs_collapsed);
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file metadata cannot be read, the file cannot be
+/// read or written, it keeps changing on disk while being formatted, another mdfmt
+/// run holds it for longer than [`FormatOptions::lock_timeout_ms`], or its headings
+/// cannot be promoted without [`FormatterConfig::clamp_headings`].
+pub fn process_md_file_with_options<P: AsRef<Path>>(
+    path: P,
+    options: &FormatOptions,
+    timings: Option<&mut FileTimings>,
+) -> io::Result<FileChanges> {
+    run_md_file(path.as_ref(), options, true, timings)
+}
+
+/// Like [`process_md_file_with_options`], for a file in another encoding than
+/// UTF-8, such as ISO-8859-1 (Latin-1) in legacy documentation.
+///
+/// The file is decoded from `encoding`, formatted, and encoded back to it before it
+/// is written. This is the same as setting [`FormatOptions::encoding`].
+///
+/// # Arguments
+///
+/// * `path` - Path to the markdown file to format
+/// * `encoding` - The encoding of the file, e.g. from [`Encoding::for_label`]
+/// * `options` - File-level and formatting options
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use encoding_rs::WINDOWS_1252;
+/// use mdfmt::process_md::{FormatOptions, process_md_file_with_encoding};
+/// use std::path::Path;
+///
+/// let changes = process_md_file_with_encoding(
+///     Path::new("legacy/guide.md"),
+///     WINDOWS_1252,
+///     &FormatOptions::default(),
+/// )?;
+/// println!("{:?}", changes.outcome);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` like [`process_md_file_with_options`], or one of kind
+/// `InvalidData` if the file is not valid in `encoding`, or if the formatted content
+/// has a character that `encoding` cannot represent; the file is left alone then.
+pub fn process_md_file_with_encoding<P: AsRef<Path>>(
+    path: P,
+    encoding: &'static Encoding,
+    options: &FormatOptions,
+) -> io::Result<FileChanges> {
+    let options = FormatOptions {
+        encoding,
+        ..options.clone()
+    };
+    run_md_file(path.as_ref(), &options, true, None)
+}
+
+/// Like [`process_md_file_with_options`], but writing the formatted content to
+/// `out_path` instead of back to the file, which is never modified.
+///
+/// The content is written even when it is unchanged, so formatting every file of a
+/// tree this way gives a complete copy of it. Missing parent directories of
+/// `out_path` are created. A file that [`process_md_file_with_options`] would
+/// delete for being empty is not written, and its outcome is still
+/// [`FileOutcome::Deleted`] or [`FileOutcome::Trashed`]; a file over the size
+/// limit is copied as it is.
+///
+/// # Arguments
+///
+/// * `path` - Path to the markdown file to format
+/// * `out_path` - Where the formatted content is written
+/// * `options` - File-level and formatting options
+/// * `timings` - Where to record stage timings, if wanted
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::process_md::{FormatOptions, process_md_file_to};
+/// use std::path::Path;
+///
+/// let changes = process_md_file_to(
+///     Path::new("docs/guide.md"),
+///     Path::new("build/docs/guide.md"),
+///     &FormatOptions::default(),
+///     None,
+/// )?;
+/// println!("{:?}", changes.outcome);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file metadata cannot be read, the file cannot be
+/// read, `out_path` cannot be written, or the headings of the file cannot be
+/// promoted without [`FormatterConfig::clamp_headings`].
+pub fn process_md_file_to<P: AsRef<Path>, Q: AsRef<Path>>(
+    path: P,
+    out_path: Q,
+    options: &FormatOptions,
+    timings: Option<&mut FileTimings>,
+) -> io::Result<FileChanges> {
+    let destination = Destination::Copy(out_path.as_ref());
+    run_md_file_with_hook(path.as_ref(), options, destination, timings, &mut |_| {})
+}
+
+/// Like [`process_md_file_with_options`], but without writing or deleting anything.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file metadata cannot be read, the file cannot be
+/// read, or its headings cannot be promoted without
+/// [`FormatterConfig::clamp_headings`].
+pub fn check_md_file_with_options<P: AsRef<Path>>(
+    path: P,
+    options: &FormatOptions,
+    timings: Option<&mut FileTimings>,
+) -> io::Result<FileChanges> {
+    run_md_file(path.as_ref(), options, false, timings)
+}
+
+/// Process several markdown files in parallel with
+/// [`process_md_file_with_options`].
+///
+/// The files are processed on the current rayon thread pool, so the number of
+/// threads can be limited by calling this inside [`rayon::ThreadPool::install`].
+/// Each file gets its own result, so one file failing does not stop the others.
+///
+/// # Arguments
+///
+/// * `paths` - Paths to the markdown files to process
+/// * `options` - File-level and formatting options used for every file, e.g. from
+///   [`Config::format_options`](crate::config::Config::format_options)
+///
+/// # Returns
+///
+/// Every path with the result of processing it, in the order of `paths`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::process_md::{FileOutcome, FormatOptions, process_md_files};
+/// use std::path::PathBuf;
+///
+/// let paths = vec![PathBuf::from("README.md"), PathBuf::from("CHANGELOG.md")];
+/// let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+/// let results = pool.install(|| process_md_files(&paths, &FormatOptions::default()));
+/// for (path, result) in results {
+///     match result {
+///         Ok(changes) if changes.outcome == FileOutcome::Modified => {
+///             println!("{}: modified", path.display());
+///         }
+///         Ok(_) => {}
+///         Err(e) => eprintln!("{}: error: {}", path.display(), e),
+///     }
+/// }
+/// ```
+pub fn process_md_files(
+    paths: &[PathBuf],
+    options: &FormatOptions,
+) -> Vec<(PathBuf, io::Result<FileChanges>)> {
+    paths
+        .par_iter()
+        .map(|path| (path.clone(), run_md_file(path, options, true, None)))
+        .collect()
+}
+
+/// Format a markdown file and write the result to `writer` instead of back to the file.
+///
+/// The file itself is never modified, and the formatted content is written even when
+/// it is unchanged. Empty files are formatted like any other content rather than
+/// deleted.
+///
+/// Returns `true` if formatting changed the content.
+///
+/// # Arguments
+///
+/// * `path` - Path to the markdown file to format
+/// * `writer` - Where the formatted content is written
+/// * `config` - The content rules used to format the file
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::process_md::{FormatterConfig, format_to_writer};
+/// use std::path::Path;
+///
+/// let mut output = Vec::new();
+/// let changed = format_to_writer(Path::new("example.md"), &mut output, &FormatterConfig::default())?;
+/// if changed {
+///     println!("{}", String::from_utf8_lossy(&output));
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file cannot be read or writing to `writer` fails.
+pub fn format_to_writer<W: Write>(
+    path: &Path,
+    writer: &mut W,
+    config: &FormatterConfig,
+) -> io::Result<bool> {
+    let content = read_file(path)?;
+    let formatted = format_with_config(&content, config);
+    write_content(writer, &formatted)?;
+    Ok(matches!(formatted, Cow::Owned(_)))
+}
+
+/// Read the markdown file at `path`.
+///
+/// Unlike [`fs::read_to_string`], a file that is not valid UTF-8 is reported with
+/// the position of the first invalid byte (see [`decode_utf8`]).
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file cannot be read, or one of kind `InvalidData`
+/// if it is not valid UTF-8.
+pub fn read_file(path: &Path) -> io::Result<String> {
+    decode_utf8(fs::read(path)?)
+}
+
+/// Read the markdown file at `path`, which is in `encoding`.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file cannot be read, or one of kind `InvalidData`
+/// if it is not valid in `encoding`.
+pub fn read_file_with_encoding(path: &Path, encoding: &'static Encoding) -> io::Result<String> {
+    decode(fs::read(path)?, encoding)
+}
+
+/// Turn the bytes of a file in `encoding` into a string, like [`decode_utf8`] for
+/// UTF-8. Bytes that have no character in `encoding` are an error, never replaced.
+fn decode(bytes: Vec<u8>, encoding: &'static Encoding) -> io::Result<String> {
+    if encoding == UTF_8 {
+        return decode_utf8(bytes);
+    }
+    encoding
+        .decode_without_bom_handling_and_without_replacement(&bytes)
+        .map(Cow::into_owned)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("not valid {}", encoding.name()),
+            )
+        })
+}
+
+/// Turn formatted content back into the bytes of a file in `encoding`.
+///
+/// Returns an `io::Error` of kind `InvalidData`, naming the first character and its
+/// line, if `encoding` cannot represent all of `content`.
+fn encode<'a>(content: &'a str, encoding: &'static Encoding) -> io::Result<Cow<'a, [u8]>> {
+    if encoding == UTF_8 {
+        return Ok(Cow::Borrowed(content.as_bytes()));
+    }
+    let mut encoder = encoding.new_encoder();
+    let capacity = encoder
+        .max_buffer_length_from_utf8_without_replacement(content.len())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::OutOfMemory, "file too large"))?;
+    let mut bytes = Vec::with_capacity(capacity);
+    match encoder.encode_from_utf8_to_vec_without_replacement(content, &mut bytes, true) {
+        (EncoderResult::InputEmpty, _) => Ok(Cow::Owned(bytes)),
+        (EncoderResult::Unmappable(c), read) => {
+            let line = content[..read].matches('\n').count() + 1;
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{:?} (U+{:04X}) on line {} cannot be written as {}",
+                    c,
+                    c as u32,
+                    line,
+                    encoding.name()
+                ),
+            ))
+        }
+        (EncoderResult::OutputFull, _) => unreachable!("the buffer fits the longest output"),
+    }
+}
+
+/// How many bytes around an invalid UTF-8 sequence [`decode_utf8`] shows.
+const UTF8_ERROR_CONTEXT: usize = 4;
+
+/// Turn the bytes of a file into a string, without copying them.
+///
+/// # Arguments
+///
+/// * `bytes` - The content of a file
+///
+/// # Returns
+///
+/// The content as a string.
+///
+/// # Errors
+///
+/// Returns an `io::Error` of kind `InvalidData` if `bytes` are not valid UTF-8. Its
+/// message gives the byte offset and line number of the first invalid sequence, and
+/// the bytes around it in hex, with the invalid ones in brackets.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::decode_utf8;
+///
+/// assert_eq!(decode_utf8(b"# Caf\xc3\xa9\n".to_vec()).unwrap(), "# Caf\u{e9}\n");
+/// let err = decode_utf8(b"# Title\nCaf\xe9 au lait\n".to_vec()).unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "invalid UTF-8 at byte 11 (line 2): 0a 43 61 66 [e9] 20 61 75 20"
+/// );
+/// ```
+pub fn decode_utf8(bytes: Vec<u8>) -> io::Result<String> {
+    String::from_utf8(bytes).map_err(|e| {
+        let bytes = e.as_bytes();
+        let start = e.utf8_error().valid_up_to();
+        // A sequence cut off by the end of the file runs up to it
+        let end = start + e.utf8_error().error_len().unwrap_or(bytes.len() - start);
+        let hex = |bytes: &[u8]| -> Vec<String> {
+            bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+        };
+        let mut context = hex(&bytes[start.saturating_sub(UTF8_ERROR_CONTEXT)..start]);
+        context.push(format!("[{}]", hex(&bytes[start..end]).join(" ")));
+        context.extend(hex(&bytes[end..bytes.len().min(end + UTF8_ERROR_CONTEXT)]));
+        let line = bytes[..start].iter().filter(|&&b| b == b'\n').count() + 1;
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "invalid UTF-8 at byte {} (line {}): {}",
+                start,
+                line,
+                context.join(" ")
+            ),
+        )
+    })
+}
+
+/// Write formatted content to `writer` and flush it.
+fn write_content<W: Write>(writer: &mut W, content: &str) -> io::Result<()> {
+    write_bytes(writer, content.as_bytes())
+}
+
+/// Write the bytes of a file to `writer` and flush it.
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(bytes)?;
+    writer.flush()
+}
+
+/// Replace the content of the file at `path`, encoded in `encoding`, through a
+/// buffered writer.
+///
+/// The file is only opened (and truncated) once the new content is known and
+/// encoded, so content that `encoding` cannot represent leaves the file alone.
+fn write_file(path: &Path, content: &str, encoding: &'static Encoding) -> io::Result<()> {
+    let bytes = encode(content, encoding)?;
+    write_bytes(&mut BufWriter::new(File::create(path)?), &bytes)
+}
+
+/// How long [`FormatOptions::lock_timeout_ms`] waits for a file by default.
+pub const DEFAULT_LOCK_TIMEOUT_MS: u64 = 5000;
+
+/// How often a locked file is tried again.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A file held with an exclusive lock while it is read, formatted, and written, so
+/// two mdfmt runs never format the same file at once.
+///
+/// The lock is an advisory `flock` lock, so other programs are not blocked, and it
+/// is only taken on Unix. The file is read and written through the handle that
+/// holds the lock, never through a file that replaced it at the same path. Files
+/// that cannot be opened for writing are not locked, since they cannot be written
+/// anyway. The lock is released on drop.
+struct LockedFile(Option<File>);
+
+impl LockedFile {
+    /// A file that is read and written by path, without a lock.
+    const UNLOCKED: LockedFile = LockedFile(None);
+
+    /// Lock the file at `path`, waiting at most `timeout_ms` milliseconds for other
+    /// processes to release it.
+    fn acquire(path: &Path, timeout_ms: u64) -> io::Result<Self> {
+        let file = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                return Ok(LockedFile::UNLOCKED);
+            }
+            Err(e) => return Err(e),
+        };
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+        loop {
+            match try_lock_exclusive(&file) {
+                Ok(true) => return Ok(LockedFile(Some(file))),
+                Ok(false) if Instant::now() < deadline => thread::sleep(LOCK_RETRY_INTERVAL),
+                Ok(false) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("locked by another process for {} ms, skipping", timeout_ms),
+                    ));
+                }
+                // Some platforms and file systems have no locks; format without one
+                Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                    debug!("{}: not locked: {}", path.display(), e);
+                    return Ok(LockedFile::UNLOCKED);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The metadata of the file, which tells whether it was replaced when compared
+    /// with that of the file at `path` later.
+    fn metadata(&self, path: &Path) -> io::Result<fs::Metadata> {
+        match &self.0 {
+            Some(file) => file.metadata(),
+            None => fs::metadata(path),
         }
     }
 
-    // Check if file has frontmatter
-    let (frontmatter, body) = if let Some(stripped) = original_content.strip_prefix("---\n") {
-        if let Some(end_pos) = stripped.find("\n---\n") {
-            // end_pos is relative to stripped content, so we need to add back the initial "---\n" (4 chars)
-            // and then add the length of "\n---\n" (5 chars) to get the position after frontmatter
-            let frontmatter_end = 4 + end_pos + 5; // "---\n" + content + "\n---\n"
-            let frontmatter = &original_content[..frontmatter_end];
-            let body = &original_content[frontmatter_end..];
-            (Some(frontmatter), body)
-        } else {
-            (None, original_content.as_str())
-        }
-    } else {
-        (None, original_content.as_str())
-    };
-
-    // If body is empty or only whitespace and we have frontmatter, delete the file if allowed
-    if frontmatter.is_some() && body.trim().is_empty() {
-        if allow_delete {
-            fs::remove_file(path)?;
-            return Ok((true, false));
-        } else {
-            // Skip processing but don't delete
-            return Ok((false, false));
+    /// Read the whole file, which is in `encoding`.
+    fn read(&self, path: &Path, encoding: &'static Encoding) -> io::Result<String> {
+        let Some(mut file) = self.0.as_ref() else {
+            return read_file_with_encoding(path, encoding);
+        };
+        let mut content = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut content)?;
+        decode(content, encoding)
+    }
+
+    /// Replace the content of the file, like [`write_file`].
+    fn write(&self, path: &Path, content: &str, encoding: &'static Encoding) -> io::Result<()> {
+        let Some(mut file) = self.0.as_ref() else {
+            return write_file(path, content, encoding);
+        };
+        let bytes = encode(content, encoding)?;
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write_bytes(&mut BufWriter::new(file), &bytes)
+    }
+}
+
+/// Try to take an exclusive lock on `file` without waiting.
+///
+/// Returns `Ok(false)` if another process holds a lock on it.
+#[cfg(unix)]
+fn try_lock_exclusive(file: &File) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: `flock` only takes a descriptor, which stays open while `file` lives
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let e = io::Error::last_os_error();
+    if e.kind() == io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(e)
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock_exclusive(_file: &File) -> io::Result<bool> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// What [`run_md_file_with_hook`] does with the formatted content.
+#[derive(Debug, Clone, Copy)]
+enum Destination<'a> {
+    /// Nothing; only the outcome is computed.
+    Check,
+    /// Write it back to the file, or delete the file.
+    InPlace,
+    /// Write it to another file, also when it is unchanged.
+    Copy(&'a Path),
+}
+
+/// Shared implementation of [`process_md_file`] and [`check_md_file`].
+///
+/// When `write` is false, the outcome is computed but the file is left untouched.
+/// Stage timings are only measured when `timings` is provided.
+fn run_md_file(
+    path: &Path,
+    options: &FormatOptions,
+    write: bool,
+    timings: Option<&mut FileTimings>,
+) -> io::Result<FileChanges> {
+    let destination = if write {
+        Destination::InPlace
+    } else {
+        Destination::Check
+    };
+    run_md_file_with_hook(path, options, destination, timings, &mut |_| {})
+}
+
+/// Like [`run_md_file`], calling `before_write` just before the file is written or
+/// deleted, so tests can modify the file at the worst possible moment.
+///
+/// A file that changed on disk since it was read is never overwritten. It is read
+/// and formatted once more, and skipped with an error if it changed again.
+fn run_md_file_with_hook(
+    path: &Path,
+    options: &FormatOptions,
+    destination: Destination,
+    mut timings: Option<&mut FileTimings>,
+    before_write: &mut dyn FnMut(&Path),
+) -> io::Result<FileChanges> {
+    for _ in 0..2 {
+        // Locked anew on every pass, in case the file was replaced in the meantime
+        let file = match destination {
+            Destination::InPlace => LockedFile::acquire(path, options.lock_timeout_ms)?,
+            Destination::Check | Destination::Copy(_) => LockedFile::UNLOCKED,
+        };
+        let changes = format_md_file_once(
+            path,
+            &file,
+            options,
+            destination,
+            timings.as_deref_mut(),
+            before_write,
+        )?;
+        if let Some(changes) = changes {
+            return Ok(changes);
+        }
+    }
+    Err(io::Error::other("changed on disk, skipping"))
+}
+
+/// The size and modification time of a file, used to detect concurrent changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    fn new(metadata: &fs::Metadata) -> Self {
+        FileStamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+
+    /// Whether the file at `path` still has this size and modification time.
+    fn matches(&self, path: &Path) -> io::Result<bool> {
+        Ok(FileStamp::new(&fs::metadata(path)?) == *self)
+    }
+}
+
+/// A single read-format-write pass of [`run_md_file_with_hook`], reading and
+/// writing the file at `path` through `file`.
+///
+/// Returns `None`, without writing anything, if the file changed on disk after it
+/// was read.
+fn format_md_file_once(
+    path: &Path,
+    file: &LockedFile,
+    options: &FormatOptions,
+    destination: Destination,
+    mut timings: Option<&mut FileTimings>,
+    before_write: &mut dyn FnMut(&Path),
+) -> io::Result<Option<FileChanges>> {
+    // Check the size before reading, so huge files are never loaded into memory
+    let metadata = file.metadata(path)?;
+    if let Some(max_size) = options.max_size {
+        if metadata.len() > max_size {
+            debug!(
+                "{}: skipped, {} bytes is over the limit of {}",
+                path.display(),
+                metadata.len(),
+                max_size
+            );
+            if let Destination::Copy(out_path) = destination {
+                create_parent_dirs(out_path)?;
+                fs::copy(path, out_path)?;
+            }
+            return Ok(Some(FileOutcome::TooLarge.into()));
+        }
+    }
+    let stamp = FileStamp::new(&metadata);
+
+    let started = timings.as_ref().map(|_| Instant::now());
+    let original_content = file.read(path, options.encoding)?;
+    if let (Some(timings), Some(started)) = (timings.as_deref_mut(), started) {
+        timings.read = started.elapsed();
+        timings.bytes = original_content.len() as u64;
+    }
+
+    // The content rules clamp promoted headings, so refusing to clamp happens here
+    let formatter = &options.formatter;
+    if formatter.heading_shift < 0 && !formatter.clamp_headings {
+        check_heading_promotion(&original_content, formatter.heading_shift.unsigned_abs())?;
+    }
+
+    let started = timings.as_ref().map(|_| Instant::now());
+    debug!("{}: formatting", path.display());
+    let mut result = format_content(&original_content, options);
+    if let (Some(timings), Some(started)) = (timings.as_deref_mut(), started) {
+        timings.format = started.elapsed();
+    }
+    debug!("{}: {:?}", path.display(), result.outcome);
+
+    let changes = FileChanges {
+        outcome: result.outcome,
+        edits: result.edits,
+        kind: Some(result.kind),
+        warnings: std::mem::take(&mut result.warnings),
+    };
+    match destination {
+        Destination::Check => return Ok(Some(changes)),
+        Destination::InPlace if result.outcome == FileOutcome::Unchanged => {
+            return Ok(Some(changes));
+        }
+        Destination::InPlace => {}
+        // Copies are written whole, so a file changing meanwhile is no problem
+        Destination::Copy(out_path) => {
+            if !result.would_delete() {
+                let started = timings.as_ref().map(|_| Instant::now());
+                create_parent_dirs(out_path)?;
+                write_file(out_path, &result.content, options.encoding)?;
+                if let (Some(timings), Some(started)) = (timings, started) {
+                    timings.write = started.elapsed();
+                }
+            }
+            return Ok(Some(changes));
+        }
+    }
+
+    // Never clobber content written by someone else since the file was read
+    before_write(path);
+    if !stamp.matches(path)? {
+        debug!("{}: changed on disk since it was read", path.display());
+        return Ok(None);
+    }
+    if result.would_delete() {
+        remove_timed(path, options.delete, timings)?;
+    } else {
+        let started = timings.as_ref().map(|_| Instant::now());
+        file.write(path, &result.content, options.encoding)?;
+        #[cfg(feature = "preserve-mtime")]
+        if options.preserve_mtime {
+            restore_file_times(path, &metadata)?;
+        }
+        if let (Some(timings), Some(started)) = (timings, started) {
+            timings.write = started.elapsed();
+        }
+    }
+    Ok(Some(changes))
+}
+
+/// Create the missing parent directories of `path`.
+fn create_parent_dirs(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+/// Set the access and modification times of `path` back to those in `metadata`.
+#[cfg(feature = "preserve-mtime")]
+fn restore_file_times(path: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+    filetime::set_file_times(
+        path,
+        filetime::FileTime::from_last_access_time(metadata),
+        filetime::FileTime::from_last_modification_time(metadata),
+    )
+}
+
+/// Delete a file with the given strategy, recording the time taken as the write stage.
+fn remove_timed(
+    path: &Path,
+    delete: DeleteStrategy,
+    timings: Option<&mut FileTimings>,
+) -> io::Result<()> {
+    let started = timings.as_ref().map(|_| Instant::now());
+    match delete {
+        DeleteStrategy::Keep => {}
+        DeleteStrategy::Remove => fs::remove_file(path)?,
+        // Never fall back to deleting permanently when the trash is unavailable
+        DeleteStrategy::Trash => trash::delete(path)
+            .map_err(|e| io::Error::other(format!("could not move to trash, file kept: {}", e)))?,
+    }
+    if let (Some(timings), Some(started)) = (timings, started) {
+        timings.write = started.elapsed();
+    }
+    Ok(())
+}
+
+/// Process a markdown file, restricting formatting to the given line ranges.
+///
+/// This is the file-level counterpart of [`format_line_ranges`]: only blank line edits
+/// inside or adjacent to `ranges` (1-based, inclusive) are applied, and the rest of the
+/// file is written back verbatim. Empty files are never deleted in this mode.
+///
+/// Returns `true` if the file was modified.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::process_md::process_md_file_in_ranges;
+/// use std::path::Path;
+///
+/// // Only touch lines 10 to 20, e.g. taken from a diff hunk
+/// let modified = process_md_file_in_ranges(Path::new("example.md"), &[10..=20])?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file cannot be read or written, if it changed on
+/// disk while being formatted (it is not overwritten in that case), or if another
+/// mdfmt run holds it for longer than [`DEFAULT_LOCK_TIMEOUT_MS`].
+pub fn process_md_file_in_ranges<P: AsRef<Path>>(
+    path: P,
+    ranges: &[RangeInclusive<usize>],
+) -> io::Result<bool> {
+    process_md_file_in_ranges_with_options(path, ranges, &FormatOptions::default())
+}
+
+/// Process a markdown file like [`process_md_file_in_ranges`], with the formatter
+/// configuration, encoding, and lock timeout of `options`.
+///
+/// The lines inside `ranges` get the content rules of the configuration, as in
+/// [`format_line_ranges_with_config`]. The size limit of `options` is not checked,
+/// and empty files are never deleted in this mode.
+///
+/// Returns `true` if the file was modified.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::process_md::{FormatOptions, process_md_file_in_ranges_with_options};
+/// use std::path::Path;
+///
+/// let mut options = FormatOptions::default();
+/// options.formatter.max_consecutive_blank_lines = 2;
+/// let modified =
+///     process_md_file_in_ranges_with_options(Path::new("example.md"), &[10..=20], &options)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file cannot be read, decoded, or written, if it
+/// changed on disk while being formatted (it is not overwritten in that case), or if
+/// another mdfmt run holds it for longer than [`FormatOptions::lock_timeout_ms`].
+pub fn process_md_file_in_ranges_with_options<P: AsRef<Path>>(
+    path: P,
+    ranges: &[RangeInclusive<usize>],
+    options: &FormatOptions,
+) -> io::Result<bool> {
+    rewrite_file(
+        path.as_ref(),
+        options.lock_timeout_ms,
+        options.encoding,
+        |content| format_line_ranges_with_config(content, ranges, &options.formatter),
+    )
+}
+
+/// Process a markdown file, formatting only lines `start` to `end` (1-based,
+/// inclusive).
+///
+/// This is the file-level counterpart of [`format_range`]: the rest of the file is
+/// written back verbatim, and empty files are never deleted in this mode.
+///
+/// Returns `true` if the file was modified.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdfmt::process_md::{FormatterConfig, process_md_file_in_range};
+/// use std::path::Path;
+///
+/// // Format the lines selected in an editor
+/// let config = FormatterConfig::default();
+/// let modified = process_md_file_in_range(Path::new("example.md"), 10, 20, &config)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file cannot be read or written, if it changed on
+/// disk while being formatted (it is not overwritten in that case), or if another
+/// mdfmt run holds it for longer than [`DEFAULT_LOCK_TIMEOUT_MS`].
+pub fn process_md_file_in_range<P: AsRef<Path>>(
+    path: P,
+    start: usize,
+    end: usize,
+    config: &FormatterConfig,
+) -> io::Result<bool> {
+    rewrite_file(path.as_ref(), DEFAULT_LOCK_TIMEOUT_MS, UTF_8, |content| {
+        format_range(content, start, end, config)
+    })
+}
+
+/// Replace the content of the file at `path` with `format` applied to it, unless
+/// that leaves it unchanged or the file changed on disk in the meantime.
+fn rewrite_file(
+    path: &Path,
+    lock_timeout_ms: u64,
+    encoding: &'static Encoding,
+    format: impl FnOnce(&str) -> String,
+) -> io::Result<bool> {
+    let file = LockedFile::acquire(path, lock_timeout_ms)?;
+    let stamp = FileStamp::new(&file.metadata(path)?);
+    let original_content = file.read(path, encoding)?;
+    let processed_content = format(&original_content);
+
+    if processed_content != original_content {
+        if !stamp.matches(path)? {
+            return Err(io::Error::other("changed on disk, skipping"));
+        }
+        file.write(path, &processed_content, encoding)?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Configuration for the optional content rules applied by [`format_with_config`].
+///
+/// Every rule is disabled by default, so the default configuration formats exactly
+/// like [`remove_multiple_blank_lines`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatterConfig {
+    /// How many blank lines in a row are kept outside code fences and frontmatter.
+    /// Values below 1 are treated as 1.
+    pub max_consecutive_blank_lines: usize,
+    /// Wrap bare URLs in angle brackets (see [`fix_bare_urls`]).
+    pub fix_bare_urls: bool,
+    /// Use the file name as alt text for images without any (see
+    /// [`fix_missing_alt_text`]).
+    pub fix_missing_alt_text: bool,
+    /// Treat `~~~` fences as display math delimiters, like `$$`, instead of code fences.
+    ///
+    /// Math block contents are passed through completely unchanged, while code fences
+    /// lose blank lines directly after the opening and before the closing fence.
+    pub tilde_math_blocks: bool,
+    /// Surround tables (runs of lines starting with `|`) and HTML blocks with blank
+    /// lines, like headings and code fences.
+    pub separate_tables_and_html: bool,
+    /// Rewrite code fence languages, e.g. `{"graph": "mermaid"}`, matched
+    /// case-insensitively (see [`normalize_code_fence_language`]).
+    pub code_fence_language_aliases: HashMap<String, String>,
+    /// Remove zero-width and soft hyphen characters outside code fences (see
+    /// [`remove_zero_width_characters`]). This is lossy, since some scripts and emoji
+    /// sequences rely on zero-width joiners, so every affected line is reported in
+    /// [`FormatResult::warnings`].
+    pub remove_zero_width_chars: bool,
+    /// Lowercase code fence languages and remove whitespace around info strings, e.g.
+    /// ```` ``` Rust ```` to ```` ```rust ````. Attributes after the language, as in
+    /// ```` ```python {linenos=true} ````, are kept as they are.
+    ///
+    /// This only changes how a language is written. Languages are first replaced
+    /// by their aliases, and the result is lowercased.
+    pub normalize_fence_languages: bool,
+    /// Also rewrite common short code fence languages to their canonical names with
+    /// [`LANGUAGE_ALIAS_MAP`], e.g. ```` ```rs ```` to ```` ```rust ````, as if they
+    /// were in [`code_fence_language_aliases`](Self::code_fence_language_aliases).
+    ///
+    /// The configured aliases are looked up first, and the built-in ones only for
+    /// languages they do not map. Both are applied in the same pass as
+    /// [`normalize_fence_languages`](Self::normalize_fence_languages), which then
+    /// lowercases the result.
+    pub builtin_fence_aliases: bool,
+    /// Add this language to opening code fences without an info string, e.g.
+    /// `text`. Closing fences and fences shown inside longer fences are never
+    /// changed.
+    pub default_fence_language: Option<String>,
+    /// Report code fences without a language in [`FormatResult::warnings`] (see
+    /// [`lint_missing_fence_language`]). Nothing is reported when
+    /// [`default_fence_language`](Self::default_fence_language) fills them in.
+    pub require_fence_language: bool,
+    /// Shift every ATX heading by this many levels. Positive values demote and
+    /// negative values promote (see [`demote_headings`] and [`promote_headings`]).
+    pub heading_shift: i8,
+    /// Require exactly this many blank lines before headings that follow other
+    /// content, e.g. two before `#` and `##` headings.
+    ///
+    /// With `None`, a heading gets one blank line before it if it has none, and
+    /// longer runs are only limited by
+    /// [`max_consecutive_blank_lines`](Self::max_consecutive_blank_lines), which the
+    /// spacing options may exceed. Headings at the start of the document or directly
+    /// after the frontmatter are never spaced, and a code fence or other separated
+    /// block next to a heading still gets at least one blank line.
+    pub blank_lines_before_heading: Option<HeadingBlankLines>,
+    /// Require exactly this many blank lines after headings that are followed by
+    /// other content, like
+    /// [`blank_lines_before_heading`](Self::blank_lines_before_heading). Between two
+    /// headings, the larger count wins.
+    pub blank_lines_after_heading: Option<HeadingBlankLines>,
+    /// Promote headings that would go past level 1 to level 1.
+    ///
+    /// The content rules always clamp; without this option,
+    /// [`process_md_file_with_options`] refuses to format such files instead.
+    pub clamp_headings: bool,
+    /// Rewrite code fence markers to backticks or tildes.
+    ///
+    /// Ignored with [`tilde_math_blocks`](Self::tilde_math_blocks), where the marker
+    /// decides between code and math.
+    pub fence_style: FenceStyle,
+    /// Align the columns of pipe tables (see [`format_table`]).
+    pub format_tables: bool,
+    /// The line endings of the formatted document. Every line is converted,
+    /// including those in code fences and frontmatter; with [`LineEnding::Keep`],
+    /// to the line endings most lines of the document already have.
+    pub line_ending: LineEnding,
+    /// End the document with a newline with `Some(true)`, or without one with
+    /// `Some(false)`. With `None`, the document keeps the final newline it has.
+    pub insert_final_newline: Option<bool>,
+    /// Remove trailing whitespace outside code fences, math blocks, and frontmatter
+    /// (see [`trim_trailing_whitespace`]).
+    pub trim_trailing_whitespace: bool,
+    /// Rewrite link titles to these delimiters (see [`normalize_link_titles`]). With
+    /// `None`, titles keep their delimiters.
+    pub link_title_style: Option<LinkTitleStyle>,
+    /// Remove trailing periods, question marks, and exclamation marks from headings
+    /// (see [`fix_heading_punctuation`]).
+    pub remove_heading_trailing_punctuation: bool,
+    /// Remove headings that have no text, only `#` markers, unless they are the only
+    /// heading of the document (see [`remove_empty_headings`]).
+    pub remove_empty_headings: bool,
+    /// Make the underlines of setext headings as long as their text (see
+    /// [`normalize_setext_underline_length`]).
+    pub normalize_setext_underlines: bool,
+    /// Put one space after the `#` markers of ATX headings and collapse runs of
+    /// spaces in their text (see [`normalize_heading_spacing`]).
+    pub normalize_heading_spacing: bool,
+    /// Regenerate the table of contents between the `<!-- mdfmt-toc-start -->` and
+    /// `<!-- mdfmt-toc-end -->` markers, listing the headings of these levels (see
+    /// [`update_toc`]). Documents without the markers are not changed.
+    pub toc: Option<TocLevels>,
+    /// Sort the top-level keys of YAML frontmatter, putting the keys in this list
+    /// first and the others in alphabetical order (see [`sort_frontmatter_keys`]).
+    /// With `None`, keys stay in their order.
+    pub sort_frontmatter_keys: Option<Vec<String>>,
+    /// Re-indent nested list items to this many spaces per nesting level (see
+    /// [`reindent_lists`]). With `None`, lists keep their indentation.
+    pub list_indent: Option<usize>,
+    /// The number of columns a tab advances to when re-indenting lists.
+    pub tab_width: usize,
+    /// Put exactly one space between list markers and the item text (see
+    /// [`normalize_list_marker_spacing`](crate::lists::normalize_list_marker_spacing)).
+    pub normalize_list_marker_spacing: bool,
+    /// Expand tabs in the indentation of lines to spaces, with tab stops this many
+    /// columns apart (see [`expand_leading_tabs`]). With `None`, tabs are kept.
+    pub tabs_to_spaces: Option<usize>,
+    /// Wrap the lines of block quotes longer than this many characters (see
+    /// [`wrap_blockquote_lines`](crate::wrap::wrap_blockquote_lines)). With `None`,
+    /// quotes keep their line breaks.
+    pub wrap_blockquotes: Option<usize>,
+    /// The flavor of Markdown the document is written in. In
+    /// [`ContentMode::Mdx`], blank lines are never inserted next to JSX.
+    pub content_mode: ContentMode,
+    /// HTML comments such as `<!--more-->`, whose surrounding blank lines are kept
+    /// exactly as they are: none are removed or inserted next to a line that is
+    /// one of these once trimmed. CMS excerpt markers often rely on their spacing.
+    pub protected_html_comments: Vec<String>,
+}
+
+impl Default for FormatterConfig {
+    fn default() -> Self {
+        FormatterConfig {
+            max_consecutive_blank_lines: 1,
+            fix_bare_urls: false,
+            fix_missing_alt_text: false,
+            tilde_math_blocks: false,
+            separate_tables_and_html: false,
+            code_fence_language_aliases: HashMap::new(),
+            remove_zero_width_chars: false,
+            normalize_fence_languages: false,
+            builtin_fence_aliases: false,
+            default_fence_language: None,
+            require_fence_language: false,
+            blank_lines_before_heading: None,
+            blank_lines_after_heading: None,
+            heading_shift: 0,
+            clamp_headings: false,
+            fence_style: FenceStyle::Keep,
+            format_tables: false,
+            line_ending: LineEnding::Keep,
+            insert_final_newline: None,
+            trim_trailing_whitespace: false,
+            link_title_style: None,
+            remove_heading_trailing_punctuation: false,
+            remove_empty_headings: false,
+            normalize_setext_underlines: false,
+            normalize_heading_spacing: false,
+            toc: None,
+            sort_frontmatter_keys: None,
+            list_indent: None,
+            tab_width: 4,
+            normalize_list_marker_spacing: false,
+            tabs_to_spaces: None,
+            wrap_blockquotes: None,
+            content_mode: ContentMode::Markdown,
+            protected_html_comments: Vec::new(),
+        }
+    }
+}
+
+/// A number of blank lines for each heading level, used by
+/// [`FormatterConfig::blank_lines_before_heading`] and
+/// [`FormatterConfig::blank_lines_after_heading`].
+///
+/// In configuration files this is either one count for every level, e.g. `2`, or a
+/// list of counts starting at level 1, e.g. `[2, 2, 1]`; the last count also applies
+/// to the deeper levels. On the command line, lists are written as `2,2,1`.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::HeadingBlankLines;
+///
+/// let lines: HeadingBlankLines = "2,2,1".parse()?;
+/// assert_eq!(lines.for_level(1), 2);
+/// assert_eq!(lines.for_level(3), 1);
+/// assert_eq!(lines.for_level(6), 1);
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "BlankLineCounts", into = "BlankLineCounts")]
+pub struct HeadingBlankLines(pub [usize; 6]);
+
+impl HeadingBlankLines {
+    /// The same count for every heading level.
+    pub fn all(count: usize) -> Self {
+        HeadingBlankLines([count; 6])
+    }
+
+    /// The count for headings of `level`, 1 to 6.
+    pub fn for_level(&self, level: usize) -> usize {
+        self.0[level.clamp(1, 6) - 1]
+    }
+}
+
+impl TryFrom<Vec<usize>> for HeadingBlankLines {
+    type Error = String;
+
+    fn try_from(counts: Vec<usize>) -> Result<Self, Self::Error> {
+        let Some(&last) = counts.last() else {
+            return Err("expected at least one blank line count".to_string());
+        };
+        if counts.len() > 6 {
+            return Err(format!(
+                "expected at most 6 blank line counts, one per heading level, got {}",
+                counts.len()
+            ));
+        }
+        let mut lines = [last; 6];
+        lines[..counts.len()].copy_from_slice(&counts);
+        Ok(HeadingBlankLines(lines))
+    }
+}
+
+impl FromStr for HeadingBlankLines {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let counts = s
+            .split(',')
+            .map(|count| {
+                count
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("'{}' is not a blank line count", count.trim()))
+            })
+            .collect::<Result<Vec<usize>, _>>()?;
+        counts.try_into()
+    }
+}
+
+/// How [`HeadingBlankLines`] are written in configuration files.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum BlankLineCounts {
+    All(usize),
+    PerLevel(Vec<usize>),
+}
+
+impl From<HeadingBlankLines> for BlankLineCounts {
+    /// The shortest way to write `lines`, without the counts that repeat the last.
+    fn from(lines: HeadingBlankLines) -> Self {
+        let mut counts = lines.0.to_vec();
+        while counts.len() > 1 && counts[counts.len() - 2] == counts[counts.len() - 1] {
+            counts.pop();
+        }
+        match counts[..] {
+            [count] => BlankLineCounts::All(count),
+            _ => BlankLineCounts::PerLevel(counts),
+        }
+    }
+}
+
+impl TryFrom<BlankLineCounts> for HeadingBlankLines {
+    type Error = String;
+
+    fn try_from(counts: BlankLineCounts) -> Result<Self, Self::Error> {
+        match counts {
+            BlankLineCounts::All(count) => Ok(HeadingBlankLines::all(count)),
+            BlankLineCounts::PerLevel(counts) => counts.try_into(),
+        }
+    }
+}
+
+/// The marker character code fences are written with.
+///
+/// In configuration files, the styles are written as `"keep"`, `"backtick"`, and
+/// `"tilde"`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FenceStyle {
+    /// Leave fence markers as they are.
+    #[default]
+    Keep,
+    /// Write fences with backticks, e.g. ```` ``` ````.
+    Backtick,
+    /// Write fences with tildes, e.g. `~~~`.
+    Tilde,
+}
+
+impl FenceStyle {
+    /// The marker character of this style, or `None` for [`FenceStyle::Keep`].
+    fn marker(self) -> Option<u8> {
+        match self {
+            FenceStyle::Keep => None,
+            FenceStyle::Backtick => Some(b'`'),
+            FenceStyle::Tilde => Some(b'~'),
+        }
+    }
+}
+
+/// The line endings the formatter writes.
+///
+/// In configuration files, the line endings are written as `"lf"`, `"crlf"`, and
+/// `"keep"`. A `\r` that does not end a line is never changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// Unix line endings (`\n`).
+    Lf,
+    /// Windows line endings (`\r\n`).
+    Crlf,
+    /// The line endings most lines of the document have, so a file with mixed
+    /// line endings gets the dominant ones everywhere. Ties go to `\n`.
+    #[default]
+    Keep,
+}
+
+impl LineEnding {
+    /// The line endings to write for `content`, with [`LineEnding::Keep`] replaced
+    /// by the dominant line endings of `content`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::process_md::LineEnding;
+    ///
+    /// assert_eq!(LineEnding::Keep.resolve("a\r\nb\r\nc\n"), LineEnding::Crlf);
+    /// assert_eq!(LineEnding::Keep.resolve("a\r\nb\n"), LineEnding::Lf);
+    /// assert_eq!(LineEnding::Lf.resolve("a\r\nb\r\n"), LineEnding::Lf);
+    /// ```
+    pub fn resolve(self, content: &str) -> LineEnding {
+        if self != LineEnding::Keep {
+            return self;
+        }
+        let crlf = content.matches("\r\n").count();
+        if crlf > content.matches('\n').count() - crlf {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    /// Whether `content` has line endings other than this style.
+    fn differs(self, content: &str) -> bool {
+        match self.resolve(content) {
+            LineEnding::Crlf => content
+                .match_indices('\n')
+                .any(|(i, _)| !content[..i].ends_with('\r')),
+            LineEnding::Lf | LineEnding::Keep => content.contains("\r\n"),
+        }
+    }
+}
+
+/// The flavor of Markdown a document is written in.
+///
+/// In configuration files, the modes are written as `"markdown"` and `"mdx"`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentMode {
+    /// Plain Markdown.
+    #[default]
+    Markdown,
+    /// MDX, which mixes JSX tags such as `<Tabs>` and `{expressions}` into
+    /// Markdown. Lines starting with `<` or `{` are kept as JSX, and blank lines
+    /// are never inserted next to them, since JSX may continue the content around
+    /// it.
+    Mdx,
+}
+
+impl ContentMode {
+    /// The mode for a file, from its extension: `.mdx` files are MDX, and
+    /// everything else is Markdown.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mdfmt::process_md::ContentMode;
+    /// use std::path::Path;
+    ///
+    /// assert_eq!(ContentMode::from_path(Path::new("docs/intro.mdx")), ContentMode::Mdx);
+    /// assert_eq!(ContentMode::from_path(Path::new("README.md")), ContentMode::Markdown);
+    /// ```
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("mdx") => ContentMode::Mdx,
+            _ => ContentMode::Markdown,
+        }
+    }
+}
+
+/// The delimiters of link titles, for [`normalize_link_titles`].
+///
+/// In configuration files, the styles are written as `"double_quote"`,
+/// `"single_quote"`, and `"parenthesis"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkTitleStyle {
+    /// `[text](url "title")`
+    DoubleQuote,
+    /// `[text](url 'title')`
+    SingleQuote,
+    /// `[text](url (title))`
+    Parenthesis,
+}
+
+impl LinkTitleStyle {
+    /// The opening and closing delimiter.
+    fn delimiters(self) -> [char; 2] {
+        match self {
+            LinkTitleStyle::DoubleQuote => ['"', '"'],
+            LinkTitleStyle::SingleQuote => ['\'', '\''],
+            LinkTitleStyle::Parenthesis => ['(', ')'],
+        }
+    }
+}
+
+/// What to do with files that are empty or contain only frontmatter.
+///
+/// In configuration files, the strategies are written as `"keep"`, `"remove"`, and
+/// `"trash"`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeleteStrategy {
+    /// Leave empty files in place.
+    #[default]
+    Keep,
+    /// Delete empty files permanently.
+    Remove,
+    /// Move empty files to the system trash, so they can be restored.
+    ///
+    /// Where the trash is unavailable, the file is kept and an error is returned.
+    Trash,
+}
+
+impl DeleteStrategy {
+    /// The outcome reported for an empty file, or `None` if it is kept.
+    fn outcome(self) -> Option<FileOutcome> {
+        match self {
+            DeleteStrategy::Keep => None,
+            DeleteStrategy::Remove => Some(FileOutcome::Deleted),
+            DeleteStrategy::Trash => Some(FileOutcome::Trashed),
+        }
+    }
+}
+
+/// File-level options for [`process_md_file_with_options`].
+///
+/// The default options never delete files, have no size limit, wait up to
+/// [`DEFAULT_LOCK_TIMEOUT_MS`] for locked files, and format with the default
+/// [`FormatterConfig`], exactly like [`process_md_file`] with
+/// [`DeleteStrategy::Keep`].
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// What to do with files that are empty or contain only frontmatter.
+    pub delete: DeleteStrategy,
+    /// Truncate files that contain only whitespace to zero bytes instead of
+    /// deleting or skipping them. Zero-byte and frontmatter-only files are not
+    /// affected.
+    pub normalize_empty: bool,
+    /// Format files that contain only frontmatter like any other document, instead
+    /// of deleting or skipping them. Their trailing blank lines are removed, and the
+    /// frontmatter itself is left alone.
+    pub include_empty: bool,
+    /// Skip files larger than this many bytes without reading them.
+    pub max_size: Option<u64>,
+    /// How many milliseconds to wait for another mdfmt run to finish with a file
+    /// before skipping it with an error of kind [`io::ErrorKind::TimedOut`]. Files
+    /// are locked while they are read, formatted, and written back.
+    pub lock_timeout_ms: u64,
+    /// Leave files alone when formatting would only add or remove newlines at
+    /// their end, so a final newline policy does not rewrite every file of an
+    /// imported tree. Files with other changes get the policy applied as well.
+    pub ignore_final_newline_only: bool,
+    /// The encoding files are read in and written back in. Files that are not valid
+    /// in it are skipped with an error, and so are files whose formatted content has
+    /// characters it cannot represent.
+    pub encoding: &'static Encoding,
+    /// The content rules used to format each file.
+    pub formatter: FormatterConfig,
+    /// Restore the access and modification times of files after rewriting them, so
+    /// build tools that compare timestamps, such as Make, do not rebuild everything
+    /// after a formatting run.
+    #[cfg(feature = "preserve-mtime")]
+    pub preserve_mtime: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            delete: DeleteStrategy::default(),
+            normalize_empty: false,
+            include_empty: false,
+            max_size: None,
+            lock_timeout_ms: DEFAULT_LOCK_TIMEOUT_MS,
+            ignore_final_newline_only: false,
+            encoding: UTF_8,
+            formatter: FormatterConfig::default(),
+            #[cfg(feature = "preserve-mtime")]
+            preserve_mtime: false,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Default options with the given deletion strategy.
+    fn deleting(delete: DeleteStrategy) -> Self {
+        FormatOptions {
+            delete,
+            ..FormatOptions::default()
+        }
+    }
+}
+
+/// Format content with the blank line rules plus the optional rules enabled in `config`.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::{FormatterConfig, format_with_config};
+///
+/// let config = FormatterConfig {
+///     fix_bare_urls: true,
+///     ..FormatterConfig::default()
+/// };
+/// let output = format_with_config("Text\n\n\nhttps://example.com", &config);
+/// assert_eq!(output, "Text\n\n<https://example.com>");
+/// ```
+pub fn format_with_config<'a>(content: &'a str, config: &FormatterConfig) -> Cow<'a, str> {
+    let line_ending = config.line_ending.resolve(content);
+    format_observed(
+        apply_content_rules(content, config),
+        config,
+        line_ending,
+        &mut (),
+    )
+}
+
+/// Apply the optional content rules enabled in `config`.
+fn apply_content_rules<'a>(original: &'a str, config: &FormatterConfig) -> Cow<'a, str> {
+    let mut content = Cow::Borrowed(original);
+    // Content only becomes owned when a rule changed it, so callers can tell
+    // unchanged input apart by the `Cow` variant
+    let mut apply = |rule: &str, fix: &dyn Fn(&str) -> String| {
+        let fixed = fix(&content);
+        if fixed != *content {
+            trace!("{} changed the content", rule);
+            content = Cow::Owned(fixed);
+        }
+    };
+    // Invisible characters go first, so they cannot hide anything from the other rules
+    if config.remove_zero_width_chars {
+        apply("remove_zero_width_chars", &remove_zero_width_characters);
+    }
+    if config.trim_trailing_whitespace {
+        apply("trim_trailing_whitespace", &trim_trailing_whitespace);
+    }
+    if let Some(tab_width) = config.tabs_to_spaces {
+        apply("tabs_to_spaces", &|content| {
+            expand_leading_tabs(content, tab_width)
+        });
+    }
+    if let Some(key_order) = &config.sort_frontmatter_keys {
+        apply("sort_frontmatter_keys", &|content| {
+            sort_frontmatter_keys(content, key_order)
+        });
+    }
+    if config.fix_bare_urls {
+        apply("fix_bare_urls", &fix_bare_urls);
+    }
+    if config.fix_missing_alt_text {
+        apply("fix_missing_alt_text", &fix_missing_alt_text);
+    }
+    if let Some(style) = config.link_title_style {
+        apply("link_title_style", &|content| {
+            normalize_link_titles(content, style)
+        });
+    }
+    if config.remove_heading_trailing_punctuation {
+        apply(
+            "remove_heading_trailing_punctuation",
+            &fix_heading_punctuation,
+        );
+    }
+    if let Some(language) = &config.default_fence_language {
+        apply("default_fence_language", &|content| {
+            add_default_fence_language(content, language)
+        });
+    }
+    if config.normalize_fence_languages
+        || config.builtin_fence_aliases
+        || !config.code_fence_language_aliases.is_empty()
+    {
+        apply("code_fence_language_aliases", &|content| {
+            rewrite_code_fence_languages(
+                content,
+                &config.code_fence_language_aliases,
+                config.builtin_fence_aliases,
+                config.normalize_fence_languages,
+            )
+        });
+    }
+    if config.heading_shift != 0 {
+        apply("heading_shift", &|content| {
+            shift_headings(content, config.heading_shift.into())
+        });
+    }
+    if config.remove_empty_headings {
+        apply("remove_empty_headings", &remove_empty_headings);
+    }
+    // After the other rules that change the text of headings
+    if config.normalize_heading_spacing {
+        apply("normalize_heading_spacing", &normalize_heading_spacing);
+    }
+    // After the heading rules, so the list shows the headings as they end up
+    if let Some(levels) = config.toc {
+        apply("toc", &|content| update_toc(content, levels));
+    }
+    // Before re-indenting, which nests items by the column their text starts at
+    if config.normalize_list_marker_spacing {
+        apply("normalize_list_marker_spacing", &normalize_list_markers);
+    }
+    // After the table of contents, which is a nested list itself
+    if let Some(indent) = config.list_indent {
+        apply("list_indent", &|content| {
+            reindent_lists(content, indent, config.tab_width)
+        });
+    }
+    // After every rule that changes the text of a heading or its indentation
+    if config.normalize_setext_underlines {
+        apply(
+            "normalize_setext_underlines",
+            &normalize_setext_underline_length,
+        );
+    }
+    // With tilde math blocks, the marker decides between code and math
+    if let Some(marker) = config
+        .fence_style
+        .marker()
+        .filter(|_| !config.tilde_math_blocks)
+    {
+        apply("fence_style", &|content| {
+            rewrite_fence_markers(content, marker)
+        });
+    }
+    // Tables go last, since the other rules can change the width of their cells
+    if config.format_tables {
+        apply("format_tables", &format_tables);
+    }
+    // Quotes are wrapped at the width the other rules leave their lines at
+    if let Some(width) = config.wrap_blockquotes {
+        apply("wrap_blockquotes", &|content| {
+            wrap_blockquote_lines(content, width)
+        });
+    }
+    // Rules can undo each other, e.g. a regenerated table of contents is
+    // re-indented back to what it was
+    if content == original {
+        return Cow::Borrowed(original);
+    }
+    content
+}
+
+/// Apply the blank line rules to `content`, which the content rules were already
+/// applied to, reporting every blank line decision to `observer`.
+///
+/// `line_ending` is [`FormatterConfig::line_ending`] resolved for the input, which
+/// the content rules may have changed.
+fn format_observed<'a, S: LineSink>(
+    content: Cow<'a, str>,
+    config: &FormatterConfig,
+    line_ending: LineEnding,
+    observer: &mut S,
+) -> Cow<'a, str> {
+    let original = match content {
+        Cow::Borrowed(content) => Some(content),
+        Cow::Owned(_) => None,
+    };
+    let formatted = match content {
+        Cow::Borrowed(content) => write_formatted(content, config, observer),
+        Cow::Owned(content) => match write_formatted(&content, config, observer) {
+            Cow::Borrowed(_) => Cow::Owned(content),
+            Cow::Owned(formatted) => Cow::Owned(formatted),
+        },
+    };
+    let formatted = match (finish_lines(formatted, config, line_ending), original) {
+        // Converting back to CRLF can restore the input exactly
+        (Cow::Owned(formatted), Some(original)) if formatted == original => Cow::Borrowed(original),
+        (formatted, _) => formatted,
+    };
+    // Self-test: a second pass over formatted output must not change it.
+    debug_assert!(
+        finish_lines(
+            write_formatted(&formatted, config, &mut ()),
+            config,
+            line_ending
+        ) == formatted,
+        "formatter is not idempotent"
+    );
+    formatted
+}
+
+/// Apply [`FormatterConfig::insert_final_newline`] and the resolved
+/// [`FormatterConfig::line_ending`] to formatted content, which only has LF line
+/// endings.
+fn finish_lines<'a>(
+    mut content: Cow<'a, str>,
+    config: &FormatterConfig,
+    line_ending: LineEnding,
+) -> Cow<'a, str> {
+    match config.insert_final_newline {
+        Some(true) if !content.is_empty() && !content.ends_with('\n') => {
+            content.to_mut().push('\n');
+        }
+        Some(false) if content.ends_with('\n') => {
+            let len = content.trim_end_matches('\n').len();
+            content.to_mut().truncate(len);
+        }
+        _ => {}
+    }
+    if line_ending == LineEnding::Crlf && content.contains('\n') {
+        content = Cow::Owned(content.replace('\n', "\r\n"));
+    }
+    content
+}
+
+/// Common short code fence languages, mostly file extensions, and the canonical
+/// names highlighters know them by.
+///
+/// Used by [`FormatterConfig::builtin_fence_aliases`] and
+/// [`detect_language_from_extension`].
+pub const LANGUAGE_ALIAS_MAP: &[(&str, &str)] = &[
+    ("cs", "csharp"),
+    ("ex", "elixir"),
+    ("exs", "elixir"),
+    ("hs", "haskell"),
+    ("js", "javascript"),
+    ("kt", "kotlin"),
+    ("md", "markdown"),
+    ("pl", "perl"),
+    ("ps1", "powershell"),
+    ("py", "python"),
+    ("rb", "ruby"),
+    ("rs", "rust"),
+    ("sh", "bash"),
+    ("ts", "typescript"),
+    ("yml", "yaml"),
+];
+
+/// Look up the canonical name of a short code fence language in
+/// [`LANGUAGE_ALIAS_MAP`], ignoring case.
+///
+/// # Arguments
+///
+/// * `language` - The language of a code fence, e.g. `rs`
+///
+/// # Returns
+///
+/// The canonical name, or `None` if `language` is not a known alias.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::detect_language_from_extension;
+///
+/// assert_eq!(detect_language_from_extension("rs"), Some("rust"));
+/// assert_eq!(detect_language_from_extension("PY"), Some("python"));
+/// assert_eq!(detect_language_from_extension("rust"), None);
+/// ```
+pub fn detect_language_from_extension(language: &str) -> Option<&'static str> {
+    LANGUAGE_ALIAS_MAP
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(language))
+        .map(|&(_, canonical)| canonical)
+}
+
+/// Map the language of a code fence info string to its canonical name.
+///
+/// The language is the first word of the info string, up to any `{` that starts
+/// attributes. It is looked up in `aliases` case-insensitively; if there is no alias,
+/// the language is returned unchanged. Quarto-style info strings such as `{r}` have
+/// no language before the attributes, so the result is empty.
+///
+/// # Arguments
+///
+/// * `info` - The info string after the opening fence marker, e.g. `graph TD`
+/// * `aliases` - Map from alias to canonical language name
+///
+/// # Returns
+///
+/// The canonical language, or the original language if it has no alias.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::normalize_code_fence_language;
+/// use std::collections::HashMap;
+///
+/// let aliases = HashMap::from([("sequenceDiagram".to_string(), "mermaid".to_string())]);
+/// assert_eq!(normalize_code_fence_language("SequenceDiagram", &aliases), "mermaid");
+/// assert_eq!(normalize_code_fence_language("rust ignore", &aliases), "rust");
+/// assert_eq!(normalize_code_fence_language("python{linenos=true}", &aliases), "python");
+/// ```
+pub fn normalize_code_fence_language<'a>(
+    info: &'a str,
+    aliases: &'a HashMap<String, String>,
+) -> &'a str {
+    let info = info.trim_start();
+    let language = &info[..language_len(info)];
+    aliases
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(language))
+        .map_or(language, |(_, canonical)| canonical.as_str())
+}
+
+/// The length of the language at the start of an info string, which ends at
+/// whitespace or at a `{` that starts attributes.
+pub(crate) fn language_len(info: &str) -> usize {
+    info.find(|c: char| c.is_whitespace() || c == '{')
+        .unwrap_or(info.len())
+}
+
+/// Rewrite the languages of opening code fences according to `aliases`, and with
+/// `builtin`, languages without an entry in `aliases` according to
+/// [`LANGUAGE_ALIAS_MAP`].
+///
+/// With `normalize`, the language is also lowercased, and whitespace around the info
+/// string is removed. Everything else, including the rest of the info string and line
+/// endings, is kept.
+fn rewrite_code_fence_languages(
+    content: &str,
+    aliases: &HashMap<String, String>,
+    builtin: bool,
+    normalize: bool,
+) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        if regions[i] != LineRegion::CodeFenceOpening {
+            output.push_str(line);
+            continue;
+        }
+
+        // Split the line into indentation + fence marker, the language, and the rest
+        let text = trimmed[i];
+        let indent = text.len() - text.trim_start().len();
+        let marker = text[indent..]
+            .bytes()
+            .take_while(|&b| b == b'`' || b == b'~')
+            .count();
+        let info_start = indent + marker;
+        let language_start =
+            info_start + (text[info_start..].len() - text[info_start..].trim_start().len());
+        let language_end = language_start + language_len(&text[language_start..]);
+        let mut language = normalize_code_fence_language(&text[language_start..], aliases);
+        if builtin && language == &text[language_start..language_end] {
+            language = detect_language_from_extension(language).unwrap_or(language);
+        }
+
+        if normalize {
+            output.push_str(&line[..info_start]);
+            output.push_str(&language.to_lowercase());
+            output.push_str(text[language_end..].trim_end());
+            output.push_str(&line[text.len()..]);
+        } else {
+            output.push_str(&line[..language_start]);
+            output.push_str(language);
+            output.push_str(&line[language_end..]);
+        }
+    }
+
+    output
+}
+
+/// Remove zero-width spaces, zero-width (non-)joiners, and soft hyphens.
+///
+/// These characters are invisible when rendered, but break searches, links, and
+/// spell checkers. Lines inside code fences and math blocks keep them, since they
+/// may be intentional there. Use [`lint_zero_width_characters`] to find the lines
+/// this changes.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to clean
+///
+/// # Returns
+///
+/// The content without those characters; everything else, including line endings,
+/// is kept.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::remove_zero_width_characters;
+///
+/// let input = "co\u{AD}operate\u{200B}\n```\n\u{200B}\n```\n";
+/// assert_eq!(remove_zero_width_characters(input), "cooperate\n```\n\u{200B}\n```\n");
+/// ```
+pub fn remove_zero_width_characters(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        if strips_zero_width(regions[i]) {
+            output.extend(line.chars().filter(|&c| !is_zero_width(c)));
+        } else {
+            output.push_str(line);
+        }
+    }
+
+    output
+}
+
+/// Add `language` to the opening code fences that have no info string.
+///
+/// Whitespace after a bare fence marker is replaced; closing fences are never
+/// changed.
+fn add_default_fence_language(content: &str, language: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        let text = trimmed[i];
+        if regions[i] != LineRegion::CodeFenceOpening || !fence_info(text).is_empty() {
+            output.push_str(line);
+            continue;
+        }
+        let indent = text.len() - text.trim_start().len();
+        let marker_end = indent + fence_marker(text.trim_start()).len();
+        output.push_str(&line[..marker_end]);
+        output.push_str(language);
+        output.push_str(&line[text.len()..]);
+    }
+
+    output
+}
+
+/// Remove spaces and tabs at the end of lines.
+///
+/// Lines in code fences, math blocks, and frontmatter are left alone, since their
+/// whitespace may be meaningful. Note that this also removes hard line breaks
+/// written as two trailing spaces; a backslash at the end of the line keeps them.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to clean
+///
+/// # Returns
+///
+/// The content without trailing whitespace; line endings are kept.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::trim_trailing_whitespace;
+///
+/// let input = "Text \t\r\n```\ncode  \n```\n";
+/// assert_eq!(trim_trailing_whitespace(input), "Text\r\n```\ncode  \n```\n");
+/// ```
+pub fn trim_trailing_whitespace(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        let text = trimmed[i];
+        if regions[i] == LineRegion::Text {
+            output.push_str(text.trim_end_matches([' ', '\t']));
+            output.push_str(&line[text.len()..]);
+        } else {
+            output.push_str(line);
+        }
+    }
+
+    output
+}
+
+/// Expand tabs in the indentation of lines to spaces.
+///
+/// Each tab advances to the next multiple of `tab_width` columns, counting the
+/// spaces before it, so `"  \t- item"` with a width of 4 becomes `"    - item"`.
+/// Lines in code fences, indented code blocks, math blocks, and frontmatter are
+/// left alone, and so are tabs after the first character that is not whitespace,
+/// e.g. in table cells.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to expand
+/// * `tab_width` - The number of columns between tab stops
+///
+/// # Returns
+///
+/// The content with spaces instead of leading tabs; line endings are kept.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::expand_leading_tabs;
+///
+/// let input = "- a\n\t- b\tc\n\nText\n\n\tcode\n";
+/// assert_eq!(
+///     expand_leading_tabs(input, 4),
+///     "- a\n    - b\tc\n\nText\n\n\tcode\n"
+/// );
+/// assert_eq!(expand_leading_tabs("- a\n \t- b\n", 2), "- a\n  - b\n");
+/// ```
+pub fn expand_leading_tabs(content: &str, tab_width: usize) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let code = indented_code_lines(&trimmed, &regions, tab_width);
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        let text = trimmed[i];
+        let (column, rest) = indentation(text, tab_width.max(1));
+        let prefix = &text[..text.len() - rest.len()];
+        if regions[i] == LineRegion::Text && !code[i] && !rest.is_empty() && prefix.contains('\t') {
+            output.push_str(&" ".repeat(column));
+            output.push_str(&line[prefix.len()..]);
+        } else {
+            output.push_str(line);
+        }
+    }
+
+    output
+}
+
+/// Rewrite the opening and closing markers of code fences to `marker`.
+///
+/// The marker length, indentation, info string, and fence content are kept. A fence
+/// stays as it is when converting it would change where it ends or what it means:
+/// when its content has a line starting with as long a run of the new marker, which
+/// would close the converted fence early, or when a backtick fence would get an info
+/// string containing a backtick.
+fn rewrite_fence_markers(content: &str, marker: u8) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let mut output = String::with_capacity(content.len());
+
+    let mut i = 0;
+    while i < lines.len() {
+        if regions[i] != LineRegion::CodeFenceOpening {
+            output.push_str(lines[i]);
+            i += 1;
+            continue;
+        }
+
+        // The fence runs to its closing line, or to the end of the document
+        let end = (i + 1..lines.len())
+            .find(|&j| regions[j] != LineRegion::CodeFence)
+            .unwrap_or(lines.len());
+        let opening = trimmed[i].trim();
+        let old_marker = fence_marker(opening);
+        let new_marker = char::from(marker).to_string().repeat(old_marker.len());
+        let closed = end > i + 1 && trimmed[end - 1].trim().starts_with(old_marker);
+        let body = &trimmed[i + 1..if closed { end - 1 } else { end }];
+        let convertible = opening.as_bytes()[0] != marker
+            && !body
+                .iter()
+                .any(|line| line.trim().starts_with(new_marker.as_str()))
+            && !(marker == b'`' && opening.trim_start_matches('~').contains('`'));
+
+        for (j, line) in lines.iter().enumerate().take(end).skip(i) {
+            if convertible && (j == i || (closed && j == end - 1)) {
+                let indent = line.len() - line.trim_start().len();
+                let run = line[indent..]
+                    .bytes()
+                    .take_while(|&b| b == line.as_bytes()[indent])
+                    .count();
+                output.push_str(&line[..indent]);
+                output.extend(std::iter::repeat_n(char::from(marker), run));
+                output.push_str(&line[indent + run..]);
+            } else {
+                output.push_str(line);
+            }
+        }
+        i = end;
+    }
+
+    output
+}
+
+/// Inline link destinations followed by a title, e.g. `](https://example.com "Title")`.
+static LINK_TITLE_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"\]\((?:<[^>]*>|[^\s()<]+)\s+(?P<title>"(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*'|\((?:[^()\\]|\\.)*\))\s*\)"#,
+    )
+    .expect("link title pattern is valid")
+});
+
+/// Rewrite the delimiters of inline link and image titles to one style, e.g.
+/// `[text](url 'title')` to `[text](url "title")`.
+///
+/// Quotes and parentheses inside a title are escaped as the new delimiters require,
+/// and escapes the old delimiters needed are removed, so `'It\'s "new"'` becomes
+/// `"It's \"new\""`. Links in code fences, math blocks, frontmatter, and inline
+/// code spans are left alone, as are titles that span several lines.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to rewrite
+/// * `style` - The delimiters every title should use
+///
+/// # Returns
+///
+/// The content with every link title rewritten; everything else, including line
+/// endings, is kept.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::{LinkTitleStyle, normalize_link_titles};
+///
+/// let input = "[Docs](https://example.com 'The \"docs\"') and ![Logo](logo.png (Logo))";
+/// assert_eq!(
+///     normalize_link_titles(input, LinkTitleStyle::DoubleQuote),
+///     "[Docs](https://example.com \"The \\\"docs\\\"\") and ![Logo](logo.png \"Logo\")"
+/// );
+/// ```
+pub fn normalize_link_titles(content: &str, style: LinkTitleStyle) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        if regions[i] != LineRegion::Text {
+            output.push_str(line);
+            continue;
+        }
+        let code_spans = code_span_ranges(trimmed[i]);
+        let mut last = 0;
+        for captures in LINK_TITLE_PATTERN.captures_iter(trimmed[i]) {
+            let title = captures
+                .name("title")
+                .expect("title group always participates");
+            if code_spans.iter().any(|span| span.contains(&title.start())) {
+                continue;
+            }
+            output.push_str(&line[last..title.start()]);
+            output.push_str(&retitle(title.as_str(), style));
+            last = title.end();
+        }
+        output.push_str(&line[last..]);
+    }
+
+    output
+}
+
+/// Rewrite one delimited link title, such as `'title'`, to `style`.
+fn retitle(title: &str, style: LinkTitleStyle) -> String {
+    let old = match title.as_bytes()[0] {
+        b'"' => ['"', '"'],
+        b'\'' => ['\'', '\''],
+        _ => ['(', ')'],
+    };
+    let new = style.delimiters();
+    let mut output = String::with_capacity(title.len() + 2);
+    output.push(new[0]);
+    let mut chars = title[1..title.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                // The old delimiters no longer need their escapes
+                Some(escaped) if old.contains(&escaped) && !new.contains(&escaped) => {
+                    output.push(escaped);
+                }
+                Some(escaped) => {
+                    output.push('\\');
+                    output.push(escaped);
+                }
+                None => output.push('\\'),
+            }
+        } else {
+            if new.contains(&c) {
+                output.push('\\');
+            }
+            output.push(c);
+        }
+    }
+    output.push(new[1]);
+    output
+}
+
+/// The alignment of a table column, set by the colons in the separator row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnAlignment {
+    /// `---`
+    None,
+    /// `:---`
+    Left,
+    /// `---:`
+    Right,
+    /// `:---:`
+    Center,
+}
+
+/// Split a table row into its trimmed cells.
+///
+/// The optional leading and trailing pipes are dropped, and escaped pipes (`\|`)
+/// stay part of their cell.
+fn table_cells(row: &str) -> Vec<&str> {
+    let row = row.trim();
+    let row = row.strip_prefix('|').unwrap_or(row);
+    let mut cells = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in row.char_indices() {
+        match c {
+            '|' if !escaped => {
+                cells.push(row[start..i].trim());
+                start = i + 1;
+            }
+            '\\' => {
+                escaped = !escaped;
+                continue;
+            }
+            _ => {}
+        }
+        escaped = false;
+    }
+    // A trailing pipe closes the last cell instead of starting an empty one
+    if start < row.len() || cells.is_empty() {
+        cells.push(row[start..].trim());
+    }
+    cells
+}
+
+/// The column alignments of a table separator row such as `| :--- | ---: |`, or
+/// `None` if `row` is not one.
+fn table_alignments(row: &str) -> Option<Vec<ColumnAlignment>> {
+    if !row.contains('|') {
+        return None;
+    }
+    table_cells(row)
+        .into_iter()
+        .map(|cell| {
+            let left = cell.starts_with(':');
+            let right = cell.len() > 1 && cell.ends_with(':');
+            let dashes = &cell[usize::from(left)..cell.len() - usize::from(right)];
+            if dashes.is_empty() || !dashes.bytes().all(|b| b == b'-') {
+                return None;
+            }
+            Some(match (left, right) {
+                (false, false) => ColumnAlignment::None,
+                (true, false) => ColumnAlignment::Left,
+                (false, true) => ColumnAlignment::Right,
+                (true, true) => ColumnAlignment::Center,
+            })
+        })
+        .collect()
+}
+
+/// Align the columns of a GFM pipe table.
+///
+/// Every cell is padded with spaces to the width of the widest cell in its column,
+/// so the pipes line up. Cells in right-aligned columns (`---:`) are padded on the
+/// left and cells in centered columns (`:---:`) on both sides. The separator row is
+/// rewritten with dashes to the column width, keeping its alignment colons. Rows
+/// with fewer cells than the header get empty cells, and every row gets leading and
+/// trailing pipes and the indentation of the header row.
+///
+/// Widths are counted in characters, so columns with wide characters such as CJK
+/// text may still look uneven in a terminal.
+///
+/// # Arguments
+///
+/// * `rows` - The lines of the table without line endings: the header row, the
+///   separator row, and any data rows
+///
+/// # Returns
+///
+/// The aligned rows joined with `\n`, without a trailing newline. Rows that do not
+/// form a table, because the second row is not a separator row with one cell for
+/// every header cell, are returned unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::format_table;
+///
+/// let table = format_table(&["| Name | Size |", "|:-|-:|", "| a.md | 12 |"]);
+/// assert_eq!(table, "| Name | Size |\n| :--- | ---: |\n| a.md |   12 |");
+/// ```
+pub fn format_table(rows: &[&str]) -> String {
+    let alignments = match rows.get(1).and_then(|row| table_alignments(row)) {
+        Some(alignments) if alignments.len() == table_cells(rows[0]).len() => alignments,
+        _ => return rows.join("\n"),
+    };
+    let indent = &rows[0][..rows[0].len() - rows[0].trim_start().len()];
+    let mut cells: Vec<Vec<&str>> = rows.iter().map(|row| table_cells(row)).collect();
+    for row in &mut cells {
+        if row.len() < alignments.len() {
+            row.resize(alignments.len(), "");
+        }
+    }
+    // Data rows may have more cells than the header; they are aligned as well
+    let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            cells
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| i != 1)
+                .filter_map(|(_, row)| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(3)
+        })
+        .collect();
+
+    let mut output = String::new();
+    for (i, row) in cells.iter().enumerate() {
+        if i > 0 {
+            output.push('\n');
+        }
+        output.push_str(indent);
+        output.push('|');
+        for (column, cell) in row.iter().enumerate() {
+            let width = widths[column];
+            let alignment = alignments
+                .get(column)
+                .copied()
+                .unwrap_or(ColumnAlignment::None);
+            output.push(' ');
+            if i == 1 {
+                let (left, right) = match alignment {
+                    ColumnAlignment::None => ("", ""),
+                    ColumnAlignment::Left => (":", ""),
+                    ColumnAlignment::Right => ("", ":"),
+                    ColumnAlignment::Center => (":", ":"),
+                };
+                output.push_str(left);
+                output.push_str(&"-".repeat(width - left.len() - right.len()));
+                output.push_str(right);
+            } else {
+                let padding = width - cell.chars().count();
+                let before = match alignment {
+                    ColumnAlignment::Right => padding,
+                    ColumnAlignment::Center => padding / 2,
+                    ColumnAlignment::None | ColumnAlignment::Left => 0,
+                };
+                output.push_str(&" ".repeat(before));
+                output.push_str(cell);
+                output.push_str(&" ".repeat(padding - before));
+            }
+            output.push_str(" |");
+        }
+    }
+    output
+}
+
+/// Align the columns of every pipe table in regular text (see [`format_table`]).
+///
+/// A table is a line containing `|` followed by a separator row, and runs over the
+/// following lines that contain `|`. Line endings are kept.
+fn format_tables(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let is_row = |i: usize| regions[i] == LineRegion::Text && trimmed[i].contains('|');
+    let mut output = String::with_capacity(content.len());
+
+    let mut i = 0;
+    while i < lines.len() {
+        let is_table = is_row(i)
+            && i + 1 < lines.len()
+            && is_row(i + 1)
+            && table_alignments(trimmed[i + 1])
+                .is_some_and(|alignments| alignments.len() == table_cells(trimmed[i]).len());
+        if !is_table {
+            output.push_str(lines[i]);
+            i += 1;
+            continue;
+        }
+
+        let end = (i + 2..lines.len())
+            .find(|&j| !is_row(j))
+            .unwrap_or(lines.len());
+        let table = format_table(&trimmed[i..end]);
+        for (j, row) in (i..end).zip(table.split('\n')) {
+            output.push_str(row);
+            output.push_str(&lines[j][trimmed[j].len()..]);
+        }
+        i = end;
+    }
+
+    output
+}
+
+/// Demote every ATX heading by `levels`, e.g. `#` to `##` for one level.
+///
+/// Headings in frontmatter, code fences, and math blocks are left alone. Headings
+/// never go below level 6, so `#####` demoted by two levels becomes `######`.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to restructure
+/// * `levels` - How many levels to demote each heading by
+///
+/// # Returns
+///
+/// The content with every heading demoted.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::demote_headings;
+///
+/// let output = demote_headings("# Post\n\n##### Notes\n\n```\n# comment\n```\n", 2);
+/// assert_eq!(output, "### Post\n\n###### Notes\n\n```\n# comment\n```\n");
+/// ```
+pub fn demote_headings(content: &str, levels: u8) -> String {
+    shift_headings(content, levels.into())
+}
+
+/// Promote every ATX heading by `levels`, e.g. `##` to `#` for one level.
+///
+/// Headings in frontmatter, code fences, and math blocks are left alone. A heading
+/// that would go past level 1 is an error, unless `clamp` is true, in which case it
+/// becomes a level 1 heading.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to restructure
+/// * `levels` - How many levels to promote each heading by
+/// * `clamp` - Whether to clamp headings at level 1 instead of refusing
+///
+/// # Returns
+///
+/// The content with every heading promoted.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::promote_headings;
+///
+/// let output = promote_headings("## Chapter\n\n### Section\n", 1, false)?;
+/// assert_eq!(output, "# Chapter\n\n## Section\n");
+///
+/// assert!(promote_headings("## Chapter\n", 2, false).is_err());
+/// assert_eq!(promote_headings("## Chapter\n", 2, true)?, "# Chapter\n");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns an `io::Error` of kind `InvalidData` naming the first heading that would go
+/// past level 1, if `clamp` is false.
+pub fn promote_headings(content: &str, levels: u8, clamp: bool) -> io::Result<String> {
+    if !clamp {
+        check_heading_promotion(content, levels)?;
+    }
+    Ok(shift_headings(content, -i16::from(levels)))
+}
+
+/// Fail if promoting the headings in `content` by `levels` would take any of them
+/// past level 1.
+fn check_heading_promotion(content: &str, levels: u8) -> io::Result<()> {
+    let lines: Vec<&str> = content.lines().collect();
+    match atx_headings(&lines).find(|&(_, level)| level <= usize::from(levels)) {
+        Some((i, level)) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "heading on line {} is level {} and cannot be promoted by {} level{}",
+                i + 1,
+                level,
+                levels,
+                if levels == 1 { "" } else { "s" }
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Shift every ATX heading by `shift` levels, keeping levels between 1 and 6.
+///
+/// Positive values demote, negative values promote. Everything but the `#`
+/// markers, including line endings, is kept.
+fn shift_headings(content: &str, shift: i16) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let mut headings = atx_headings(&trimmed).peekable();
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        let Some((_, level)) = headings.next_if(|&(heading, _)| heading == i) else {
+            output.push_str(line);
+            continue;
+        };
+        // Only the marker run changes, so indentation and the title are kept
+        let marker_start = line.len() - line.trim_start().len();
+        let new_level = (level as i16 + shift).clamp(1, 6) as usize;
+        output.push_str(&line[..marker_start]);
+        output.extend(std::iter::repeat_n('#', new_level));
+        output.push_str(&line[marker_start + level..]);
+    }
+
+    output
+}
+
+/// Make the underlines of setext headings exactly as long as the heading text.
+///
+/// The length is counted in characters, not bytes, from the first to the last
+/// character of the line above that is not whitespace, so `Café` gets four `=`.
+/// Only lines of nothing but `=` or `-` directly under a line of paragraph text are
+/// rewritten; their indentation, trailing whitespace, and line ending are kept.
+/// Frontmatter, code fences, and math blocks are left alone.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to normalize
+///
+/// # Returns
+///
+/// The content with every setext underline as long as its heading.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::normalize_setext_underline_length;
+///
+/// let input = "Café\n=========\n\nUsage\n--\n\n- item\n---\n";
+/// assert_eq!(
+///     normalize_setext_underline_length(input),
+///     "Café\n====\n\nUsage\n-----\n\n- item\n---\n"
+/// );
+/// ```
+pub fn normalize_setext_underline_length(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let mut underlines = setext_underlines(&trimmed).peekable();
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        if underlines.next_if_eq(&i).is_none() {
+            output.push_str(line);
+            continue;
+        }
+        let underline = line.trim_start();
+        let indent = line.len() - underline.len();
+        let marker = if underline.starts_with('=') { '=' } else { '-' };
+        output.push_str(&line[..indent]);
+        output.extend(std::iter::repeat_n(
+            marker,
+            trimmed[i - 1].trim().chars().count(),
+        ));
+        output.push_str(underline.trim_start_matches(marker));
+    }
+
+    output
+}
+
+/// Put exactly one space between the `#` markers of ATX headings and their text,
+/// and collapse runs of spaces and tabs inside the text to one space.
+///
+/// Inline code spans keep their spacing, and so does an attribute list such as
+/// `{#install}` or `{: .note}` at the end of the text. The closing `#` sequence
+/// and whatever follows the text are kept as they are. Headings in frontmatter,
+/// code fences, and math blocks are left alone, and so are escaped `\#` markers
+/// and tags such as `#draft`, which are not headings.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to normalize
+///
+/// # Returns
+///
+/// The content with single spaces in its headings; line endings are kept.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::normalize_heading_spacing;
+///
+/// let input = "##    Getting   Started\n\n## The `foo  bar`  API   {#api  .x}\n";
+/// assert_eq!(
+///     normalize_heading_spacing(input),
+///     "## Getting Started\n\n## The `foo  bar` API {#api  .x}\n"
+/// );
+/// ```
+pub fn normalize_heading_spacing(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let mut headings = atx_headings(&trimmed).peekable();
+    let mut output = String::with_capacity(content.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        let Some((_, level)) = headings.next_if(|&(heading, _)| heading == i) else {
+            output.push_str(line);
+            continue;
+        };
+        let range = atx_heading_text(trimmed[i], level);
+        let text = trimmed[i][range.clone()].trim_start_matches([' ', '\t']);
+        if text.is_empty() {
+            output.push_str(line);
+            continue;
+        }
+        let attributes = text
+            .rfind('{')
+            .filter(|&at| text[..at].ends_with([' ', '\t']) && is_attribute_list(&text[at..]))
+            .unwrap_or(text.len());
+        output.push_str(&line[..range.start]);
+        output.push(' ');
+        push_collapsed_spaces(&text[..attributes], &mut output);
+        output.push_str(&text[attributes..]);
+        output.push_str(&line[range.end..]);
+    }
+
+    output
+}
+
+/// Write `text` with every run of spaces and tabs outside code spans replaced by
+/// a single space.
+fn push_collapsed_spaces(text: &str, output: &mut String) {
+    let mut after_space = false;
+    let mut start = 0;
+    let end = text.len()..text.len();
+    for span in code_span_ranges(text).into_iter().chain([end]) {
+        for c in text[start..span.start].chars() {
+            let space = c == ' ' || c == '\t';
+            if !(space && after_space) {
+                output.push(if space { ' ' } else { c });
+            }
+            after_space = space;
+        }
+        output.push_str(&text[span.clone()]);
+        after_space &= span.is_empty();
+        start = span.end;
+    }
+}
+
+/// The 0-based line numbers and levels of the ATX headings in regular text.
+///
+/// Unlike [`is_heading`], this requires whitespace or the end of the line after the
+/// `#` markers, so tags such as `#draft` are not treated as headings.
+pub(crate) fn atx_headings<'a>(
+    lines: &'a [&str],
+) -> impl Iterator<Item = (usize, usize)> + use<'a> {
+    let regions = line_regions(lines);
+    lines.iter().enumerate().filter_map(move |(i, line)| {
+        if regions[i] != LineRegion::Text {
+            return None;
+        }
+        let marker = line.trim_start();
+        let level = marker.bytes().take_while(|&b| b == b'#').count();
+        let rest = &marker[level..];
+        ((1..=6).contains(&level) && (rest.is_empty() || rest.starts_with([' ', '\t'])))
+            .then_some((i, level))
+    })
+}
+
+/// The 0-based line numbers of the underlines of the setext headings in regular
+/// text: lines of only `=` or only `-`, indented by at most 3 spaces, directly
+/// under the last line of a paragraph.
+///
+/// List items are recognized like the list rules do, so `2) item` followed by
+/// `---` is an item and a thematic break, not a heading.
+pub(crate) fn setext_underlines<'a>(lines: &'a [&str]) -> impl Iterator<Item = usize> + use<'a> {
+    let regions = line_regions(lines);
+    (1..lines.len()).filter(move |&i| {
+        let (indent, underline) = indentation(lines[i], 4);
+        let underline = underline.trim_end();
+        let title = lines[i - 1];
+        let is_underline = indent < 4
+            && !underline.is_empty()
+            && (underline.bytes().all(|b| b == b'=') || underline.bytes().all(|b| b == b'-'));
+        let trimmed_title = title.trim();
+        let is_paragraph = !trimmed_title.is_empty()
+            && !title.starts_with("    ")
+            && !is_heading(trimmed_title)
+            && marker_len(trimmed_title).is_none()
+            && !trimmed_title.starts_with(['>', '|', '<'])
+            && !trimmed_title.bytes().all(|b| b == b'=' || b == b'-');
+        regions[i] == LineRegion::Text
+            && regions[i - 1] == LineRegion::Text
+            && is_underline
+            && is_paragraph
+    })
+}
+
+/// The byte range of the text of an ATX heading of `level` in `line`: after the `#`
+/// markers and up to the closing `#` sequence, if there is one.
+///
+/// Whitespace before the closing sequence is not part of the text, but whitespace
+/// after the markers is.
+pub(crate) fn atx_heading_text(line: &str, level: usize) -> Range<usize> {
+    let start = line.len() - line.trim_start().len() + level;
+    let text = line.trim_end();
+    // A closing sequence needs whitespace before it, or is all there is
+    let without_closing = text.trim_end_matches('#');
+    let end = if without_closing.len() == start || without_closing.ends_with([' ', '\t']) {
+        without_closing.trim_end().len().max(start)
+    } else {
+        text.len()
+    };
+    start..end
+}
+
+/// The kind of region a line belongs to, as tracked by the formatter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LineRegion {
+    /// Inside the frontmatter block, including its `---` delimiters.
+    Frontmatter,
+    /// The opening line of a code fence, which carries the info string.
+    CodeFenceOpening,
+    /// Inside a code fence, including its closing fence line.
+    CodeFence,
+    /// Inside a `$$` display math block, including its delimiter lines.
+    MathBlock,
+    /// Regular markdown content.
+    Text,
+}
+
+/// Classify every line as frontmatter, code fence, math block, or regular text.
+///
+/// This uses the same frontmatter, fence, and math block detection as
+/// [`blank_line_edits`], so rules built on it protect exactly the regions the
+/// formatter protects.
+pub(crate) fn line_regions(lines: &[&str]) -> Vec<LineRegion> {
+    let mut regions = Vec::with_capacity(lines.len());
+    let mut in_frontmatter = false;
+    let mut in_code_fence = false;
+    let mut code_fence_marker = "";
+    let mut in_math_block = false;
+
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if i == 0 && trimmed == "---" {
+            in_frontmatter = true;
+            regions.push(LineRegion::Frontmatter);
+        } else if in_frontmatter {
+            in_frontmatter = trimmed != "---";
+            regions.push(LineRegion::Frontmatter);
+        } else if in_code_fence {
+            in_code_fence = !trimmed.starts_with(code_fence_marker);
+            regions.push(LineRegion::CodeFence);
+        } else if in_math_block {
+            in_math_block = !closes_math_block(trimmed, MATH_DELIMITER);
+            regions.push(LineRegion::MathBlock);
+        } else if opens_math_block(trimmed, MATH_DELIMITER) {
+            in_math_block = true;
+            regions.push(LineRegion::MathBlock);
+        } else if is_fence(trimmed) {
+            in_code_fence = true;
+            code_fence_marker = fence_marker(trimmed);
+            regions.push(LineRegion::CodeFenceOpening);
+        } else {
+            regions.push(LineRegion::Text);
+        }
+    }
+
+    regions
+}
+
+/// Log the frontmatter, code fences, and math blocks of `content`, which the blank
+/// line rules leave as they are, at the trace level.
+fn trace_protected_regions(content: &str) {
+    let lines: Vec<&str> = content.lines().collect();
+    let regions = line_regions(&lines);
+    let mut start = 0;
+    for (i, region) in regions.iter().enumerate() {
+        // A fence directly after another one starts a new region
+        let ends = regions.get(i + 1).is_none_or(|next| {
+            next != region
+                && !(*region == LineRegion::CodeFenceOpening && *next == LineRegion::CodeFence)
+                || *next == LineRegion::CodeFenceOpening
+        });
+        if !ends {
+            continue;
+        }
+        let kind = match region {
+            LineRegion::Frontmatter => Some("frontmatter"),
+            LineRegion::CodeFenceOpening | LineRegion::CodeFence => Some("code fence"),
+            LineRegion::MathBlock => Some("math block"),
+            LineRegion::Text => None,
+        };
+        if let Some(kind) = kind {
+            trace!("lines {}-{}: {} kept as is", start + 1, i + 1, kind);
+        }
+        start = i + 1;
+    }
+}
+
+/// The delimiter of display math blocks.
+pub(crate) const MATH_DELIMITER: &str = "$$";
+
+/// Check whether a trimmed line opens a math block delimited by `delimiter`.
+///
+/// A `$$` line that also closes the math on the same line (`$$x^2$$`) is a single
+/// line of display math, not the start of a block.
+pub(crate) fn opens_math_block(trimmed: &str, delimiter: &str) -> bool {
+    trimmed.starts_with(delimiter)
+        && !(delimiter == MATH_DELIMITER
+            && trimmed.len() >= 2 * delimiter.len()
+            && trimmed.ends_with(delimiter))
+}
+
+/// Check whether a trimmed line inside a math block closes it.
+///
+/// `$$` may also close the block at the end of a line of math, as in `x^2 $$`.
+pub(crate) fn closes_math_block(trimmed: &str, delimiter: &str) -> bool {
+    trimmed.starts_with(delimiter) || (delimiter == MATH_DELIMITER && trimmed.ends_with(delimiter))
+}
+
+/// Remove multiple consecutive blank lines and ensure proper spacing around markdown elements.
+/// This function preserves frontmatter and code fence contents while adding blank lines
+/// around headings, code fences, and list markers.
+///
+/// This is [`ast::Document::parse`] followed by [`ast::Document::render`] with the default
+/// [`FormatterConfig`], except that content which is already formatted is borrowed
+/// instead of copied.
+///
+/// # Arguments
+///
+/// * `content` - The content to process
+///
+/// # Returns
+///
+/// The processed content with multiple blank lines reduced to single blank lines
+/// and proper spacing around markdown elements.
+///
+/// # Examples
+///
+/// Basic usage:
+/// ```
+/// use mdfmt::process_md::remove_multiple_blank_lines;
+///
+/// let input = "Line 1\n\n\n\nLine 2\n\nLine 3";
+/// let output = remove_multiple_blank_lines(input);
+/// assert_eq!(output, "Line 1\n\nLine 2\n\nLine 3");
+/// ```
+///
+/// Preserving frontmatter:
+/// ```
+/// use mdfmt::process_md::remove_multiple_blank_lines;
+///
+/// let input = "---\ntitle: Test\n\n\nauthor: Me\n---\n\n\n\nContent";
+/// let output = remove_multiple_blank_lines(input);
+/// assert_eq!(output, "---\ntitle: Test\n\n\nauthor: Me\n---\n\nContent");
+/// ```
+///
+/// Adding spacing around headings:
+/// ```
+/// use mdfmt::process_md::remove_multiple_blank_lines;
+///
+/// let input = "Text\n# Heading\nMore text";
+/// let output = remove_multiple_blank_lines(input);
+/// assert_eq!(output, "Text\n\n# Heading\n\nMore text");
+/// ```
+pub fn remove_multiple_blank_lines(content: &str) -> Cow<'_, str> {
+    format_with_config(content, &FormatterConfig::default())
+}
+
+/// Check whether content is already formatted, so that [`format_content`] with the
+/// same options would leave it as it is.
+///
+/// This is cheaper than formatting and comparing, since no output is built for
+/// content that needs no changes. Formatting is idempotent, so the output of
+/// [`format_content`] is always formatted.
+///
+/// # Arguments
+///
+/// * `content` - The markdown document to check
+/// * `opts` - File-level and formatting options
+///
+/// # Returns
+///
+/// `true` if the outcome would be [`FileOutcome::Unchanged`], and `false` if the
+/// content would be modified, normalized, or deleted.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::{FormatOptions, is_formatted};
+///
+/// let opts = FormatOptions::default();
+/// assert!(is_formatted("# Title\n\nText\n", &opts));
+/// assert!(!is_formatted("# Title\nText\n", &opts));
+/// ```
+pub fn is_formatted(content: &str, opts: &FormatOptions) -> bool {
+    format_content(content, opts).outcome == FileOutcome::Unchanged
+}
+
+/// A single line-level edit produced by the blank line formatter.
+///
+/// Line indices are 0-based and refer to the lines of the original content
+/// (as split by [`str::lines`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEdit {
+    /// Insert a blank line before the given input line.
+    InsertBlankBefore(usize),
+    /// Remove the given blank line, or blank `>` line in a block quote.
+    Remove(usize),
+}
+
+impl LineEdit {
+    /// The 0-based input line index this edit applies to.
+    pub fn line(&self) -> usize {
+        match *self {
+            LineEdit::InsertBlankBefore(line) | LineEdit::Remove(line) => line,
+        }
+    }
+}
+
+/// Compute the edits [`remove_multiple_blank_lines`] would make, without applying them.
+///
+/// The returned edits are sorted by line index. Applying all of them with
+/// [`apply_line_edits`] yields exactly the output of [`remove_multiple_blank_lines`];
+/// applying a subset leaves every other line verbatim.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::{blank_line_edits, LineEdit};
+///
+/// let edits = blank_line_edits("Text\n# Heading\n\n\nMore");
+/// assert_eq!(
+///     edits,
+///     vec![LineEdit::InsertBlankBefore(1), LineEdit::Remove(3)]
+/// );
+/// ```
+pub fn blank_line_edits(content: &str) -> Vec<LineEdit> {
+    blank_line_edits_with_config(content, &FormatterConfig::default())
+}
+
+/// Compute the blank line edits, honoring the block detection options in `config`.
+///
+/// Only [`FormatterConfig::tilde_math_blocks`] affects the blank line rules; the
+/// content rewriting options are applied separately by [`format_with_config`].
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::{FormatterConfig, LineEdit, blank_line_edits_with_config};
+///
+/// let config = FormatterConfig {
+///     tilde_math_blocks: true,
+///     ..FormatterConfig::default()
+/// };
+/// // Blank lines inside the ~~~ math block are kept
+/// let edits = blank_line_edits_with_config("Text\n~~~\n\nx^2\n~~~", &config);
+/// assert_eq!(edits, vec![LineEdit::InsertBlankBefore(1)]);
+/// ```
+pub fn blank_line_edits_with_config(content: &str, config: &FormatterConfig) -> Vec<LineEdit> {
+    let mut edits = Vec::new();
+    ast::render_content_into(content, config, &mut edits);
+    edits.sort_by_key(LineEdit::line);
+    edits
+}
+
+/// Receives the formatter's decision for every input line, in output order.
+///
+/// This lets the same rendering of a [`ast::Document`] either collect [`LineEdit`]s or
+/// write the formatted output directly.
+pub(crate) trait LineSink {
+    /// Input line `index` is emitted unchanged.
+    fn keep(&mut self, index: usize, line: &str);
+    /// A blank line is inserted before input line `before`, because of `rule`.
+    fn insert_blank(&mut self, before: usize, rule: EditRule);
+    /// Input line `index` is dropped, because of `rule`.
+    fn remove(&mut self, index: usize, rule: EditRule);
+}
+
+impl LineSink for () {
+    fn keep(&mut self, _index: usize, _line: &str) {}
+
+    fn insert_blank(&mut self, _before: usize, _rule: EditRule) {}
+
+    fn remove(&mut self, _index: usize, _rule: EditRule) {}
+}
+
+impl<S: LineSink + ?Sized> LineSink for &mut S {
+    fn keep(&mut self, index: usize, line: &str) {
+        (**self).keep(index, line);
+    }
+
+    fn insert_blank(&mut self, before: usize, rule: EditRule) {
+        (**self).insert_blank(before, rule);
+    }
+
+    fn remove(&mut self, index: usize, rule: EditRule) {
+        (**self).remove(index, rule);
+    }
+}
+
+/// Forwards every decision to both sinks, e.g. to write the output and collect the
+/// edits in the same pass.
+impl<A: LineSink, B: LineSink> LineSink for (A, B) {
+    fn keep(&mut self, index: usize, line: &str) {
+        self.0.keep(index, line);
+        self.1.keep(index, line);
+    }
+
+    fn insert_blank(&mut self, before: usize, rule: EditRule) {
+        self.0.insert_blank(before, rule);
+        self.1.insert_blank(before, rule);
+    }
+
+    fn remove(&mut self, index: usize, rule: EditRule) {
+        self.0.remove(index, rule);
+        self.1.remove(index, rule);
+    }
+}
+
+impl LineSink for Vec<LineEdit> {
+    fn keep(&mut self, _index: usize, _line: &str) {}
+
+    fn insert_blank(&mut self, before: usize, _rule: EditRule) {
+        self.push(LineEdit::InsertBlankBefore(before));
+    }
+
+    fn remove(&mut self, index: usize, _rule: EditRule) {
+        self.push(LineEdit::Remove(index));
+    }
+}
+
+/// Writes the formatted lines into a single pre-sized `String`.
+///
+/// As long as every line is kept as-is, nothing is copied: the output is still a
+/// prefix of the input, of which only the length is tracked. The copy starts at the
+/// first inserted or removed line.
+struct OutputWriter<'a> {
+    content: &'a str,
+    /// The output, once it has diverged from the input
+    output: Option<String>,
+    /// Length of the input prefix the output matches, until it diverges
+    prefix_len: usize,
+    lines: usize,
+}
+
+impl OutputWriter<'_> {
+    fn push_line(&mut self, line: &str) {
+        match &mut self.output {
+            Some(output) => {
+                if self.lines > 0 {
+                    output.push('\n');
+                }
+                output.push_str(line);
+            }
+            None => self.prefix_len += line.len() + usize::from(self.lines > 0),
+        }
+        self.lines += 1;
+    }
+
+    fn diverge(&mut self) {
+        if self.output.is_none() {
+            // Inserted blank lines are rare, so the input size is a close upper bound
+            let mut output = String::with_capacity(self.content.len() + 16);
+            output.push_str(&self.content[..self.prefix_len]);
+            self.output = Some(output);
+        }
+    }
+}
+
+impl LineSink for OutputWriter<'_> {
+    fn keep(&mut self, _index: usize, line: &str) {
+        self.push_line(line);
+    }
+
+    fn insert_blank(&mut self, _before: usize, _rule: EditRule) {
+        self.diverge();
+        self.push_line("");
+    }
+
+    fn remove(&mut self, _index: usize, _rule: EditRule) {
+        self.diverge();
+    }
+}
+
+/// Format `content` in a single pass, writing straight into the output string.
+///
+/// Every decision is also reported to `observer`. Returns `Cow::Borrowed` if the
+/// formatter made no edits.
+fn write_formatted<'a, S: LineSink>(
+    content: &'a str,
+    config: &FormatterConfig,
+    observer: &mut S,
+) -> Cow<'a, str> {
+    let mut writer = OutputWriter {
+        content,
+        output: None,
+        prefix_len: 0,
+        lines: 0,
+    };
+    // Splitting into lines turns CRLF into LF, so CRLF content always changes
+    if content.contains("\r\n") {
+        writer.diverge();
+    }
+    ast::render_content_into(content, config, &mut (&mut writer, observer));
+
+    match writer.output {
+        Some(mut output) => {
+            if content.ends_with('\n') && writer.lines > 0 {
+                output.push('\n');
+            }
+            Cow::Owned(output)
+        }
+        None => Cow::Borrowed(content),
+    }
+}
+
+/// Check whether a line is an ATX heading.
+pub(crate) fn is_heading(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.starts_with('#') && trimmed.chars().take_while(|c| *c == '#').count() <= 6
+}
+
+/// Check whether a line starts a bullet or ordered list item.
+pub(crate) fn is_list_marker(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.starts_with("- ")
+        || trimmed.starts_with("* ")
+        || trimmed.starts_with("+ ")
+        || (trimmed
+            .chars()
+            .next()
+            .map(|c| c.is_ascii_digit())
+            .unwrap_or(false)
+            && trimmed.contains(". "))
+}
+
+/// Check whether a trimmed line starts a code fence.
+pub(crate) fn is_fence(trimmed: &str) -> bool {
+    trimmed.starts_with("```") || trimmed.starts_with("~~~")
+}
+
+/// The marker run of a trimmed line that starts a code fence, e.g. ```` ```` ````.
+///
+/// The fence is closed by a line starting with the same run, so a four-backtick
+/// fence can contain ```` ``` ```` lines, e.g. to show a fenced example.
+pub(crate) fn fence_marker(trimmed: &str) -> &str {
+    let marker = trimmed.as_bytes()[0];
+    let len = trimmed.bytes().take_while(|&b| b == marker).count();
+    &trimmed[..len]
+}
+
+/// The alert kinds GitHub renders for `> [!KIND]` blockquotes.
+const ALERT_KINDS: [&str; 5] = ["NOTE", "TIP", "IMPORTANT", "WARNING", "CAUTION"];
+
+/// Check whether a line opens a GFM alert block, such as `> [!NOTE]`.
+///
+/// The alert kind is matched case-insensitively, as GitHub does.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::is_alert_header;
+///
+/// assert!(is_alert_header("> [!WARNING]"));
+/// assert!(is_alert_header(">[!note]"));
+/// assert!(!is_alert_header("> [!TODO]"));
+/// assert!(!is_alert_header("> Regular quote"));
+/// ```
+pub fn is_alert_header(line: &str) -> bool {
+    line.trim()
+        .strip_prefix('>')
+        .and_then(|rest| rest.trim_start().strip_prefix("[!"))
+        .and_then(|rest| rest.trim_end().strip_suffix(']'))
+        .is_some_and(|kind| ALERT_KINDS.iter().any(|k| k.eq_ignore_ascii_case(kind)))
+}
+
+/// Apply line edits (as produced by [`blank_line_edits`]) to `content`.
+///
+/// Lines without an edit are emitted verbatim, and the final newline of `content`
+/// (or its absence) is preserved. `edits` must be sorted by line index.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::{apply_line_edits, LineEdit};
+///
+/// let output = apply_line_edits("a\n\n\nb\n", &[LineEdit::Remove(2)]);
+/// assert_eq!(output, "a\n\nb\n");
+/// ```
+pub fn apply_line_edits(content: &str, edits: &[LineEdit]) -> String {
+    let mut result: Vec<&str> = Vec::new();
+    let mut edits = edits.iter().peekable();
+    let mut line_count = 0;
+
+    for (i, line) in content.lines().enumerate() {
+        let mut removed = false;
+        while let Some(edit) = edits.next_if(|edit| edit.line() == i) {
+            match edit {
+                LineEdit::InsertBlankBefore(_) => result.push(""),
+                LineEdit::Remove(_) => removed = true,
+            }
+        }
+        if !removed {
+            result.push(line);
+        }
+        line_count = i + 1;
+    }
+
+    // Insertions past the last line are only possible with hand-built edits
+    for edit in edits {
+        if matches!(edit, LineEdit::InsertBlankBefore(line) if *line >= line_count) {
+            result.push("");
+        }
+    }
+
+    let mut result_content = result.join("\n");
+    if content.ends_with('\n') && !result.is_empty() {
+        result_content.push('\n');
+    }
+    result_content
+}
+
+/// What a document consists of, as far as empty file handling is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    /// Zero bytes.
+    Empty,
+    /// Only whitespace, e.g. a few newlines.
+    Blank,
+    /// A frontmatter block followed by nothing but whitespace.
+    FrontmatterOnly,
+    /// Anything else; only these documents are formatted, unless
+    /// [`FormatOptions::include_empty`] is set.
+    Document,
+}
+
+impl ContentKind {
+    /// Classify `content`.
+    fn of(content: &str) -> Self {
+        if content.is_empty() {
+            return ContentKind::Empty;
+        }
+        if content.trim().is_empty() {
+            return ContentKind::Blank;
+        }
+        // The frontmatter ends at the first closing `---` line, detected like
+        // `line_regions` does, so CRLF frontmatter counts as well
+        let mut lines = content.lines();
+        if lines.next().map(str::trim) == Some("---")
+            && lines.by_ref().any(|line| line.trim() == "---")
+            && lines.all(|line| line.trim().is_empty())
+        {
+            ContentKind::FrontmatterOnly
+        } else {
+            ContentKind::Document
+        }
+    }
+
+    /// Classify `content` as the formatter sees it with `config`.
+    ///
+    /// Invisible characters that would be removed do not make a file non-empty, or a
+    /// second pass would delete or normalize what the first one left behind.
+    pub(crate) fn with_config(content: &str, config: &FormatterConfig) -> Self {
+        if config.remove_zero_width_chars {
+            ContentKind::of(&remove_zero_width_characters(content))
+        } else {
+            ContentKind::of(content)
+        }
+    }
+
+    /// Whether documents of this kind are formatted, instead of being deleted,
+    /// normalized, or left alone. See [`FormatOptions::include_empty`].
+    pub(crate) fn is_formatted(self, include_empty: bool) -> bool {
+        self == ContentKind::Document || (self == ContentKind::FrontmatterOnly && include_empty)
+    }
+}
+
+/// The result of formatting a document, including where blank lines changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatResult<'a> {
+    /// The content the file should have afterwards. Borrowed from the input when
+    /// it is unchanged, which includes files that would be deleted.
+    pub content: Cow<'a, str>,
+    /// What the input consists of.
+    pub kind: ContentKind,
+    /// What [`process_md_file_with_options`] would do with a file with this content.
+    ///
+    /// This is never [`FileOutcome::TooLarge`], since the size limit only applies
+    /// before a file is read.
+    pub outcome: FileOutcome,
+    /// 1-indexed line numbers, in the original content, of blank lines that were removed.
+    pub blank_line_removals: Vec<usize>,
+    /// 1-indexed line numbers, in the formatted content, of blank lines that were inserted.
+    pub blank_line_insertions: Vec<usize>,
+    /// How many edits each rule made.
+    pub edits: EditCounts,
+    /// Warnings from the enabled checks, such as the characters removed by
+    /// [`FormatterConfig::remove_zero_width_chars`] and the fences reported by
+    /// [`FormatterConfig::require_fence_language`], ordered by their line numbers in
+    /// the original content.
+    pub warnings: Vec<LintWarning>,
+}
+
+impl FormatResult<'_> {
+    /// Whether formatting changed the content.
+    pub fn is_modified(&self) -> bool {
+        matches!(
+            self.outcome,
+            FileOutcome::Modified | FileOutcome::Normalized
+        )
+    }
+
+    /// Whether the file would be deleted or moved to the trash for being empty.
+    pub fn would_delete(&self) -> bool {
+        matches!(self.outcome, FileOutcome::Deleted | FileOutcome::Trashed)
+    }
+}
+
+/// Format content with the full file pipeline, without touching the filesystem.
+///
+/// This makes the same decisions as [`process_md_file_with_options`]: empty and
+/// frontmatter-only documents are deleted or kept according to
+/// [`FormatOptions::delete`], whitespace-only documents may be normalized,
+/// frontmatter-only documents may be included, and everything else is formatted
+/// with [`FormatOptions::formatter`].
+/// [`FormatOptions::max_size`] is ignored.
+///
+/// The line numbers of every blank line change are reported as well, for editor
+/// integrations that decorate changed lines.
+///
+/// # Arguments
+///
+/// * `content` - The markdown document to format
+/// * `opts` - File-level and formatting options
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::{
+///     ContentKind, DeleteStrategy, FileOutcome, FormatOptions, format_content,
+/// };
+///
+/// let result = format_content("Text\n# Heading\n\n\nMore", &FormatOptions::default());
+/// assert_eq!(result.content, "Text\n\n# Heading\n\nMore");
+/// assert_eq!(result.outcome, FileOutcome::Modified);
+/// assert_eq!(result.blank_line_insertions, vec![2]);
+/// assert_eq!(result.blank_line_removals, vec![4]);
+///
+/// let opts = FormatOptions {
+///     delete: DeleteStrategy::Remove,
+///     ..FormatOptions::default()
+/// };
+/// let result = format_content("---\ntitle: Draft\n---\n\n", &opts);
+/// assert_eq!(result.kind, ContentKind::FrontmatterOnly);
+/// assert!(result.would_delete());
+/// ```
+pub fn format_content<'a>(content: &'a str, opts: &FormatOptions) -> FormatResult<'a> {
+    let kind = ContentKind::with_config(content, &opts.formatter);
+    let mut result = FormatResult {
+        content: Cow::Borrowed(content),
+        kind,
+        outcome: FileOutcome::Unchanged,
+        blank_line_removals: Vec::new(),
+        blank_line_insertions: Vec::new(),
+        edits: EditCounts::default(),
+        warnings: Vec::new(),
+    };
+
+    if !kind.is_formatted(opts.include_empty) {
+        // Whitespace-only files can be truncated instead, so they stay as placeholders
+        if kind == ContentKind::Blank && opts.normalize_empty {
+            result.content = Cow::Borrowed("");
+            result.outcome = FileOutcome::Normalized;
+        } else if let Some(outcome) = opts.delete.outcome() {
+            result.outcome = outcome;
+        }
+        return result;
+    }
+
+    if opts.formatter.remove_zero_width_chars {
+        result.warnings = lint_zero_width_characters(content);
+    }
+    if opts.formatter.require_fence_language && opts.formatter.default_fence_language.is_none() {
+        result.warnings.extend(lint_missing_fence_language(content));
+        result.warnings.sort_by_key(|warning| warning.line);
+    }
+    if log_enabled!(Level::Trace) {
+        trace_protected_regions(content);
+    }
+    let rewritten = apply_content_rules(content, &opts.formatter);
+    let lines_rewritten = match &rewritten {
+        Cow::Borrowed(_) => 0,
+        Cow::Owned(rewritten) => content
+            .lines()
+            .zip(rewritten.lines())
+            .filter(|(before, after)| before != after)
+            .count(),
+    };
+    let mut edits = Vec::new();
+    let mut counter = EditCounter::default();
+    result.content = format_observed(
+        rewritten,
+        &opts.formatter,
+        opts.formatter.line_ending.resolve(content),
+        &mut (&mut edits, &mut counter),
+    );
+    result.edits = EditCounts {
+        lines_rewritten,
+        line_endings_normalized: opts.formatter.line_ending.differs(content),
+        final_newline_fixed: opts
+            .formatter
+            .insert_final_newline
+            .is_some_and(|insert| insert != content.ends_with('\n')),
+        ..counter.counts
+    };
+    if opts.ignore_final_newline_only
+        && result.content.trim_end_matches(['\n', '\r']) == content.trim_end_matches(['\n', '\r'])
+    {
+        debug!("only the final newline would change, keeping the content");
+        result.content = Cow::Borrowed(content);
+        result.edits = EditCounts::default();
+        return result;
+    }
+    if let Cow::Owned(_) = result.content {
+        result.outcome = FileOutcome::Modified;
+    }
+    edits.sort_by_key(LineEdit::line);
+
+    // Content rules rewrite within lines, so line numbers still refer to `content`
+    let mut output_line = 0;
+    let mut pending = edits.iter().peekable();
+    for i in 0..content.lines().count() {
+        let mut removed = false;
+        while let Some(edit) = pending.next_if(|edit| edit.line() == i) {
+            match edit {
+                LineEdit::InsertBlankBefore(_) => {
+                    output_line += 1;
+                    result.blank_line_insertions.push(output_line);
+                }
+                LineEdit::Remove(_) => {
+                    result.blank_line_removals.push(i + 1);
+                    removed = true;
+                }
+            }
+        }
+        if !removed {
+            output_line += 1;
+        }
+    }
+    result
+}
+
+/// Format only the parts of `content` that touch the given line ranges.
+///
+/// Ranges are 1-based and inclusive, as found in unified diff hunks. Blank line
+/// edits are applied only when the affected line is inside, or directly adjacent
+/// to, one of the ranges; every other line is emitted verbatim.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::format_line_ranges;
+///
+/// let input = "A\n\n\n\nB\n# Heading\nC";
+/// // Only line 6 (the heading) was changed, so the blank run above is left alone
+/// assert_eq!(format_line_ranges(input, &[6..=6]), "A\n\n\n\nB\n\n# Heading\n\nC");
+/// ```
+pub fn format_line_ranges(content: &str, ranges: &[RangeInclusive<usize>]) -> String {
+    format_line_ranges_with_config(content, ranges, &FormatterConfig::default())
+}
+
+/// Format only the parts of `content` that touch the given line ranges, with the
+/// content rules and blank line options of `config`.
+///
+/// Like [`format_line_ranges`], blank line edits are applied when the affected
+/// line is inside, or directly adjacent to, one of the ranges. The content rules
+/// only rewrite the lines inside the ranges, as in [`format_range`], and the whole
+/// document gets the line endings of [`FormatterConfig::line_ending`].
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::{FormatterConfig, format_line_ranges_with_config};
+///
+/// let config = FormatterConfig {
+///     max_consecutive_blank_lines: 2,
+///     fix_bare_urls: true,
+///     ..FormatterConfig::default()
+/// };
+/// let input = "https://a.com\n\n\n\nhttps://b.com\n";
+/// assert_eq!(
+///     format_line_ranges_with_config(input, &[4..=5], &config),
+///     "https://a.com\n\n\n<https://b.com>\n"
+/// );
+/// ```
+pub fn format_line_ranges_with_config(
+    content: &str,
+    ranges: &[RangeInclusive<usize>],
+    config: &FormatterConfig,
+) -> String {
+    let touches = |line: usize| ranges.iter().any(|range| range.contains(&line));
+    format_ranges(content, ranges, config, |edit| match *edit {
+        // 0-based input line `i` is 1-based line `i + 1`; also accept its neighbours
+        LineEdit::Remove(i) => touches(i) || touches(i + 1) || touches(i + 2),
+        // An insertion sits between 1-based lines `i` and `i + 1`
+        LineEdit::InsertBlankBefore(i) => touches(i) || touches(i + 1),
+    })
+}
+
+/// Format only lines `start` to `end` of `content` (1-based, inclusive), e.g. the
+/// selection of an editor's range formatting request.
+///
+/// The whole document is parsed, so a range that starts inside a code fence or the
+/// frontmatter is still treated as such. The lines in the range get the content
+/// rules and blank line edits of `config`, blank lines may be inserted directly
+/// before or after the range, and every other line is emitted verbatim. Fence
+/// markers are not rewritten for [`FormatterConfig::fence_style`] when the range
+/// starts or ends inside a code fence, since both ends of a fence need the same
+/// marker. The whole document gets the line endings of
+/// [`FormatterConfig::line_ending`].
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::{FormatterConfig, format_range};
+///
+/// let input = "A\n\n\n\nB\n# Heading\nC\n```\n\n\n```\n";
+/// let config = FormatterConfig::default();
+/// // The blank run above the heading is left alone
+/// assert_eq!(
+///     format_range(input, 6, 6, &config),
+///     "A\n\n\n\nB\n\n# Heading\n\nC\n```\n\n\n```\n"
+/// );
+/// // Lines 9 and 10 are known to be inside the fence opened on line 8
+/// assert_eq!(
+///     format_range(input, 9, 10, &config),
+///     "A\n\n\n\nB\n# Heading\nC\n```\n```\n"
+/// );
+/// ```
+pub fn format_range(content: &str, start: usize, end: usize, config: &FormatterConfig) -> String {
+    let range = start..=end;
+    format_ranges(
+        content,
+        std::slice::from_ref(&range),
+        config,
+        |edit| match *edit {
+            LineEdit::Remove(i) => range.contains(&(i + 1)),
+            // An insertion sits between 1-based lines `i` and `i + 1`
+            LineEdit::InsertBlankBefore(i) => range.contains(&i) || range.contains(&(i + 1)),
+        },
+    )
+}
+
+/// Apply the content rules of `config` to the lines of `content` in `ranges`
+/// (1-based, inclusive) and the blank line edits that `keep` accepts.
+fn format_ranges(
+    content: &str,
+    ranges: &[RangeInclusive<usize>],
+    config: &FormatterConfig,
+    keep: impl Fn(&LineEdit) -> bool,
+) -> String {
+    let regions = line_regions(&content.lines().collect::<Vec<_>>());
+    let inside_fence = |index: usize| regions.get(index) == Some(&LineRegion::CodeFence);
+    let kept_markers;
+    let config = if ranges
+        .iter()
+        .any(|range| inside_fence(range.start().saturating_sub(1)) || inside_fence(*range.end()))
+    {
+        kept_markers = FormatterConfig {
+            fence_style: FenceStyle::Keep,
+            ..config.clone()
+        };
+        &kept_markers
+    } else {
+        config
+    };
+
+    // Lines are taken from the rewritten content by number, which only works for
+    // rules that rewrite lines in place; rules that add or remove lines, such as
+    // the table of contents, would shift every line after them
+    let rewritten = Some(apply_content_rules(content, config))
+        .filter(|rewritten| rewritten.lines().count() == content.lines().count())
+        .unwrap_or(Cow::Borrowed(content));
+    let mut partial = content
+        .lines()
+        .zip(rewritten.lines())
+        .enumerate()
+        .map(|(i, (original, formatted))| {
+            if ranges.iter().any(|range| range.contains(&(i + 1))) {
+                formatted
+            } else {
+                original
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if content.ends_with('\n') {
+        partial.push('\n');
+    }
+
+    let edits: Vec<LineEdit> = blank_line_edits_with_config(&rewritten, config)
+        .into_iter()
+        .filter(|edit| keep(edit))
+        .collect();
+    let formatted = apply_line_edits(&partial, &edits);
+    match config.line_ending.resolve(content) {
+        LineEnding::Crlf => formatted.replace('\n', "\r\n"),
+        LineEnding::Lf | LineEnding::Keep => formatted,
+    }
+}
+
+/// The result of [`format_range_edit`]: formatted text that replaces a span of
+/// lines of the original content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeFormatResult {
+    /// The 0-based, half-open span of lines of the original content that
+    /// `replacement` replaces, including their line breaks.
+    pub lines: Range<usize>,
+    /// The formatted text of those lines, which may have blank lines added or
+    /// removed.
+    pub replacement: String,
+}
+
+/// Format the lines `range` of `content` (0-based, half-open), e.g. the selection
+/// of an editor's "format selection" command, and return only the text that
+/// changes.
+///
+/// The range is widened to whole blocks, so it never starts or ends inside the
+/// frontmatter, a code fence, a math block, or a table. The whole document is
+/// still scanned, so a range starting inside a code fence is known to be inside
+/// it. The widened lines are then formatted like [`format_range`] formats them,
+/// with the content rules of [`FormatOptions::formatter`]; the rest of the
+/// document is left alone, and empty documents are never deleted. An empty range
+/// formats nothing.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content of the whole document
+/// * `range` - The lines to format, as 0-based line indices
+/// * `opts` - The options to format with
+///
+/// # Returns
+///
+/// The span of lines to replace, which contains `range` (cut off at the end of the
+/// document), and the formatted text to replace them with.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::process_md::{FormatOptions, format_range_edit};
+///
+/// let input = "Intro\n\n\n\nText\n```sh\n\nls\n```\nMore\n";
+/// // Line 6 is inside the fence, which is formatted as a whole
+/// let result = format_range_edit(input, 6..7, &FormatOptions::default());
+/// assert_eq!(result.lines, 5..9);
+/// assert_eq!(result.replacement, "\n```sh\nls\n```\n\n");
+/// ```
+pub fn format_range_edit(
+    content: &str,
+    range: Range<usize>,
+    opts: &FormatOptions,
+) -> RangeFormatResult {
+    let lines: Vec<&str> = content.lines().collect();
+    let end = range.end.min(lines.len());
+    let mut start = range.start.min(end);
+    if start == end {
+        return RangeFormatResult {
+            lines: start..end,
+            replacement: String::new(),
+        };
+    }
+
+    let regions = line_regions(&lines);
+    let same_block = |i: usize| match (regions[i], regions[i + 1]) {
+        (LineRegion::Frontmatter, LineRegion::Frontmatter)
+        | (LineRegion::MathBlock, LineRegion::MathBlock)
+        | (LineRegion::CodeFenceOpening | LineRegion::CodeFence, LineRegion::CodeFence) => true,
+        (LineRegion::Text, LineRegion::Text) => {
+            lines[i].trim_start().starts_with('|') && lines[i + 1].trim_start().starts_with('|')
+        }
+        _ => false,
+    };
+    while start > 0 && same_block(start - 1) {
+        start -= 1;
+    }
+    let mut end = end;
+    while end < lines.len() && same_block(end - 1) {
+        end += 1;
+    }
+
+    // Only the widened lines and the blank lines next to them change, so the lines
+    // before and after them are the same in the output, apart from line endings
+    let formatted = format_range(content, start + 1, end, &opts.formatter);
+    let output: Vec<&str> = formatted.split_inclusive('\n').collect();
+    let output_end = output.len() - (lines.len() - end);
+    // Except at the end of a document without a final newline, where removing the
+    // blank lines at the end also removes the line break before them
+    let original: Vec<&str> = content.split_inclusive('\n').collect();
+    let ends_line = |segment: &str| segment.ends_with('\n');
+    let start = (0..start.min(output_end))
+        .find(|&i| {
+            lines[i] != output[i].trim_end_matches(['\r', '\n'])
+                || ends_line(original[i]) != ends_line(output[i])
+        })
+        .unwrap_or(start.min(output_end));
+    let replacement = output[start..output_end].concat();
+    RangeFormatResult {
+        lines: start..end,
+        replacement,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder;
+
+    #[test]
+    fn test_process_md_files() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let messy = temp.path().join("messy.md");
+        let clean = temp.path().join("clean.md");
+        let missing = temp.path().join("missing.md");
+        fs::write(&messy, "# Title\nText\n\n\n").unwrap();
+        fs::write(&clean, "# Title\n").unwrap();
+
+        let paths = vec![messy.clone(), missing.clone(), clean.clone()];
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let results = pool.install(|| process_md_files(&paths, &FormatOptions::default()));
+        let outcomes: Vec<_> = results
+            .iter()
+            .map(|(path, result)| (path, result.as_ref().map(|changes| changes.outcome).ok()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                (&messy, Some(FileOutcome::Modified)),
+                (&missing, None),
+                (&clean, Some(FileOutcome::Unchanged)),
+            ]
+        );
+        assert_eq!(fs::read_to_string(&messy).unwrap(), "# Title\n\nText\n");
+    }
+
+    #[test]
+    fn test_format_to_writer_leaves_file_untouched() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let path = temp.path().join("doc.md");
+        fs::write(&path, "# Title\nText\n\n\n").unwrap();
+
+        let mut output = Vec::new();
+        let changed = format_to_writer(&path, &mut output, &FormatterConfig::default()).unwrap();
+        assert!(changed);
+        assert_eq!(output, b"# Title\n\nText\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Title\nText\n\n\n");
+
+        // Unchanged content is still written out in full
+        fs::write(&path, "# Title\n").unwrap();
+        let mut output = Vec::new();
+        let changed = format_to_writer(&path, &mut output, &FormatterConfig::default()).unwrap();
+        assert!(!changed);
+        assert_eq!(output, b"# Title\n");
+    }
+
+    #[cfg(feature = "preserve-mtime")]
+    #[test]
+    fn test_preserve_mtime() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let path = temp.path().join("doc.md");
+        let past = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+        let options = FormatOptions {
+            preserve_mtime: true,
+            ..FormatOptions::default()
+        };
+
+        fs::write(&path, "# Title\nText\n").unwrap();
+        filetime::set_file_mtime(&path, past).unwrap();
+        let changes = process_md_file_with_options(&path, &options, None).unwrap();
+        assert_eq!(changes.outcome, FileOutcome::Modified);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Title\n\nText\n");
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(
+            filetime::FileTime::from_last_modification_time(&metadata),
+            past
+        );
+
+        // Without the option, rewriting the file moves its modification time
+        fs::write(&path, "# Title\nText\n").unwrap();
+        filetime::set_file_mtime(&path, past).unwrap();
+        process_md_file_with_options(&path, &FormatOptions::default(), None).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert_ne!(
+            filetime::FileTime::from_last_modification_time(&metadata),
+            past
+        );
+    }
+
+    #[test]
+    fn test_process_md_file_to() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let dir = temp.path();
+        let out = dir.join("out/nested");
+        let options = FormatOptions::default();
+        fs::write(dir.join("a.md"), "# Title\nText\n").unwrap();
+        fs::write(dir.join("clean.md"), "Clean\n").unwrap();
+        fs::write(dir.join("empty.md"), "").unwrap();
+
+        let changes = process_md_file_to(dir.join("a.md"), out.join("a.md"), &options, None);
+        assert_eq!(changes.unwrap().outcome, FileOutcome::Modified);
+        assert_eq!(
+            fs::read_to_string(dir.join("a.md")).unwrap(),
+            "# Title\nText\n"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("a.md")).unwrap(),
+            "# Title\n\nText\n"
+        );
+        // Clean files are copied, and empty files to delete are neither written nor deleted
+        let changes =
+            process_md_file_to(dir.join("clean.md"), out.join("clean.md"), &options, None);
+        assert_eq!(changes.unwrap().outcome, FileOutcome::Unchanged);
+        assert_eq!(fs::read_to_string(out.join("clean.md")).unwrap(), "Clean\n");
+        let options = FormatOptions {
+            delete: DeleteStrategy::Remove,
+            ..FormatOptions::default()
+        };
+        let changes =
+            process_md_file_to(dir.join("empty.md"), out.join("empty.md"), &options, None);
+        assert_eq!(changes.unwrap().outcome, FileOutcome::Deleted);
+        assert!(dir.join("empty.md").exists());
+        assert!(!out.join("empty.md").exists());
+
+        // Files over the size limit are copied as they are
+        let options = FormatOptions {
+            max_size: Some(4),
+            ..FormatOptions::default()
+        };
+        let changes = process_md_file_to(dir.join("a.md"), out.join("large.md"), &options, None);
+        assert_eq!(changes.unwrap().outcome, FileOutcome::TooLarge);
+        assert_eq!(
+            fs::read_to_string(out.join("large.md")).unwrap(),
+            "# Title\nText\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_empty_truncates_whitespace_only_files() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
+        let blank = temp_dir.join("blank.md");
+        let empty = temp_dir.join("empty.md");
+        let stub = temp_dir.join("stub.md");
+        fs::write(&blank, "\n\n  \n").unwrap();
+        fs::write(&empty, "").unwrap();
+        fs::write(&stub, "---\ntitle: Stub\n---\n\n").unwrap();
+
+        // Normalizing takes precedence over deleting whitespace-only files
+        let options = FormatOptions {
+            delete: DeleteStrategy::Remove,
+            normalize_empty: true,
+            ..FormatOptions::default()
+        };
+        let outcome = process_md_file_with_options(&blank, &options, None)
+            .unwrap()
+            .outcome;
+        assert_eq!(outcome, FileOutcome::Normalized);
+        assert_eq!(fs::read_to_string(&blank).unwrap(), "");
+
+        // A second run finds a zero-byte file, which is deleted as before
+        let outcome = process_md_file_with_options(&blank, &options, None)
+            .unwrap()
+            .outcome;
+        assert_eq!(outcome, FileOutcome::Deleted);
+        let outcome = process_md_file_with_options(&stub, &options, None)
+            .unwrap()
+            .outcome;
+        assert_eq!(outcome, FileOutcome::Deleted);
+
+        let options = FormatOptions {
+            normalize_empty: true,
+            ..FormatOptions::default()
+        };
+        let outcome = process_md_file_with_options(&empty, &options, None)
+            .unwrap()
+            .outcome;
+        assert_eq!(outcome, FileOutcome::Unchanged);
+        assert!(empty.exists());
+    }
+
+    #[test]
+    fn test_file_changed_before_write_is_reformatted() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
+        let path = temp_dir.join("doc.md");
+        fs::write(&path, "Stale\n\n\n\ncontent\n").unwrap();
+
+        // A generator rewrites the file between the first read and write
+        let mut calls = 0;
+        let outcome = run_md_file_with_hook(
+            &path,
+            &FormatOptions::default(),
+            Destination::InPlace,
+            None,
+            &mut |path| {
+                calls += 1;
+                if calls == 1 {
+                    fs::write(path, "Fresh content\n\n\n\nfrom the generator\n").unwrap();
+                }
+            },
+        )
+        .unwrap()
+        .outcome;
+        assert_eq!(outcome, FileOutcome::Modified);
+        assert_eq!(calls, 2);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "Fresh content\n\nfrom the generator\n"
+        );
+    }
+
+    #[test]
+    fn test_file_that_keeps_changing_is_not_clobbered() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
+        let path = temp_dir.join("doc.md");
+        fs::write(&path, "Line 1\n\n\n\nLine 2\n").unwrap();
+
+        let options = FormatOptions {
+            delete: DeleteStrategy::Remove,
+            ..FormatOptions::default()
+        };
+        let mut generation = 0;
+        let err = run_md_file_with_hook(&path, &options, Destination::InPlace, None, &mut |path| {
+            generation += 1;
+            fs::write(path, format!("Generation {}\n\n\n", generation)).unwrap();
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("changed on disk"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Generation 2\n\n\n");
+
+        // An empty file that gains content is not deleted, but formatted on the retry
+        fs::write(&path, "\n").unwrap();
+        let outcome =
+            run_md_file_with_hook(&path, &options, Destination::InPlace, None, &mut |path| {
+                fs::write(path, "# Real content\n").unwrap();
+            })
+            .unwrap()
+            .outcome;
+        assert_eq!(outcome, FileOutcome::Unchanged);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Real content\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_locked_file_is_skipped_after_timeout() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let path = temp.path().join("doc.md");
+        fs::write(&path, "Line 1\n\n\n\nLine 2\n").unwrap();
+
+        // Another run holding the file
+        let other = File::open(&path).unwrap();
+        assert!(try_lock_exclusive(&other).unwrap());
+        let options = FormatOptions {
+            lock_timeout_ms: 50,
+            ..FormatOptions::default()
+        };
+        let err = process_md_file_with_options(&path, &options, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 1\n\n\n\nLine 2\n");
+        // Checking does not need the lock
+        assert_eq!(
+            check_md_file(&path, DeleteStrategy::Keep).unwrap(),
+            (false, true)
+        );
+
+        // Once it is released, the file is formatted through the locked handle
+        drop(other);
+        let outcome = process_md_file_with_options(&path, &options, None)
+            .unwrap()
+            .outcome;
+        assert_eq!(outcome, FileOutcome::Modified);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 1\n\nLine 2\n");
+    }
+
+    #[test]
+    fn test_max_size_skips_file_without_changes() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let temp_dir = temp.path();
+        let path = temp_dir.join("large.md");
+        fs::write(&path, "Line 1\n\n\n\nLine 2\n").unwrap();
+
+        let mut options = FormatOptions {
+            max_size: Some(8),
+            ..FormatOptions::default()
+        };
+        let outcome = process_md_file_with_options(&path, &options, None)
+            .unwrap()
+            .outcome;
+        assert_eq!(outcome, FileOutcome::TooLarge);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 1\n\n\n\nLine 2\n");
+
+        options.max_size = Some(1024);
+        let outcome = process_md_file_with_options(&path, &options, None)
+            .unwrap()
+            .outcome;
+        assert_eq!(outcome, FileOutcome::Modified);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Line 1\n\nLine 2\n");
+    }
+
+    #[test]
+    fn test_remove_multiple_blank_lines() {
+        let input = "Line 1\n\n\n\nLine 2\n\nLine 3\n\n\n";
+        let expected = "Line 1\n\nLine 2\n\nLine 3\n";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_remove_multiple_blank_lines_no_change() {
+        let input = "Line 1\nLine 2\n\nLine 3";
+        assert_eq!(remove_multiple_blank_lines(input), input);
+    }
+
+    #[test]
+    fn test_unchanged_content_is_borrowed() {
+        assert!(matches!(
+            remove_multiple_blank_lines("# Title\n\nText\n"),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(
+            remove_multiple_blank_lines("Text\n\n\n"),
+            Cow::Owned(_)
+        ));
+        // CRLF line endings are kept, but mixed ones are not
+        assert!(matches!(
+            remove_multiple_blank_lines("a\r\nb\r\n"),
+            Cow::Borrowed(_)
+        ));
+        assert!(is_formatted("", &FormatOptions::default()));
+        assert!(is_formatted("a\r\n", &FormatOptions::default()));
+        assert!(!is_formatted("a\r\nb\n", &FormatOptions::default()));
+        // Enabled rules that find nothing to fix leave the content borrowed too
+        let config = FormatterConfig {
+            fix_bare_urls: true,
+            fix_missing_alt_text: true,
+            ..FormatterConfig::default()
+        };
+        assert!(matches!(
+            format_with_config("![Logo](logo.png) <https://a.example>\n", &config),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(
+            format_with_config("![](logo.png)\n", &config),
+            "![logo](logo.png)\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_multiple_blank_lines_preserve_ending() {
+        let input_no_newline = "Line 1\n\n\nLine 2";
+        let expected_no_newline = "Line 1\n\nLine 2";
+        assert_eq!(
+            remove_multiple_blank_lines(input_no_newline),
+            expected_no_newline
+        );
+    }
+
+    #[test]
+    fn test_preserve_frontmatter() {
+        let input =
+            "---\ntitle: Test\n\n\n\nauthor: Me\n---\n\n\n\nContent here\n\n\n\nMore content";
+        let expected = "---\ntitle: Test\n\n\n\nauthor: Me\n---\n\nContent here\n\nMore content";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_multi_byte_frontmatter() {
+        // Multi-byte characters right at the delimiters must not split a character
+        let options = FormatOptions {
+            formatter: FormatterConfig {
+                sort_frontmatter_keys: Some(Vec::new()),
+                remove_zero_width_chars: true,
+                trim_trailing_whitespace: true,
+                ..FormatterConfig::default()
+            },
+            ..FormatOptions::default()
+        };
+        let input = "---\r\ntitre: « Été »\u{200b}\r\nauteur: 日本\r\n---\u{a0}\r\n# Ünïcode\r\n";
+        let result = format_content(input, &options);
+        assert_eq!(result.kind, ContentKind::Document);
+        assert_eq!(
+            result.content,
+            "---\r\nauteur: 日本\r\ntitre: « Été »\r\n---\u{a0}\r\n\r\n# Ünïcode\r\n"
+        );
+        let input = "---\nclé: ✓\n---\n\u{3000}\n";
+        assert_eq!(
+            format_content(input, &options).kind,
+            ContentKind::FrontmatterOnly
+        );
+    }
+
+    #[test]
+    fn test_one_blank_line_after_frontmatter() {
+        let configs = [
+            FormatterConfig::default(),
+            FormatterConfig {
+                max_consecutive_blank_lines: 3,
+                blank_lines_before_heading: Some(HeadingBlankLines::all(2)),
+                ..FormatterConfig::default()
+            },
+            FormatterConfig {
+                blank_lines_before_heading: Some(HeadingBlankLines::all(0)),
+                ..FormatterConfig::default()
+            },
+        ];
+        for config in &configs {
+            for body in ["# Heading\n", "- item\n", "```\ncode\n```\n"] {
+                let expected = format!("---\ntitle: x\n---\n\n{}", body);
+                for blanks in [0, 1, 3] {
+                    let input = format!("---\ntitle: x\n---\n{}{}", "\n".repeat(blanks), body);
+                    assert_eq!(
+                        format_with_config(&input, config),
+                        expected,
+                        "{} blank lines before {:?}",
+                        blanks,
+                        body
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_blank_lines_at_document_edges() {
+        let configs = [
+            FormatterConfig::default(),
+            FormatterConfig {
+                max_consecutive_blank_lines: 3,
+                blank_lines_before_heading: Some(HeadingBlankLines::all(1)),
+                blank_lines_after_heading: Some(HeadingBlankLines::all(2)),
+                separate_tables_and_html: true,
+                ..FormatterConfig::default()
+            },
+        ];
+        let cases = [
+            ("# Title", "# Title"),
+            ("- item", "- item"),
+            ("```\ncode\n```", "```\ncode\n```"),
+            ("Text\n# Title", "Text\n\n# Title"),
+        ];
+        for config in &configs {
+            for (input, expected) in cases {
+                for newline in ["", "\n"] {
+                    let input = format!("{}{}", input, newline);
+                    let expected = format!("{}{}", expected, newline);
+                    let once = format_with_config(&input, config);
+                    assert_eq!(once, expected, "{:?}", input);
+                    assert_eq!(format_with_config(&once, config), expected, "{:?}", input);
+                }
+
+                // The final newline is decided after the blank lines
+                for insert in [true, false] {
+                    let config = FormatterConfig {
+                        insert_final_newline: Some(insert),
+                        ..config.clone()
+                    };
+                    let expected = format!("{}{}", expected, if insert { "\n" } else { "" });
+                    for newline in ["", "\n", "\n\n\n"] {
+                        let input = format!("{}{}", input, newline);
+                        assert_eq!(format_with_config(&input, &config), expected);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_preserve_code_fences() {
+        let input = "Some text\n\n\n\n```rust\nfn main() {\n\n\n\n    println!(\"Hello\");\n}\n```\n\n\n\nMore text";
+        let expected = "Some text\n\n```rust\nfn main() {\n\n\n\n    println!(\"Hello\");\n}\n```\n\nMore text";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_preserve_tilde_code_fences() {
+        let input = "Some text\n\n\n\n~~~python\ndef hello():\n\n\n\n    print(\"Hello\")\n~~~\n\n\n\nMore text";
+        let expected =
+            "Some text\n\n~~~python\ndef hello():\n\n\n\n    print(\"Hello\")\n~~~\n\nMore text";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_frontmatter_and_code_fences_combined() {
+        let input = "---\ntitle: Test\n\n\n\nauthor: Me\n---\n\n\n\nSome text\n\n\n\n```rust\nfn test() {\n\n\n\n    // code\n}\n```\n\n\n\nEnd";
+        let expected = "---\ntitle: Test\n\n\n\nauthor: Me\n---\n\nSome text\n\n```rust\nfn test() {\n\n\n\n    // code\n}\n```\n\nEnd";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_nested_code_fences_not_supported() {
+        // This test documents current behavior - nested code fences are not supported
+        // The first closing fence will end the code block
+        let input = "```\ncode\n```inner\nmore code\n```\n\n\n\ntext";
+        // Breakdown:
+        // 1. ``` starts code fence
+        // 2. ```inner ends it (because it starts with ```)
+        // 3. "more code" is normal text (no blank line processing needed)
+        // 4. ``` starts a new code fence
+        // 5. "\n\n\n\ntext" is inside the new code fence - blank lines after fence start are removed
+        let expected = "```\ncode\n```inner\n\nmore code\n\n```\ntext";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_code_fence_no_blank_after_start_before_end() {
+        let input = "Text\n```\n\ncode line\n\n```\nText";
+        let expected = "Text\n\n```\ncode line\n```\n\nText";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_code_fence_removes_all_blanks_after_start_before_end() {
+        // Every blank line is removed in one pass, so formatting is idempotent
+        let input = "```\n\n\n\ncode line\n\n\n```\nText";
+        let expected = "```\ncode line\n```\n\nText";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+        assert!(is_formatted(expected, &FormatOptions::default()));
+    }
+
+    #[test]
+    fn test_unclosed_code_fence_keeps_trailing_blanks_in_one_pass() {
+        let input = "Text\n```\ncode\n\n\nmore\n\n\n";
+        let output = remove_multiple_blank_lines(input);
+        assert_eq!(output, "Text\n\n```\ncode\n\n\nmore\n");
+        assert!(is_formatted(&output, &FormatOptions::default()));
+    }
+
+    #[test]
+    fn test_code_fence_preserve_internal_blanks() {
+        let input = "Text\n```\nline1\n\n\nline2\n```\nText";
+        let expected = "Text\n\n```\nline1\n\n\nline2\n```\n\nText";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_heading_and_list_blank_lines() {
+        let input = "Text\n# Heading1\nText\n- item1\n- item2\nText\n1. item3\n2. item4\nText\n* item5\n* item6\nText\n+ item7\n+ item8\nText";
+        let expected = "Text\n\n# Heading1\n\nText\n\n- item1\n- item2\n\nText\n\n1. item3\n2. item4\n\nText\n\n* item5\n* item6\n\nText\n\n+ item7\n+ item8\n\nText";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_code_fence_blank_lines() {
+        let input = "Text\n```rust\nfn main() {}\n```\nText";
+        let expected = "Text\n\n```rust\nfn main() {}\n```\n\nText";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_no_extra_blank_lines_for_existing() {
+        let input = "Text\n\n# Heading\n\nText\n\n- item\n\nText";
+        let expected = "Text\n\n# Heading\n\nText\n\n- item\n\nText";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_consecutive_list_items() {
+        let input = "Text\n- A\n- B\n- C\nText";
+        let expected = "Text\n\n- A\n- B\n- C\n\nText";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_math_block_preserved_as_unit() {
+        let input = "Text\n$$\n\na^2 + b^2\n\n\n= c^2\n$$\nMore\n\n\n$$x$$\nEnd";
+        let expected = "Text\n\n$$\n\na^2 + b^2\n\n\n= c^2\n$$\n\nMore\n\n$$x$$\nEnd";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_tilde_math_blocks_option() {
+        let input = "Text\n~~~\n\nx\n\n~~~\nMore";
+        let config = FormatterConfig {
+            tilde_math_blocks: true,
+            ..FormatterConfig::default()
+        };
+        assert_eq!(
+            format_with_config(input, &config),
+            "Text\n\n~~~\n\nx\n\n~~~\n\nMore"
+        );
+        // Without the option, ~~~ is a code fence and loses its edge blank lines
+        assert_eq!(
+            remove_multiple_blank_lines(input),
+            "Text\n\n~~~\nx\n~~~\n\nMore"
+        );
+    }
+
+    #[test]
+    fn test_code_fence_language_aliases() {
+        let config = FormatterConfig {
+            code_fence_language_aliases: HashMap::from([
+                ("graph".to_string(), "mermaid".to_string()),
+                ("sequenceDiagram".to_string(), "mermaid".to_string()),
+            ]),
+            ..FormatterConfig::default()
+        };
+        let input =
+            "```Graph TD\ngraph\n```\n\n~~~ sequencediagram\r\nA->>B: hi\r\n~~~\n\n```rust\n```\n";
+        let expected =
+            "```mermaid TD\ngraph\n```\n\n~~~ mermaid\r\nA->>B: hi\r\n~~~\n\n```rust\n```\n";
+        assert_eq!(
+            rewrite_code_fence_languages(input, &config.code_fence_language_aliases, false, false),
+            expected
+        );
+        assert!(format_with_config(input, &config).starts_with("```mermaid TD\n"));
+    }
+
+    #[test]
+    fn test_normalize_fence_languages() {
+        let config = FormatterConfig {
+            code_fence_language_aliases: HashMap::from([("rs".to_string(), "rust".to_string())]),
+            normalize_fence_languages: true,
+            ..FormatterConfig::default()
+        };
+        let cases = [
+            ("```Rust\n```\n", "```rust\n```\n"),
+            ("```  rust  \r\n```\r\n", "```rust\r\n```\r\n"),
+            ("```RS\n```\n", "```rust\n```\n"),
+            (
+                "~~~ Python {linenos=TRUE}\n~~~\n",
+                "~~~python {linenos=TRUE}\n~~~\n",
+            ),
+            ("```Python{.Numbered}\n```\n", "```python{.Numbered}\n```\n"),
+            ("```{r echo=FALSE}\n```\n", "```{r echo=FALSE}\n```\n"),
+            ("```Unknown-Lang\n```\n", "```unknown-lang\n```\n"),
+            ("``` \n```\n", "```\n```\n"),
+        ];
+        for (input, expected) in cases {
+            let output = rewrite_code_fence_languages(
+                input,
+                &config.code_fence_language_aliases,
+                false,
+                true,
+            );
+            assert_eq!(output, expected, "{:?}", input);
+        }
+        assert_eq!(format_with_config("``` RS\n", &config), "```rust\n");
+    }
+
+    #[test]
+    fn test_builtin_fence_aliases() {
+        let config = FormatterConfig {
+            builtin_fence_aliases: true,
+            ..FormatterConfig::default()
+        };
+        // Fences shown inside longer fences are content, not fences
+        let input = "```rs\nfn main() {}\n```\n\n~~~ PY {linenos=true}\n~~~\n\n````sh\n```rs\n````\n\n```rust\n```\n";
+        let expected = "```rust\nfn main() {}\n```\n\n~~~ python {linenos=true}\n~~~\n\n````bash\n```rs\n````\n\n```rust\n```\n";
+        assert_eq!(format_with_config(input, &config), expected);
+
+        // Configured aliases win over the built-in ones
+        let config = FormatterConfig {
+            code_fence_language_aliases: HashMap::from([("SH".to_string(), "shell".to_string())]),
+            ..config
+        };
+        assert_eq!(
+            format_with_config("```sh\n```\n\n```ts\n```\n", &config),
+            "```shell\n```\n\n```typescript\n```\n"
+        );
+        // Normalization lowercases and trims whatever the aliases produce
+        let config = FormatterConfig {
+            code_fence_language_aliases: HashMap::from([("SH".to_string(), "Shell".to_string())]),
+            normalize_fence_languages: true,
+            ..config
+        };
+        assert_eq!(
+            format_with_config("``` RS \n```\n\n```sh\n```\n", &config),
+            "```rust\n```\n\n```shell\n```\n"
+        );
+        assert!(LANGUAGE_ALIAS_MAP.iter().all(|(alias, canonical)| {
+            detect_language_from_extension(canonical).is_none() && alias.to_lowercase() == *alias
+        }));
+    }
+
+    #[test]
+    fn test_remove_zero_width_characters() {
+        let input = "---\ntitle: Co\u{AD}op\n---\n\nA\u{200B}B\u{200C}\r\n```\nx\u{200D}y\n```\n";
+        let expected = "---\ntitle: Coop\n---\n\nAB\r\n```\nx\u{200D}y\n```\n";
+        assert_eq!(remove_zero_width_characters(input), expected);
+
+        let opts = FormatOptions {
+            formatter: FormatterConfig {
+                remove_zero_width_chars: true,
+                ..FormatterConfig::default()
+            },
+            ..FormatOptions::default()
+        };
+        let result = format_content(input, &opts);
+        assert_eq!(result.edits.lines_rewritten, 2);
+        let lines: Vec<usize> = result.warnings.iter().map(|warning| warning.line).collect();
+        assert_eq!(lines, vec![2, 5]);
+        // Disabled by default, since it is lossy
+        let result = format_content(input, &FormatOptions::default());
+        assert!(result.content.contains('\u{200B}'));
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_expand_leading_tabs() {
+        let input = "---\n\tkey: value\n---\n\n- a\n \t- b\r\n\t\t- c\n\n| x\t| y |\n\n\tcode\n```\n\tfenced\n```\n";
+        assert_eq!(
+            expand_leading_tabs(input, 4),
+            "---\n\tkey: value\n---\n\n- a\n    - b\r\n        - c\n\n| x\t| y |\n\n\tcode\n```\n\tfenced\n```\n"
+        );
+        assert_eq!(expand_leading_tabs("- a\n\t- b\n", 2), "- a\n  - b\n");
+
+        let opts = FormatOptions {
+            formatter: FormatterConfig {
+                tabs_to_spaces: Some(4),
+                ..FormatterConfig::default()
+            },
+            ..FormatOptions::default()
+        };
+        // Tabs that stay are not a modification
+        let result = format_content("Text\tmore\n\n\tcode\n", &opts);
+        assert_eq!(result.outcome, FileOutcome::Unchanged);
+        let result = format_content("- a\n\t- b\n", &opts);
+        assert_eq!(result.outcome, FileOutcome::Modified);
+        assert_eq!(result.content, "- a\n    - b\n");
+    }
+
+    #[test]
+    fn test_normalize_link_titles() {
+        let input = "[a](x 'It\\'s \"new\"') [b](<y z> (Say \\(hi\\)))\r\n`[c](x 'code')`\n```\n[d](x 'fence')\n```\n[e](x \"Already\")\n";
+        assert_eq!(
+            normalize_link_titles(input, LinkTitleStyle::DoubleQuote),
+            "[a](x \"It's \\\"new\\\"\") [b](<y z> \"Say (hi)\")\r\n`[c](x 'code')`\n```\n[d](x 'fence')\n```\n[e](x \"Already\")\n"
+        );
+        assert_eq!(
+            normalize_link_titles("[a](x \"It's (new)\")", LinkTitleStyle::SingleQuote),
+            "[a](x 'It\\'s (new)')"
+        );
+        assert_eq!(
+            normalize_link_titles("[a](x \"It's (new)\")", LinkTitleStyle::Parenthesis),
+            "[a](x (It's \\(new\\)))"
+        );
+        // Destinations without a title, and titles spanning lines, are left alone
+        let untitled = "[a](x) [b](x 'open\ntitle')";
+        assert_eq!(
+            normalize_link_titles(untitled, LinkTitleStyle::DoubleQuote),
+            untitled
+        );
+
+        for style in [
+            LinkTitleStyle::DoubleQuote,
+            LinkTitleStyle::SingleQuote,
+            LinkTitleStyle::Parenthesis,
+        ] {
+            let once = normalize_link_titles(input, style);
+            assert_eq!(normalize_link_titles(&once, style), once, "{:?}", style);
+        }
+    }
+
+    #[test]
+    fn test_format_table() {
+        let rows = [
+            "  Name|Align|x",
+            "  :-- | :-: |--:",
+            "  | README.md | c | 1",
+            "|a \\| b|",
+        ];
+        assert_eq!(
+            format_table(&rows),
+            "  | Name      | Align |   x |\n  | :-------- | :---: | --: |\n  | README.md |   c   |   1 |\n  | a \\| b    |       |     |"
+        );
+        // Already aligned tables stay as they are
+        let formatted = format_table(&rows);
+        let lines: Vec<&str> = formatted.lines().collect();
+        assert_eq!(format_table(&lines), formatted);
+        // Not a table: the separator row has the wrong number of cells
+        assert_eq!(format_table(&["a | b", "| --- |"]), "a | b\n| --- |");
+        assert_eq!(format_table(&["| a |"]), "| a |");
+
+        let input = "| a | b |\r\n|-|-|\r\n| long | x |\r\ntext\n\n```\n|a|b|\n|-|-|\n```\n";
+        assert_eq!(
+            format_tables(input),
+            "| a    | b   |\r\n| ---- | --- |\r\n| long | x   |\r\ntext\n\n```\n|a|b|\n|-|-|\n```\n"
+        );
+
+        let config = FormatterConfig {
+            format_tables: true,
+            ..FormatterConfig::default()
+        };
+        let input = "Sizes:\n|a|b|\n|-|-|\n|long|x|\n";
+        assert_eq!(
+            format_with_config(input, &config),
+            "Sizes:\n| a    | b   |\n| ---- | --- |\n| long | x   |\n"
+        );
+        assert_eq!(
+            format_with_config(input, &FormatterConfig::default()),
+            input
+        );
+    }
+
+    #[test]
+    fn test_fence_style() {
+        let input =
+            "~~~~ rust {.numbered}\r\nfn main() {}\r\n~~~~\r\n\n  ```\n  x\n  ```\n\n~~~\nopen";
+        assert_eq!(
+            rewrite_fence_markers(input, b'`'),
+            "```` rust {.numbered}\r\nfn main() {}\r\n````\r\n\n  ```\n  x\n  ```\n\n```\nopen"
+        );
+        assert_eq!(
+            rewrite_fence_markers(input, b'~'),
+            "~~~~ rust {.numbered}\r\nfn main() {}\r\n~~~~\r\n\n  ~~~\n  x\n  ~~~\n\n~~~\nopen"
+        );
+
+        let config = FormatterConfig {
+            fence_style: FenceStyle::Backtick,
+            ..FormatterConfig::default()
+        };
+        assert_eq!(format_with_config("~~~\n\nx\n~~~", &config), "```\nx\n```");
+        let math = FormatterConfig {
+            tilde_math_blocks: true,
+            ..config
+        };
+        assert_eq!(format_with_config("~~~\nx\n~~~", &math), "~~~\nx\n~~~");
+    }
+
+    #[test]
+    fn test_default_fence_language() {
+        let input = "```\nls\n```\n\n~~~  \r\nx\r\n~~~\r\n\n````markdown\n```\nls\n```\n````\n\n```sh\n```\n";
+        let expected = "```text\nls\n```\n\n~~~text\r\nx\r\n~~~\r\n\n````markdown\n```\nls\n```\n````\n\n```sh\n```\n";
+        assert_eq!(add_default_fence_language(input, "text"), expected);
+    }
+
+    #[test]
+    fn test_require_fence_language_warnings() {
+        let mut opts = FormatOptions {
+            formatter: FormatterConfig {
+                require_fence_language: true,
+                ..FormatterConfig::default()
+            },
+            ..FormatOptions::default()
+        };
+        let input = "```\nls\n```\n";
+        let result = format_content(input, &opts);
+        assert_eq!(result.outcome, FileOutcome::Unchanged);
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.warnings[0].line, 1);
+
+        // Filling in a default language leaves nothing to report
+        opts.formatter.default_fence_language = Some("text".to_string());
+        let result = format_content(input, &opts);
+        assert_eq!(result.content, "```text\nls\n```\n");
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_longer_fences_contain_shorter_ones() {
+        let input = "````markdown\n```\n\n\ncode\n```\n````\nText\n";
+        assert_eq!(
+            remove_multiple_blank_lines(input),
+            "````markdown\n```\n\n\ncode\n```\n````\n\nText\n"
+        );
+        // A longer run of the new marker is needed to close the converted fence
+        let input = "~~~~\n```\nx\n```\n~~~~\n";
+        assert_eq!(
+            rewrite_fence_markers(input, b'`'),
+            "````\n```\nx\n```\n````\n"
+        );
+    }
+
+    #[test]
+    fn test_fence_style_keeps_fences_that_would_change() {
+        // A ``` line inside the tilde fence would close a backtick fence early
+        let input = "~~~markdown\n```rust\nfn main() {}\n```\n~~~\n";
+        assert_eq!(rewrite_fence_markers(input, b'`'), input);
+        let input = "```\n~~~\n```\n";
+        assert_eq!(rewrite_fence_markers(input, b'~'), input);
+        // Backtick fences cannot have backticks in their info string
+        let input = "~~~ `odd`\nx\n~~~\n";
+        assert_eq!(rewrite_fence_markers(input, b'`'), input);
+    }
+
+    #[test]
+    fn test_gfm_alert_block_kept_together() {
+        let input =
+            "# Title\n> [!NOTE]\n> Useful information.\n>\n> - Still part of the note\n- Item";
+        let expected =
+            "# Title\n\n> [!NOTE]\n> Useful information.\n>\n> - Still part of the note\n\n- Item";
+        assert_eq!(remove_multiple_blank_lines(input), expected);
+    }
+
+    #[test]
+    fn test_apply_all_edits_matches_formatter() {
+        let input = "---\ntitle: x\n---\n# H\ntext\n\n\n```\n\ncode\n```\n- a\n\n\n";
+        let edits = blank_line_edits(input);
+        assert_eq!(
+            apply_line_edits(input, &edits),
+            remove_multiple_blank_lines(input)
+        );
+    }
+
+    #[test]
+    fn test_apply_no_edits_is_verbatim() {
+        for input in ["a\n\n", "a\n\n \n", "\n\n", "```\nx\n\n\n"] {
+            assert_eq!(apply_line_edits(input, &[]), input);
         }
     }
-    // Process content to remove multiple consecutive blank lines
-    let processed_content = remove_multiple_blank_lines(&original_content);
 
-    // Check if content was modified
-    if processed_content != original_content {
-        fs::write(path, processed_content)?;
-        Ok((false, true))
-    } else {
-        Ok((false, false))
+    #[test]
+    fn test_format_line_ranges_outside_range_is_verbatim() {
+        let input = "A\n\n\n\nB\nC\n# Heading\nD\n\n\n";
+        assert_eq!(format_line_ranges(input, &[]), input);
+        assert_eq!(
+            format_line_ranges(input, &[2..=3]),
+            "A\n\nB\nC\n# Heading\nD\n\n\n"
+        );
+        assert_eq!(
+            format_line_ranges(input, &[7..=7]),
+            "A\n\n\n\nB\nC\n\n# Heading\n\nD\n\n\n"
+        );
     }
-}
 
-/// Remove multiple consecutive blank lines and ensure proper spacing around markdown elements.
-/// This function preserves frontmatter and code fence contents while adding blank lines
-/// around headings, code fences, and list markers.
-///
-/// # Arguments
-///
-/// * `content` - The content to process
-///
-/// # Returns
-///
-/// The processed content with multiple blank lines reduced to single blank lines
-/// and proper spacing around markdown elements.
-///
-/// # Examples
-///
-/// Basic usage:
-/// ```
-/// use mdfmt::process_md::remove_multiple_blank_lines;
-///
-/// let input = "Line 1\n\n\n\nLine 2\n\nLine 3";
-/// let output = remove_multiple_blank_lines(input);
-/// assert_eq!(output, "Line 1\n\nLine 2\n\nLine 3");
-/// ```
-///
-/// Preserving frontmatter:
-/// ```
-/// use mdfmt::process_md::remove_multiple_blank_lines;
-///
-/// let input = "---\ntitle: Test\n\n\nauthor: Me\n---\n\n\n\nContent";
-/// let output = remove_multiple_blank_lines(input);
-/// assert_eq!(output, "---\ntitle: Test\n\n\nauthor: Me\n---\n\nContent");
-/// ```
-///
-/// Adding spacing around headings:
-/// ```
-/// use mdfmt::process_md::remove_multiple_blank_lines;
-///
-/// let input = "Text\n# Heading\nMore text";
-/// let output = remove_multiple_blank_lines(input);
-/// assert_eq!(output, "Text\n\n# Heading\n\nMore text");
-/// ```
-pub fn remove_multiple_blank_lines(content: &str) -> String {
-    let lines: Vec<&str> = content.lines().collect();
-    let mut result = Vec::new();
-    let mut prev_was_empty = false;
-    let mut in_frontmatter = false;
-    let mut in_code_fence = false;
-    let mut code_fence_marker = "";
+    #[test]
+    fn test_format_line_ranges_with_config() {
+        let config = FormatterConfig {
+            fix_bare_urls: true,
+            line_ending: LineEnding::Crlf,
+            ..FormatterConfig::default()
+        };
+        let input = "https://a.com\n\n\nhttps://b.com\n# Title\n";
+        assert_eq!(
+            format_line_ranges_with_config(input, &[4..=4], &config),
+            "https://a.com\r\n\r\n<https://b.com>\r\n\r\n# Title\r\n"
+        );
+        // Rules that add lines would shift the ranges, so none are applied
+        let config = FormatterConfig {
+            wrap_blockquotes: Some(10),
+            ..config
+        };
+        let input = "https://a.com\n\n\n> one two three\n";
+        assert_eq!(
+            format_line_ranges_with_config(input, &[1..=4], &config),
+            "https://a.com\r\n\r\n> one two three\r\n"
+        );
+    }
 
-    // Helper functions for detecting markdown elements
-    let is_heading = |line: &str| {
-        let trimmed = line.trim();
-        trimmed.starts_with('#') && trimmed.chars().take_while(|c| *c == '#').count() <= 6
-    };
-    let is_list_marker = |line: &str| {
-        let trimmed = line.trim();
-        trimmed.starts_with("- ")
-            || trimmed.starts_with("* ")
-            || trimmed.starts_with("+ ")
-            || (trimmed
-                .chars()
-                .next()
-                .map(|c| c.is_ascii_digit())
-                .unwrap_or(false)
-                && trimmed.contains(". "))
-    };
+    #[test]
+    fn test_format_range() {
+        let config = FormatterConfig {
+            fix_bare_urls: true,
+            fence_style: FenceStyle::Tilde,
+            ..FormatterConfig::default()
+        };
+        let input = "https://a.com\n```\n\n\nhttps://b.com\n```\n# Title\nhttps://c.com\n";
+        assert_eq!(format_range(input, 1, 0, &config), input);
+        // Starts inside the fence, so its markers are kept and the URL is code
+        assert_eq!(
+            format_range(input, 3, 8, &config),
+            "https://a.com\n```\nhttps://b.com\n```\n\n# Title\n\n<https://c.com>\n"
+        );
+        assert_eq!(
+            format_range(input, 1, 6, &config),
+            "<https://a.com>\n\n~~~\nhttps://b.com\n~~~\n\n# Title\nhttps://c.com\n"
+        );
+        // Ranges past the end of the document are cut off
+        assert_eq!(
+            format_range("Text\n# Title", 2, 10, &config),
+            "Text\n\n# Title"
+        );
+    }
 
-    for (i, line) in lines.iter().enumerate() {
-        // Check for frontmatter start/end
-        if i == 0 && line.trim() == "---" {
-            in_frontmatter = true;
-            result.push(*line);
-            continue;
-        } else if in_frontmatter && line.trim() == "---" {
-            in_frontmatter = false;
-            result.push(*line);
-            // Add a blank line after frontmatter ends only if next line is not already blank
-            if lines.get(i + 1).is_some_and(|next| !next.trim().is_empty()) {
-                result.push("");
-            }
-            continue;
-        }
+    #[test]
+    fn test_format_range_edit() {
+        let opts = FormatOptions::default();
+        let input = "---\ntitle: A\n---\nText\n```\n\n\ncode\n```\n| a |\n| - |\n\n\n\nEnd";
+        let apply = |result: &RangeFormatResult| {
+            let lines: Vec<&str> = input.split_inclusive('\n').collect();
+            format!(
+                "{}{}{}",
+                lines[..result.lines.start].concat(),
+                result.replacement,
+                lines[result.lines.end..].concat()
+            )
+        };
 
-        // Check for code fence start/end
-        if !in_frontmatter {
-            let trimmed = line.trim();
-            if (trimmed.starts_with("```") || trimmed.starts_with("~~~")) && !in_code_fence {
-                // Insert blank line before code fence if previous line is not blank
-                if !result.is_empty() && result.last().is_some_and(|l| !l.trim().is_empty()) {
-                    result.push("");
-                }
-                // Starting a code fence
-                in_code_fence = true;
-                code_fence_marker = if trimmed.starts_with("```") {
-                    "```"
-                } else {
-                    "~~~"
-                };
-                result.push(*line);
-                prev_was_empty = false;
-                continue;
-            } else if in_code_fence
-                && (trimmed.starts_with(code_fence_marker)
-                    && trimmed.len() >= code_fence_marker.len())
-            {
-                // Ending a code fence - must start with the same marker
-                in_code_fence = false;
-                code_fence_marker = "";
-                result.push(*line);
-                // Insert blank line after code fence if next line is not blank
-                if lines.get(i + 1).is_some_and(|next| !next.trim().is_empty()) {
-                    result.push("");
-                }
-                prev_was_empty = false;
-                continue;
-            }
-        }
+        // Starts inside the fence, so the whole fence is formatted
+        let result = format_range_edit(input, 6..7, &opts);
+        assert_eq!(result.lines, 4..9);
+        assert_eq!(result.replacement, "\n```\ncode\n```\n\n");
+        assert_eq!(
+            apply(&result),
+            "---\ntitle: A\n---\nText\n\n```\ncode\n```\n\n| a |\n| - |\n\n\n\nEnd"
+        );
 
-        // If we're inside frontmatter or code fence, don't process blank lines
-        if in_frontmatter || in_code_fence {
-            // Special handling for code fence: remove blank lines immediately after opening or before closing
-            if in_code_fence {
-                let is_blank = line.trim().is_empty();
-
-                // Check if this is immediately after code fence start
-                let prev_line = if i > 0 { lines.get(i - 1) } else { None };
-                let prev_was_fence_start = prev_line
-                    .map(|l| {
-                        let trimmed = l.trim();
-                        (trimmed.starts_with("```") || trimmed.starts_with("~~~"))
-                            && !in_frontmatter
-                    })
-                    .unwrap_or(false);
-
-                // Check if next line is code fence end
-                let next_line = lines.get(i + 1);
-                let next_is_fence_end = next_line
-                    .map(|l| {
-                        let trimmed = l.trim();
-                        trimmed.starts_with(code_fence_marker)
-                            && trimmed.len() >= code_fence_marker.len()
-                    })
-                    .unwrap_or(false);
-
-                // Skip blank line if it's immediately after fence start or before fence end
-                if is_blank && (prev_was_fence_start || next_is_fence_end) {
-                    continue;
-                }
-            }
+        // Covers the opening fence and the text before it, and ends in the table;
+        // the blank line after the frontmatter goes before the range
+        let result = format_range_edit(input, 3..10, &opts);
+        assert_eq!(result.lines, 3..11);
+        assert_eq!(
+            result.replacement,
+            "\nText\n\n```\ncode\n```\n\n| a |\n| - |\n"
+        );
 
-            result.push(*line);
-            prev_was_empty = false;
-            continue;
-        }
+        // Starts in the frontmatter; the blank run at the end is collapsed
+        assert_eq!(format_range_edit(input, 1..2, &opts).lines, 0..3);
+        let result = format_range_edit(input, 12..100, &opts);
+        assert_eq!((result.lines, result.replacement.as_str()), (12..15, "End"));
 
-        // Insert blank line before heading or list group start if previous line is not blank
-        let is_list_group_start = is_list_marker(line)
-            && (i == 0 || !is_list_marker(lines.get(i.saturating_sub(1)).unwrap_or(&"")));
+        // Nothing to format
+        let result = format_range_edit(input, 20..30, &opts);
+        assert_eq!((result.lines, result.replacement.as_str()), (15..15, ""));
+        assert_eq!(format_range_edit("", 0..1, &opts).lines, 0..0);
+    }
 
-        if (is_heading(line) || is_list_group_start)
-            && !result.is_empty()
-            && result.last().is_some_and(|l| !l.trim().is_empty())
-        {
-            result.push("");
-        }
+    #[test]
+    fn test_format_content_reports_line_numbers() {
+        let input = "Text\n```\n\ncode\n```\nText\n\n\n\nEnd\n";
+        let result = format_content(input, &FormatOptions::default());
+        assert_eq!(result.content, remove_multiple_blank_lines(input));
+        assert_eq!(result.content, "Text\n\n```\ncode\n```\n\nText\n\nEnd\n");
+        assert_eq!(result.blank_line_removals, vec![3, 8, 9]);
+        assert_eq!(result.blank_line_insertions, vec![2, 6]);
+        assert!(result.is_modified());
+    }
 
-        // Normal blank line processing for content outside protected areas
-        let is_empty = line.trim().is_empty();
+    #[test]
+    fn test_format_content_unchanged() {
+        let result = format_content("Line 1\n\nLine 2\n", &FormatOptions::default());
+        assert!(!result.is_modified());
+        assert!(matches!(
+            result.content,
+            Cow::Borrowed("Line 1\n\nLine 2\n")
+        ));
+    }
 
-        if is_empty {
-            if !prev_was_empty {
-                result.push(*line);
-            }
-            prev_was_empty = true;
-        } else {
-            result.push(*line);
-            prev_was_empty = false;
+    #[test]
+    fn test_format_content_classifies_empty_documents() {
+        let remove = FormatOptions::deleting(DeleteStrategy::Remove);
+        let cases = [
+            ("", ContentKind::Empty),
+            ("\n  \n", ContentKind::Blank),
+            ("---\ntitle: Test\n---\n", ContentKind::FrontmatterOnly),
+            ("---\ntitle: Test\n---\n\n \n", ContentKind::FrontmatterOnly),
+            (
+                "---\r\ntitle: Test\r\n---\r\n",
+                ContentKind::FrontmatterOnly,
+            ),
+            ("---\ntitle: Test\n---", ContentKind::FrontmatterOnly),
+            ("---\ntitle: Test\n---\nBody\n", ContentKind::Document),
+            ("---\n---\n---\n", ContentKind::Document),
+            ("---\ntitle: Test\n", ContentKind::Document),
+        ];
+        for (input, kind) in cases {
+            let result = format_content(input, &remove);
+            assert_eq!(result.kind, kind, "{:?}", input);
+            assert_eq!(result.would_delete(), kind != ContentKind::Document);
         }
+    }
 
-        // Insert blank line after heading or list group end if next line is not blank
-        let is_list_group_end = is_list_marker(line)
-            && !lines
-                .get(i + 1)
-                .map(|next| is_list_marker(next))
-                .unwrap_or(false);
-
-        if (is_heading(line) || is_list_group_end)
-            && lines.get(i + 1).is_some_and(|next| !next.trim().is_empty())
-        {
-            result.push("");
-        }
+    #[test]
+    fn test_format_content_delete_strategy() {
+        let input = "---\ntitle: Test\n---\n";
+        let outcome = |delete| format_content(input, &FormatOptions::deleting(delete)).outcome;
+        assert_eq!(outcome(DeleteStrategy::Keep), FileOutcome::Unchanged);
+        assert_eq!(outcome(DeleteStrategy::Remove), FileOutcome::Deleted);
+        assert_eq!(outcome(DeleteStrategy::Trash), FileOutcome::Trashed);
     }
 
-    // Preserve the original ending (newline or not)
-    let result_content = result.join("\n");
-    if content.ends_with('\n') && !result_content.ends_with('\n') {
-        format!("{}\n", result_content)
-    } else if !content.ends_with('\n') && result_content.ends_with('\n') {
-        result_content.trim_end_matches('\n').to_string()
-    } else {
-        result_content
+    #[test]
+    fn test_format_content_include_empty() {
+        let opts = FormatOptions {
+            delete: DeleteStrategy::Remove,
+            include_empty: true,
+            ..FormatOptions::default()
+        };
+        let result = format_content("---\ntitle: Stub\n\n\n---\n\n\n", &opts);
+        assert_eq!(result.kind, ContentKind::FrontmatterOnly);
+        assert_eq!(result.outcome, FileOutcome::Modified);
+        assert_eq!(result.content, "---\ntitle: Stub\n\n\n---\n");
+
+        let result = format_content("---\ntitle: Stub\n---\n", &opts);
+        assert_eq!(result.outcome, FileOutcome::Unchanged);
+        // Files without frontmatter are still deleted
+        assert_eq!(format_content("\n", &opts).outcome, FileOutcome::Deleted);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_format_content_normalizes_only_whitespace() {
+        let opts = FormatOptions {
+            delete: DeleteStrategy::Remove,
+            normalize_empty: true,
+            ..FormatOptions::default()
+        };
+        let result = format_content(" \n\n", &opts);
+        assert_eq!(result.outcome, FileOutcome::Normalized);
+        assert_eq!(result.content, "");
+        assert!(result.is_modified());
+        // Zero-byte files are already normalized, so the delete strategy applies
+        assert_eq!(format_content("", &opts).outcome, FileOutcome::Deleted);
+    }
 
     #[test]
-    fn test_remove_multiple_blank_lines() {
-        let input = "Line 1\n\n\n\nLine 2\n\nLine 3\n\n\n";
-        let expected = "Line 1\n\nLine 2\n\nLine 3\n";
-        assert_eq!(remove_multiple_blank_lines(input), expected);
+    fn test_format_content_counts_edits_by_rule() {
+        let input =
+            "---\ntitle: Test\n---\nIntro\n\n\n\nText\n- A\n- B\nText\n```\n\ncode\n```\n\n\n";
+        let result = format_content(input, &FormatOptions::default());
+        assert_eq!(
+            result.edits,
+            EditCounts {
+                blank_runs_collapsed: 2,
+                fence_separators: 1,
+                list_separators: 2,
+                frontmatter_separators: 1,
+                fence_blank_lines_removed: 1,
+                trailing_blank_lines_removed: 1,
+                ..EditCounts::default()
+            }
+        );
+
+        let result = format_content("# Title\r\nText\r\n", &FormatOptions::default());
+        assert_eq!(result.edits.heading_separators, 1);
+        assert!(!result.edits.line_endings_normalized);
+
+        let result = format_content("# Title\r\n\r\nText\r\nMore\n", &FormatOptions::default());
+        assert_eq!(result.content, "# Title\r\n\r\nText\r\nMore\r\n");
+        assert!(result.edits.line_endings_normalized);
+
+        let result = format_content("Clean\n", &FormatOptions::default());
+        assert!(result.edits.is_empty());
     }
 
     #[test]
-    fn test_remove_multiple_blank_lines_no_change() {
-        let input = "Line 1\nLine 2\n\nLine 3";
-        assert_eq!(remove_multiple_blank_lines(input), input);
+    fn test_ignore_final_newline_only() {
+        let opts = FormatOptions {
+            ignore_final_newline_only: true,
+            formatter: FormatterConfig {
+                insert_final_newline: Some(true),
+                ..FormatterConfig::default()
+            },
+            ..FormatOptions::default()
+        };
+        for input in ["# Title\n\nText", "# Title\n\nText\n\n\n"] {
+            let result = format_content(input, &opts);
+            assert_eq!(result.outcome, FileOutcome::Unchanged);
+            assert_eq!(result.content, input);
+            assert!(result.edits.is_empty());
+            assert!(result.blank_line_removals.is_empty());
+        }
+        // Other changes bring the final newline along
+        let result = format_content("# Title\nText", &opts);
+        assert_eq!(result.outcome, FileOutcome::Modified);
+        assert_eq!(result.content, "# Title\n\nText\n");
     }
 
     #[test]
-    fn test_remove_multiple_blank_lines_preserve_ending() {
-        let input_no_newline = "Line 1\n\n\nLine 2";
-        let expected_no_newline = "Line 1\n\nLine 2";
+    fn test_line_endings_and_final_newline() {
+        let crlf = |insert_final_newline| FormatOptions {
+            formatter: FormatterConfig {
+                line_ending: LineEnding::Crlf,
+                insert_final_newline,
+                trim_trailing_whitespace: true,
+                ..FormatterConfig::default()
+            },
+            ..FormatOptions::default()
+        };
+
+        let result = format_content("# Title  \nText\r\n\n\n```\ncode  \n```", &crlf(Some(true)));
         assert_eq!(
-            remove_multiple_blank_lines(input_no_newline),
-            expected_no_newline
+            result.content,
+            "# Title\r\n\r\nText\r\n\r\n```\r\ncode  \r\n```\r\n"
+        );
+        assert_eq!(result.edits.lines_rewritten, 1);
+        assert!(result.edits.line_endings_normalized);
+        assert!(result.edits.final_newline_fixed);
+
+        // Content that already has the configured line endings is left as it is
+        let result = format_content("# Title\r\n\r\nText\r\n", &crlf(None));
+        assert_eq!(result.outcome, FileOutcome::Unchanged);
+        assert!(matches!(result.content, Cow::Borrowed(_)));
+        assert!(result.edits.is_empty());
+
+        let result = format_content("# Title\r\n\r\nText\r\n\r\n", &crlf(Some(false)));
+        assert_eq!(result.content, "# Title\r\n\r\nText");
+        assert!(result.edits.final_newline_fixed);
+
+        let lf = FormatterConfig {
+            line_ending: LineEnding::Lf,
+            insert_final_newline: Some(true),
+            ..FormatterConfig::default()
+        };
+        assert_eq!(format_with_config("Text", &lf), "Text\n");
+        assert_eq!(format_with_config("Text\n", &lf), "Text\n");
+        assert_eq!(format_with_config("Text\r\n", &lf), "Text\n");
+
+        // Code fences and frontmatter are converted too, but a `\r` inside a line
+        // is kept
+        let input = "---\r\na: 1\r\n---\r\n\r\nOld\rMac\r\n\r\n```\r\ncode\r\n```\r\n";
+        assert_eq!(
+            format_with_config(input, &lf),
+            "---\na: 1\n---\n\nOld\rMac\n\n```\ncode\n```\n"
+        );
+        let keep = FormatterConfig::default();
+        assert_eq!(format_with_config(input, &keep), input);
+        assert_eq!(
+            format_with_config("a\nb\r\nc\n```\r\nx\r\n```\r\n", &keep),
+            "a\r\nb\r\nc\r\n\r\n```\r\nx\r\n```\r\n"
+        );
+        assert_eq!(
+            format_with_config("a\r\nb\n", &keep),
+            "a\nb\n",
+            "ties go to LF"
         );
     }
 
     #[test]
-    fn test_preserve_frontmatter() {
-        let input =
-            "---\ntitle: Test\n\n\n\nauthor: Me\n---\n\n\n\nContent here\n\n\n\nMore content";
-        let expected = "---\ntitle: Test\n\n\n\nauthor: Me\n---\n\nContent here\n\nMore content";
-        assert_eq!(remove_multiple_blank_lines(input), expected);
+    fn test_format_content_applies_formatter_config() {
+        let opts = FormatOptions {
+            formatter: FormatterConfig {
+                fix_bare_urls: true,
+                ..FormatterConfig::default()
+            },
+            ..FormatOptions::default()
+        };
+        let result = format_content("See https://a.example\n", &opts);
+        assert_eq!(result.content, "See <https://a.example>\n");
+        assert_eq!(result.outcome, FileOutcome::Modified);
+        assert_eq!(result.edits.lines_rewritten, 1);
+        assert!(result.blank_line_removals.is_empty());
+        assert!(result.blank_line_insertions.is_empty());
     }
 
     #[test]
-    fn test_preserve_code_fences() {
-        let input = "Some text\n\n\n\n```rust\nfn main() {\n\n\n\n    println!(\"Hello\");\n}\n```\n\n\n\nMore text";
-        let expected = "Some text\n\n```rust\nfn main() {\n\n\n\n    println!(\"Hello\");\n}\n```\n\nMore text";
-        assert_eq!(remove_multiple_blank_lines(input), expected);
+    fn test_demote_headings_skips_protected_regions() {
+        let input = "---\n# not: a heading\n---\n# Title\r\n  ## Indented ##\n#draft tag\n```\n# comment\n```\n$$\n# x\n$$\n#####\n";
+        let expected = "---\n# not: a heading\n---\n## Title\r\n  ### Indented ##\n#draft tag\n```\n# comment\n```\n$$\n# x\n$$\n######\n";
+        assert_eq!(demote_headings(input, 1), expected);
+        assert_eq!(demote_headings("# A\n#### B", 4), "##### A\n###### B");
+        assert_eq!(demote_headings("Text\n", 3), "Text\n");
     }
 
     #[test]
-    fn test_preserve_tilde_code_fences() {
-        let input = "Some text\n\n\n\n~~~python\ndef hello():\n\n\n\n    print(\"Hello\")\n~~~\n\n\n\nMore text";
-        let expected =
-            "Some text\n\n~~~python\ndef hello():\n\n\n\n    print(\"Hello\")\n~~~\n\nMore text";
-        assert_eq!(remove_multiple_blank_lines(input), expected);
+    fn test_promote_headings_refuses_or_clamps() {
+        let input = "Intro\n\n### Part\n\n## Chapter\n";
+        assert_eq!(
+            promote_headings(input, 1, false).unwrap(),
+            "Intro\n\n## Part\n\n# Chapter\n"
+        );
+
+        let err = promote_headings(input, 2, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "heading on line 5 is level 2 and cannot be promoted by 2 levels"
+        );
+        assert_eq!(
+            promote_headings(input, 2, true).unwrap(),
+            "Intro\n\n# Part\n\n# Chapter\n"
+        );
     }
 
     #[test]
-    fn test_frontmatter_and_code_fences_combined() {
-        let input = "---\ntitle: Test\n\n\n\nauthor: Me\n---\n\n\n\nSome text\n\n\n\n```rust\nfn test() {\n\n\n\n    // code\n}\n```\n\n\n\nEnd";
-        let expected = "---\ntitle: Test\n\n\n\nauthor: Me\n---\n\nSome text\n\n```rust\nfn test() {\n\n\n\n    // code\n}\n```\n\nEnd";
-        assert_eq!(remove_multiple_blank_lines(input), expected);
+    fn test_file_with_unpromotable_headings_is_not_written() {
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let path = temp.path().join("doc.md");
+        fs::write(&path, "# Title\n\n\n## Section\n").unwrap();
+
+        let mut options = FormatOptions {
+            formatter: FormatterConfig {
+                heading_shift: -1,
+                ..FormatterConfig::default()
+            },
+            ..FormatOptions::default()
+        };
+        let err = process_md_file_with_options(&path, &options, None).unwrap_err();
+        assert!(err.to_string().contains("line 1 is level 1"));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# Title\n\n\n## Section\n"
+        );
+
+        options.formatter.clamp_headings = true;
+        let changes = process_md_file_with_options(&path, &options, None).unwrap();
+        assert_eq!(changes.outcome, FileOutcome::Modified);
+        assert_eq!(changes.edits.lines_rewritten, 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Title\n\n# Section\n");
     }
 
     #[test]
-    fn test_nested_code_fences_not_supported() {
-        // This test documents current behavior - nested code fences are not supported
-        // The first closing fence will end the code block
-        let input = "```\ncode\n```inner\nmore code\n```\n\n\n\ntext";
-        // Breakdown:
-        // 1. ``` starts code fence
-        // 2. ```inner ends it (because it starts with ```)
-        // 3. "more code" is normal text (no blank line processing needed)
-        // 4. ``` starts a new code fence
-        // 5. "\n\n\n\ntext" is inside the new code fence - first blank line after fence start is removed
-        let expected = "```\ncode\n```inner\n\nmore code\n\n```\n\n\ntext";
-        assert_eq!(remove_multiple_blank_lines(input), expected);
+    fn test_max_consecutive_blank_lines() {
+        let config = FormatterConfig {
+            max_consecutive_blank_lines: 2,
+            ..FormatterConfig::default()
+        };
+        let input = "A\n\n\n\n\nB\n\n\nC\n```\n\n\n\nx\n```\n\n\n\n";
+        assert_eq!(
+            format_with_config(input, &config),
+            "A\n\n\nB\n\n\nC\n\n```\nx\n```\n"
+        );
     }
 
     #[test]
-    fn test_code_fence_no_blank_after_start_before_end() {
-        let input = "Text\n```\n\ncode line\n\n```\nText";
-        let expected = "Text\n\n```\ncode line\n```\n\nText";
-        assert_eq!(remove_multiple_blank_lines(input), expected);
+    fn test_decode_utf8_errors() {
+        let message = |bytes: &[u8]| decode_utf8(bytes.to_vec()).unwrap_err().to_string();
+        // A sequence cut off by the end of the file is shown whole
+        assert_eq!(
+            message(b"a\nb\nc\xe2\x82"),
+            "invalid UTF-8 at byte 5 (line 3): 0a 62 0a 63 [e2 82]"
+        );
+        assert_eq!(
+            message(b"\xc3\x28 text"),
+            "invalid UTF-8 at byte 0 (line 1): [c3] 28 20 74 65"
+        );
+        assert_eq!(
+            decode_utf8(b"\xff".to_vec()).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
     }
 
     #[test]
-    fn test_code_fence_preserve_internal_blanks() {
-        let input = "Text\n```\nline1\n\n\nline2\n```\nText";
-        let expected = "Text\n\n```\nline1\n\n\nline2\n```\n\nText";
-        assert_eq!(remove_multiple_blank_lines(input), expected);
+    fn test_process_md_file_with_encoding() {
+        use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+        let temp = Builder::new().prefix("mdfmt_test_").tempdir().unwrap();
+        let path = temp.path().join("legacy.md");
+        fs::write(&path, b"# Caf\xe9\nCr\xe8me br\xfbl\xe9e\n\n\n").unwrap();
+        assert_eq!(
+            read_file(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        let changes =
+            process_md_file_with_encoding(&path, WINDOWS_1252, &FormatOptions::default()).unwrap();
+        assert_eq!(changes.outcome, FileOutcome::Modified);
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            b"# Caf\xe9\n\nCr\xe8me br\xfbl\xe9e\n"
+        );
+
+        // Content the encoding cannot represent is never written
+        let err = encode("# Title\n\nA \u{2192} B\n", WINDOWS_1252).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "'\u{2192}' (U+2192) on line 3 cannot be written as windows-1252"
+        );
+        assert_eq!(
+            encode("\u{3042}\n", SHIFT_JIS).unwrap().as_ref(),
+            b"\x82\xa0\n"
+        );
+        assert!(matches!(encode("\u{e9}", UTF_8), Ok(Cow::Borrowed(_))));
+        assert_eq!(
+            decode(b"\x82".to_vec(), SHIFT_JIS).unwrap_err().to_string(),
+            "not valid Shift_JIS"
+        );
     }
 
     #[test]
-    fn test_heading_and_list_blank_lines() {
-        let input = "Text\n# Heading1\nText\n- item1\n- item2\nText\n1. item3\n2. item4\nText\n* item5\n* item6\nText\n+ item7\n+ item8\nText";
-        let expected = "Text\n\n# Heading1\n\nText\n\n- item1\n- item2\n\nText\n\n1. item3\n2. item4\n\nText\n\n* item5\n* item6\n\nText\n\n+ item7\n+ item8\n\nText";
-        assert_eq!(remove_multiple_blank_lines(input), expected);
+    fn test_normalize_heading_spacing() {
+        let cases = [
+            ("#\tTitle\r\n", "# Title\r\n"),
+            // Closing sequences and trailing whitespace are kept
+            ("###  A \t B   ###  \n", "### A B   ###  \n"),
+            // Code spans keep their spaces, also with longer backtick runs
+            (
+                "## ``a  `b`  c``   and  `x\ty`\n",
+                "## ``a  `b`  c`` and `x\ty`\n",
+            ),
+            // An unclosed backtick is literal text
+            ("# a  `b  c\n", "# a `b c\n"),
+            // Only a final attribute list keeps its spacing
+            (
+                "# {#a  b} Title  {: .x  .y}\n",
+                "# {#a b} Title {: .x  .y}\n",
+            ),
+            ("# Title{#id  x}\n", "# Title{#id x}\n"),
+            // Empty headings, tags, escapes, and code are not headings to change
+            ("#   \n#   ##\n", "#   \n#   ##\n"),
+            ("#draft  post\n\\#  Not\n", "#draft  post\n\\#  Not\n"),
+            ("```\n#  Comment\n```\n", "```\n#  Comment\n```\n"),
+            ("---\n#  yaml:  x\n---\n", "---\n#  yaml:  x\n---\n"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                normalize_heading_spacing(input),
+                expected,
+                "input: {:?}",
+                input
+            );
+            assert_eq!(normalize_heading_spacing(expected), expected);
+        }
     }
 
     #[test]
-    fn test_code_fence_blank_lines() {
-        let input = "Text\n```rust\nfn main() {}\n```\nText";
-        let expected = "Text\n\n```rust\nfn main() {}\n```\n\nText";
-        assert_eq!(remove_multiple_blank_lines(input), expected);
+    fn test_normalize_setext_underline_length() {
+        let cases = [
+            // Indentation, trailing whitespace, and line endings are kept
+            (
+                "  Ünïcödé  \r\n  ==  \r\n",
+                "  Ünïcödé  \r\n  =======  \r\n",
+            ),
+            ("One\nTwo\n=\n", "One\nTwo\n===\n"),
+            // Thematic breaks and underlines in protected regions are not headings
+            ("---\ntitle: x\n---\n\n---\n", "---\ntitle: x\n---\n\n---\n"),
+            ("```\ncode\n===\n```\n", "```\ncode\n===\n```\n"),
+            (
+                "# Title\n---\n> Quote\n---\n",
+                "# Title\n---\n> Quote\n---\n",
+            ),
+            ("    code\n    ---\n", "    code\n    ---\n"),
+            // Items with `)` markers are followed by thematic breaks, and lines
+            // indented by 4 spaces continue the paragraph
+            ("2)  two\n---\n", "2)  two\n---\n"),
+            ("Text\n    ---\n", "Text\n    ---\n"),
+            ("Text\n   ---\n", "Text\n   ----\n"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize_setext_underline_length(input), expected);
+        }
     }
 
     #[test]
-    fn test_no_extra_blank_lines_for_existing() {
-        let input = "Text\n\n# Heading\n\nText\n\n- item\n\nText";
-        let expected = "Text\n\n# Heading\n\nText\n\n- item\n\nText";
-        assert_eq!(remove_multiple_blank_lines(input), expected);
+    fn test_protected_html_comments() {
+        let config = FormatterConfig {
+            protected_html_comments: vec!["<!--more-->".to_string()],
+            separate_tables_and_html: true,
+            ..FormatterConfig::default()
+        };
+        // Blank lines next to the marker are kept, and none are inserted
+        let input = "# Post\n<!--more-->\n\n\n\nText\n\n\n\n  <!--more-->  \n## Next\n";
+        assert_eq!(format_with_config(input, &config), input);
+        // Other comments and blank runs are formatted as usual
+        let input = "Intro\n<!-- note -->\n\n\nText\n\n\nMore\n";
+        assert_eq!(
+            format_with_config(input, &config),
+            "Intro\n\n<!-- note -->\n\nText\n\nMore\n"
+        );
     }
 
     #[test]
-    fn test_consecutive_list_items() {
-        let input = "Text\n- A\n- B\n- C\nText";
-        let expected = "Text\n\n- A\n- B\n- C\n\nText";
-        assert_eq!(remove_multiple_blank_lines(input), expected);
+    fn test_heading_blank_lines() {
+        let config = FormatterConfig {
+            blank_lines_before_heading: Some("2,2,1".parse().unwrap()),
+            blank_lines_after_heading: Some(HeadingBlankLines::all(1)),
+            max_consecutive_blank_lines: 2,
+            ..FormatterConfig::default()
+        };
+        let input = "# Title\nIntro\n\n\n\n## Usage\n\n\nText\n### Details\n\n\n\nMore\n```\n\n\n```\n## End\n";
+        let expected = "# Title\n\nIntro\n\n\n## Usage\n\nText\n\n### Details\n\nMore\n\n```\n```\n\n\n## End\n";
+        let result = format_content(
+            input,
+            &FormatOptions {
+                formatter: config.clone(),
+                ..FormatOptions::default()
+            },
+        );
+        assert_eq!(result.content, expected);
+        assert_eq!(result.edits.heading_blank_lines_removed, 2);
+        assert_eq!(format_with_config(expected, &config), expected);
+
+        // Never before the first heading, also not after frontmatter
+        let input = "---\ntitle: x\n---\n# Title\n";
+        assert_eq!(
+            format_with_config(input, &config),
+            "---\ntitle: x\n---\n\n# Title\n"
+        );
+        assert_eq!(format_with_config("# Title\n", &config), "# Title\n");
+
+        // Zero blank lines after a heading, but fences still get a separator
+        let config = FormatterConfig {
+            blank_lines_after_heading: Some(HeadingBlankLines::all(0)),
+            ..FormatterConfig::default()
+        };
+        let input = "# Title\n\nText\n\n## Code\n```\nx\n```\n";
+        assert_eq!(
+            format_with_config(input, &config),
+            "# Title\nText\n\n## Code\n\n```\nx\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_heading_blank_lines_parse() {
+        let lines: HeadingBlankLines = "3".parse().unwrap();
+        assert_eq!(lines, HeadingBlankLines::all(3));
+        assert!("".parse::<HeadingBlankLines>().is_err());
+        assert!("1,x".parse::<HeadingBlankLines>().is_err());
+        assert!("1,1,1,1,1,1,1".parse::<HeadingBlankLines>().is_err());
     }
 
     #[test]
@@ -0,0 +1,154 @@
+//! Statistics about the content of a document.
+//!
+//! [`document_stats`] collects them in a [`DocumentStats`], using the same block
+//! detection as the formatter, e.g. to report which languages the code examples of
+//! a documentation tree are written in.
+
+use crate::lint::fence_info;
+use crate::lists::indented_code_lines;
+use crate::process_md::{LineRegion, language_len, line_regions};
+use serde::Serialize;
+
+/// Statistics about a document.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DocumentStats {
+    /// The number of ```` ``` ```` and `~~~` code fences, including unclosed ones.
+    pub fenced_code_block_count: usize,
+    /// The number of code blocks indented by 4 or more columns.
+    pub indented_code_block_count: usize,
+    /// The language of every code fence in document order, e.g. `rust` for
+    /// ```` ```rust ignore ````, or an empty string for a fence without one.
+    pub code_block_languages: Vec<String>,
+}
+
+/// Collect the statistics of `content`.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to analyze
+///
+/// # Returns
+///
+/// The statistics of the document.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::stats::document_stats;
+///
+/// let stats = document_stats("```rust\nfn main() {}\n```\n\n    $ cargo run\n\n~~~\nplain\n~~~\n");
+/// assert_eq!(stats.fenced_code_block_count, 2);
+/// assert_eq!(stats.indented_code_block_count, 1);
+/// assert_eq!(stats.code_block_languages, ["rust", ""]);
+/// ```
+pub fn document_stats(content: &str) -> DocumentStats {
+    let lines: Vec<&str> = content.lines().collect();
+    let regions = line_regions(&lines);
+    let code_block_languages = fence_languages(&lines, &regions);
+    DocumentStats {
+        fenced_code_block_count: code_block_languages.len(),
+        indented_code_block_count: count_indented_blocks(&lines, &regions),
+        code_block_languages,
+    }
+}
+
+/// Count the code blocks of a document.
+///
+/// Code fences are matched with their closing fences the way the formatter matches
+/// them, so fences shown inside a longer fence are not counted, and an unclosed
+/// fence counts as one block. Indented code blocks are runs of lines indented by 4
+/// or more columns that do not continue a paragraph, counted once even when blank
+/// lines split them. Inside list items, the indentation is counted from the text of
+/// the item.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to analyze
+///
+/// # Returns
+///
+/// The number of fenced and of indented code blocks, in that order.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::stats::count_code_blocks;
+///
+/// let input = "````md\n```sh\nls\n```\n````\n\nText\n    not code\n\n    code\n\n    more code\n";
+/// assert_eq!(count_code_blocks(input), (1, 1));
+/// ```
+pub fn count_code_blocks(content: &str) -> (usize, usize) {
+    let lines: Vec<&str> = content.lines().collect();
+    let regions = line_regions(&lines);
+    (
+        fence_languages(&lines, &regions).len(),
+        count_indented_blocks(&lines, &regions),
+    )
+}
+
+/// The language of every code fence, or an empty string for fences without one.
+fn fence_languages(lines: &[&str], regions: &[LineRegion]) -> Vec<String> {
+    lines
+        .iter()
+        .zip(regions)
+        .filter(|&(_, region)| *region == LineRegion::CodeFenceOpening)
+        .map(|(line, _)| {
+            let info = fence_info(line);
+            info[..language_len(info)].to_string()
+        })
+        .collect()
+}
+
+/// The number of indented code blocks. Blank lines between indented lines do not
+/// end a block.
+fn count_indented_blocks(lines: &[&str], regions: &[LineRegion]) -> usize {
+    let code = indented_code_lines(lines, regions, 4);
+    let mut count = 0;
+    let mut in_block = false;
+    for (line, is_code) in lines.iter().zip(code) {
+        if is_code && !in_block {
+            count += 1;
+        }
+        if is_code || !line.trim().is_empty() {
+            in_block = is_code;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_code_blocks() {
+        assert_eq!(count_code_blocks(""), (0, 0));
+        assert_eq!(count_code_blocks("Text\n"), (0, 0));
+        // Unclosed fences count, fences in frontmatter and math blocks do not
+        assert_eq!(
+            count_code_blocks("---\nx: |\n  ```\n---\n$$\n```\n$$\n~~~\nopen\n"),
+            (1, 0)
+        );
+        // Inside list items, code is indented 4 columns past the item text
+        assert_eq!(
+            count_code_blocks("- Item\n\n      code\n  - Nested\n\n    text\n\n\tcode\n"),
+            (0, 1)
+        );
+        assert_eq!(count_code_blocks("    one\n\nText\n\n    two\n"), (0, 2));
+    }
+
+    #[test]
+    fn test_document_stats() {
+        let stats = document_stats(
+            "```Python title=\"x.py\"\n```\n  ~~~{r}\n  ~~~\n```js{1,3}\n```\n\n    code\n",
+        );
+        assert_eq!(
+            stats,
+            DocumentStats {
+                fenced_code_block_count: 3,
+                indented_code_block_count: 1,
+                code_block_languages: vec!["Python".to_string(), String::new(), "js".to_string()],
+            }
+        );
+    }
+}
@@ -0,0 +1,547 @@
+//! Tables of contents maintained between marker comments.
+//!
+//! A document opts in by containing both markers, each on a line of its own:
+//!
+//! ```markdown
+//! <!-- mdfmt-toc-start -->
+//! <!-- mdfmt-toc-end -->
+//! ```
+//!
+//! [`update_toc`] replaces everything between them with a nested list of links to
+//! the ATX headings of the document, using the anchors GitHub generates for them.
+//! Documents without both markers are left alone, and a table of contents that is
+//! up to date is left exactly as it is.
+
+use crate::lint::code_span_ranges;
+use crate::process_md::{LineRegion, atx_heading_text, atx_headings, line_regions};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// The line a table of contents starts after.
+pub const TOC_START: &str = "<!-- mdfmt-toc-start -->";
+
+/// The line a table of contents ends before.
+pub const TOC_END: &str = "<!-- mdfmt-toc-end -->";
+
+/// The heading levels listed in a table of contents, e.g. `2..4` for `##` to
+/// `####`.
+///
+/// Both ends are included. On the command line and in configuration files, the
+/// levels are written as `MIN..MAX`.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::toc::TocLevels;
+///
+/// let levels: TocLevels = "2..4".parse()?;
+/// assert!(!levels.contains(1));
+/// assert!(levels.contains(4));
+/// assert_eq!(levels, TocLevels::new(2, 4)?);
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TocLevels {
+    min: usize,
+    max: usize,
+}
+
+impl TocLevels {
+    /// The levels `min` to `max`, both from 1 to 6.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if a level is outside 1 to 6, or `min` is greater
+    /// than `max`.
+    pub fn new(min: usize, max: usize) -> Result<Self, String> {
+        for level in [min, max] {
+            if !(1..=6).contains(&level) {
+                return Err(format!("heading level {} is not between 1 and 6", level));
+            }
+        }
+        if min > max {
+            return Err(format!("heading level {} is after level {}", min, max));
+        }
+        Ok(TocLevels { min, max })
+    }
+
+    /// Whether headings of `level` are listed.
+    pub fn contains(&self, level: usize) -> bool {
+        (self.min..=self.max).contains(&level)
+    }
+}
+
+impl Default for TocLevels {
+    /// Every heading level.
+    fn default() -> Self {
+        TocLevels { min: 1, max: 6 }
+    }
+}
+
+impl FromStr for TocLevels {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = s
+            .split_once("..")
+            .ok_or_else(|| format!("expected MIN..MAX, got '{}'", s))?;
+        let parse = |level: &str| {
+            level
+                .trim()
+                .parse()
+                .map_err(|_| format!("'{}' is not a heading level", level.trim()))
+        };
+        TocLevels::new(parse(min)?, parse(max)?)
+    }
+}
+
+impl TryFrom<String> for TocLevels {
+    type Error = String;
+
+    fn try_from(levels: String) -> Result<Self, Self::Error> {
+        levels.parse()
+    }
+}
+
+impl fmt::Display for TocLevels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.min, self.max)
+    }
+}
+
+impl From<TocLevels> for String {
+    fn from(levels: TocLevels) -> Self {
+        levels.to_string()
+    }
+}
+
+/// Regenerate the table of contents between [`TOC_START`] and [`TOC_END`].
+///
+/// Every ATX heading outside the table of contents with a level in `levels` gets
+/// a list item linking to its anchor, nested under the closest heading of a lower
+/// level before it. Link labels are the heading text with links and code spans
+/// replaced by their text. Anchors follow GitHub: the text is lowercased, its
+/// punctuation removed, and spaces turned into hyphens, and the second heading with
+/// the same anchor gets `-1` appended, the third `-2`, and so on.
+///
+/// The list is separated from the markers by blank lines, so formatting leaves it
+/// alone. Markers inside code fences are ignored, and only the first pair is used.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to update
+/// * `levels` - The heading levels to list
+///
+/// # Returns
+///
+/// The content with the table of contents regenerated, or unchanged if it has no
+/// markers.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::toc::{TocLevels, update_toc};
+///
+/// let input = "# Guide\n\n<!-- mdfmt-toc-start -->\n<!-- mdfmt-toc-end -->\n\n## Install `mdfmt`\n\n### From [source](#build)\n\n## Usage\n";
+/// assert_eq!(
+///     update_toc(input, TocLevels::new(2, 3)?),
+///     "# Guide\n\n<!-- mdfmt-toc-start -->\n\n- [Install mdfmt](#install-mdfmt)\n  - [From source](#from-source)\n- [Usage](#usage)\n\n<!-- mdfmt-toc-end -->\n\n## Install `mdfmt`\n\n### From [source](#build)\n\n## Usage\n"
+/// );
+/// # Ok::<(), String>(())
+/// ```
+pub fn update_toc(content: &str, levels: TocLevels) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let is_marker =
+        |i: usize, marker: &str| regions[i] == LineRegion::Text && trimmed[i].trim() == marker;
+    let Some(start) = (0..lines.len()).find(|&i| is_marker(i, TOC_START)) else {
+        return content.to_string();
+    };
+    let Some(end) = (start + 1..lines.len()).find(|&i| is_marker(i, TOC_END)) else {
+        return content.to_string();
+    };
+
+    // Anchors are numbered across all headings, listed or not
+    let mut anchors: HashMap<String, usize> = HashMap::new();
+    let mut nesting: Vec<usize> = Vec::new();
+    let mut items = Vec::new();
+    for (i, level) in atx_headings(&trimmed) {
+        if (start..end).contains(&i) {
+            continue;
+        }
+        let text = &trimmed[i][atx_heading_text(trimmed[i], level)];
+        let slug = slugify(&plain_text(text));
+        let count = anchors.entry(slug.clone()).or_insert(0);
+        let anchor = match *count {
+            0 => slug,
+            n => format!("{}-{}", slug, n),
+        };
+        *count += 1;
+
+        if levels.contains(level) {
+            while nesting.last().is_some_and(|&parent| parent >= level) {
+                nesting.pop();
+            }
+            items.push(format!(
+                "{}- [{}](#{})",
+                "  ".repeat(nesting.len()),
+                inline_text(text.trim(), true),
+                anchor
+            ));
+            nesting.push(level);
+        }
+    }
+
+    let newline = &lines[start][trimmed[start].len()..];
+    let mut output: String = lines[..=start].concat();
+    if !items.is_empty() {
+        output.push_str(newline);
+        for item in items {
+            output.push_str(&item);
+            output.push_str(newline);
+        }
+        output.push_str(newline);
+    }
+    output.push_str(&lines[end..].concat());
+    output
+}
+
+/// The text of a heading as it reads when rendered: links, images, and autolinks
+/// are replaced by their text and code spans by their content.
+///
+/// With `escape`, the result is Markdown for a link label, so characters from code
+/// spans and unmatched brackets that would be markup are escaped, and backslash
+/// escapes are kept. Otherwise it is plain text, for anchors.
+fn inline_text(text: &str, escape: bool) -> String {
+    let code_spans = code_span_ranges(text);
+    let mut output = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if let Some(span) = code_spans.iter().find(|span| span.start == i) {
+            let ticks = rest.bytes().take_while(|&b| b == b'`').count();
+            let code = &text[span.start + ticks..span.end - ticks];
+            // One space on both sides is padding, unless the code is only spaces
+            let code = match code.strip_prefix(' ').and_then(|c| c.strip_suffix(' ')) {
+                Some(inner) if !code.trim().is_empty() => inner,
+                _ => code,
+            };
+            push_text(&mut output, code, escape);
+            i = span.end;
+        } else if rest.starts_with('\\')
+            && rest[1..].starts_with(|c: char| c.is_ascii_punctuation())
+        {
+            output.push_str(if escape { &rest[..2] } else { &rest[1..2] });
+            i += 2;
+        } else if let Some((label, len)) = link(rest) {
+            output.push_str(&inline_text(label, escape));
+            i += len;
+        } else if let Some(len) = autolink(rest) {
+            output.push_str(&rest[1..len - 1]);
+            i += len;
+        } else {
+            let c = rest.chars().next().expect("i is before the end of text");
+            match c {
+                '[' | ']' if escape => output.push_str(if c == '[' { "\\[" } else { "\\]" }),
+                _ => output.push(c),
+            }
+            i += c.len_utf8();
+        }
+    }
+    output
+}
+
+/// The text of the heading text `text` as it reads when rendered, without any
+/// inline formatting, which is what GitHub generates anchors from.
+pub(crate) fn plain_text(text: &str) -> String {
+    let text = text.trim();
+    let mut stripped = String::with_capacity(text.len());
+    let mut i = 0;
+    for run in emphasis_delimiters(text) {
+        stripped.push_str(&text[i..run.start]);
+        i = run.end;
+    }
+    stripped.push_str(&text[i..]);
+    inline_text(&stripped, false)
+}
+
+/// The byte ranges of the `*` and `_` runs in `text` that open or close emphasis.
+///
+/// Runs are matched like CommonMark delimiter runs: a run can open when it is
+/// followed by text and close when it follows text, and `_` runs inside a word do
+/// neither. A closing run matches the closest open run of the same character, and
+/// runs left without a match stay text.
+fn emphasis_delimiters(text: &str) -> Vec<Range<usize>> {
+    let code_spans = code_span_ranges(text);
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    // Runs that can open, waiting for a closing run
+    let mut openers: Vec<(u8, Range<usize>)> = Vec::new();
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < text.len() {
+        if let Some(span) = code_spans.iter().find(|span| span.start == i) {
+            i = span.end;
+            continue;
+        }
+        let c = bytes[i];
+        if c == b'\\' {
+            i += 2;
+            continue;
+        }
+        if c != b'*' && c != b'_' {
+            i += 1;
+            continue;
+        }
+        let end = i + bytes[i..].iter().take_while(|&&b| b == c).count();
+        let before = text[..i].chars().next_back();
+        let after = text[end..].chars().next();
+        let is_space = |c: Option<char>| c.is_none_or(char::is_whitespace);
+        let is_punctuation = |c: Option<char>| c.is_some_and(|c| c.is_ascii_punctuation());
+        let left = !is_space(after)
+            && (!is_punctuation(after) || is_space(before) || is_punctuation(before));
+        let right = !is_space(before)
+            && (!is_punctuation(before) || is_space(after) || is_punctuation(after));
+        let (can_open, can_close) = if c == b'_' {
+            (
+                left && (!right || is_punctuation(before)),
+                right && (!left || is_punctuation(after)),
+            )
+        } else {
+            (left, right)
+        };
+        // A run closes as much of the open runs as its length allows
+        let mut closer = i..end;
+        while can_close && !closer.is_empty() {
+            let Some(index) = openers.iter().rposition(|(marker, _)| *marker == c) else {
+                break;
+            };
+            openers.truncate(index + 1);
+            let opener = &mut openers[index].1;
+            let len = opener.len().min(closer.len());
+            runs.push(opener.end - len..opener.end);
+            runs.push(closer.start..closer.start + len);
+            opener.end -= len;
+            closer.start += len;
+            if opener.start == opener.end {
+                openers.pop();
+            }
+        }
+        if can_open && !closer.is_empty() {
+            openers.push((c, closer));
+        }
+        i = end;
+    }
+    runs.sort_by_key(|run| run.start);
+    runs
+}
+
+/// Append `text` to `output`, escaping Markdown punctuation if `escape` is set.
+fn push_text(output: &mut String, text: &str, escape: bool) {
+    for c in text.chars() {
+        if escape && matches!(c, '\\' | '[' | ']' | '`' | '*' | '_' | '<' | '>') {
+            output.push('\\');
+        }
+        output.push(c);
+    }
+}
+
+/// The label and length of the inline link, reference link, or image at the start
+/// of `text`, such as `[label](url)`, `[label][ref]`, or `![label](src)`.
+fn link(text: &str) -> Option<(&str, usize)> {
+    let start = if text.starts_with("![") { 2 } else { 1 };
+    if !text[start - 1..].starts_with('[') {
+        return None;
+    }
+    let label_end = start + closing(&text[start..], '[', ']')?;
+    let after = &text[label_end + 1..];
+    let close = match after.chars().next()? {
+        '(' => ')',
+        '[' => ']',
+        _ => return None,
+    };
+    let open = if close == ')' { '(' } else { '[' };
+    let target_end = closing(&after[1..], open, close)?;
+    Some((&text[start..label_end], label_end + 1 + target_end + 2))
+}
+
+/// The byte offset in `text` of the `close` that balances an already opened `open`,
+/// skipping backslash escapes.
+fn closing(text: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == open {
+            depth += 1;
+        } else if c == close {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// The length of the autolink at the start of `text`, such as
+/// `<https://example.com>`.
+fn autolink(text: &str) -> Option<usize> {
+    let inner = text.strip_prefix('<')?;
+    let inner = &inner[..inner.find('>')?];
+    let is_autolink =
+        !inner.is_empty() && !inner.contains([' ', '<']) && inner.contains([':', '@']);
+    is_autolink.then_some(inner.len() + 2)
+}
+
+/// The anchor GitHub generates for a heading with the plain `text`: lowercased,
+/// with everything but letters, digits, spaces, hyphens, and underscores removed,
+/// and spaces turned into hyphens.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::toc::slugify;
+///
+/// assert_eq!(slugify("What's new in 2.0?"), "whats-new-in-20");
+/// assert_eq!(slugify("Über die `API`"), "über-die-api");
+/// ```
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            _ if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_toc() {
+        let input = "\
+# Title
+
+<!-- mdfmt-toc-start -->
+- [Stale](#stale)
+<!-- mdfmt-toc-end -->
+
+## Setup
+### Install
+#### Deep
+## Setup
+```
+## Not a heading
+<!-- mdfmt-toc-end -->
+```
+### See [the `docs`](https://example.com) ##
+## Setup!
+## The _private_ API
+";
+        let toc = "\
+<!-- mdfmt-toc-start -->
+
+- [Title](#title)
+  - [Setup](#setup)
+    - [Install](#install)
+  - [Setup](#setup-1)
+    - [See the docs](#see-the-docs)
+  - [Setup!](#setup-2)
+  - [The _private_ API](#the-private-api)
+
+<!-- mdfmt-toc-end -->
+";
+        let output = update_toc(input, TocLevels::new(1, 3).unwrap());
+        assert_eq!(
+            output,
+            input.replace(
+                "<!-- mdfmt-toc-start -->\n- [Stale](#stale)\n<!-- mdfmt-toc-end -->\n",
+                toc
+            )
+        );
+        assert_eq!(update_toc(&output, TocLevels::new(1, 3).unwrap()), output);
+
+        // Without headings in range the markers end up next to each other, and without
+        // both markers nothing changes
+        let empty =
+            "<!-- mdfmt-toc-start -->\n\n- [Old](#old)\n\n<!-- mdfmt-toc-end -->\r\n# A\r\n";
+        assert_eq!(
+            update_toc(empty, TocLevels::new(2, 6).unwrap()),
+            "<!-- mdfmt-toc-start -->\n<!-- mdfmt-toc-end -->\r\n# A\r\n"
+        );
+        for input in [
+            "# A\n<!-- mdfmt-toc-start -->\n",
+            "<!-- mdfmt-toc-end -->\n# A\n",
+        ] {
+            assert_eq!(update_toc(input, TocLevels::default()), input);
+        }
+    }
+
+    #[test]
+    fn test_inline_text() {
+        let cases = [
+            ("Plain", "Plain", "Plain"),
+            ("Use `a[0]` now", "Use a\\[0\\] now", "Use a[0] now"),
+            ("`` `tick` ``", "\\`tick\\`", "`tick`"),
+            (
+                "![Logo](logo.png) and [ref][1]",
+                "Logo and ref",
+                "Logo and ref",
+            ),
+            (
+                "Mail <me@example.com>",
+                "Mail me@example.com",
+                "Mail me@example.com",
+            ),
+            ("Array \\[1\\] [x", "Array \\[1\\] \\[x", "Array [1] [x"),
+            ("*Bold* `<b>`", "*Bold* \\<b\\>", "*Bold* <b>"),
+        ];
+        for (text, label, plain) in cases {
+            assert_eq!(inline_text(text, true), label, "{:?}", text);
+            assert_eq!(inline_text(text, false), plain, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let cases = [
+            ("*Bold* and __strong__ text", "Bold and strong text"),
+            ("The _private_ `*ptr*` API", "The private *ptr* API"),
+            (
+                "snake_case_name and 2 * 3 * 4",
+                "snake_case_name and 2 * 3 * 4",
+            ),
+            ("[*Linked*](#x) \\*kept\\*", "Linked *kept*"),
+            ("***Nested* emphasis**", "Nested emphasis"),
+            ("Unmatched *star", "Unmatched *star"),
+        ];
+        for (text, plain) in cases {
+            assert_eq!(plain_text(text), plain, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_toc_levels() {
+        assert_eq!("1..6".parse(), Ok(TocLevels::default()));
+        assert_eq!(" 3 .. 3 ".parse(), TocLevels::new(3, 3));
+        for invalid in ["2", "0..3", "4..2", "2..7", "a..b"] {
+            assert!(invalid.parse::<TocLevels>().is_err(), "{:?}", invalid);
+        }
+    }
+}
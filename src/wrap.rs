@@ -0,0 +1,259 @@
+//! Wrapping long lines.
+//!
+//! [`wrap_blockquote_lines`] breaks the lines of block quotes that are longer than
+//! a maximum width, repeating the `>` markers of the line on every continuation
+//! line, so nested quotes keep their depth.
+
+use crate::lists::{indented_code_lines, marker_len};
+use crate::process_md::{
+    LineRegion, MATH_DELIMITER, closes_math_block, fence_marker, is_fence, line_regions,
+    opens_math_block,
+};
+
+/// Wrap the lines of block quotes that are longer than `max_width` columns.
+///
+/// Lines are broken at single spaces, and every continuation line starts with the
+/// prefix of the line it was broken from, e.g. `>> ` or `> > `, so it stays at the
+/// same depth. In a list item inside a quote, continuation lines are also indented
+/// to the text of the item. Lines are never joined, and a word longer than the
+/// width stays whole on its own line.
+///
+/// A line is not broken before a word that would start a block of its own at the
+/// start of a line, such as `-`, `1.`, `#`, or `>`, nor after a backslash, which
+/// would turn the break into a hard line break. Headings, table rows, HTML, alert
+/// headers, and link reference definitions in quotes are kept, as are code fences,
+/// math blocks, and indented code inside and outside quotes, and frontmatter.
+///
+/// # Arguments
+///
+/// * `content` - The markdown content to wrap
+/// * `max_width` - The maximum number of characters per line, including the prefix
+///
+/// # Returns
+///
+/// The content with its long quote lines wrapped; line endings are kept.
+///
+/// # Examples
+///
+/// ```
+/// use mdfmt::wrap::wrap_blockquote_lines;
+///
+/// let input = "> The quick brown fox jumps over the lazy dog.\n>> Nested quotes keep their depth.\n";
+/// assert_eq!(
+///     wrap_blockquote_lines(input, 24),
+///     "> The quick brown fox\n> jumps over the lazy\n> dog.\n>> Nested quotes keep\n>> their depth.\n"
+/// );
+/// ```
+pub fn wrap_blockquote_lines(content: &str, max_width: usize) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let trimmed: Vec<&str> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']))
+        .collect();
+    let regions = line_regions(&trimmed);
+    let code = indented_code_lines(&trimmed, &regions, 4);
+    let mut output = String::with_capacity(content.len());
+    // The fence or math block open inside the current quote, if any
+    let mut quoted_block: Option<&str> = None;
+
+    for (i, line) in lines.iter().enumerate() {
+        let text = trimmed[i];
+        let quote = (regions[i] == LineRegion::Text && !code[i])
+            .then(|| split_quote(text))
+            .flatten();
+        let Some((prefix, rest)) = quote else {
+            quoted_block = None;
+            output.push_str(line);
+            continue;
+        };
+        let block_line = rest.trim();
+        if let Some(closing) = quoted_block {
+            let closes = if closing == MATH_DELIMITER {
+                closes_math_block(block_line, closing)
+            } else {
+                block_line.starts_with(closing)
+            };
+            if closes {
+                quoted_block = None;
+            }
+        } else if opens_math_block(block_line, MATH_DELIMITER) {
+            quoted_block = Some(MATH_DELIMITER);
+        } else if is_fence(block_line) {
+            quoted_block = Some(fence_marker(block_line));
+        } else if text.chars().count() > max_width && is_wrappable(rest) {
+            let newline = if line.ends_with("\r\n") { "\r\n" } else { "\n" };
+            wrap_line(prefix, rest, max_width, newline, &mut output);
+            output.push_str(&line[text.len()..]);
+            continue;
+        }
+        output.push_str(line);
+    }
+
+    output
+}
+
+/// Split a quoted line into its prefix of `>` markers, including the indentation
+/// and the spaces around them, and its text, or `None` if it is not quoted.
+fn split_quote(line: &str) -> Option<(&str, &str)> {
+    let mut rest = line.trim_start_matches([' ', '\t']).strip_prefix('>')?;
+    loop {
+        rest = rest.strip_prefix(' ').unwrap_or(rest);
+        let text = rest.trim_start_matches(' ');
+        // An indented code block in the quote, which keeps its line breaks
+        if rest.len() - text.len() >= 4 {
+            return None;
+        }
+        match text.strip_prefix('>') {
+            Some(after) => rest = after,
+            None => {
+                rest = text;
+                break;
+            }
+        }
+    }
+    let start = line.len() - rest.len();
+    Some((&line[..start], &line[start..]))
+}
+
+/// Check whether the text of a quoted line can be wrapped, i.e. it is regular text
+/// and not a heading, table row, HTML, alert header, or link reference definition.
+fn is_wrappable(text: &str) -> bool {
+    !(text.is_empty()
+        || text.starts_with(['#', '<', '|'])
+        || text.contains('|')
+        || text.starts_with("[!")
+        || text.starts_with('[') && text.contains("]:"))
+}
+
+/// Write `text` broken into lines of at most `max_width` characters, each starting
+/// with `prefix`, or with `prefix` and the indentation of a list item for the
+/// continuation lines of an item.
+fn wrap_line(prefix: &str, text: &str, max_width: usize, newline: &str, output: &mut String) {
+    let item_indent = marker_len(text).map_or(0, |len| {
+        len + text[len..].len() - text[len..].trim_start_matches(' ').len()
+    });
+    let continuation = format!("{}{}", prefix, " ".repeat(item_indent));
+    let mut line_prefix = prefix;
+    let mut rest = text;
+    // The marker of an item is never left alone on its line
+    let mut skip = item_indent;
+    loop {
+        let width = max_width.saturating_sub(line_prefix.chars().count());
+        let Some(at) = break_at(rest, width, skip) else {
+            break;
+        };
+        output.push_str(line_prefix);
+        output.push_str(&rest[..at]);
+        output.push_str(newline);
+        rest = &rest[at + 1..];
+        line_prefix = &continuation;
+        skip = 0;
+    }
+    output.push_str(line_prefix);
+    output.push_str(rest);
+}
+
+/// The byte index of the space after the first `skip` bytes to break `text` at so
+/// the part before it is at most `width` characters, or as short as possible when
+/// no break fits, or `None` if `text` fits or cannot be broken.
+fn break_at(text: &str, width: usize, skip: usize) -> Option<usize> {
+    if text.chars().count() <= width {
+        return None;
+    }
+    let mut best = None;
+    for (chars, (i, c)) in text.char_indices().enumerate() {
+        if best.is_some() && chars > width {
+            break;
+        }
+        if c == ' ' && i >= skip && can_break(&text[..i], &text[i + 1..]) {
+            best = Some(i);
+        }
+    }
+    best
+}
+
+/// Check whether a line can be broken between `before` and `after`, which are
+/// separated by a single space.
+fn can_break(before: &str, after: &str) -> bool {
+    let word = after.split(' ').next().unwrap_or_default();
+    !(before.is_empty() || before.ends_with([' ', '\\']) || word.is_empty() || starts_block(word))
+}
+
+/// Check whether a line starting with `word` would start a block instead of
+/// continuing a paragraph.
+fn starts_block(word: &str) -> bool {
+    word.starts_with(['#', '>', '<', '|'])
+        || ["```", "~~~", MATH_DELIMITER]
+            .iter()
+            .any(|marker| word.starts_with(marker))
+        || marker_len(word).is_some()
+        || ['-', '=', '*', '_']
+            .iter()
+            .any(|&c| word.chars().all(|ch| ch == c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_blockquote_lines() {
+        let cases = [
+            // Short lines and lines outside quotes are kept
+            (
+                "> Short\nNot quoted at all, however long it is\n",
+                "> Short\nNot quoted at all, however long it is\n",
+            ),
+            // Both nesting styles keep their markers
+            (
+                "> > one two three four\n>>two three four five\n",
+                "> > one two\n> > three four\n>>two three\n>>four five\n",
+            ),
+            // Continuation lines of list items are indented to the item text
+            (
+                "> 1.  one two three four\n",
+                "> 1.  one two\n>     three\n>     four\n",
+            ),
+            // Words that would start a block move to the previous line, and
+            // breaks never follow a backslash
+            (
+                "> one two - three # four\n",
+                "> one two -\n> three # four\n",
+            ),
+            ("> aaa bbb\\ cccc dd\n", "> aaa\n> bbb\\ cccc dd\n"),
+            // Long words stay whole, and hard line breaks stay at the end
+            (
+                "> https://example.com/long x  \r\n",
+                "> https://example.com/long\r\n> x  \r\n",
+            ),
+            // Fences, math, and code inside and outside quotes are kept
+            (
+                "> ```\n> one two three four\n> ```\n> $$\n> one two three four\n> $$\n>     one two three four\n",
+                "> ```\n> one two three four\n> ```\n> $$\n> one two three four\n> $$\n>     one two three four\n",
+            ),
+            (
+                "```\n> one two three four\n```\n\n    > one two three four\n",
+                "```\n> one two three four\n```\n\n    > one two three four\n",
+            ),
+            // So are headings, tables, HTML, and alerts in quotes
+            (
+                "> # one two three four\n> one | two | three\n> <div class=\"x y\">\n> [!NOTE]\n",
+                "> # one two three four\n> one | two | three\n> <div class=\"x y\">\n> [!NOTE]\n",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                wrap_blockquote_lines(input, 14),
+                expected,
+                "input: {:?}",
+                input
+            );
+            assert_eq!(
+                wrap_blockquote_lines(expected, 14),
+                expected,
+                "input: {:?}",
+                expected
+            );
+        }
+    }
+}
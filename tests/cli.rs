@@ -0,0 +1,2487 @@
+//! Integration tests for the `mdfmt` command line interface.
+
+use assert_cmd::Command;
+use std::fs;
+use std::path::Path;
+use tempfile::{Builder, TempDir};
+
+/// Create a fresh temporary directory containing the given files.
+///
+/// The directory is removed when the returned `TempDir` is dropped.
+fn setup(name: &str, files: &[(&str, &str)]) -> TempDir {
+    let temp_dir = Builder::new()
+        .prefix(&format!("{}_", name))
+        .tempdir()
+        .unwrap();
+    for (file, content) in files {
+        fs::write(temp_dir.path().join(file), content).unwrap();
+    }
+    temp_dir
+}
+
+fn mdfmt() -> Command {
+    Command::cargo_bin("mdfmt").unwrap()
+}
+
+#[test]
+fn test_exit_code_clean() {
+    let temp = setup("mdfmt_cli_clean", &[("clean.md", "# Title\n\nText\n")]);
+    let dir = temp.path();
+
+    mdfmt().arg(dir).assert().code(0);
+    mdfmt().arg("--check").arg(dir).assert().code(0);
+}
+
+// Directories given with backslashes are searched like any other
+#[cfg(windows)]
+#[test]
+fn test_windows_directory_with_backslashes() {
+    let temp = setup("mdfmt_cli_windows_paths", &[]);
+    let dir = temp.path().join("docs").join("guide");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("page.md"), "# Page\nText\n").unwrap();
+
+    let path = format!("{}\\docs\\guide", temp.path().display());
+    mdfmt().arg("--check").arg(&path).assert().code(1);
+    mdfmt().arg(&path).assert().code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("page.md")).unwrap(),
+        "# Page\n\nText\n"
+    );
+}
+
+#[test]
+fn test_exit_code_changes_made() {
+    let temp = setup("mdfmt_cli_changed", &[("messy.md", "# Title\nText\n\n\n")]);
+    let dir = temp.path();
+
+    mdfmt().arg(dir).assert().code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("messy.md")).unwrap(),
+        "# Title\n\nText\n"
+    );
+    // A second run finds nothing left to do
+    mdfmt().arg(dir).assert().code(0);
+}
+
+#[test]
+fn test_exit_code_check_does_not_write() {
+    let temp = setup("mdfmt_cli_check", &[("messy.md", "# Title\nText\n")]);
+    let dir = temp.path();
+
+    mdfmt().arg("--check").arg(dir).assert().code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("messy.md")).unwrap(),
+        "# Title\nText\n"
+    );
+}
+
+#[test]
+fn test_exit_code_check_would_delete() {
+    let temp = setup(
+        "mdfmt_cli_check_delete",
+        &[("empty.md", "---\ntitle: x\n---\n")],
+    );
+    let dir = temp.path();
+
+    mdfmt().arg("--check").arg(dir).assert().code(0);
+    mdfmt()
+        .args(["--check", "--delete"])
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert!(dir.join("empty.md").exists());
+}
+
+#[test]
+fn test_exit_code_no_error_on_unformatted() {
+    let temp = setup("mdfmt_cli_no_error", &[("messy.md", "# Title\nText\n")]);
+    let dir = temp.path();
+
+    mdfmt()
+        .args(["--check", "--no-error-on-unformatted"])
+        .arg(dir)
+        .assert()
+        .code(0);
+    mdfmt()
+        .arg("--no-error-on-unformatted")
+        .arg(dir)
+        .assert()
+        .code(0);
+}
+
+#[test]
+fn test_exit_code_errors() {
+    let temp = setup("mdfmt_cli_errors", &[("messy.md", "# Title\nText\n")]);
+    let dir = temp.path();
+    fs::write(dir.join("invalid.md"), [0xff, 0xfe, 0x00]).unwrap();
+
+    // Errors take precedence over changes, even with the escape hatch
+    mdfmt().arg(dir).assert().code(2);
+    mdfmt()
+        .arg("--no-error-on-unformatted")
+        .arg(dir)
+        .assert()
+        .code(2);
+    mdfmt().arg(dir.join("missing.md")).assert().code(2);
+}
+
+#[test]
+fn test_multiple_paths_are_deduplicated() {
+    let temp = setup(
+        "mdfmt_cli_multi_path",
+        &[("a.md", "# A\nText\n"), ("b.md", "# B\nText\n")],
+    );
+    let dir = temp.path();
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join("sub").join("c.md"), "# C\nText\n").unwrap();
+
+    let output = mdfmt()
+        .arg(dir.join("a.md"))
+        .arg(dir.join("sub"))
+        .arg(dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("Found 3 markdown files"));
+    assert!(stdout.contains("Files modified: 3"));
+}
+
+#[test]
+fn test_multiple_paths_stop_on_missing_path() {
+    let temp = setup("mdfmt_cli_multi_missing", &[("a.md", "# A\nText\n")]);
+    let dir = temp.path();
+
+    mdfmt()
+        .arg(dir.join("a.md"))
+        .arg(dir.join("missing"))
+        .assert()
+        .code(2);
+    assert_eq!(fs::read_to_string(dir.join("a.md")).unwrap(), "# A\nText\n");
+}
+
+#[test]
+fn test_quiet_prints_nothing_on_success() {
+    let temp = setup("mdfmt_cli_quiet", &[("messy.md", "# Title\nText\n")]);
+    let dir = temp.path();
+
+    let output = mdfmt().arg("--quiet").arg(dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    // The progress bar is hidden when stderr is not a terminal
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_dry_run_reports_without_writing() {
+    let temp = setup(
+        "mdfmt_cli_dry_run",
+        &[
+            ("clean.md", "# Clean\n"),
+            ("messy.md", "# Title\nText\n"),
+            ("empty.md", ""),
+        ],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt()
+        .args(["--dry-run", "--delete"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("messy.md: would be modified"));
+    assert!(stdout.contains("empty.md: would be deleted"));
+    assert!(!stdout.contains("clean.md"));
+    assert!(stdout.contains("Files that would be modified: 1"));
+    assert!(stdout.contains("Files that would be deleted: 1"));
+    assert!(dir.join("empty.md").exists());
+    assert_eq!(
+        fs::read_to_string(dir.join("messy.md")).unwrap(),
+        "# Title\nText\n"
+    );
+
+    let output = mdfmt()
+        .args(["--dry-run", "--verbose"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("clean.md: no changes needed"));
+    assert!(stdout.contains("@@ -1,2 +1,3 @@\n # Title\n+\n Text\n"));
+}
+
+#[test]
+fn test_dry_run_diff_uses_config() {
+    let temp = setup(
+        "mdfmt_cli_dry_run_diff_config",
+        &[
+            ("doc.md", "# Title\n\nSee https://a.com\n\n\nEnd\n"),
+            (
+                ".mdfmt.toml",
+                "max_consecutive_blank_lines = 2\nfix_bare_urls = true\n",
+            ),
+        ],
+    );
+    let dir = temp.path();
+    fs::create_dir(dir.join(".git")).unwrap();
+
+    // The diff shows what would be written: the URL is wrapped, and the two blank
+    // lines are allowed
+    let output = mdfmt()
+        .args(["--dry-run", "--verbose"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("@@ -2,3 +2,3 @@\n \n-See https://a.com\n+See <https://a.com>\n \n"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("\n-\n"), "{}", stdout);
+}
+
+#[test]
+fn test_json_output_with_timings() {
+    let temp = setup(
+        "mdfmt_cli_json_timings",
+        &[("clean.md", "# Clean\n"), ("messy.md", "# Title\nText\n")],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt()
+        .args(["--output-format", "json", "--timings", "--check"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let files = report["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0]["status"], "unchanged");
+    assert_eq!(files[1]["status"], "modified");
+    assert_eq!(files[1]["timings"]["bytes"], 13);
+    assert_eq!(report["summary"]["modified"], 1);
+    assert_eq!(report["summary"]["bytes"], 21);
+
+    // Without --timings no timing data is collected
+    let output = mdfmt()
+        .args(["--output", "json", "--check"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(report["files"][0].get("timings").is_none());
+    assert!(report["summary"].get("elapsed_ms").is_none());
+}
+
+#[test]
+fn test_edit_counts_in_verbose_and_json_output() {
+    let temp = setup(
+        "mdfmt_cli_edit_counts",
+        &[("doc.md", "Intro\n# Title\nText\n\n\n\nMore\n\n\nEnd\n")],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt()
+        .args(["--check", "--verbose"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(
+            "doc.md: would be modified (2 blank runs collapsed, 2 heading separators added)"
+        ),
+        "{}",
+        stdout
+    );
+
+    // Without --verbose the status line stays short
+    let output = mdfmt().arg("--check").arg(dir).output().unwrap();
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("blank runs collapsed"));
+
+    let output = mdfmt()
+        .args(["--check", "--output-format", "json"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let edits = &report["files"][0]["edits"];
+    assert_eq!(edits["blank_runs_collapsed"], 2);
+    assert_eq!(edits["heading_separators"], 2);
+    assert_eq!(edits["list_separators"], 0);
+}
+
+#[test]
+fn test_demote_and_promote_headings() {
+    let temp = setup(
+        "mdfmt_cli_headings",
+        &[
+            ("post.md", "# Post\n\n## Notes\n"),
+            ("chapter.md", "## Chapter\n\n### Section\n"),
+        ],
+    );
+    let dir = temp.path();
+
+    mdfmt()
+        .args(["--demote-headings", "1"])
+        .arg(dir.join("post.md"))
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("post.md")).unwrap(),
+        "## Post\n\n### Notes\n"
+    );
+
+    // Promoting past level 1 is refused unless headings are clamped
+    let output = mdfmt()
+        .args(["--promote-headings", "2"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be promoted by 2 levels"));
+    assert_eq!(
+        fs::read_to_string(dir.join("chapter.md")).unwrap(),
+        "## Chapter\n\n### Section\n"
+    );
+
+    mdfmt()
+        .args(["--promote-headings", "2", "--clamp-headings"])
+        .arg(dir.join("chapter.md"))
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("chapter.md")).unwrap(),
+        "# Chapter\n\n# Section\n"
+    );
+
+    mdfmt()
+        .args(["--demote-headings", "1", "--promote-headings", "1"])
+        .arg(dir)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_fence_style() {
+    let nested = "~~~markdown\n```sh\nmdfmt .\n```\n~~~\n";
+    let temp = setup(
+        "mdfmt_cli_fence_style",
+        &[
+            ("plain.md", "~~~ rust\nfn main() {}\n~~~\n"),
+            ("nested.md", nested),
+        ],
+    );
+    let dir = temp.path();
+
+    mdfmt()
+        .args(["--fence-style", "backtick"])
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("plain.md")).unwrap(),
+        "``` rust\nfn main() {}\n```\n"
+    );
+    // Converting would close the fence at the nested ``` line, so it is kept
+    assert_eq!(fs::read_to_string(dir.join("nested.md")).unwrap(), nested);
+
+    mdfmt()
+        .args(["--fence-style", "tilde"])
+        .arg(dir.join("plain.md"))
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("plain.md")).unwrap(),
+        "~~~ rust\nfn main() {}\n~~~\n"
+    );
+}
+
+#[test]
+fn test_normalize_fence_lang() {
+    let temp = setup(
+        "mdfmt_cli_fence_lang",
+        &[
+            (".mdfmt.toml", "[fence_aliases]\nrs = \"rust\"\n"),
+            (
+                "code.md",
+                "```RS \nfn main() {}\n```\n\n``` Python {linenos=true}\npass\n```\n\n```{r}\n1\n```\n",
+            ),
+        ],
+    );
+    let dir = temp.path();
+
+    mdfmt()
+        .arg("--normalize-fence-lang")
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("code.md")).unwrap(),
+        "```rust\nfn main() {}\n```\n\n```python {linenos=true}\npass\n```\n\n```{r}\n1\n```\n"
+    );
+}
+
+#[test]
+fn test_remove_zero_width_chars() {
+    let temp = setup(
+        "mdfmt_cli_zero_width",
+        &[
+            (".mdfmt.toml", "remove_zero_width_chars = true\n"),
+            (
+                "copied.md",
+                "# Title\n\nSoft\u{AD}ware\n\nFine\n\nZero\u{200B}width\n",
+            ),
+        ],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt().arg(dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("copied.md:3: warning: invisible soft hyphen (U+00AD)"));
+    assert!(stderr.contains("copied.md:7: warning: invisible zero-width space (U+200B)"));
+    assert_eq!(
+        fs::read_to_string(dir.join("copied.md")).unwrap(),
+        "# Title\n\nSoftware\n\nFine\n\nZerowidth\n"
+    );
+}
+
+#[test]
+fn test_format_tables() {
+    let temp = setup(
+        "mdfmt_cli_format_tables",
+        &[
+            (".mdfmt.toml", "format_tables = true\n"),
+            (
+                "table.md",
+                "# Sizes\n\n| File | Bytes |\n|:--|--:|\n| README.md | 1200 |\n",
+            ),
+        ],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt().arg("--check").arg(dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let output = mdfmt().arg(dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let expected =
+        "# Sizes\n\n| File      | Bytes |\n| :-------- | ----: |\n| README.md |  1200 |\n";
+    assert_eq!(fs::read_to_string(dir.join("table.md")).unwrap(), expected);
+    let output = mdfmt().arg("--check").arg(dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+}
+
+/// Run git in `dir`, returning its stdout.
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args([
+            "-c",
+            "user.name=mdfmt",
+            "-c",
+            "user.email=mdfmt@example.com",
+        ])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_format_only_staged() {
+    let messy = "# Title\nText\n";
+    let temp = setup(
+        "mdfmt_cli_format_only_staged",
+        &[
+            ("staged.md", messy),
+            ("partial.md", messy),
+            ("unstaged.md", messy),
+        ],
+    );
+    let dir = temp.path();
+    git(dir, &["init", "-q"]);
+    git(dir, &["add", "staged.md", "partial.md"]);
+    fs::write(dir.join("partial.md"), "# Title\nText\nMore\n").unwrap();
+
+    let output = mdfmt()
+        .arg("--format-only-staged")
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("partial.md: warning: not staged again because it has unstaged changes")
+    );
+    // The formatted staged file is staged again, the partially staged one is not
+    assert_eq!(git(dir, &["show", ":staged.md"]), "# Title\n\nText\n");
+    assert_eq!(git(dir, &["show", ":partial.md"]), messy);
+    assert_eq!(
+        fs::read_to_string(dir.join("partial.md")).unwrap(),
+        "# Title\n\nText\nMore\n"
+    );
+    assert_eq!(fs::read_to_string(dir.join("unstaged.md")).unwrap(), messy);
+
+    fs::write(dir.join("staged.md"), messy).unwrap();
+    git(dir, &["add", "staged.md"]);
+    mdfmt()
+        .args(["--format-only-staged", "--no-restage"])
+        .current_dir(dir)
+        .assert()
+        .code(1);
+    assert_eq!(git(dir, &["show", ":staged.md"]), messy);
+
+    let temp = setup("mdfmt_cli_format_only_staged_no_repo", &[]);
+    let output = mdfmt()
+        .arg("--format-only-staged")
+        .arg(temp.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not inside a git repository"));
+}
+
+#[test]
+fn test_editorconfig() {
+    let editorconfig = "root = true\n\n[*.md]\nend_of_line = crlf\ninsert_final_newline = true\ntrim_trailing_whitespace = true\n\n[CHANGELOG.md]\nend_of_line = lf\ntrim_trailing_whitespace = false\n";
+    let files = [
+        (".editorconfig", editorconfig),
+        ("README.md", "# Title  \nText"),
+        ("CHANGELOG.md", "# Changes  \nText"),
+    ];
+    let temp = setup("mdfmt_cli_editorconfig", &files);
+    let dir = temp.path();
+
+    mdfmt().arg(dir).assert().code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("README.md")).unwrap(),
+        "# Title\r\n\r\nText\r\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("CHANGELOG.md")).unwrap(),
+        "# Changes  \n\nText\n"
+    );
+    mdfmt().arg("--check").arg(dir).assert().code(0);
+
+    // Flags on the command line win, and --no-editorconfig ignores the file
+    mdfmt()
+        .args(["--check", "--end-of-line", "lf"])
+        .arg(dir.join("README.md"))
+        .assert()
+        .code(1);
+    mdfmt()
+        .args(["--check", "--end-of-line", "keep"])
+        .arg(dir.join("CHANGELOG.md"))
+        .assert()
+        .code(0);
+    let temp = setup("mdfmt_cli_no_editorconfig", &files);
+    let dir = temp.path();
+    mdfmt().arg("--no-editorconfig").arg(dir).assert().code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("README.md")).unwrap(),
+        "# Title  \n\nText"
+    );
+}
+
+#[test]
+fn test_editorconfig_indentation() {
+    let files = [
+        (
+            ".editorconfig",
+            "root = true\n\n[*.md]\nindent_style = space\nindent_size = 2\n",
+        ),
+        ("list.md", "- a\n\t- b\n"),
+    ];
+    let temp = setup("mdfmt_cli_editorconfig_indentation", &files);
+    let dir = temp.path();
+
+    mdfmt().arg("--no-editorconfig").arg(dir).assert().code(0);
+    mdfmt().arg(dir).assert().code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("list.md")).unwrap(),
+        "- a\n  - b\n"
+    );
+}
+
+#[test]
+fn test_end_of_line() {
+    let crlf = "---\r\ntitle: T\r\n---\r\n\r\n# Title\r\n\r\n```\r\ncode\r\n```\r\n";
+    let temp = setup(
+        "mdfmt_cli_end_of_line",
+        &[
+            ("crlf.md", crlf),
+            ("mixed.md", "# Title\r\n\r\nOne\r\nTwo\n"),
+        ],
+    );
+    let dir = temp.path();
+
+    // By default, every file keeps the line endings most of its lines have
+    let output = mdfmt().arg("-v").arg(dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("mixed.md: modified (line endings normalized)"));
+    assert_eq!(fs::read_to_string(dir.join("crlf.md")).unwrap(), crlf);
+    assert_eq!(
+        fs::read_to_string(dir.join("mixed.md")).unwrap(),
+        "# Title\r\n\r\nOne\r\nTwo\r\n"
+    );
+
+    mdfmt()
+        .args(["--end-of-line", "lf"])
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("crlf.md")).unwrap(),
+        crlf.replace("\r\n", "\n")
+    );
+    mdfmt()
+        .args(["--check", "--end-of-line", "keep"])
+        .arg(dir)
+        .assert()
+        .code(0);
+    mdfmt()
+        .args(["--check", "--end-of-line", "cr"])
+        .arg(dir)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_fence_languages_required_or_defaulted() {
+    let content = "```\nls\n```\n\n````markdown\n```sh\nls\n```\n````\n";
+    let temp = setup("mdfmt_cli_fence_default", &[("code.md", content)]);
+    let dir = temp.path();
+
+    let output = mdfmt()
+        .args(["--check", "--require-fence-lang"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("code.md:1: warning: code fence has no language"));
+    assert_eq!(stderr.matches("warning").count(), 1);
+
+    mdfmt()
+        .args(["--default-fence-lang", "text"])
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("code.md")).unwrap(),
+        "```text\nls\n```\n\n````markdown\n```sh\nls\n```\n````\n"
+    );
+}
+
+#[test]
+fn test_blank_lines_around_headings() {
+    let temp = setup(
+        "mdfmt_cli_heading_blank_lines",
+        &[(
+            "doc.md",
+            "# Title\nIntro\n## Usage\n\n\nText\n### Details\nMore\n",
+        )],
+    );
+    let dir = temp.path();
+
+    mdfmt()
+        .args(["--blank-lines-before-heading", "1,2"])
+        .args(["--blank-lines-after-heading", "1"])
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("doc.md")).unwrap(),
+        "# Title\n\nIntro\n\n\n## Usage\n\nText\n\n\n### Details\n\nMore\n"
+    );
+
+    mdfmt()
+        .args(["--blank-lines-before-heading", "2,x"])
+        .arg(dir)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_line_range() {
+    let content = "# Title\n\n\n\nIntro\n```\n\n\ncode\n```\n## Usage\nText\n";
+    let temp = setup("mdfmt_cli_line_range", &[("doc.md", content)]);
+    let path = temp.path().join("doc.md");
+
+    mdfmt()
+        .args(["--check", "--line-range", "5:9"])
+        .arg(&path)
+        .assert()
+        .code(1);
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+    // Lines 7 and 8 are blank lines inside the fence, the rest is left alone
+    mdfmt()
+        .args(["--line-range", "7:8"])
+        .arg(&path)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Title\n\n\n\nIntro\n```\ncode\n```\n## Usage\nText\n"
+    );
+
+    for range in ["0:3", "5:2", "5"] {
+        mdfmt()
+            .args(["--line-range", range])
+            .arg(&path)
+            .assert()
+            .code(2);
+    }
+    mdfmt()
+        .args(["--line-range", "1:2"])
+        .arg(temp.path())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_diff_filter_uses_config() {
+    let content = "# Title\n\n\n\nIntro\nSee https://example.com\n\n\n\n\nEnd\n";
+    let temp = setup(
+        "mdfmt_cli_diff_filter_config",
+        &[
+            ("doc.md", content),
+            (
+                ".mdfmt.toml",
+                "max_consecutive_blank_lines = 3\nfix_bare_urls = true\n",
+            ),
+        ],
+    );
+    let dir = temp.path();
+    fs::create_dir(dir.join(".git")).unwrap();
+    let path = dir.join("doc.md");
+    let diff = "--- a/doc.md\n+++ b/doc.md\n@@ -1,0 +2,10 @@\n";
+
+    // Three blank lines are allowed, so only the run of four is shortened
+    mdfmt()
+        .arg("--diff-filter")
+        .arg(dir)
+        .write_stdin(diff)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Title\n\n\n\nIntro\nSee <https://example.com>\n\n\n\nEnd\n"
+    );
+}
+
+#[test]
+fn test_lint_reports_without_modifying() {
+    let messy = "# Title\nSee https://example.com\n\n\n\n```\nls\n```\n";
+    let temp = setup(
+        "mdfmt_cli_lint",
+        &[
+            ("b.md", messy),
+            ("a.md", "# Title\n\n![](chart.png)\n"),
+            ("clean.md", "# Title\n\nSee https://example.com\n"),
+        ],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt().arg("--lint").arg(dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout =
+        String::from_utf8_lossy(&output.stdout).replace(&format!("{}/", dir.display()), "");
+    assert_eq!(
+        stdout,
+        "\
+a.md:3:1: MB102 image `![](chart.png)` has no alt text
+b.md:1: MB003 missing blank line after heading
+b.md:2:5: MB101 bare URL `https://example.com` should be wrapped in angle brackets or a link
+b.md:4: MB001 multiple consecutive blank lines
+b.md:6:1: MB103 code fence has no language
+clean.md:3:5: MB101 bare URL `https://example.com` should be wrapped in angle brackets or a link
+6 problems (3 errors) in 3 files
+"
+    );
+    assert_eq!(fs::read_to_string(dir.join("b.md")).unwrap(), messy);
+
+    // Warnings alone do not fail the run
+    mdfmt()
+        .arg("--lint")
+        .arg(dir.join("clean.md"))
+        .assert()
+        .code(0);
+
+    let output = mdfmt()
+        .args(["--lint", "--output-format", "json"])
+        .arg(dir.join("b.md"))
+        .output()
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["summary"]["problems"], 4);
+    assert_eq!(report["violations"][1]["severity"], "warning");
+    assert_eq!(
+        report["violations"][1]["suggestion"],
+        "See <https://example.com>"
+    );
+}
+
+#[test]
+fn test_remove_empty_headings() {
+    let temp = setup(
+        "mdfmt_cli_remove_empty_headings",
+        &[("guide.md", "# Guide\n\n## Setup\n\n##\n\nRun it.\n")],
+    );
+    let path = temp.path().join("guide.md");
+
+    let output = mdfmt().arg("--lint").arg(&path).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("guide.md:3:1: MB108 heading has no content\n"));
+    assert!(stdout.contains("guide.md:5:1: MB108 heading has no text\n"));
+
+    mdfmt().arg(&path).assert().code(0);
+    mdfmt()
+        .arg("--remove-empty-headings")
+        .arg(&path)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Guide\n\n## Setup\n\nRun it.\n"
+    );
+}
+
+#[test]
+fn test_lint_heading_structure() {
+    let content = "## Guide\n\n#### Setup\n\nText\n\n# Other\n\nText\n\n# Another\n\nText\n";
+    let temp = setup("mdfmt_cli_lint_heading_structure", &[("guide.md", content)]);
+    let dir = temp.path();
+    fs::create_dir(dir.join(".git")).unwrap();
+    // The checks can be turned off in a config file, here for one directory
+    fs::create_dir(dir.join("relaxed")).unwrap();
+    fs::write(dir.join("relaxed").join("guide.md"), content).unwrap();
+    fs::write(
+        dir.join("relaxed").join(".mdfmt.toml"),
+        "[lint]\nallow-multiple-h1 = true\nallow-first-heading-not-h1 = true\n",
+    )
+    .unwrap();
+
+    let output = mdfmt().arg("--lint").arg(dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout =
+        String::from_utf8_lossy(&output.stdout).replace(&format!("{}/", dir.display()), "");
+    assert_eq!(
+        stdout,
+        "\
+guide.md:1:1: MB110 first heading is level 2, not level 1
+guide.md:3:1: MB111 heading level jumps from 2 to 4
+guide.md:11:1: MB109 more than one level 1 heading; the first is on line 7
+relaxed/guide.md:3:1: MB111 heading level jumps from 2 to 4
+4 problems (0 errors) in 2 files
+"
+    );
+}
+
+#[test]
+fn test_color_output() {
+    let temp = setup(
+        "mdfmt_cli_color",
+        &[
+            ("messy.md", "# Title\nText\n\n\n\nMore\n"),
+            ("empty.md", ""),
+        ],
+    );
+    let dir = temp.path();
+    fs::write(dir.join("binary.md"), [0xff, 0xfe, 0x00]).unwrap();
+    let run = |args: &[&str]| {
+        let output = mdfmt()
+            .args(["--check", "--delete"])
+            .args(args)
+            .arg(dir)
+            .env_remove("NO_COLOR")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        (
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    };
+
+    // Captured output is not a terminal, so there are no colors by default
+    for args in [&[][..], &["--color", "never"], &["--verbose"]] {
+        let (stdout, stderr) = run(args);
+        assert!(stdout.contains("would be modified"));
+        assert!(
+            !stdout.contains('\x1b') && !stderr.contains('\x1b'),
+            "{:?}",
+            args
+        );
+    }
+
+    let (stdout, stderr) = run(&["--color", "always"]);
+    assert!(stdout.contains("\x1b[33m"));
+    assert!(stdout.contains("\x1b[31m"));
+    assert!(stdout.contains("Files processed: \x1b[1m3\x1b[0m"));
+    assert!(stderr.contains("\x1b[91m"));
+    // The JSON report never gets escape sequences
+    let (stdout, _) = run(&["--color", "always", "--output-format", "json"]);
+    assert!(!stdout.contains('\x1b'));
+
+    let output = mdfmt()
+        .args(["--dry-run", "--verbose", "--color", "always"])
+        .arg(dir.join("messy.md"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\x1b[32m+\x1b[0m"));
+    assert!(stdout.contains("\x1b[31m-\x1b[0m"));
+}
+
+#[test]
+fn test_list_different() {
+    let messy = "# Title\nText\n";
+    let temp = setup(
+        "mdfmt_cli_list_different",
+        &[
+            ("messy.md", messy),
+            ("clean.md", "# Title\n\nText\n"),
+            ("skipped.md", messy),
+        ],
+    );
+    let dir = temp.path();
+    fs::create_dir(dir.join("docs")).unwrap();
+    fs::write(dir.join("docs").join("guide.md"), messy).unwrap();
+
+    let output = mdfmt()
+        .current_dir(dir)
+        .args(["--list-different", "--exclude", "skipped.md"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "{}\nmessy.md\n",
+            Path::new("docs").join("guide.md").display()
+        )
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "2 files would be changed\n"
+    );
+    assert_eq!(fs::read_to_string(dir.join("messy.md")).unwrap(), messy);
+
+    // Quiet drops the count line, and clean files give an empty list
+    let output = mdfmt()
+        .current_dir(dir)
+        .args(["-l", "-q", "clean.md"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_list_files() {
+    let messy = "# Title\nText\n";
+    let temp = setup(
+        "mdfmt_cli_list_files",
+        &[
+            ("b.md", messy),
+            ("a.md", messy),
+            ("clean.md", "# Title\n\nText\n"),
+        ],
+    );
+    let dir = temp.path();
+    let absolute = |name: &str| std::path::absolute(dir.join(name)).unwrap();
+
+    let output = mdfmt()
+        .args(["--check", "--list-files", "--sort-by", "name"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "{}\n{}\n",
+            absolute("a.md").display(),
+            absolute("b.md").display()
+        )
+    );
+    assert!(output.stderr.is_empty());
+    assert_eq!(fs::read_to_string(dir.join("a.md")).unwrap(), messy);
+
+    let output = mdfmt()
+        .args(["--check", "--list-files", "--output-format", "json"])
+        .arg(dir.join("a.md"))
+        .arg(dir.join("clean.md"))
+        .output()
+        .unwrap();
+    let paths: Vec<String> = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(paths, [absolute("a.md").display().to_string()]);
+
+    let output = mdfmt()
+        .args(["--check", "--list-files", "--output-format", "json"])
+        .arg(dir.join("clean.md"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "[]\n");
+
+    mdfmt().arg("--list-files").arg(dir).assert().code(2);
+}
+
+/// The canonical path of `dir` in its verbatim (`\\?\C:\...`) and drive-rooted
+/// (`C:\...`) forms.
+#[cfg(windows)]
+fn windows_paths(dir: &Path) -> (std::path::PathBuf, std::path::PathBuf) {
+    let verbatim = dir.canonicalize().unwrap();
+    let drive = verbatim
+        .to_str()
+        .unwrap()
+        .strip_prefix(r"\\?\")
+        .unwrap()
+        .into();
+    (verbatim, drive)
+}
+
+#[cfg(windows)]
+#[test]
+fn test_windows_drive_rooted_paths() {
+    let temp = setup(
+        "mdfmt_cli_windows_drive",
+        &[("messy.md", "# Title\nText\n")],
+    );
+    let (verbatim, drive) = windows_paths(temp.path());
+
+    // Both forms find the file, and it is reported relative to the current directory
+    for path in [&verbatim, &drive] {
+        let output = mdfmt()
+            .current_dir(&drive)
+            .arg("-l")
+            .arg(path)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "messy.md\n");
+    }
+
+    // Outside the current directory, without the verbatim prefix
+    let output = mdfmt()
+        .args(["--check", "--list-files"])
+        .arg(&verbatim)
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}\n", drive.join("messy.md").display())
+    );
+}
+
+#[cfg(windows)]
+#[test]
+fn test_windows_unc_paths() {
+    let temp = setup("mdfmt_cli_windows_unc", &[("messy.md", "# Title\nText\n")]);
+    let (_, drive) = windows_paths(temp.path());
+    let drive = drive.to_str().unwrap();
+    // The administrative share of the drive, e.g. \\localhost\C$\Users\...
+    let unc = format!(r"\\localhost\{}$\{}", &drive[..1], &drive[3..]);
+    let verbatim_unc = format!(r"\\?\UNC\localhost\{}$\{}", &drive[..1], &drive[3..]);
+
+    for path in [&unc, &verbatim_unc] {
+        let output = mdfmt().arg("--check").arg(path).output().unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains(&format!(r"{}\messy.md: would be modified", unc)),
+            "{}",
+            stdout
+        );
+    }
+}
+
+#[cfg(windows)]
+#[test]
+fn test_windows_paths_with_spaces() {
+    let temp = setup("mdfmt_cli_windows_spaces", &[]);
+    let dir = temp.path().join("my docs").join("sub dir");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("read me.md"), "# Title\nText\n").unwrap();
+
+    // Forward slashes in arguments are reported with native separators
+    let output = mdfmt()
+        .current_dir(temp.path())
+        .args(["-l", "my docs/sub dir"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "my docs\\sub dir\\read me.md\n"
+    );
+
+    mdfmt()
+        .current_dir(temp.path())
+        .arg(r"my docs\sub dir\read me.md")
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("read me.md")).unwrap(),
+        "# Title\n\nText\n"
+    );
+}
+
+#[cfg(feature = "preserve-mtime")]
+#[test]
+fn test_preserve_mtime() {
+    let temp = setup("mdfmt_cli_preserve_mtime", &[("doc.md", "# Title\nText\n")]);
+    let path = temp.path().join("doc.md");
+    let past = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(past)
+        .unwrap();
+
+    mdfmt().arg("--preserve-mtime").arg(&path).assert().code(1);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "# Title\n\nText\n");
+    assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), past);
+}
+
+#[test]
+fn test_toc() {
+    let content =
+        "# Guide\n\n<!-- mdfmt-toc-start -->\n<!-- mdfmt-toc-end -->\n\n## Setup\n\n### Install\n";
+    let temp = setup(
+        "mdfmt_cli_toc",
+        &[
+            ("guide.md", content),
+            ("plain.md", "# Plain\n\n## Section\n"),
+        ],
+    );
+    let dir = temp.path();
+
+    mdfmt()
+        .args(["--toc", "--toc-levels", "2..3"])
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("guide.md")).unwrap(),
+        content.replace(
+            "-->\n<!--",
+            "-->\n\n- [Setup](#setup)\n  - [Install](#install)\n\n<!--"
+        )
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("plain.md")).unwrap(),
+        "# Plain\n\n## Section\n"
+    );
+    mdfmt()
+        .args(["--check", "--toc", "--toc-levels", "2..3"])
+        .arg(dir)
+        .assert()
+        .code(0);
+
+    mdfmt()
+        .args(["--toc-levels", "2..3"])
+        .arg(dir)
+        .assert()
+        .code(2);
+    mdfmt()
+        .args(["--toc", "--toc-levels", "3..2"])
+        .arg(dir)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_sort_frontmatter() {
+    let broken = "---\ntitle: Broken\ntags: [a, b\n---\n\n# Broken\n";
+    let temp = setup(
+        "mdfmt_cli_sort_frontmatter",
+        &[
+            (
+                "post.md",
+                "---\ntags:\n  - rust\ndate: 2024-05-01\n# Shown in the tab\ntitle: Post\n---\n\n# Post\n",
+            ),
+            ("broken.md", broken),
+        ],
+    );
+    let dir = temp.path();
+
+    mdfmt().arg(dir).assert().code(0);
+    mdfmt().arg("--sort-frontmatter").arg(dir).assert().code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("post.md")).unwrap(),
+        "---\ndate: 2024-05-01\ntags:\n  - rust\n# Shown in the tab\ntitle: Post\n---\n\n# Post\n"
+    );
+    assert_eq!(fs::read_to_string(dir.join("broken.md")).unwrap(), broken);
+
+    fs::write(
+        dir.join(".mdfmt.toml"),
+        "sort_frontmatter = true\nfrontmatter_key_order = [\"title\", \"date\"]\n",
+    )
+    .unwrap();
+    mdfmt().arg(dir).assert().code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("post.md")).unwrap(),
+        "---\n# Shown in the tab\ntitle: Post\ndate: 2024-05-01\ntags:\n  - rust\n---\n\n# Post\n"
+    );
+
+    let output = mdfmt()
+        .arg("--lint")
+        .arg(dir.join("broken.md"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("broken.md:4:1: MB106 frontmatter is not valid YAML: "),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_list_indent() {
+    let temp = setup(
+        "mdfmt_cli_list_indent",
+        &[(
+            "list.md",
+            "- a\n  - b\n\t- c\n\n    ```sh\n    run\n    ```\n",
+        )],
+    );
+    let dir = temp.path();
+
+    mdfmt().arg(dir).assert().code(0);
+    mdfmt()
+        .arg("--tab-width")
+        .arg("2")
+        .arg(dir)
+        .assert()
+        .code(2);
+    mdfmt()
+        .arg("--list-indent")
+        .arg("4")
+        .arg("--tab-width")
+        .arg("2")
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("list.md")).unwrap(),
+        "- a\n    - b\n    - c\n\n      ```sh\n      run\n      ```\n"
+    );
+
+    fs::write(dir.join(".mdfmt.toml"), "list_indent = 2\n").unwrap();
+    mdfmt().arg(dir).assert().code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("list.md")).unwrap(),
+        "- a\n  - b\n  - c\n\n    ```sh\n    run\n    ```\n"
+    );
+}
+
+#[test]
+fn test_normalize_list_spacing() {
+    let temp = setup(
+        "mdfmt_cli_normalize_list_spacing",
+        &[(
+            "list.md",
+            "-   a\n    *  b\n\n1.  c\n\n```\n-   code\n```\n",
+        )],
+    );
+    let dir = temp.path();
+
+    mdfmt().arg(dir).assert().code(0);
+    mdfmt()
+        .arg("--normalize-list-spacing")
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("list.md")).unwrap(),
+        "- a\n    * b\n\n1. c\n\n```\n-   code\n```\n"
+    );
+}
+
+#[test]
+fn test_log_levels() {
+    let temp = setup(
+        "mdfmt_cli_log_levels",
+        &[("doc.md", "# Title\n\n\n```\n\n```\nText\n")],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt().arg("--dry-run").arg(dir).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    // Each -v shows one more level: which files were formatted, then every edit
+    let output = mdfmt()
+        .args(["--dry-run", "-vv"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("[INFO mdfmt] 1 markdown files with 1 distinct configurations"));
+    assert!(stderr.contains("doc.md: Modified"));
+    assert!(!stderr.contains("TRACE"));
+    let output = mdfmt()
+        .args(["--dry-run", "-vvv"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("[TRACE mdfmt::process_md] lines 4-6: code fence kept as is"));
+    assert!(stderr.contains("[TRACE mdfmt::process_md] removed line 3 (BlankRun)"));
+    assert!(stderr.contains(
+        "[TRACE mdfmt::process_md] inserted a blank line before line 7 (FenceSeparator)"
+    ));
+
+    // RUST_LOG overrides the flags
+    let output = mdfmt()
+        .env("RUST_LOG", "other=trace,mdfmt=debug")
+        .arg("--dry-run")
+        .arg(dir)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("[DEBUG mdfmt::find_md_files]"));
+    assert!(!stderr.contains("TRACE"));
+}
+
+#[test]
+fn test_wrap_blockquotes() {
+    let temp = setup(
+        "mdfmt_cli_wrap_blockquotes",
+        &[(
+            "quote.md",
+            "> A quote that is too long for one line\n>> - A nested item too\n\nText that is not quoted stays\n",
+        )],
+    );
+    let dir = temp.path();
+
+    mdfmt().arg(dir).assert().code(0);
+    mdfmt()
+        .args(["--wrap-blockquotes", "20"])
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("quote.md")).unwrap(),
+        "> A quote that is\n> too long for one\n> line\n>> - A nested item\n>>   too\n\nText that is not quoted stays\n"
+    );
+    mdfmt()
+        .args(["--wrap-blockquotes", "0"])
+        .arg(dir)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_tabs_to_spaces() {
+    let temp = setup(
+        "mdfmt_cli_tabs_to_spaces",
+        &[
+            ("list.md", "- a\n\t- b\tc\n"),
+            ("code.md", "Text\tmore\n\n\tcode\n"),
+        ],
+    );
+    let dir = temp.path();
+
+    mdfmt().arg("--tabs-to-spaces").arg(dir).assert().code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("list.md")).unwrap(),
+        "- a\n    - b\tc\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("code.md")).unwrap(),
+        "Text\tmore\n\n\tcode\n"
+    );
+
+    mdfmt()
+        .arg("--check")
+        .arg("--tabs-to-spaces")
+        .arg(dir)
+        .assert()
+        .code(0);
+    fs::write(dir.join("list.md"), "- a\n\t- b\n").unwrap();
+    mdfmt().arg("--tabs-to-spaces=2").arg(dir).assert().code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("list.md")).unwrap(),
+        "- a\n  - b\n"
+    );
+}
+
+#[test]
+fn test_report_unchanged() {
+    let temp = setup(
+        "mdfmt_cli_report_unchanged",
+        &[
+            ("messy.md", "# Title\nText\n"),
+            ("clean.md", "# Title\n\nText\n"),
+            ("plain.md", "Text\n"),
+        ],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt()
+        .current_dir(dir)
+        .args(["--check", "--report-unchanged", "--sort-by", "name", "."])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Files already formatted:\n  clean.md\n  plain.md\n"));
+    assert!(stdout.contains("  Files already formatted: 2\n"));
+    assert!(!stdout.contains("  messy.md"));
+
+    // Quiet only prints the count
+    let output = mdfmt()
+        .args(["--check", "--report-unchanged", "-q"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "2 files already formatted\n"
+    );
+
+    let output = mdfmt()
+        .args(["--check", "--report-unchanged", "--output-format", "json"])
+        .arg(dir.join("clean.md"))
+        .output()
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        report["unchanged"],
+        serde_json::json!([dir.join("clean.md")])
+    );
+    assert_eq!(report["summary"]["unchanged"], 1);
+
+    mdfmt().arg("--report-unchanged").arg(dir).assert().code(2);
+}
+
+#[test]
+fn test_find_duplicates() {
+    let temp = setup(
+        "mdfmt_cli_duplicates",
+        &[
+            ("a.md", "# Setup\n\nRun it.\n"),
+            ("b.md", "# Other\n"),
+            // Only differs in blank lines, which formatting removes
+            ("c.md", "# Setup\nRun it.\n\n\n"),
+        ],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt()
+        .current_dir(dir)
+        .args(["--find-duplicates", "."])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout).replace("./", "");
+    assert_eq!(
+        stdout,
+        "a.md\nc.md\n\n1 group of duplicate files in 3 files\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("c.md")).unwrap(),
+        "# Setup\nRun it.\n\n\n"
+    );
+
+    mdfmt()
+        .args(["--find-duplicates", "--quiet"])
+        .arg(dir.join("b.md"))
+        .assert()
+        .code(0)
+        .stdout("");
+}
+
+#[test]
+fn test_extract_headings() {
+    let guide = "---\ntitle: Guide\n---\n\n# Guide\n\n## Install `mdfmt`\n\n```sh\n# comment\n```\n\n## *Usage*\n\n\n## Usage\n";
+    let temp = setup(
+        "mdfmt_cli_extract_headings",
+        &[("guide.md", guide), ("empty.md", "Text\n")],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt()
+        .current_dir(dir)
+        .args(["--extract-headings", "."])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout).replace("./", "");
+    assert_eq!(
+        stdout,
+        "empty.md\n\nguide.md\n 5  Guide (#guide)\n 7    Install mdfmt (#install-mdfmt)\n13    Usage (#usage)\n16    Usage (#usage-1)\n\n4 headings in 2 files\n"
+    );
+    assert_eq!(fs::read_to_string(dir.join("guide.md")).unwrap(), guide);
+
+    let output = mdfmt()
+        .args(["--extract-headings", "--output-format", "json"])
+        .arg(dir.join("guide.md"))
+        .output()
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["summary"]["headings"], 4);
+    assert_eq!(
+        report["files"][0]["headings"][1],
+        serde_json::json!({
+            "level": 2,
+            "text": "Install mdfmt",
+            "line": 7,
+            "slug": "install-mdfmt",
+        })
+    );
+}
+
+#[test]
+fn test_config_files_are_inherited_per_directory() {
+    let content = "# Title\n\nSee https://example.com\n";
+    let fixed = "# Title\n\nSee <https://example.com>\n";
+    let temp = setup(
+        "mdfmt_cli_config",
+        &[
+            (".mdfmt.toml", "fix_bare_urls = true\n"),
+            ("root.md", content),
+        ],
+    );
+    let dir = temp.path();
+    fs::create_dir(dir.join(".git")).unwrap();
+    for sub in ["api", "guides"] {
+        fs::create_dir(dir.join(sub)).unwrap();
+        fs::write(dir.join(sub).join("doc.md"), content).unwrap();
+    }
+    fs::write(
+        dir.join("api").join(".mdfmt.toml"),
+        "fix_bare_urls = false\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("guides").join(".mdfmt.toml"),
+        "delete = \"remove\"\n",
+    )
+    .unwrap();
+
+    mdfmt().arg(dir).assert().code(1);
+    assert_eq!(fs::read_to_string(dir.join("root.md")).unwrap(), fixed);
+    assert_eq!(
+        fs::read_to_string(dir.join("api").join("doc.md")).unwrap(),
+        content
+    );
+    // guides/ only sets `delete`, so it inherits fix_bare_urls from the root
+    assert_eq!(
+        fs::read_to_string(dir.join("guides").join("doc.md")).unwrap(),
+        fixed
+    );
+
+    // --no-config falls back to the defaults
+    fs::write(dir.join("root.md"), content).unwrap();
+    mdfmt().arg("--no-config").arg(dir).assert().code(0);
+    assert_eq!(fs::read_to_string(dir.join("root.md")).unwrap(), content);
+
+    fs::write(dir.join(".mdfmt.toml"), "max_blank_lines = 3\n").unwrap();
+    let output = mdfmt().arg(dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid config file"));
+}
+
+#[test]
+fn test_print_config() {
+    let temp = setup(
+        "mdfmt_cli_print_config",
+        &[
+            (".mdfmt.toml", "fix_bare_urls = true\n"),
+            (".editorconfig", "[*.md]\nend_of_line = crlf\n"),
+            ("doc.md", "# Title\n"),
+        ],
+    );
+    let dir = temp.path();
+    fs::create_dir(dir.join(".git")).unwrap();
+    fs::create_dir(dir.join("api")).unwrap();
+    fs::write(
+        dir.join("api").join(".mdfmt.toml"),
+        "[fence_aliases]\nsh = \"bash\"\n",
+    )
+    .unwrap();
+
+    // Defaults, config files, .editorconfig, and flags, in a file mdfmt can read
+    let output = mdfmt()
+        .args(["--print-config", "--toc"])
+        .arg(dir.join("doc.md"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for line in [
+        "delete = \"keep\"\n",
+        "fix_bare_urls = true\n",
+        "line_ending = \"crlf\"\n",
+        "toc = true\n",
+        "toc_levels = \"1..6\"\n",
+    ] {
+        assert!(stdout.contains(line), "{:?} not in {}", line, stdout);
+    }
+    assert!(!stdout.contains("list_indent"));
+    let printed = dir.join("printed.toml");
+    fs::write(&printed, stdout.as_bytes()).unwrap();
+    assert!(mdfmt::config::Config::from_file(&printed).is_ok());
+
+    // A directory gets its own config files, but no .editorconfig sections
+    let output = mdfmt()
+        .arg("--print-config")
+        .arg(dir.join("api"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("fix_bare_urls = true\n"));
+    assert!(stdout.contains("[code_fence_language_aliases]\nsh = \"bash\"\n"));
+    assert!(stdout.contains("line_ending = \"keep\"\n"));
+
+    mdfmt()
+        .arg("--print-config")
+        .arg(dir)
+        .arg(dir.join("api"))
+        .assert()
+        .code(2);
+}
+
+// The scripts include the hidden subcommands and every optional flag, so they are
+// generated with all features
+#[cfg(all(
+    feature = "completions",
+    feature = "manpage",
+    feature = "preserve-mtime"
+))]
+#[test]
+fn test_checked_in_completions_are_up_to_date() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("completions");
+    for (shell, file_name) in [
+        ("bash", "mdfmt.bash"),
+        ("zsh", "_mdfmt"),
+        ("fish", "mdfmt.fish"),
+        ("powershell", "_mdfmt.ps1"),
+    ] {
+        let output = mdfmt().args(["completions", shell]).output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            fs::read_to_string(dir.join(file_name)).unwrap(),
+            "completions/{} is outdated, run `cargo xtask completions`",
+            file_name
+        );
+    }
+
+    mdfmt().args(["completions", "tcsh"]).assert().code(2);
+}
+
+#[cfg(feature = "completions")]
+#[test]
+fn test_generate_completions_flag() {
+    let subcommand = mdfmt().args(["completions", "zsh"]).output().unwrap();
+    let flag = mdfmt()
+        .args(["--generate-completions", "Zsh"])
+        .output()
+        .unwrap();
+    assert!(flag.status.success());
+    assert_eq!(flag.stdout, subcommand.stdout);
+
+    mdfmt()
+        .args(["--generate-completions", "tcsh"])
+        .assert()
+        .code(2);
+}
+
+#[cfg(feature = "manpage")]
+#[test]
+fn test_manpage() {
+    let output = mdfmt().arg("manpage").output().unwrap();
+    assert!(output.status.success());
+    let page = String::from_utf8(output.stdout).unwrap();
+    assert!(page.contains(".TH mdfmt 1"));
+    assert!(page.contains("\\-\\-check"));
+}
+
+#[cfg(any(feature = "completions", feature = "manpage"))]
+#[test]
+fn test_subcommand_names_are_paths_after_options() {
+    let temp = setup("mdfmt_cli_subcommand_dir", &[]);
+    let dir = temp.path();
+    fs::create_dir(dir.join("completions")).unwrap();
+    fs::write(dir.join("completions/a.md"), "# A\n").unwrap();
+
+    // A directory named like a subcommand is formatted after `--` or any option
+    mdfmt()
+        .current_dir(dir)
+        .args(["--", "completions"])
+        .assert()
+        .code(0);
+    mdfmt()
+        .current_dir(dir)
+        .args(["--check", "completions"])
+        .assert()
+        .code(0);
+    mdfmt()
+        .current_dir(dir)
+        .args(["--check", "./completions"])
+        .assert()
+        .code(0);
+}
+
+#[test]
+fn test_timings_report_slowest_files() {
+    let temp = setup("mdfmt_cli_timings", &[("a.md", "# A\n")]);
+    let dir = temp.path();
+
+    let output = mdfmt().arg("--timings").arg(dir).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Total time:"));
+    assert!(stdout.contains("Bytes processed: 4"));
+    assert!(stdout.contains("Slowest files:"));
+}
+
+#[test]
+fn test_cache_skips_clean_files() {
+    let temp = setup(
+        "mdfmt_cli_cache",
+        &[("clean.md", "# Clean\n"), ("messy.md", "# Messy\nText\n")],
+    );
+    let dir = temp.path();
+    let cache = dir.join(".mdfmt-cache");
+
+    // The first run formats messy.md and records both files as clean
+    let output = mdfmt()
+        .arg("--cache-location")
+        .arg(&cache)
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Files skipped (cached): 0"));
+    assert!(cache.is_file());
+
+    let output = mdfmt()
+        .args(["--check", "--output", "json", "--cache-location"])
+        .arg(&cache)
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["files"][0]["status"], "cached");
+    assert_eq!(report["summary"]["cached"], 2);
+
+    // Editing a file invalidates its entry, and --no-cache ignores the cache entirely
+    fs::write(dir.join("clean.md"), "# Changed\nText\n").unwrap();
+    let output = mdfmt()
+        .args(["--check", "--cache-location"])
+        .arg(&cache)
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Files skipped (cached): 1"));
+
+    let output = mdfmt()
+        .args(["--check", "--no-cache", "--cache-location"])
+        .arg(&cache)
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("cached"));
+}
+
+#[test]
+fn test_exclude_with_negated_pattern() {
+    let temp = setup(
+        "mdfmt_cli_exclude",
+        &[
+            ("CHANGELOG.md", "# Changelog\nText\n"),
+            ("guide.md", "# Guide\nText\n"),
+        ],
+    );
+    let dir = temp.path();
+
+    // Everything is excluded except the changelog, which the negated pattern keeps
+    let output = mdfmt()
+        .args(["--check", "--include", "**/*.md", "--exclude", "*.md"])
+        .args(["--exclude", "!CHANGELOG.md"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("CHANGELOG.md: would be modified"));
+    assert!(!stdout.contains("guide.md"));
+
+    mdfmt().args(["--exclude", "a["]).arg(dir).assert().code(2);
+}
+
+#[test]
+fn test_sorted_output_lists_files_in_input_order() {
+    let files: Vec<(String, &str)> = (0..20)
+        .map(|i| (format!("file{:02}.md", i), "# Title\nText\n"))
+        .collect();
+    let files: Vec<(&str, &str)> = files.iter().map(|(n, c)| (n.as_str(), *c)).collect();
+    let temp = setup("mdfmt_cli_sorted_output", &files);
+    let dir = temp.path();
+
+    let output = mdfmt()
+        .args(["--check", "--sorted-output"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let listed: Vec<_> = stdout
+        .lines()
+        .filter(|line| line.contains(".md: would be modified"))
+        .collect();
+    assert_eq!(listed.len(), 20);
+    let mut sorted = listed.clone();
+    sorted.sort();
+    assert_eq!(listed, sorted);
+}
+
+#[test]
+fn test_parallel_reports_do_not_interleave() {
+    let contents: Vec<(String, String)> = (0..300)
+        .map(|i| {
+            (
+                format!("file{:03}.md", i),
+                format!("# Title {i:03}\nText {i:03}\n```\ncode {i:03}\n```\n"),
+            )
+        })
+        .collect();
+    let files: Vec<(&str, &str)> = contents
+        .iter()
+        .map(|(name, content)| (name.as_str(), content.as_str()))
+        .collect();
+    let temp = setup("mdfmt_cli_parallel_reports", &files);
+    let dir = temp.path();
+    for i in 0..50 {
+        fs::write(dir.join(format!("bad{:03}.md", i)), b"\xff\xfe").unwrap();
+    }
+
+    let output = mdfmt()
+        .args(["--dry-run", "--verbose", "--require-fence-lang"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+
+    // Every diff hunk line follows the status line of its own file
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut current = None;
+    let (mut reported, mut hunk_lines) = (0, 0);
+    for line in stdout.lines() {
+        if let Some((path, _)) = line.split_once(".md: would be modified") {
+            current = Some(path[path.len() - 3..].to_string());
+            reported += 1;
+        } else if line.starts_with(" # Title ") || line.starts_with(" Text ") {
+            let number = current.as_deref().expect("hunk without a status line");
+            assert!(
+                line.ends_with(number),
+                "{:?} is not in file{}",
+                line,
+                number
+            );
+            hunk_lines += 1;
+        }
+    }
+    assert_eq!((reported, hunk_lines), (300, 600));
+
+    // Errors and warnings are complete lines, next to the info log of --verbose
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let (mut warnings, mut errors) = (0, 0);
+    for line in stderr.lines().filter(|line| !line.starts_with("[INFO ")) {
+        let name = line.rsplit(['/', '\\']).next().unwrap();
+        if name.starts_with("file") && name.ends_with(".md:3: warning: code fence has no language")
+        {
+            warnings += 1;
+        } else if name.starts_with("bad")
+            && name.ends_with(".md: error: invalid UTF-8 at byte 0 (line 1): [ff] fe")
+        {
+            errors += 1;
+        } else {
+            panic!("unexpected line on stderr: {:?}", line);
+        }
+    }
+    assert_eq!((warnings, errors), (300, 50));
+}
+
+#[test]
+fn test_ignore_final_newline_only() {
+    let temp = setup(
+        "mdfmt_cli_ignore_final_newline_only",
+        &[
+            ("clean.md", "# Title\n\nText"),
+            ("messy.md", "# Title\nText"),
+        ],
+    );
+    let dir = temp.path();
+
+    mdfmt()
+        .args(["--final-newline", "--ignore-final-newline-only", "--check"])
+        .arg(dir.join("clean.md"))
+        .assert()
+        .code(0);
+    mdfmt()
+        .args(["--final-newline", "--ignore-final-newline-only"])
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("clean.md")).unwrap(),
+        "# Title\n\nText"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("messy.md")).unwrap(),
+        "# Title\n\nText\n"
+    );
+}
+
+#[test]
+fn test_encoding() {
+    let temp = setup("mdfmt_cli_encoding", &[]);
+    let dir = temp.path();
+    fs::write(dir.join("legacy.md"), b"# Caf\xe9\nCr\xe8me\n").unwrap();
+
+    // Latin-1 files are not valid UTF-8
+    mdfmt().arg("--check").arg(dir).assert().code(2);
+    mdfmt()
+        .args(["--encoding", "latin1"])
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read(dir.join("legacy.md")).unwrap(),
+        b"# Caf\xe9\n\nCr\xe8me\n"
+    );
+    mdfmt()
+        .args(["--encoding", "ISO-8859-1", "--check"])
+        .arg(dir)
+        .assert()
+        .code(0);
+
+    for name in ["klingon", "utf-16"] {
+        let output = mdfmt()
+            .args(["--encoding", name])
+            .arg(dir)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2), "{}", name);
+    }
+}
+
+#[test]
+fn test_max_size_skips_large_files() {
+    let large = format!("# Large\n{}", "Text\n\n\n".repeat(1024));
+    let temp = setup(
+        "mdfmt_cli_max_size",
+        &[("small.md", "# Small\nText\n"), ("large.md", &large)],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt()
+        .args(["--output", "json", "--max-size", "1K"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["files"][0]["status"], "too_large");
+    assert_eq!(report["files"][1]["status"], "modified");
+    assert_eq!(report["summary"]["too_large"], 1);
+    assert_eq!(report["summary"]["errors"], 0);
+    assert_eq!(report["summary"]["panicked"], 0);
+    assert_eq!(fs::read_to_string(dir.join("large.md")).unwrap(), large);
+
+    let output = mdfmt()
+        .args(["--verbose", "--max-size", "1K"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("large.md: skipped (too large)"));
+    assert!(stdout.contains("Files skipped (too large): 1"));
+
+    // Invalid sizes are rejected by argument parsing
+    mdfmt()
+        .args(["--max-size", "5X"])
+        .arg(dir)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_stdin_json_formats_without_touching_files() {
+    let temp = setup("mdfmt_cli_stdin_json", &[("doc.md", "# Doc\nText\n")]);
+    let dir = temp.path();
+    let path = dir.join("doc.md");
+    let request = serde_json::json!({"path": path, "content": "# Other\nContent\n"});
+
+    let output = mdfmt()
+        .arg("--stdin-json")
+        .write_stdin(format!("{}\n", request))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["path"], request["path"]);
+    assert_eq!(response["content"], "# Other\n\nContent\n");
+    assert_eq!(response["changed"], true);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "# Doc\nText\n");
+
+    // Malformed requests get an error response and a failing exit code
+    let output = mdfmt()
+        .arg("--stdin-json")
+        .write_stdin("{\"content\": 1}\n")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(response["error"].is_string());
+}
+
+#[test]
+fn test_stdin_format_mdx() {
+    let request = serde_json::json!({"path": "-", "content": "# Intro\n<Note>\nText\n</Note>\n"});
+
+    let output = mdfmt()
+        .args(["--stdin-json", "--stdin-format", "mdx"])
+        .write_stdin(format!("{}\n", request))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["content"], request["content"]);
+    assert_eq!(response["changed"], false);
+
+    // Without the flag, the tag is separated from the heading like HTML
+    let output = mdfmt()
+        .arg("--stdin-json")
+        .write_stdin(format!("{}\n", request))
+        .output()
+        .unwrap();
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["content"], "# Intro\n\n<Note>\nText\n</Note>\n");
+
+    mdfmt().args(["--stdin-format", "mdx"]).assert().failure();
+}
+
+#[test]
+fn test_stdin_filepath() {
+    let temp = setup(
+        "mdfmt_cli_stdin_filepath",
+        &[
+            (".mdfmt.toml", "fix_bare_urls = true\n"),
+            (".editorconfig", "[*.{md,mdx}]\nend_of_line = crlf\n"),
+        ],
+    );
+    let dir = temp.path();
+    fs::create_dir(dir.join(".git")).unwrap();
+    let request =
+        serde_json::json!({"path": "-", "content": "# Intro\n<Note>\n\nSee https://example.com\n"});
+
+    // The options of the file apply, and the file is never created
+    let path = dir.join("new.mdx");
+    let output = mdfmt()
+        .args(["--stdin-json", "--stdin-filepath"])
+        .arg(&path)
+        .write_stdin(format!("{}\n", request))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        response["content"],
+        "# Intro\r\n<Note>\r\n\r\nSee <https://example.com>\r\n"
+    );
+    assert!(!path.exists());
+
+    // --stdin-format still decides the flavor, and --no-config skips the config files
+    let output = mdfmt()
+        .args(["--stdin-json", "--no-config", "--no-editorconfig"])
+        .args(["--stdin-format", "markdown", "--stdin-filepath"])
+        .arg(&path)
+        .write_stdin(format!("{}\n", request))
+        .output()
+        .unwrap();
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        response["content"],
+        "# Intro\n\n<Note>\n\nSee https://example.com\n"
+    );
+
+    // The path names the file in errors, and it needs --stdin-json
+    fs::write(dir.join(".mdfmt.toml"), "fix_bare_urls = \"x\"\n").unwrap();
+    let output = mdfmt()
+        .args(["--stdin-json", "--stdin-filepath"])
+        .arg(&path)
+        .write_stdin(format!("{}\n", request))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("new.mdx"), "{}", stderr);
+    let output = mdfmt()
+        .args(["--stdin-filepath", "doc.md"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("requires --stdin-json"), "{}", stderr);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_lsp() {
+    let temp = setup(
+        "mdfmt_cli_lsp",
+        &[
+            (".mdfmt.toml", "fix_bare_urls = true\n"),
+            ("doc.md", "# On disk\n"),
+        ],
+    );
+    let dir = temp.path();
+    fs::create_dir(dir.join(".git")).unwrap();
+    let uri = format!("file://{}", dir.join("doc.md").display());
+    let messages = [
+        serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+        serde_json::json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+        serde_json::json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+            "textDocument": {"uri": uri, "languageId": "markdown", "version": 1,
+                "text": "# Unsaved\nText\n\nhttps://example.com"},
+        }}),
+        serde_json::json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/formatting", "params": {
+            "textDocument": {"uri": uri},
+            "options": {"tabSize": 4, "insertSpaces": true, "insertFinalNewline": true},
+        }}),
+        serde_json::json!({"jsonrpc": "2.0", "id": 3, "method": "shutdown"}),
+        serde_json::json!({"jsonrpc": "2.0", "method": "exit"}),
+    ];
+    let input: String = messages
+        .iter()
+        .map(|message| {
+            let body = message.to_string();
+            format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+        })
+        .collect();
+    let output = mdfmt().arg("--lsp").write_stdin(input).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let responses: Vec<serde_json::Value> = stdout
+        .split("Content-Length: ")
+        .skip(1)
+        .map(|message| serde_json::from_str(message.split_once("\r\n\r\n").unwrap().1).unwrap())
+        .collect();
+    assert_eq!(responses.len(), 3);
+    // The open text is formatted with the config of the file and the editor options
+    assert_eq!(
+        responses[1]["result"],
+        serde_json::json!([
+            {
+                "range": {"start": {"line": 1, "character": 0}, "end": {"line": 1, "character": 0}},
+                "newText": "\n",
+            },
+            {
+                "range": {"start": {"line": 3, "character": 0}, "end": {"line": 3, "character": 19}},
+                "newText": "<https://example.com>\n",
+            },
+        ])
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("doc.md")).unwrap(),
+        "# On disk\n"
+    );
+
+    // Exiting without a shutdown is an error
+    let body = serde_json::json!({"jsonrpc": "2.0", "method": "exit"}).to_string();
+    let output = mdfmt()
+        .arg("--lsp")
+        .write_stdin(format!("Content-Length: {}\r\n\r\n{}", body.len(), body))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_delete_to_trash_dry_run() {
+    let temp = setup(
+        "mdfmt_cli_trash_dry_run",
+        &[("stub.md", "---\ntitle: Stub\n---\n")],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt()
+        .args(["--dry-run", "--delete-to-trash"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("stub.md: would be trashed"));
+    assert!(dir.join("stub.md").exists());
+}
+
+// Only the freedesktop trash used on Linux can be moved with environment
+// variables; elsewhere this would fill the trash of whoever runs the tests
+#[cfg(target_os = "linux")]
+#[test]
+fn test_delete_to_trash() {
+    let temp = setup(
+        "mdfmt_cli_trash",
+        &[
+            ("stub.md", "---\ntitle: Stub\n---\n"),
+            ("doc.md", "# Doc\n"),
+        ],
+    );
+    let dir = temp.path();
+    // Keep the trash inside the test directory, on the same filesystem as the files
+    let data_home = dir.join("data");
+
+    let output = mdfmt()
+        .args(["--output", "json", "--delete-to-trash"])
+        .arg(dir)
+        .env("HOME", dir)
+        .env("XDG_DATA_HOME", &data_home)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["files"][1]["status"], "trashed");
+    assert_eq!(report["summary"]["deleted"], 1);
+    assert!(!dir.join("stub.md").exists());
+    assert!(data_home.join("Trash/files/stub.md").exists());
+}
+
+#[test]
+fn test_deleted_files_are_listed() {
+    let temp = setup(
+        "mdfmt_cli_deleted_list",
+        &[
+            ("a_stub.md", "---\ntitle: A\n---\n"),
+            ("b_doc.md", "# Doc\n"),
+            ("c_empty.md", "\n"),
+        ],
+    );
+    let dir = temp.path();
+    let list = dir.join("deleted.txt");
+
+    let output = mdfmt()
+        .args(["--check", "--delete"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Files that would be deleted:\n"));
+
+    let output = mdfmt()
+        .args(["--output", "json", "--delete", "--deleted-list"])
+        .arg(&list)
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let expected = vec![
+        dir.join("a_stub.md").display().to_string(),
+        dir.join("c_empty.md").display().to_string(),
+    ];
+    assert_eq!(report["deleted"], serde_json::json!(expected));
+    assert_eq!(
+        fs::read_to_string(&list).unwrap(),
+        format!("{}\n{}\n", expected[0], expected[1])
+    );
+}
+
+#[test]
+fn test_normalize_empty() {
+    let temp = setup(
+        "mdfmt_cli_normalize_empty",
+        &[("blank.md", "\n\n\n"), ("empty.md", "")],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt()
+        .args(["--output", "json", "--delete", "--normalize-empty"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["files"][0]["status"], "normalized");
+    assert_eq!(report["files"][1]["status"], "deleted");
+    assert_eq!(report["summary"]["normalized"], 1);
+    assert_eq!(report["summary"]["deleted"], 1);
+    assert_eq!(fs::read_to_string(dir.join("blank.md")).unwrap(), "");
+
+    let output = mdfmt().arg("--normalize-empty").arg(dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Files normalized: 0"));
+}
+
+#[test]
+fn test_warn_and_include_empty() {
+    let temp = setup(
+        "mdfmt_cli_include_empty",
+        &[
+            ("stub.md", "---\ntitle: Stub\n---\n\n\n"),
+            ("post.md", "---\ntitle: Post\n---\n\nText\n"),
+        ],
+    );
+    let dir = temp.path();
+
+    // Warnings leave the file alone
+    let output = mdfmt().arg("--warn-empty").arg(dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("stub.md: warning: file contains only frontmatter"));
+    assert!(!stderr.contains("post.md"));
+    assert_eq!(
+        fs::read_to_string(dir.join("stub.md")).unwrap(),
+        "---\ntitle: Stub\n---\n\n\n"
+    );
+
+    // Included files are formatted, and never deleted
+    mdfmt()
+        .args(["--include-empty", "--delete"])
+        .arg(dir)
+        .assert()
+        .code(1);
+    assert_eq!(
+        fs::read_to_string(dir.join("stub.md")).unwrap(),
+        "---\ntitle: Stub\n---\n"
+    );
+}
+
+#[test]
+fn test_sort_by_size() {
+    let temp = setup(
+        "mdfmt_cli_sort_by",
+        &[
+            ("a.md", "# A\n"),
+            ("b.md", "# B\nLonger text here\n"),
+            ("c.md", "# C\nText\n"),
+        ],
+    );
+    let dir = temp.path();
+
+    let output = mdfmt()
+        .args(["--check", "--sorted-output", "--sort-by", "size"])
+        .arg(dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let listed: Vec<_> = stdout
+        .lines()
+        .filter(|line| line.contains(".md: would be modified"))
+        .collect();
+    assert_eq!(listed.len(), 2);
+    assert!(listed[0].contains("b.md"));
+    assert!(listed[1].contains("c.md"));
+}
+
+#[test]
+fn test_out_dir() {
+    let temp = setup("mdfmt_cli_out_dir", &[]);
+    let dir = temp.path();
+    for (file, content) in [
+        ("docs/guide.md", "# Guide\nText\n"),
+        ("docs/api/clean.md", "Clean\n"),
+        ("docs/empty.md", ""),
+        ("other/guide.md", "Other\n"),
+    ] {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    let output = mdfmt()
+        .current_dir(dir)
+        .args(["docs", "--delete", "--out-dir", "build/docs"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let empty = Path::new("docs").join("empty.md");
+    assert!(
+        stdout.contains(&format!("{}: not written", empty.display())),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Files not written (empty): 1"),
+        "{}",
+        stdout
+    );
+    // The originals are kept, and clean files are copied too
+    assert_eq!(
+        fs::read_to_string(dir.join("docs/guide.md")).unwrap(),
+        "# Guide\nText\n"
+    );
+    assert!(dir.join("docs/empty.md").exists());
+    assert_eq!(
+        fs::read_to_string(dir.join("build/docs/guide.md")).unwrap(),
+        "# Guide\n\nText\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("build/docs/api/clean.md")).unwrap(),
+        "Clean\n"
+    );
+    assert!(!dir.join("build/docs/empty.md").exists());
+
+    // Copies inside a searched directory are not formatted again
+    mdfmt()
+        .current_dir(dir)
+        .args(["docs", "--out-dir", "docs/formatted"])
+        .assert()
+        .code(1);
+    // Nothing is written when checking, so the two options contradict each other
+    mdfmt()
+        .current_dir(dir)
+        .args(["docs", "--out-dir", "docs/formatted", "--check"])
+        .assert()
+        .code(2);
+    let output = mdfmt()
+        .current_dir(dir)
+        .args([
+            "docs",
+            "--out-dir",
+            "docs/formatted",
+            "--output-format",
+            "json",
+        ])
+        .output()
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["summary"]["processed"], 3);
+    assert_eq!(report["summary"]["omitted"], 0);
+
+    // Two inputs that map to the same output path are an error
+    let output = mdfmt()
+        .current_dir(dir)
+        .args(["docs", "other", "--out-dir", "build"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("would write both"), "{}", stderr);
+}
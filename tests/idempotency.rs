@@ -0,0 +1,378 @@
+//! Idempotency self-test: formatting already formatted markdown must not change it.
+
+use mdfmt::process_md::{
+    DeleteStrategy, FenceStyle, FormatOptions, FormatterConfig, HeadingBlankLines, LineEnding,
+    LinkTitleStyle, format_content, format_with_config, is_formatted, remove_multiple_blank_lines,
+};
+use mdfmt::toc::TocLevels;
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+use proptest::sample::select;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Collect the markdown files used as the formatting corpus.
+fn corpus() -> Vec<PathBuf> {
+    let tests_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut files = vec![tests_dir.join("test_example.md")];
+    for entry in fs::read_dir(tests_dir.join("corpus")).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "md") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Assert that a second formatting pass leaves the output of the first unchanged.
+fn assert_idempotent(name: &str, content: &str, format: impl Fn(&str) -> String) {
+    let once = format(content);
+    let twice = format(&once);
+    assert_eq!(once, twice, "formatting {name} is not idempotent");
+}
+
+#[test]
+fn test_corpus_is_idempotent() {
+    for path in corpus() {
+        let content = fs::read_to_string(&path).unwrap();
+        assert_idempotent(&path.display().to_string(), &content, |content| {
+            remove_multiple_blank_lines(content).into_owned()
+        });
+    }
+}
+
+#[test]
+fn test_corpus_is_idempotent_with_all_options() {
+    let config = FormatterConfig {
+        fix_bare_urls: true,
+        fix_missing_alt_text: true,
+        tilde_math_blocks: true,
+        code_fence_language_aliases: HashMap::from([
+            ("py".to_string(), "python".to_string()),
+            ("sh".to_string(), "bash".to_string()),
+        ]),
+        normalize_fence_languages: true,
+        builtin_fence_aliases: true,
+        remove_zero_width_chars: true,
+        format_tables: true,
+        link_title_style: Some(LinkTitleStyle::SingleQuote),
+        remove_heading_trailing_punctuation: true,
+        remove_empty_headings: true,
+        normalize_setext_underlines: true,
+        normalize_heading_spacing: true,
+        toc: Some(TocLevels::default()),
+        sort_frontmatter_keys: Some(vec!["title".to_string()]),
+        list_indent: Some(3),
+        normalize_list_marker_spacing: true,
+        tabs_to_spaces: Some(4),
+        wrap_blockquotes: Some(40),
+        trim_trailing_whitespace: true,
+        insert_final_newline: Some(true),
+        line_ending: LineEnding::Crlf,
+        // Shifting headings changes them on every pass, so it is never idempotent
+        ..FormatterConfig::default()
+    };
+    for path in corpus() {
+        let content = fs::read_to_string(&path).unwrap();
+        assert_idempotent(&path.display().to_string(), &content, |content| {
+            format_with_config(content, &config).into_owned()
+        });
+    }
+}
+
+#[test]
+fn test_corpus_is_idempotent_with_fence_styles() {
+    // All-options tests use tilde math blocks, which turn fence styles off
+    for fence_style in [FenceStyle::Backtick, FenceStyle::Tilde] {
+        let config = FormatterConfig {
+            fence_style,
+            ..FormatterConfig::default()
+        };
+        for path in corpus() {
+            let content = fs::read_to_string(&path).unwrap();
+            assert_idempotent(&path.display().to_string(), &content, |content| {
+                format_with_config(content, &config).into_owned()
+            });
+        }
+    }
+}
+
+#[test]
+fn test_corpus_with_extra_blank_lines_is_idempotent() {
+    // Doubling every newline creates long blank runs in every region
+    for path in corpus() {
+        let content = fs::read_to_string(&path).unwrap().replace('\n', "\n\n");
+        assert_idempotent(&path.display().to_string(), &content, |content| {
+            remove_multiple_blank_lines(content).into_owned()
+        });
+    }
+}
+
+#[test]
+fn test_regressions_are_idempotent() {
+    // Counterexamples found by the generated document tests below
+    let delete = |formatter| FormatOptions {
+        delete: DeleteStrategy::Remove,
+        formatter,
+        ..FormatOptions::default()
+    };
+    let zero_width = FormatterConfig {
+        remove_zero_width_chars: true,
+        ..FormatterConfig::default()
+    };
+    let final_newline = FormatterConfig {
+        insert_final_newline: Some(true),
+        ..FormatterConfig::default()
+    };
+    let setext_and_list_spacing = FormatterConfig {
+        normalize_setext_underlines: true,
+        normalize_list_marker_spacing: true,
+        ..FormatterConfig::default()
+    };
+    let cases = [
+        ("\u{200b}\n", delete(zero_width.clone())),
+        ("---\ntitle: x\n---\n\u{200b}\n", delete(zero_width.clone())),
+        (
+            "---\r\ntitle: x\r\n---\r\n",
+            delete(FormatterConfig::default()),
+        ),
+        ("---\ntitle: x\n---", delete(final_newline)),
+        ("2)  two\n---", delete(setext_and_list_spacing.clone())),
+        ("2)  two\n===", delete(setext_and_list_spacing.clone())),
+        ("Text\n    ---", delete(setext_and_list_spacing)),
+        (
+            "\u{ad}\n",
+            FormatOptions {
+                normalize_empty: true,
+                formatter: zero_width,
+                ..FormatOptions::default()
+            },
+        ),
+    ];
+    for (content, opts) in cases {
+        let once = format_content(content, &opts);
+        assert!(
+            once.would_delete() || is_formatted(&once.content, &opts),
+            "formatting {content:?} is not idempotent"
+        );
+    }
+}
+
+/// Lines that exercise every kind of block and every content rule.
+const LINES: &[&str] = &[
+    "",
+    "",
+    "   ",
+    "\t",
+    "Text",
+    "Text with trailing space  ",
+    "# Heading",
+    "## Heading ##",
+    "###### Deep",
+    "## Why? ##",
+    "# Done.",
+    "#NotAHeading",
+    "##",
+    "Setext",
+    "===",
+    "---",
+    "***",
+    "- item",
+    "  - nested",
+    "     - deeper",
+    "\t- tabbed",
+    " \t\tmixed\ttabs",
+    "-   spaced",
+    "  2)\ttabbed",
+    "   continued",
+    "1. item",
+    "10) item",
+    "2)  two",
+    "    ---",
+    "* * *",
+    "* item",
+    "> quote",
+    "> [!NOTE]",
+    ">",
+    "```",
+    "```py",
+    "``` Rust ",
+    "~~~",
+    "~~~sh",
+    "````",
+    "$$",
+    "    indented code",
+    "| a | b |",
+    "|---|:-:|",
+    "| 1 | 2 | 3 |",
+    "a | b",
+    "<div>",
+    "</div>",
+    "<!-- comment -->",
+    "![](images/cat.png)",
+    "Visit https://example.com now",
+    "[link](https://example.com 'Title')",
+    "[link](https://example.com (Title))",
+    "Zero\u{200b}width\u{ad}",
+    "`code` and \\| pipes",
+    "\u{200b}",
+    "> - quoted item",
+    "> ```",
+    ">> nested quote",
+    "> A quoted line long enough to wrap - with 1. markers # and \\ breaks inside",
+    "> > Nested quote text that goes on past the width of most settings",
+    "  ```",
+    "<details>",
+    "</details>",
+    "[^1]: Footnote",
+    "+++",
+    "title: x",
+    "date: 2024-01-01",
+    "tags:",
+    "  - b",
+    "# comment",
+    "<!-- mdfmt-toc-start -->",
+    "<!-- mdfmt-toc-end -->",
+    "## [Linked `code`](#x) #",
+    "### Heading",
+    ":::note",
+    "::: {.warning}",
+    ":::",
+    "::::",
+    "{: .note}",
+    "{#custom-id}",
+    "## Install {#install}",
+];
+
+prop_compose! {
+    /// A document built from [`LINES`], with LF or CRLF line endings and an optional
+    /// final newline.
+    fn document()(
+        frontmatter in any::<bool>(),
+        lines in vec(select(LINES), 0..40),
+        crlf in any::<bool>(),
+        final_newline in any::<bool>(),
+    ) -> String {
+        let mut lines = lines;
+        if frontmatter {
+            lines.splice(0..0, ["---", "title: x", "---"]);
+        }
+        let mut document = lines.join(if crlf { "\r\n" } else { "\n" });
+        if final_newline && !document.is_empty() {
+            document.push_str(if crlf { "\r\n" } else { "\n" });
+        }
+        document
+    }
+}
+
+prop_compose! {
+    /// Any formatter configuration, except heading shifts (see
+    /// `test_corpus_is_idempotent_with_all_options`).
+    fn formatter_config()(
+        (fix_bare_urls, fix_missing_alt_text, tilde_math_blocks, separate_tables_and_html,
+            remove_heading_trailing_punctuation, toc, normalize_setext_underlines,
+            remove_empty_headings)
+            in (any::<bool>(), any::<bool>(), any::<bool>(), any::<bool>(), any::<bool>(),
+                option::of(select(vec![TocLevels::default(), TocLevels::new(2, 3).unwrap()])),
+                any::<bool>(), any::<bool>()),
+        (aliases, normalize_fence_languages, remove_zero_width_chars, default_fence_language,
+            sort_frontmatter_keys, normalize_list_marker_spacing, normalize_heading_spacing,
+            builtin_fence_aliases)
+            in (any::<bool>(), any::<bool>(), any::<bool>(), option::of(select(&["text", "Rust"][..])),
+                option::of(select(vec![Vec::new(), vec!["title".to_string()]])), any::<bool>(),
+                any::<bool>(), any::<bool>()),
+        (max_consecutive_blank_lines, before, after, fence_style, list_indent, tab_width)
+            in (1..=2usize, option::of(0..=2usize), option::of(0..=2usize),
+                select(&[FenceStyle::Keep, FenceStyle::Backtick, FenceStyle::Tilde][..]),
+                option::of(1..=4usize), 2..=8usize),
+        (format_tables, crlf, insert_final_newline, trim_trailing_whitespace, link_title_style,
+            tabs_to_spaces, wrap_blockquotes, protect_comments)
+            in (any::<bool>(), any::<bool>(), option::of(any::<bool>()), any::<bool>(),
+                option::of(select(&[
+                    LinkTitleStyle::DoubleQuote,
+                    LinkTitleStyle::SingleQuote,
+                    LinkTitleStyle::Parenthesis,
+                ][..])),
+                option::of(1..=8usize), option::of(4..=40usize), any::<bool>()),
+    ) -> FormatterConfig {
+        FormatterConfig {
+            max_consecutive_blank_lines,
+            fix_bare_urls,
+            fix_missing_alt_text,
+            tilde_math_blocks,
+            separate_tables_and_html,
+            code_fence_language_aliases: if aliases {
+                HashMap::from([("py".to_string(), "python".to_string())])
+            } else {
+                HashMap::new()
+            },
+            normalize_fence_languages,
+            builtin_fence_aliases,
+            remove_zero_width_chars,
+            default_fence_language: default_fence_language.map(str::to_string),
+            blank_lines_before_heading: before.map(HeadingBlankLines::all),
+            blank_lines_after_heading: after.map(HeadingBlankLines::all),
+            fence_style,
+            format_tables,
+            line_ending: if crlf { LineEnding::Crlf } else { LineEnding::Lf },
+            insert_final_newline,
+            trim_trailing_whitespace,
+            link_title_style,
+            remove_heading_trailing_punctuation,
+            remove_empty_headings,
+            normalize_setext_underlines,
+            normalize_heading_spacing,
+            toc,
+            sort_frontmatter_keys,
+            list_indent,
+            tab_width,
+            normalize_list_marker_spacing,
+            tabs_to_spaces,
+            wrap_blockquotes,
+            protected_html_comments: if protect_comments {
+                vec!["<!-- comment -->".to_string()]
+            } else {
+                Vec::new()
+            },
+            ..FormatterConfig::default()
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_generated_documents_are_idempotent(content in document()) {
+        let once = remove_multiple_blank_lines(&content).into_owned();
+        prop_assert_eq!(&remove_multiple_blank_lines(&once), &once);
+        prop_assert!(is_formatted(&once, &FormatOptions::default()));
+    }
+
+    #[test]
+    fn test_generated_documents_are_idempotent_with_options(
+        content in document(),
+        formatter in formatter_config(),
+        normalize_empty in any::<bool>(),
+        include_empty in any::<bool>(),
+    ) {
+        let opts = FormatOptions {
+            delete: DeleteStrategy::Remove,
+            normalize_empty,
+            include_empty,
+            formatter,
+            ..FormatOptions::default()
+        };
+        let once = format_content(&content, &opts);
+        if once.would_delete() {
+            return Ok(());
+        }
+        let once = once.content.into_owned();
+        prop_assert_eq!(&format_content(&once, &opts).content, &once);
+        // Normalized files are zero bytes, and the delete strategy applies to those
+        if !once.is_empty() {
+            prop_assert!(is_formatted(&once, &opts));
+        }
+    }
+}
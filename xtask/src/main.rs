@@ -0,0 +1,70 @@
+//! Development tasks for mdfmt, run with `cargo xtask <TASK>`.
+//!
+//! Tasks:
+//!
+//! - `completions` - Regenerate the shell completion scripts in `completions/`
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+/// Shells to generate completion scripts for, with the file name of each script.
+const COMPLETIONS: [(&str, &str); 4] = [
+    ("bash", "mdfmt.bash"),
+    ("zsh", "_mdfmt"),
+    ("fish", "mdfmt.fish"),
+    ("powershell", "_mdfmt.ps1"),
+];
+
+fn main() {
+    let result = match env::args().nth(1).as_deref() {
+        Some("completions") => completions(),
+        _ => {
+            eprintln!(
+                "Usage: cargo xtask <TASK>\n\nTasks:\n  completions  Regenerate the shell completion scripts in completions/"
+            );
+            process::exit(2);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// The root of the mdfmt repository.
+fn project_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask is in a subdirectory of the repository")
+        .to_path_buf()
+}
+
+/// Write the completion script of every shell in [`COMPLETIONS`] to `completions/`.
+fn completions() -> io::Result<()> {
+    let root = project_root();
+    let dir = root.join("completions");
+    fs::create_dir_all(&dir)?;
+
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    for (shell, file_name) in COMPLETIONS {
+        let output = Command::new(&cargo)
+            .current_dir(&root)
+            .args(["run", "--quiet", "--all-features", "--bin", "mdfmt"])
+            .args(["--", "completions", shell])
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "generating {} completions failed: {}",
+                shell,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        let path = dir.join(file_name);
+        fs::write(&path, output.stdout)?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}